@@ -0,0 +1,84 @@
+use std::{hint::black_box, time::Instant};
+
+use roxy::{
+    arena::{Ast, StmtNode},
+    interpreter::Interpreter,
+    parser::{Parser, Stmt},
+    scanner::Scanner,
+};
+
+const STATEMENTS: usize = 10_000;
+const ROUNDS: u32 = 20;
+const LITERAL_ITERATIONS: usize = 1_000_000;
+
+fn program() -> String {
+    let mut source = String::new();
+    for i in 0..STATEMENTS {
+        source.push_str(&format!(
+            "({} + 2) * -(3 - {}) / 4 >= 5 == !false;\n",
+            i,
+            i % 7
+        ));
+    }
+    source
+}
+
+fn main() {
+    let mut scanner = Scanner::new(program());
+    let tokens = scanner.scan_tokens().unwrap().clone();
+
+    let start = Instant::now();
+    let program = Parser::new(tokens).parse().unwrap();
+    println!("parse {} statements: {:?}", STATEMENTS, start.elapsed());
+
+    let start = Instant::now();
+    let ast = Ast::from_stmts(&program);
+    println!("lower to arena: {:?}", start.elapsed());
+
+    let mut interpreter = Interpreter::new();
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        for stmt in &program {
+            if let Stmt::Expression { expr, .. } = stmt {
+                black_box(interpreter.eval(expr).unwrap());
+            }
+        }
+    }
+    println!("boxed walk: {:?}/round", start.elapsed() / ROUNDS);
+
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        for root in ast.roots() {
+            if let StmtNode::Expression { expr, .. } = ast.stmt(*root) {
+                black_box(interpreter.eval_node(&ast, *expr).unwrap());
+            }
+        }
+    }
+    println!("arena walk: {:?}/round", start.elapsed() / ROUNDS);
+
+    // A string literal in a hot loop. The boxed walk allocates it every time,
+    // the arena clones it out of its constant table.
+    let mut scanner = Scanner::new(format!(
+        "for (i in 0..{}) \"a string literal\";",
+        LITERAL_ITERATIONS
+    ));
+    let tokens = scanner.scan_tokens().unwrap().clone();
+    let program = Parser::new(tokens).parse().unwrap();
+    let ast = Ast::from_stmts(&program);
+
+    let start = Instant::now();
+    interpreter.interpret(program).unwrap();
+    println!(
+        "boxed literal loop, {} iterations: {:?}",
+        LITERAL_ITERATIONS,
+        start.elapsed()
+    );
+
+    let start = Instant::now();
+    interpreter.interpret_ast(&ast).unwrap();
+    println!(
+        "arena literal loop, {} iterations: {:?}",
+        LITERAL_ITERATIONS,
+        start.elapsed()
+    );
+}
@@ -0,0 +1,41 @@
+use std::{hint::black_box, time::Instant};
+
+use roxy::{Interpreter, InterpreterOptions, Value};
+
+const SCRIPTS: u32 = 10_000;
+
+fn time(name: &str, mut f: impl FnMut(u32)) {
+    let start = Instant::now();
+    for i in 0..SCRIPTS {
+        f(i);
+    }
+    println!("{}: {:?} per script", name, start.elapsed() / SCRIPTS);
+}
+
+// Small formulas like a spreadsheet would have, each its own program.
+fn main() {
+    let options = InterpreterOptions {
+        prelude: Some("var rate = 0.2; var fee = 3;".to_string()),
+        ..InterpreterOptions::default()
+    };
+    let formula = |i: u32| {
+        format!(
+            "var price = {}; var tax = price * rate; price + tax + fee;",
+            i
+        )
+    };
+
+    time("new interpreter", |i| {
+        let mut interpreter = Interpreter::with_options(options.clone());
+        let mut statements = roxy::parse(&formula(i)).unwrap();
+        statements.pop();
+        interpreter.interpret(statements).unwrap();
+        black_box(interpreter.get_global("tax"));
+    });
+
+    let mut interpreter = Interpreter::with_options(options.clone());
+    time("run_isolated", |i| {
+        let value = interpreter.run_isolated(&formula(i)).unwrap();
+        black_box(value.unwrap_or(Value::Nil));
+    });
+}
@@ -0,0 +1,38 @@
+use std::{hint::black_box, time::Instant};
+
+use roxy::{parser::Parser, scanner::Scanner, synthetic, Interpreter};
+
+const TOKENS: usize = 100_000;
+const ROUNDS: u32 = 10;
+
+fn time(name: &str, mut f: impl FnMut()) {
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        f();
+    }
+    println!("{}: {:?}", name, start.elapsed() / ROUNDS);
+}
+
+fn main() {
+    let source = synthetic::program(TOKENS);
+    time("scan", || {
+        let mut scanner = Scanner::new(source.clone());
+        black_box(scanner.scan_tokens().unwrap().len());
+    });
+
+    let mut scanner = Scanner::new(source);
+    let tokens = scanner.scan_tokens().unwrap().clone();
+    time("parse", || {
+        black_box(Parser::new(tokens.clone()).parse().unwrap());
+    });
+
+    let fib = synthetic::fib(20, 100);
+    time("fib", || roxy::run(&fib).unwrap());
+
+    let concat = synthetic::concat(10_000);
+    time("concat", || {
+        let mut interpreter = Interpreter::new();
+        roxy::run_with(&concat, &mut interpreter).unwrap();
+        black_box(interpreter.get_global("s"));
+    });
+}
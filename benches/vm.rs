@@ -0,0 +1,33 @@
+use std::{hint::black_box, time::Instant};
+
+use roxy::{
+    compiler::Compiler, interpreter::Interpreter, parser::Parser, scanner::Scanner, vm::Vm,
+};
+
+const ROUNDS: u32 = 100_000;
+
+fn main() {
+    // No functions yet, so there is no fib to run; a wide arithmetic
+    // expression stands in for it.
+    let source = "((1 + 2) * (3 - 4) / 5 + -(6 * 7) - (8 / 9)) * ((10 + 11) - (12 * 13)) >= \
+                  (14 - 15) * 16 == !(17 < 18)"
+        .to_string();
+    let mut scanner = Scanner::new(source);
+    let tokens = scanner.scan_tokens().unwrap();
+    let expr = Parser::new(tokens.clone()).expression().unwrap();
+    let chunk = Compiler::compile_expression(&expr).unwrap();
+
+    let mut interpreter = Interpreter::new();
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        black_box(interpreter.eval(&expr).unwrap());
+    }
+    println!("tree-walker: {:?}", start.elapsed());
+
+    let mut vm = Vm::new();
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        black_box(vm.run_with(&chunk, &mut interpreter).unwrap());
+    }
+    println!("vm: {:?}", start.elapsed());
+}
@@ -0,0 +1,656 @@
+use std::{collections::HashMap, sync::Arc};
+
+use crate::{
+    diagnostics::Span,
+    interpreter::Value,
+    parser::{
+        Assign, Binary, BinaryOperator, Call, Dbg, Expr, Grouping, Index, Literal, Range, SetIndex,
+        Stmt, Unary, UnaryOperator,
+    },
+};
+
+// Flattened version of the parser's AST. Nodes live in two vectors and refer
+// to their children by index, so walking a program doesn't chase a Box for
+// every sub-expression.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct ExprId(pub u32);
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct StmtId(pub u32);
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum ExprNode {
+    Binary {
+        left: ExprId,
+        operator: BinaryOperator,
+        right: ExprId,
+        span: Span,
+    },
+    Unary {
+        operator: UnaryOperator,
+        right: ExprId,
+    },
+    // Index into the constants, see `Ast::add_constant`.
+    Constant(u32),
+    Grouping(ExprId),
+    Variable(String),
+    Assign {
+        name: String,
+        value: ExprId,
+    },
+    Call {
+        callee: ExprId,
+        arguments: Vec<ExprId>,
+        line: usize,
+    },
+    Index {
+        object: ExprId,
+        index: ExprId,
+        line: usize,
+    },
+    SetIndex {
+        object: ExprId,
+        index: ExprId,
+        value: ExprId,
+        line: usize,
+    },
+    Range {
+        start: ExprId,
+        end: ExprId,
+        inclusive: bool,
+    },
+    Dbg {
+        expr: ExprId,
+        span: Span,
+        line: usize,
+    },
+    Error(Span),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum StmtNode {
+    Expression {
+        expr: ExprId,
+        span: Span,
+        line: usize,
+    },
+    Print {
+        exprs: Vec<ExprId>,
+        span: Span,
+        line: usize,
+    },
+    Var {
+        name: String,
+        initializer: ExprId,
+        span: Span,
+        line: usize,
+    },
+    Block {
+        statements: Vec<StmtId>,
+        span: Span,
+        line: usize,
+    },
+    If {
+        condition: ExprId,
+        then_branch: StmtId,
+        else_branch: Option<StmtId>,
+        span: Span,
+        line: usize,
+    },
+    Try {
+        body: StmtId,
+        catch: Option<(String, StmtId)>,
+        finally: Option<StmtId>,
+        span: Span,
+        line: usize,
+    },
+    Throw {
+        expr: ExprId,
+        span: Span,
+        line: usize,
+    },
+    ForIn {
+        name: String,
+        iterable: ExprId,
+        body: StmtId,
+        span: Span,
+        line: usize,
+    },
+    Error {
+        span: Span,
+        line: usize,
+    },
+}
+
+impl StmtNode {
+    pub fn span(&self) -> Span {
+        match self {
+            StmtNode::Expression { span, .. }
+            | StmtNode::Print { span, .. }
+            | StmtNode::Var { span, .. }
+            | StmtNode::Block { span, .. }
+            | StmtNode::If { span, .. }
+            | StmtNode::Try { span, .. }
+            | StmtNode::Throw { span, .. }
+            | StmtNode::ForIn { span, .. }
+            | StmtNode::Error { span, .. } => *span,
+        }
+    }
+
+    pub fn line(&self) -> usize {
+        match self {
+            StmtNode::Expression { line, .. }
+            | StmtNode::Print { line, .. }
+            | StmtNode::Var { line, .. }
+            | StmtNode::Block { line, .. }
+            | StmtNode::If { line, .. }
+            | StmtNode::Try { line, .. }
+            | StmtNode::Throw { line, .. }
+            | StmtNode::ForIn { line, .. }
+            | StmtNode::Error { line, .. } => *line,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Ast {
+    exprs: Vec<ExprNode>,
+    stmts: Vec<StmtNode>,
+    roots: Vec<StmtId>,
+    // Every literal in the program, once. Evaluating one clones the value,
+    // which for strings only bumps a reference count.
+    constants: Vec<Value>,
+    constant_ids: HashMap<ConstantKey, u32>,
+}
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+enum ConstantKey {
+    // The bits, f64 has no Eq. Literals are never NaN.
+    Number(u64),
+    String(Arc<str>),
+    Boolean(bool),
+    Nil,
+}
+
+impl Ast {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_stmts(stmts: &[Stmt]) -> Self {
+        let mut ast = Ast::new();
+        for stmt in stmts {
+            let id = ast.lower_stmt(stmt);
+            ast.roots.push(id);
+        }
+        ast
+    }
+
+    pub fn add_expr(&mut self, node: ExprNode) -> ExprId {
+        self.exprs.push(node);
+        ExprId(self.exprs.len() as u32 - 1)
+    }
+
+    pub fn add_stmt(&mut self, node: StmtNode) -> StmtId {
+        self.stmts.push(node);
+        StmtId(self.stmts.len() as u32 - 1)
+    }
+
+    pub fn add_constant(&mut self, literal: &Literal) -> u32 {
+        let key = match literal {
+            Literal::Number(n) => ConstantKey::Number(n.to_bits()),
+            Literal::String(s) => ConstantKey::String(Arc::clone(s)),
+            Literal::Boolean(b) => ConstantKey::Boolean(*b),
+            Literal::Nil => ConstantKey::Nil,
+        };
+        if let Some(index) = self.constant_ids.get(&key) {
+            return *index;
+        }
+        self.constants.push(match literal {
+            Literal::Number(n) => Value::Number(*n),
+            Literal::String(s) => Value::String(Arc::clone(s)),
+            Literal::Boolean(b) => Value::Boolean(*b),
+            Literal::Nil => Value::Nil,
+        });
+        let index = self.constants.len() as u32 - 1;
+        self.constant_ids.insert(key, index);
+        index
+    }
+
+    pub fn constant(&self, index: u32) -> &Value {
+        &self.constants[index as usize]
+    }
+
+    pub fn constants(&self) -> &[Value] {
+        &self.constants
+    }
+
+    pub fn expr(&self, id: ExprId) -> &ExprNode {
+        &self.exprs[id.0 as usize]
+    }
+
+    pub fn stmt(&self, id: StmtId) -> &StmtNode {
+        &self.stmts[id.0 as usize]
+    }
+
+    pub fn roots(&self) -> &[StmtId] {
+        &self.roots
+    }
+
+    // Every statement, nested ones included, in no particular order.
+    pub fn stmts(&self) -> &[StmtNode] {
+        &self.stmts
+    }
+
+    // Whether a part of the program failed to parse. Error nodes can only be
+    // in the vectors, so there is no tree to walk.
+    pub fn has_errors(&self) -> bool {
+        self.exprs
+            .iter()
+            .any(|expr| matches!(expr, ExprNode::Error(_)))
+            || self
+                .stmts
+                .iter()
+                .any(|stmt| matches!(stmt, StmtNode::Error { .. }))
+    }
+
+    pub fn lower_expr(&mut self, expr: &Expr) -> ExprId {
+        let node = match expr {
+            Expr::Binary(b) => ExprNode::Binary {
+                left: self.lower_expr(&b.left),
+                operator: b.operator,
+                right: self.lower_expr(&b.right),
+                span: b.span,
+            },
+            Expr::Unary(u) => ExprNode::Unary {
+                operator: u.operator,
+                right: self.lower_expr(&u.right),
+            },
+            Expr::Literal(l) => ExprNode::Constant(self.add_constant(l)),
+            Expr::Grouping(g) => ExprNode::Grouping(self.lower_expr(&g.expr)),
+            Expr::Variable(name) => ExprNode::Variable(name.clone()),
+            Expr::Assign(a) => ExprNode::Assign {
+                name: a.name.clone(),
+                value: self.lower_expr(&a.value),
+            },
+            Expr::Call(c) => ExprNode::Call {
+                callee: self.lower_expr(&c.callee),
+                arguments: c
+                    .arguments
+                    .iter()
+                    .map(|argument| self.lower_expr(argument))
+                    .collect(),
+                line: c.line,
+            },
+            Expr::Index(i) => ExprNode::Index {
+                object: self.lower_expr(&i.object),
+                index: self.lower_expr(&i.index),
+                line: i.line,
+            },
+            Expr::SetIndex(i) => ExprNode::SetIndex {
+                object: self.lower_expr(&i.object),
+                index: self.lower_expr(&i.index),
+                value: self.lower_expr(&i.value),
+                line: i.line,
+            },
+            Expr::Range(r) => ExprNode::Range {
+                start: self.lower_expr(&r.start),
+                end: self.lower_expr(&r.end),
+                inclusive: r.inclusive,
+            },
+            Expr::Dbg(d) => ExprNode::Dbg {
+                expr: self.lower_expr(&d.expr),
+                span: d.span,
+                line: d.line,
+            },
+            Expr::Error(span) => ExprNode::Error(*span),
+        };
+        self.add_expr(node)
+    }
+
+    pub fn lower_stmt(&mut self, stmt: &Stmt) -> StmtId {
+        let node = match stmt {
+            Stmt::Expression { expr, span, line } => StmtNode::Expression {
+                expr: self.lower_expr(expr),
+                span: *span,
+                line: *line,
+            },
+            Stmt::Print { exprs, span, line } => StmtNode::Print {
+                exprs: exprs.iter().map(|expr| self.lower_expr(expr)).collect(),
+                span: *span,
+                line: *line,
+            },
+            Stmt::Var {
+                name,
+                initializer,
+                span,
+                line,
+            } => StmtNode::Var {
+                name: name.clone(),
+                initializer: self.lower_expr(initializer),
+                span: *span,
+                line: *line,
+            },
+            Stmt::Block {
+                statements,
+                span,
+                line,
+            } => StmtNode::Block {
+                statements: statements
+                    .iter()
+                    .map(|stmt| self.lower_stmt(stmt))
+                    .collect(),
+                span: *span,
+                line: *line,
+            },
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+                span,
+                line,
+            } => StmtNode::If {
+                condition: self.lower_expr(condition),
+                then_branch: self.lower_stmt(then_branch),
+                else_branch: else_branch
+                    .as_ref()
+                    .map(|else_branch| self.lower_stmt(else_branch)),
+                span: *span,
+                line: *line,
+            },
+            Stmt::Try {
+                body,
+                catch,
+                finally,
+                span,
+                line,
+            } => StmtNode::Try {
+                body: self.lower_stmt(body),
+                catch: catch
+                    .as_ref()
+                    .map(|(name, handler)| (name.clone(), self.lower_stmt(handler))),
+                finally: finally.as_ref().map(|finally| self.lower_stmt(finally)),
+                span: *span,
+                line: *line,
+            },
+            Stmt::Throw { expr, span, line } => StmtNode::Throw {
+                expr: self.lower_expr(expr),
+                span: *span,
+                line: *line,
+            },
+            Stmt::ForIn {
+                name,
+                iterable,
+                body,
+                span,
+                line,
+            } => StmtNode::ForIn {
+                name: name.clone(),
+                iterable: self.lower_expr(iterable),
+                body: self.lower_stmt(body),
+                span: *span,
+                line: *line,
+            },
+            Stmt::Error { span, line } => StmtNode::Error {
+                span: *span,
+                line: *line,
+            },
+        };
+        self.add_stmt(node)
+    }
+
+    pub fn to_expr(&self, id: ExprId) -> Expr {
+        match self.expr(id) {
+            ExprNode::Binary {
+                left,
+                operator,
+                right,
+                span,
+            } => Expr::Binary(Binary {
+                left: Box::new(self.to_expr(*left)),
+                operator: *operator,
+                right: Box::new(self.to_expr(*right)),
+                span: *span,
+            }),
+            ExprNode::Unary { operator, right } => Expr::Unary(Unary {
+                operator: *operator,
+                right: Box::new(self.to_expr(*right)),
+            }),
+            ExprNode::Constant(index) => Expr::Literal(match self.constant(*index) {
+                Value::Number(n) => Literal::Number(*n),
+                Value::String(s) => Literal::String(Arc::clone(s)),
+                Value::Boolean(b) => Literal::Boolean(*b),
+                Value::Nil => Literal::Nil,
+                value => unreachable!("{} can't be written as a literal", value.type_name()),
+            }),
+            ExprNode::Grouping(expr) => Expr::Grouping(Grouping {
+                expr: Box::new(self.to_expr(*expr)),
+            }),
+            ExprNode::Variable(name) => Expr::Variable(name.clone()),
+            ExprNode::Assign { name, value } => Expr::Assign(Assign {
+                name: name.clone(),
+                value: Box::new(self.to_expr(*value)),
+            }),
+            ExprNode::Call {
+                callee,
+                arguments,
+                line,
+            } => Expr::Call(Call {
+                callee: Box::new(self.to_expr(*callee)),
+                arguments: arguments
+                    .iter()
+                    .map(|argument| self.to_expr(*argument))
+                    .collect(),
+                line: *line,
+            }),
+            ExprNode::Index {
+                object,
+                index,
+                line,
+            } => Expr::Index(Index {
+                object: Box::new(self.to_expr(*object)),
+                index: Box::new(self.to_expr(*index)),
+                line: *line,
+            }),
+            ExprNode::SetIndex {
+                object,
+                index,
+                value,
+                line,
+            } => Expr::SetIndex(SetIndex {
+                object: Box::new(self.to_expr(*object)),
+                index: Box::new(self.to_expr(*index)),
+                value: Box::new(self.to_expr(*value)),
+                line: *line,
+            }),
+            ExprNode::Range {
+                start,
+                end,
+                inclusive,
+            } => Expr::Range(Range {
+                start: Box::new(self.to_expr(*start)),
+                end: Box::new(self.to_expr(*end)),
+                inclusive: *inclusive,
+            }),
+            ExprNode::Dbg { expr, span, line } => Expr::Dbg(Dbg {
+                expr: Box::new(self.to_expr(*expr)),
+                span: *span,
+                line: *line,
+            }),
+            ExprNode::Error(span) => Expr::Error(*span),
+        }
+    }
+
+    pub fn to_stmt(&self, id: StmtId) -> Stmt {
+        match self.stmt(id) {
+            StmtNode::Expression { expr, span, line } => Stmt::Expression {
+                expr: self.to_expr(*expr),
+                span: *span,
+                line: *line,
+            },
+            StmtNode::Print { exprs, span, line } => Stmt::Print {
+                exprs: exprs.iter().map(|expr| self.to_expr(*expr)).collect(),
+                span: *span,
+                line: *line,
+            },
+            StmtNode::Var {
+                name,
+                initializer,
+                span,
+                line,
+            } => Stmt::Var {
+                name: name.clone(),
+                initializer: self.to_expr(*initializer),
+                span: *span,
+                line: *line,
+            },
+            StmtNode::Block {
+                statements,
+                span,
+                line,
+            } => Stmt::Block {
+                statements: statements.iter().map(|stmt| self.to_stmt(*stmt)).collect(),
+                span: *span,
+                line: *line,
+            },
+            StmtNode::If {
+                condition,
+                then_branch,
+                else_branch,
+                span,
+                line,
+            } => Stmt::If {
+                condition: self.to_expr(*condition),
+                then_branch: Box::new(self.to_stmt(*then_branch)),
+                else_branch: else_branch.map(|else_branch| Box::new(self.to_stmt(else_branch))),
+                span: *span,
+                line: *line,
+            },
+            StmtNode::Try {
+                body,
+                catch,
+                finally,
+                span,
+                line,
+            } => Stmt::Try {
+                body: Box::new(self.to_stmt(*body)),
+                catch: catch
+                    .as_ref()
+                    .map(|(name, handler)| (name.clone(), Box::new(self.to_stmt(*handler)))),
+                finally: finally.map(|finally| Box::new(self.to_stmt(finally))),
+                span: *span,
+                line: *line,
+            },
+            StmtNode::Throw { expr, span, line } => Stmt::Throw {
+                expr: self.to_expr(*expr),
+                span: *span,
+                line: *line,
+            },
+            StmtNode::ForIn {
+                name,
+                iterable,
+                body,
+                span,
+                line,
+            } => Stmt::ForIn {
+                name: name.clone(),
+                iterable: self.to_expr(*iterable),
+                body: Box::new(self.to_stmt(*body)),
+                span: *span,
+                line: *line,
+            },
+            StmtNode::Error { span, line } => Stmt::Error {
+                span: *span,
+                line: *line,
+            },
+        }
+    }
+
+    pub fn to_stmts(&self) -> Vec<Stmt> {
+        self.roots.iter().map(|root| self.to_stmt(*root)).collect()
+    }
+}
+
+#[cfg(test)]
+mod arena_tests {
+    use super::{Ast, ExprNode};
+    use crate::{
+        interpreter::{Interpreter, Value},
+        parser::{Literal, Parser},
+        scanner::Scanner,
+    };
+
+    #[test]
+    fn round_trip() {
+        let source = r#"
+            print (5 - (3 - 1)) + -1;
+            if (2 == 2) {
+                var x = "yes";
+            } else {
+                var y = !true;
+                y = clock(1, (2))[0];
+                y[1] = 2;
+            }
+        "#
+        .to_string();
+        let mut scanner = Scanner::new(source);
+        let tokens = scanner.scan_tokens().unwrap();
+        let mut parser = Parser::new(tokens.clone());
+        let program = parser.parse().unwrap();
+        let ast = Ast::from_stmts(&program);
+        assert_eq!(ast.roots().len(), 2);
+        assert_eq!(ast.to_stmts(), program);
+    }
+
+    #[test]
+    fn children_are_stored_before_parents() {
+        let mut ast = Ast::new();
+        let constant = ast.add_constant(&Literal::Number(1.0));
+        let left = ast.add_expr(ExprNode::Constant(constant));
+        let grouping = ast.add_expr(ExprNode::Grouping(left));
+        assert_eq!(left.0, 0);
+        assert_eq!(grouping.0, 1);
+        assert_eq!(*ast.expr(grouping), ExprNode::Grouping(left),);
+    }
+
+    #[test]
+    fn identical_literals_share_a_constant() {
+        let source = r#"print "a" + "b"; print "a"; print 1 + 1.0; print nil == nil;"#;
+        let ast = Ast::from_stmts(&crate::parse(source).unwrap());
+        assert_eq!(
+            ast.constants(),
+            [
+                Value::from("a"),
+                Value::from("b"),
+                Value::Number(1.0),
+                Value::Nil
+            ]
+        );
+        assert_eq!(ast.to_stmts(), crate::parse(source).unwrap());
+    }
+
+    #[test]
+    fn finds_error_nodes() {
+        let tokens = Scanner::new("print 1;\nprint f(1, +);".to_string())
+            .scan_tokens()
+            .unwrap()
+            .clone();
+        let (statements, _) = Parser::new(tokens).parse_partial();
+        assert!(Ast::from_stmts(&statements).has_errors());
+        assert!(!Ast::from_stmts(&crate::parse("print f(1, 2);").unwrap()).has_errors());
+    }
+
+    #[test]
+    fn eval_matches_boxed_ast() {
+        let source = r#"-1 - 2 * (4 - 2) + 10 / 4"#.to_string();
+        let mut scanner = Scanner::new(source);
+        let tokens = scanner.scan_tokens().unwrap();
+        let mut parser = Parser::new(tokens.clone());
+        let expr = parser.expression().unwrap();
+        let mut ast = Ast::new();
+        let id = ast.lower_expr(&expr);
+        let mut interpreter = Interpreter::new();
+        assert_eq!(interpreter.eval_node(&ast, id), interpreter.eval(&expr));
+        assert_eq!(interpreter.eval_node(&ast, id), Ok(Value::Number(-2.5)));
+    }
+}
@@ -0,0 +1,137 @@
+use crate::parser::{Expr, Stmt};
+
+// Prints the AST as s-expressions, one line per top level statement. Unlike
+// the Display impl on Expr this makes the tree's shape explicit, which is what
+// you want when debugging precedence.
+pub fn print(statements: &[Stmt]) -> String {
+    statements
+        .iter()
+        .map(|stmt| print_stmt(stmt) + "\n")
+        .collect()
+}
+
+pub fn print_stmt(stmt: &Stmt) -> String {
+    match stmt {
+        Stmt::Expression { expr, .. } => format!("(expr {})", print_expr(expr)),
+        Stmt::Print { exprs, .. } => format!(
+            "(print {})",
+            exprs.iter().map(print_expr).collect::<Vec<_>>().join(" ")
+        ),
+        Stmt::Throw { expr, .. } => format!("(throw {})", print_expr(expr)),
+        Stmt::Error { .. } => "(error)".to_string(),
+        Stmt::ForIn {
+            name,
+            iterable,
+            body,
+            ..
+        } => format!(
+            "(for {} {} {})",
+            name,
+            print_expr(iterable),
+            print_stmt(body)
+        ),
+        Stmt::Var {
+            name, initializer, ..
+        } => format!("(var {} {})", name, print_expr(initializer)),
+        Stmt::Block { statements, .. } => {
+            let mut out = String::from("(block");
+            for stmt in statements {
+                out.push(' ');
+                out.push_str(&print_stmt(stmt));
+            }
+            out + ")"
+        }
+        Stmt::If {
+            condition,
+            then_branch,
+            else_branch,
+            ..
+        } => match else_branch {
+            Some(else_branch) => format!(
+                "(if {} {} {})",
+                print_expr(condition),
+                print_stmt(then_branch),
+                print_stmt(else_branch)
+            ),
+            None => format!("(if {} {})", print_expr(condition), print_stmt(then_branch)),
+        },
+        Stmt::Try {
+            body,
+            catch,
+            finally,
+            ..
+        } => {
+            let mut out = format!("(try {}", print_stmt(body));
+            if let Some((name, handler)) = catch {
+                out.push_str(&format!(" (catch {} {})", name, print_stmt(handler)));
+            }
+            if let Some(finally) = finally {
+                out.push_str(&format!(" (finally {})", print_stmt(finally)));
+            }
+            out + ")"
+        }
+    }
+}
+
+pub fn print_expr(expr: &Expr) -> String {
+    match expr {
+        Expr::Binary(b) => format!(
+            "({} {} {})",
+            b.operator,
+            print_expr(&b.left),
+            print_expr(&b.right)
+        ),
+        Expr::Unary(u) => format!("({} {})", u.operator, print_expr(&u.right)),
+        Expr::Literal(l) => l.to_string(),
+        Expr::Grouping(g) => format!("(group {})", print_expr(&g.expr)),
+        Expr::Variable(name) => name.clone(),
+        Expr::Assign(a) => format!("(= {} {})", a.name, print_expr(&a.value)),
+        Expr::Call(c) => {
+            let mut out = format!("(call {}", print_expr(&c.callee));
+            for argument in &c.arguments {
+                out.push(' ');
+                out.push_str(&print_expr(argument));
+            }
+            out + ")"
+        }
+        Expr::Index(i) => format!("(index {} {})", print_expr(&i.object), print_expr(&i.index)),
+        Expr::SetIndex(i) => format!(
+            "(set-index {} {} {})",
+            print_expr(&i.object),
+            print_expr(&i.index),
+            print_expr(&i.value)
+        ),
+        Expr::Range(r) => format!(
+            "({} {} {})",
+            if r.inclusive { "..=" } else { ".." },
+            print_expr(&r.start),
+            print_expr(&r.end)
+        ),
+        Expr::Dbg(d) => format!("(dbg {})", print_expr(&d.expr)),
+        Expr::Error(_) => "(error)".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod ast_printer_tests {
+    use super::print;
+    use crate::{parser::Parser, scanner::Scanner};
+
+    #[test]
+    fn prints_s_expressions() {
+        let source = r#"
+            var a = -(1 + 2) * 3;
+            if (a == nil) print "nil"; else { a = !true; f(a)(); a[0] = a[1]; }
+        "#
+        .to_string();
+        let mut scanner = Scanner::new(source);
+        let tokens = scanner.scan_tokens().unwrap();
+        let mut parser = Parser::new(tokens.clone());
+        let program = parser.parse().unwrap();
+        assert_eq!(
+            print(&program),
+            "(var a (* (- (group (+ 1 2))) 3))\n\
+             (if (== a nil) (print \"nil\") (block (expr (= a (! true))) (expr (call (call f a))) (expr (set-index a 0 (index a 1)))))\n"
+        );
+    }
+}
@@ -0,0 +1,99 @@
+use std::sync::Arc;
+
+use crate::interpreter::{Interpreter, Value};
+
+// Anything a Lox call expression can invoke. Errors are plain messages, the
+// interpreter turns them into runtime errors at the call site.
+pub trait Callable {
+    // Shown when the callable is printed and in errors about it.
+    fn name(&self) -> &str;
+    fn arity(&self) -> usize;
+    // Variadic callables take `arity` arguments or more.
+    fn variadic(&self) -> bool {
+        false
+    }
+    fn call(&self, interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, String>;
+}
+
+type NativeFn = dyn Fn(&mut Interpreter, &[Value]) -> Result<Value, String> + Send + Sync;
+
+// A function implemented in Rust by the host application.
+#[derive(Clone)]
+pub struct NativeFunction {
+    pub name: String,
+    arity: usize,
+    variadic: bool,
+    function: Arc<NativeFn>,
+}
+
+impl NativeFunction {
+    pub fn new(
+        name: &str,
+        arity: usize,
+        function: impl Fn(&[Value]) -> Result<Value, String> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            arity,
+            variadic: false,
+            function: Arc::new(move |_: &mut Interpreter, arguments: &[Value]| function(arguments)),
+        }
+    }
+
+    pub fn variadic(
+        name: &str,
+        min_arity: usize,
+        function: impl Fn(&[Value]) -> Result<Value, String> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            variadic: true,
+            ..Self::new(name, min_arity, function)
+        }
+    }
+
+    // A variadic native that can use the interpreter it runs in, like
+    // `printf` printing through the print handler.
+    pub(crate) fn variadic_with_interpreter(
+        name: &str,
+        min_arity: usize,
+        function: impl Fn(&mut Interpreter, &[Value]) -> Result<Value, String> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            arity: min_arity,
+            variadic: true,
+            function: Arc::new(function),
+        }
+    }
+}
+
+impl Callable for NativeFunction {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn arity(&self) -> usize {
+        self.arity
+    }
+
+    fn variadic(&self) -> bool {
+        self.variadic
+    }
+
+    fn call(&self, interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value, String> {
+        (self.function)(interpreter, arguments)
+    }
+}
+
+// Two natives are only equal if they wrap the same closure.
+impl PartialEq for NativeFunction {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.function, &other.function)
+    }
+}
+
+impl std::fmt::Debug for NativeFunction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<native {}>", self.name)
+    }
+}
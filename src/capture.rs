@@ -0,0 +1,221 @@
+use std::sync::{Arc, Mutex};
+
+use crate::{
+    arena::Ast,
+    diagnostics::{Diagnostic, Severity},
+    natives::DETERMINISTIC_SEED,
+    parse,
+    resolver::Resolver,
+    Interpreter, InterpreterOptions, Value,
+};
+
+// Runs a program without touching the process: output is collected instead
+// of printed, nothing reads the clock, sleeps or exits. This is what a host
+// without a terminal, like a browser playground on wasm, calls.
+
+#[derive(Clone, Default)]
+pub struct RunOptions {
+    // Stops runaway programs, unlimited when `None`.
+    pub fuel: Option<u64>,
+    // Seconds since some fixed point. Scripts only get a `clock` native when
+    // the host provides this.
+    pub now_fn: Option<Arc<dyn Fn() -> f64 + Send + Sync>>,
+    // Seed for `random` and `randomInt`. Without one they give the same
+    // numbers on every run, the system time is never read.
+    pub rng_seed: Option<u64>,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ExitKind {
+    Success,
+    // Scan or parse errors, nothing ran.
+    CompileError,
+    RuntimeError,
+}
+
+impl ExitKind {
+    // The exit code the command line interpreter would use.
+    pub fn code(&self) -> i32 {
+        match self {
+            ExitKind::Success => 0,
+            ExitKind::CompileError => 65,
+            ExitKind::RuntimeError => 70,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct RunOutcome {
+    // Everything the program printed, one value per line.
+    pub stdout: String,
+    // Errors and warnings in the order they were found.
+    pub diagnostics: Vec<Diagnostic>,
+    pub exit: ExitKind,
+}
+
+pub fn run_capture(source: &str, options: RunOptions) -> RunOutcome {
+    let stdout = Arc::new(Mutex::new(String::new()));
+    let sink = Arc::clone(&stdout);
+    let mut interpreter = Interpreter::with_options(InterpreterOptions {
+        allow_clock: false,
+        allow_sleep: false,
+        rng_seed: Some(options.rng_seed.unwrap_or(DETERMINISTIC_SEED)),
+        fuel: options.fuel,
+        ..InterpreterOptions::default()
+    });
+    interpreter.set_print_handler(move |value| {
+        let mut stdout = sink.lock().unwrap();
+        stdout.push_str(&value.to_string());
+        stdout.push('\n');
+    });
+    if let Some(now) = options.now_fn {
+        interpreter.define_native("clock", 0, move |_| Ok(Value::Number(now())));
+    }
+
+    let mut diagnostics = Vec::new();
+    let exit = match parse(source) {
+        Err(err) => {
+            diagnostics.extend(err.errors());
+            ExitKind::CompileError
+        }
+        Ok(statements) => {
+            diagnostics.extend(Resolver::resolve(&statements).iter().map(Diagnostic::from));
+            match interpreter.interpret_ast(&Ast::from_stmts(&statements)) {
+                Ok(()) => ExitKind::Success,
+                Err(err) => {
+                    diagnostics.push(Diagnostic {
+                        code: err.code(),
+                        severity: Severity::Error,
+                        message: err.to_string(),
+                        line: Some(err.line().unwrap_or(interpreter.line())),
+                        span: None,
+                        file: None,
+                    });
+                    ExitKind::RuntimeError
+                }
+            }
+        }
+    };
+    let stdout = stdout.lock().unwrap().clone();
+    RunOutcome {
+        stdout,
+        diagnostics,
+        exit,
+    }
+}
+
+#[cfg(test)]
+mod capture_tests {
+    use std::sync::Arc;
+
+    use super::{run_capture, ExitKind, RunOptions};
+    use crate::diagnostics::{Code, Diagnostic, Severity, Span};
+
+    #[test]
+    fn captures_output() {
+        let outcome = run_capture("print 1 + 2;\nprint \"two\";", RunOptions::default());
+        assert_eq!(outcome.stdout, "3\ntwo\n");
+        assert!(outcome.diagnostics.is_empty());
+        assert_eq!(outcome.exit, ExitKind::Success);
+    }
+
+    #[test]
+    fn runtime_error() {
+        let outcome = run_capture(
+            "print 1;\n{\n  var unused = 2;\n  print -\"a\";\n}",
+            RunOptions::default(),
+        );
+        assert_eq!(outcome.stdout, "1\n");
+        assert_eq!(
+            outcome.diagnostics,
+            vec![
+                Diagnostic {
+                    code: Code::UnusedVariable,
+                    severity: Severity::Warning,
+                    message: "Local variable 'unused' is never used.".to_string(),
+                    line: Some(3),
+                    span: None,
+                    file: None,
+                },
+                Diagnostic {
+                    code: Code::TypeError,
+                    severity: Severity::Error,
+                    message: "You can only negate a number".to_string(),
+                    line: Some(4),
+                    span: None,
+                    file: None,
+                },
+            ]
+        );
+        assert_eq!(outcome.exit, ExitKind::RuntimeError);
+        assert_eq!(outcome.exit.code(), 70);
+    }
+
+    #[test]
+    fn compile_error() {
+        let outcome = run_capture("print 1;\nprint 2 +;", RunOptions::default());
+        assert_eq!(outcome.stdout, "");
+        assert_eq!(
+            outcome.diagnostics,
+            vec![Diagnostic {
+                code: Code::ExpectExpression,
+                severity: Severity::Error,
+                message: "Expect expression.".to_string(),
+                line: Some(2),
+                span: Some(Span::new(18, 19)),
+                file: None,
+            }]
+        );
+        assert_eq!(outcome.exit.code(), 65);
+    }
+
+    #[test]
+    fn clock_comes_from_the_host() {
+        let outcome = run_capture("print clock;", RunOptions::default());
+        assert_eq!(outcome.exit, ExitKind::RuntimeError);
+        let options = RunOptions {
+            now_fn: Some(Arc::new(|| 1.5)),
+            ..RunOptions::default()
+        };
+        assert_eq!(run_capture("print clock() * 2;", options).stdout, "3\n");
+    }
+
+    #[test]
+    fn random_is_seeded_by_the_host() {
+        let source = "print random(), randomInt(1, 100);";
+        let first = run_capture(source, RunOptions::default());
+        assert_eq!(first.exit, ExitKind::Success);
+        assert_eq!(
+            run_capture(source, RunOptions::default()).stdout,
+            first.stdout
+        );
+        let options = RunOptions {
+            rng_seed: Some(7),
+            ..RunOptions::default()
+        };
+        let seeded = run_capture(source, options.clone());
+        assert_ne!(seeded.stdout, first.stdout);
+        assert_eq!(run_capture(source, options).stdout, seeded.stdout);
+    }
+
+    #[test]
+    fn no_sleep() {
+        let outcome = run_capture("sleep(1);", RunOptions::default());
+        assert_eq!(outcome.exit, ExitKind::RuntimeError);
+        assert_eq!(
+            outcome.diagnostics[0].message,
+            "Undefined variable 'sleep'."
+        );
+    }
+
+    #[test]
+    fn fuel() {
+        let options = RunOptions {
+            fuel: Some(10),
+            ..RunOptions::default()
+        };
+        let outcome = run_capture("for (i in 0..100) print i;", options);
+        assert_eq!(outcome.exit, ExitKind::RuntimeError);
+        assert_eq!(outcome.diagnostics[0].message, "Out of fuel.");
+    }
+}
@@ -0,0 +1,432 @@
+use std::sync::Arc;
+
+use crate::{
+    diagnostics::Span,
+    interpreter::{RuntimeError, Value},
+    parser::{BinaryOperator, Expr, Literal, Stmt, UnaryOperator},
+};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum OpCode {
+    Constant(usize),
+    Nil,
+    True,
+    False,
+    Negate,
+    Not,
+    Binary(BinaryOperator),
+    // Prints that many values from the top of the stack on one line.
+    Print(usize),
+    Pop,
+    // Globals are looked up by name, the operand indexes `Chunk::names`.
+    DefineGlobal(usize),
+    GetGlobal(usize),
+    SetGlobal(usize),
+    // Locals live on the stack, the operand is their slot.
+    GetLocal(usize),
+    SetLocal(usize),
+    // Jumps go to an absolute instruction index. `JumpIfFalse` pops the
+    // condition.
+    Jump(usize),
+    JumpIfFalse(usize),
+    // The callee is below its arguments. Errors in the call are reported on
+    // the line of its closing paren, like the tree-walker does.
+    Call { arguments: usize, line: usize },
+    Index { line: usize },
+    SetIndex { line: usize },
+    Range { inclusive: bool },
+    // Prints the value on top of the stack and leaves it there, the operand
+    // indexes `Chunk::dbg`.
+    Dbg(usize),
+    // Turns the range on top of the stack into the hidden local a `for`
+    // loop counts with.
+    ForInit,
+    // Pushes the next value of the range in `slot` as the loop variable, or
+    // jumps to `exit` when there is none left.
+    ForNext { slot: usize, exit: usize },
+    // Errors raised until the matching `PopHandler` jump to the operand.
+    // A catch handler only takes catchable errors and gets the caught value
+    // pushed, a finally handler takes every error and keeps it to raise
+    // again at `EndFinally`.
+    PushCatch(usize),
+    PushFinally(usize),
+    PopHandler,
+    // Starts a finally block that was reached without an error.
+    EnterFinally,
+    EndFinally,
+    Throw,
+    Return,
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Chunk {
+    pub code: Vec<OpCode>,
+    pub constants: Vec<Value>,
+    pub names: Vec<String>,
+    // Source line of every instruction, for runtime errors.
+    pub lines: Vec<usize>,
+    // Span, line and printed expression of every `dbg` call.
+    pub dbg: Vec<(Span, usize, String)>,
+}
+
+impl Chunk {
+    fn emit(&mut self, op: OpCode, line: usize) {
+        self.code.push(op);
+        self.lines.push(line);
+    }
+
+    fn add_constant(&mut self, value: Value) -> usize {
+        self.constants.push(value);
+        self.constants.len() - 1
+    }
+
+    fn add_name(&mut self, name: &str) -> usize {
+        match self.names.iter().position(|known| known == name) {
+            Some(index) => index,
+            None => {
+                self.names.push(name.to_string());
+                self.names.len() - 1
+            }
+        }
+    }
+}
+
+// Lowers the tree-walker's AST into a flat list of stack instructions for the vm.
+// Programs with error nodes from `Parser::parse_partial` don't compile.
+#[derive(Default)]
+pub struct Compiler {
+    chunk: Chunk,
+    // Names of the locals in stack order. Variables declared outside any
+    // block are globals.
+    locals: Vec<String>,
+    depth: usize,
+    // Line of the statement being compiled.
+    line: usize,
+}
+
+impl Compiler {
+    pub fn compile(statements: &[Stmt]) -> Result<Chunk, RuntimeError> {
+        let mut compiler = Compiler::default();
+        for stmt in statements {
+            compiler.statement(stmt)?;
+        }
+        compiler.emit(OpCode::Nil);
+        compiler.emit(OpCode::Return);
+        Ok(compiler.chunk)
+    }
+
+    pub fn compile_expression(expr: &Expr) -> Result<Chunk, RuntimeError> {
+        let mut compiler = Compiler::default();
+        compiler.expression(expr)?;
+        compiler.emit(OpCode::Return);
+        Ok(compiler.chunk)
+    }
+
+    fn emit(&mut self, op: OpCode) {
+        self.chunk.emit(op, self.line);
+    }
+
+    fn statement(&mut self, stmt: &Stmt) -> Result<(), RuntimeError> {
+        self.line = stmt.line();
+        match stmt {
+            Stmt::Print { exprs, .. } => {
+                for expr in exprs {
+                    self.expression(expr)?;
+                }
+                self.emit(OpCode::Print(exprs.len()));
+            }
+            Stmt::Expression { expr, .. } => {
+                self.expression(expr)?;
+                self.emit(OpCode::Pop);
+            }
+            Stmt::Var {
+                name, initializer, ..
+            } => {
+                self.expression(initializer)?;
+                if self.depth == 0 {
+                    let index = self.chunk.add_name(name);
+                    self.emit(OpCode::DefineGlobal(index));
+                } else {
+                    // The value stays where it is and becomes the local.
+                    self.locals.push(name.clone());
+                }
+            }
+            Stmt::Block { statements, .. } => {
+                self.scoped(|compiler| {
+                    statements
+                        .iter()
+                        .try_for_each(|stmt| compiler.statement(stmt))
+                })?;
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.expression(condition)?;
+                let to_else = self.emit_jump(OpCode::JumpIfFalse);
+                self.statement(then_branch)?;
+                if let Some(else_branch) = else_branch {
+                    let to_end = self.emit_jump(OpCode::Jump);
+                    self.patch_jump(to_else);
+                    self.statement(else_branch)?;
+                    self.patch_jump(to_end);
+                } else {
+                    self.patch_jump(to_else);
+                }
+            }
+            Stmt::ForIn {
+                name,
+                iterable,
+                body,
+                line,
+                ..
+            } => {
+                self.expression(iterable)?;
+                self.emit(OpCode::ForInit);
+                // The range is a local no name can refer to.
+                let slot = self.locals.len();
+                self.locals.push(String::new());
+                let next = self.chunk.code.len();
+                self.emit(OpCode::ForNext {
+                    slot,
+                    exit: usize::MAX,
+                });
+                self.scoped(|compiler| {
+                    compiler.locals.push(name.clone());
+                    compiler.statement(body)
+                })?;
+                self.line = *line;
+                self.emit(OpCode::Jump(next));
+                self.patch_jump(next);
+                self.locals.pop();
+                self.emit(OpCode::Pop);
+            }
+            Stmt::Try {
+                body,
+                catch,
+                finally,
+                line,
+                ..
+            } => {
+                let to_finally = finally
+                    .is_some()
+                    .then(|| self.emit_jump(OpCode::PushFinally));
+                let to_catch = catch.is_some().then(|| self.emit_jump(OpCode::PushCatch));
+                self.statement(body)?;
+                self.line = *line;
+                if let (Some(to_catch), Some((name, handler))) = (to_catch, catch) {
+                    self.emit(OpCode::PopHandler);
+                    let to_end = self.emit_jump(OpCode::Jump);
+                    self.patch_jump(to_catch);
+                    // The caught value was pushed where the local goes.
+                    self.scoped(|compiler| {
+                        compiler.locals.push(name.clone());
+                        compiler.statement(handler)
+                    })?;
+                    self.patch_jump(to_end);
+                }
+                if let (Some(to_finally), Some(finally)) = (to_finally, finally) {
+                    self.line = *line;
+                    self.emit(OpCode::PopHandler);
+                    self.emit(OpCode::EnterFinally);
+                    self.patch_jump(to_finally);
+                    self.statement(finally)?;
+                    self.emit(OpCode::EndFinally);
+                }
+            }
+            Stmt::Throw { expr, .. } => {
+                self.expression(expr)?;
+                self.emit(OpCode::Throw);
+            }
+            Stmt::Error { .. } => return Err(RuntimeError::InvalidProgram),
+        }
+        Ok(())
+    }
+
+    // Locals declared by `body` are popped once it is done.
+    fn scoped(
+        &mut self,
+        body: impl FnOnce(&mut Self) -> Result<(), RuntimeError>,
+    ) -> Result<(), RuntimeError> {
+        self.depth += 1;
+        let locals = self.locals.len();
+        body(self)?;
+        for _ in locals..self.locals.len() {
+            self.emit(OpCode::Pop);
+        }
+        self.locals.truncate(locals);
+        self.depth -= 1;
+        Ok(())
+    }
+
+    // Emits a jump whose target is filled in by `patch_jump`.
+    fn emit_jump(&mut self, jump: fn(usize) -> OpCode) -> usize {
+        self.emit(jump(usize::MAX));
+        self.chunk.code.len() - 1
+    }
+
+    // Points the jump at the next instruction.
+    fn patch_jump(&mut self, at: usize) {
+        let next = self.chunk.code.len();
+        if let OpCode::Jump(target)
+        | OpCode::JumpIfFalse(target)
+        | OpCode::PushCatch(target)
+        | OpCode::PushFinally(target)
+        | OpCode::ForNext { exit: target, .. } = &mut self.chunk.code[at]
+        {
+            *target = next;
+        }
+    }
+
+    // The innermost local with that name, shadowed ones come first.
+    fn resolve_local(&self, name: &str) -> Option<usize> {
+        self.locals.iter().rposition(|local| local == name)
+    }
+
+    fn expression(&mut self, expr: &Expr) -> Result<(), RuntimeError> {
+        match expr {
+            Expr::Binary(b) => {
+                // Left operands are walked with a loop, generated code can
+                // nest them deeper than recursion would allow.
+                let mut chain = vec![b];
+                let mut left = &*b.left;
+                while let Expr::Binary(b) = left {
+                    chain.push(b);
+                    left = &b.left;
+                }
+                self.expression(left)?;
+                for b in chain.into_iter().rev() {
+                    self.expression(&b.right)?;
+                    self.emit(OpCode::Binary(b.operator));
+                }
+            }
+            Expr::Unary(u) => {
+                self.expression(&u.right)?;
+                match u.operator {
+                    UnaryOperator::Minus => self.emit(OpCode::Negate),
+                    UnaryOperator::Not => self.emit(OpCode::Not),
+                }
+            }
+            Expr::Grouping(g) => self.expression(&g.expr)?,
+            Expr::Variable(name) => match self.resolve_local(name) {
+                Some(slot) => self.emit(OpCode::GetLocal(slot)),
+                None => {
+                    let index = self.chunk.add_name(name);
+                    self.emit(OpCode::GetGlobal(index));
+                }
+            },
+            Expr::Assign(a) => {
+                self.expression(&a.value)?;
+                match self.resolve_local(&a.name) {
+                    Some(slot) => self.emit(OpCode::SetLocal(slot)),
+                    None => {
+                        let index = self.chunk.add_name(&a.name);
+                        self.emit(OpCode::SetGlobal(index));
+                    }
+                }
+            }
+            Expr::Call(c) => {
+                self.expression(&c.callee)?;
+                for argument in &c.arguments {
+                    self.expression(argument)?;
+                }
+                self.emit(OpCode::Call {
+                    arguments: c.arguments.len(),
+                    line: c.line,
+                });
+            }
+            Expr::Index(i) => {
+                self.expression(&i.object)?;
+                self.expression(&i.index)?;
+                self.emit(OpCode::Index { line: i.line });
+            }
+            Expr::SetIndex(i) => {
+                self.expression(&i.object)?;
+                self.expression(&i.index)?;
+                self.expression(&i.value)?;
+                self.emit(OpCode::SetIndex { line: i.line });
+            }
+            Expr::Range(r) => {
+                self.expression(&r.start)?;
+                self.expression(&r.end)?;
+                self.emit(OpCode::Range {
+                    inclusive: r.inclusive,
+                });
+            }
+            Expr::Dbg(d) => {
+                self.expression(&d.expr)?;
+                self.chunk.dbg.push((d.span, d.line, d.expr.to_string()));
+                self.emit(OpCode::Dbg(self.chunk.dbg.len() - 1));
+            }
+            Expr::Error(_) => return Err(RuntimeError::InvalidProgram),
+            Expr::Literal(l) => {
+                let op = match l {
+                    Literal::Nil => OpCode::Nil,
+                    Literal::Boolean(true) => OpCode::True,
+                    Literal::Boolean(false) => OpCode::False,
+                    Literal::Number(n) => {
+                        OpCode::Constant(self.chunk.add_constant(Value::Number(*n)))
+                    }
+                    Literal::String(s) => {
+                        OpCode::Constant(self.chunk.add_constant(Value::String(Arc::clone(s))))
+                    }
+                };
+                self.emit(op);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod compiler_tests {
+    use super::{Compiler, OpCode};
+    use crate::{interpreter::Value, parser::BinaryOperator, parser::Parser, scanner::Scanner};
+
+    #[test]
+    fn binary_expression() {
+        let source = r#"1 + 2 * 3"#.to_string();
+        let mut scanner = Scanner::new(source);
+        let tokens = scanner.scan_tokens().unwrap();
+        let mut parser = Parser::new(tokens.clone());
+        let chunk = Compiler::compile_expression(&parser.expression().unwrap()).unwrap();
+        assert_eq!(
+            chunk.code,
+            vec![
+                OpCode::Constant(0),
+                OpCode::Constant(1),
+                OpCode::Constant(2),
+                OpCode::Binary(BinaryOperator::Multiply),
+                OpCode::Binary(BinaryOperator::Plus),
+                OpCode::Return,
+            ]
+        );
+        assert_eq!(
+            chunk.constants,
+            vec![Value::Number(1.0), Value::Number(2.0), Value::Number(3.0)]
+        );
+    }
+
+    #[test]
+    fn statements() {
+        let source = r#"print !nil; "unused";"#.to_string();
+        let mut scanner = Scanner::new(source);
+        let tokens = scanner.scan_tokens().unwrap();
+        let mut parser = Parser::new(tokens.clone());
+        let chunk = Compiler::compile(&parser.parse().unwrap()).unwrap();
+        assert_eq!(
+            chunk.code,
+            vec![
+                OpCode::Nil,
+                OpCode::Not,
+                OpCode::Print(1),
+                OpCode::Constant(0),
+                OpCode::Pop,
+                OpCode::Nil,
+                OpCode::Return,
+            ]
+        );
+    }
+}
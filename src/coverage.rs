@@ -0,0 +1,204 @@
+use std::collections::BTreeMap;
+
+use crate::{arena::Ast, diagnostics::Span, parser::Stmt};
+
+// Which statements ran, kept while `InterpreterOptions::coverage` is on.
+// Statements are told apart by their spans, so the two branches of
+// `if (a) x; else y;` are counted on their own. The report is still by
+// line: a line ran as often as the statement on it that ran the least.
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct Coverage {
+    pub statements: BTreeMap<Span, Count>,
+}
+
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub struct Count {
+    // Where the statement starts.
+    pub line: usize,
+    // Zero if it never ran.
+    pub runs: u64,
+}
+
+impl Coverage {
+    // Every statement in the program, including the nested ones, starts out
+    // as not run.
+    pub(crate) fn add_program(&mut self, statements: &[Stmt]) {
+        for stmt in statements {
+            self.add_statement(stmt);
+        }
+    }
+
+    // `add_program` for the arena, which has all the statements in one place.
+    pub(crate) fn add_ast(&mut self, ast: &Ast) {
+        for stmt in ast.stmts() {
+            self.entry(stmt.span(), stmt.line());
+        }
+    }
+
+    fn add_statement(&mut self, stmt: &Stmt) {
+        self.entry(stmt.span(), stmt.line());
+        match stmt {
+            Stmt::Block { statements, .. } => self.add_program(statements),
+            Stmt::If {
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.add_statement(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.add_statement(else_branch);
+                }
+            }
+            Stmt::Try {
+                body,
+                catch,
+                finally,
+                ..
+            } => {
+                self.add_statement(body);
+                if let Some((_, handler)) = catch {
+                    self.add_statement(handler);
+                }
+                if let Some(finally) = finally {
+                    self.add_statement(finally);
+                }
+            }
+            Stmt::ForIn { body, .. } => self.add_statement(body),
+            Stmt::Expression { .. }
+            | Stmt::Print { .. }
+            | Stmt::Var { .. }
+            | Stmt::Throw { .. }
+            | Stmt::Error { .. } => {}
+        }
+    }
+
+    fn entry(&mut self, span: Span, line: usize) -> &mut Count {
+        self.statements
+            .entry(span)
+            .or_insert(Count { line, runs: 0 })
+    }
+
+    pub(crate) fn hit(&mut self, span: Span, line: usize) {
+        self.entry(span, line).runs += 1;
+    }
+
+    // How often each line with a statement on it ran.
+    pub fn lines(&self) -> BTreeMap<usize, u64> {
+        let mut lines = BTreeMap::new();
+        for count in self.statements.values() {
+            let runs = lines.entry(count.line).or_insert(count.runs);
+            *runs = count.runs.min(*runs);
+        }
+        lines
+    }
+
+    pub fn hit_lines(&self) -> Vec<usize> {
+        self.filter(|runs| runs > 0)
+    }
+
+    pub fn missed_lines(&self) -> Vec<usize> {
+        self.filter(|runs| runs == 0)
+    }
+
+    fn filter(&self, keep: impl Fn(u64) -> bool) -> Vec<usize> {
+        self.lines()
+            .into_iter()
+            .filter(|(_, runs)| keep(*runs))
+            .map(|(line, _)| line)
+            .collect()
+    }
+
+    // A program without statements is fully covered.
+    pub fn percent(&self) -> f64 {
+        let lines = self.lines();
+        if lines.is_empty() {
+            return 100.0;
+        }
+        self.hit_lines().len() as f64 * 100.0 / lines.len() as f64
+    }
+
+    // The source with the run count in front of every line, like gcov does:
+    // `#####` for lines where a statement never ran and `-` for lines
+    // without statements.
+    pub fn render(&self, source: &str) -> String {
+        let lines = self.lines();
+        let mut out = String::new();
+        for (i, text) in source.lines().enumerate() {
+            let count = match lines.get(&(i + 1)) {
+                Some(0) => "#####".to_string(),
+                Some(runs) => runs.to_string(),
+                None => "-".to_string(),
+            };
+            out.push_str(&format!("{:>5} | {}\n", count, text));
+        }
+        out.push_str(&format!(
+            "{} of {} lines covered ({:.1}%)\n",
+            self.hit_lines().len(),
+            lines.len(),
+            self.percent()
+        ));
+        out
+    }
+}
+
+#[cfg(test)]
+mod coverage_tests {
+    use crate::{Interpreter, InterpreterOptions};
+
+    fn covered(source: &str) -> super::Coverage {
+        let mut interpreter = Interpreter::with_options(InterpreterOptions {
+            coverage: true,
+            ..InterpreterOptions::default()
+        });
+        interpreter.set_print_handler(|_| {});
+        interpreter
+            .interpret(crate::parse(source).unwrap())
+            .unwrap();
+        interpreter.coverage_report()
+    }
+
+    #[test]
+    fn else_never_runs() {
+        let source = "var a = 1;\nif (a > 0) {\n  print a;\n} else {\n  print -a;\n}\n";
+        let coverage = covered(source);
+        assert_eq!(coverage.hit_lines(), [1, 2, 3]);
+        assert_eq!(coverage.missed_lines(), [4, 5]);
+        assert_eq!(
+            coverage.render(source),
+            "    1 | var a = 1;\n\
+             \x20   1 | if (a > 0) {\n\
+             \x20   1 |   print a;\n\
+             ##### | } else {\n\
+             ##### |   print -a;\n\
+             \x20   - | }\n\
+             3 of 5 lines covered (60.0%)\n"
+        );
+    }
+
+    #[test]
+    fn else_on_the_same_line() {
+        let coverage = covered("var a = 1;\nif (a > 0) print a; else print -a;\nprint a;");
+        assert_eq!(coverage.missed_lines(), [2]);
+        let runs: Vec<u64> = coverage
+            .statements
+            .values()
+            .map(|count| count.runs)
+            .collect();
+        assert_eq!(runs, [1, 1, 1, 0, 1]);
+    }
+
+    #[test]
+    fn fully_covered() {
+        let coverage = covered("for (i in 0..3)\n  print i;\ntry {\n  throw 1;\n} catch (e) {}");
+        assert_eq!(coverage.missed_lines(), Vec::<usize>::new());
+        assert_eq!(coverage.lines().get(&2), Some(&3));
+        assert_eq!(coverage.percent(), 100.0);
+    }
+
+    #[test]
+    fn off_by_default() {
+        let mut interpreter = Interpreter::new();
+        interpreter.interpret(crate::parse("1;").unwrap()).unwrap();
+        assert!(interpreter.coverage_report().statements.is_empty());
+    }
+}
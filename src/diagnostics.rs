@@ -0,0 +1,669 @@
+use std::collections::HashSet;
+
+use crate::json::quote;
+
+// Byte range into the source, `end` is exclusive.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+}
+
+// Stable identifiers for every kind of error and warning, for tools that
+// would otherwise have to match on messages. The letter is the severity and
+// the hundreds are the stage reporting it. Codes are never renumbered, a
+// retired one just isn't used anymore.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum Code {
+    UnexpectedCharacter,
+    UnterminatedString,
+    EmptyCharLiteral,
+    UnterminatedChar,
+    CharLiteralTooLong,
+    InvalidEscape,
+    InexactNumber,
+    NumberOutOfRange,
+    ScanAborted,
+    ExpectExpression,
+    ExpectSemicolon,
+    ExpectOpening,
+    ExpectClosing,
+    ExpectName,
+    InvalidAssignmentTarget,
+    ExpectCatchOrFinally,
+    ExpectIn,
+    TooMuchNesting,
+    TooManyArguments,
+    EmptyStatement,
+    TrailingInput,
+    UnmatchedClosing,
+    ReservedName,
+    UndefinedVariable,
+    TypeError,
+    CallError,
+    IndexError,
+    InvalidJson,
+    UncaughtThrow,
+    OutOfFuel,
+    Cancelled,
+    InvalidProgram,
+    OutputLimit,
+    CrossTypeEquality,
+    PreludeError,
+    SizeLimit,
+    UnusedVariable,
+    ShadowedVariable,
+    SelfComparison,
+    ConstantCondition,
+    AssignmentInCondition,
+    EmptyBlock,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Stage {
+    Scan,
+    Parse,
+    Runtime,
+    // The resolver and the linter.
+    Check,
+}
+
+impl Code {
+    pub const ALL: [Code; 42] = [
+        Code::UnexpectedCharacter,
+        Code::UnterminatedString,
+        Code::EmptyCharLiteral,
+        Code::UnterminatedChar,
+        Code::CharLiteralTooLong,
+        Code::InvalidEscape,
+        Code::InexactNumber,
+        Code::NumberOutOfRange,
+        Code::ScanAborted,
+        Code::ExpectExpression,
+        Code::ExpectSemicolon,
+        Code::ExpectOpening,
+        Code::ExpectClosing,
+        Code::ExpectName,
+        Code::InvalidAssignmentTarget,
+        Code::ExpectCatchOrFinally,
+        Code::ExpectIn,
+        Code::TooMuchNesting,
+        Code::TooManyArguments,
+        Code::EmptyStatement,
+        Code::TrailingInput,
+        Code::UnmatchedClosing,
+        Code::ReservedName,
+        Code::UndefinedVariable,
+        Code::TypeError,
+        Code::CallError,
+        Code::IndexError,
+        Code::InvalidJson,
+        Code::UncaughtThrow,
+        Code::OutOfFuel,
+        Code::Cancelled,
+        Code::InvalidProgram,
+        Code::OutputLimit,
+        Code::CrossTypeEquality,
+        Code::PreludeError,
+        Code::SizeLimit,
+        Code::UnusedVariable,
+        Code::ShadowedVariable,
+        Code::SelfComparison,
+        Code::ConstantCondition,
+        Code::AssignmentInCondition,
+        Code::EmptyBlock,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Code::UnexpectedCharacter => "E0001",
+            Code::UnterminatedString => "E0002",
+            Code::EmptyCharLiteral => "E0003",
+            Code::UnterminatedChar => "E0004",
+            Code::CharLiteralTooLong => "E0005",
+            Code::InvalidEscape => "E0006",
+            Code::InexactNumber => "W0007",
+            Code::NumberOutOfRange => "E0008",
+            Code::ScanAborted => "E0009",
+            Code::ExpectExpression => "E0101",
+            Code::ExpectSemicolon => "E0102",
+            Code::ExpectOpening => "E0103",
+            Code::ExpectClosing => "E0104",
+            Code::ExpectName => "E0105",
+            Code::InvalidAssignmentTarget => "E0106",
+            Code::ExpectCatchOrFinally => "E0107",
+            Code::ExpectIn => "E0108",
+            Code::TooMuchNesting => "E0109",
+            Code::TooManyArguments => "E0110",
+            Code::EmptyStatement => "E0111",
+            Code::TrailingInput => "E0112",
+            Code::UnmatchedClosing => "E0113",
+            Code::ReservedName => "E0114",
+            Code::UndefinedVariable => "E0201",
+            Code::TypeError => "E0202",
+            Code::CallError => "E0203",
+            Code::IndexError => "E0204",
+            Code::InvalidJson => "E0205",
+            Code::UncaughtThrow => "E0206",
+            Code::OutOfFuel => "E0207",
+            Code::Cancelled => "E0208",
+            Code::InvalidProgram => "E0209",
+            Code::OutputLimit => "E0210",
+            Code::CrossTypeEquality => "W0211",
+            Code::PreludeError => "E0212",
+            Code::SizeLimit => "E0213",
+            Code::UnusedVariable => "W0301",
+            Code::ShadowedVariable => "W0302",
+            Code::SelfComparison => "W0303",
+            Code::ConstantCondition => "W0304",
+            Code::AssignmentInCondition => "W0305",
+            Code::EmptyBlock => "W0306",
+        }
+    }
+
+    pub fn stage(&self) -> Stage {
+        match self {
+            Code::UnexpectedCharacter
+            | Code::UnterminatedString
+            | Code::EmptyCharLiteral
+            | Code::UnterminatedChar
+            | Code::CharLiteralTooLong
+            | Code::InvalidEscape
+            | Code::InexactNumber
+            | Code::NumberOutOfRange
+            | Code::ScanAborted => Stage::Scan,
+            Code::ExpectExpression
+            | Code::ExpectSemicolon
+            | Code::ExpectOpening
+            | Code::ExpectClosing
+            | Code::ExpectName
+            | Code::InvalidAssignmentTarget
+            | Code::ExpectCatchOrFinally
+            | Code::ExpectIn
+            | Code::TooMuchNesting
+            | Code::TooManyArguments
+            | Code::EmptyStatement
+            | Code::TrailingInput
+            | Code::UnmatchedClosing
+            | Code::ReservedName => Stage::Parse,
+            Code::UndefinedVariable
+            | Code::TypeError
+            | Code::CallError
+            | Code::IndexError
+            | Code::InvalidJson
+            | Code::UncaughtThrow
+            | Code::OutOfFuel
+            | Code::Cancelled
+            | Code::InvalidProgram
+            | Code::OutputLimit
+            | Code::CrossTypeEquality
+            | Code::PreludeError
+            | Code::SizeLimit => Stage::Runtime,
+            Code::UnusedVariable
+            | Code::ShadowedVariable
+            | Code::SelfComparison
+            | Code::ConstantCondition
+            | Code::AssignmentInCondition
+            | Code::EmptyBlock => Stage::Check,
+        }
+    }
+
+    pub fn severity(&self) -> Severity {
+        match (self, self.stage()) {
+            (Code::InexactNumber | Code::CrossTypeEquality, _) | (_, Stage::Check) => {
+                Severity::Warning
+            }
+            _ => Severity::Error,
+        }
+    }
+}
+
+impl std::fmt::Display for Code {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+// Something suspicious that doesn't stop the program from running.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Warning {
+    pub code: Code,
+    pub message: String,
+    pub line: usize,
+}
+
+impl std::fmt::Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[line {}] Warning: {}", self.line, self.message)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+// A single error or warning in a shape hosts can render however they like.
+// Runtime errors don't always know their line, and only scan and parse
+// errors have a span.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Diagnostic {
+    pub code: Code,
+    pub severity: Severity,
+    pub message: String,
+    pub line: Option<usize>,
+    pub span: Option<Span>,
+    // Set by drivers that know where the source came from, the library
+    // itself only ever sees the text.
+    pub file: Option<String>,
+}
+
+impl Diagnostic {
+    // One line of JSON for editors. Without a span the whole line is
+    // pointed at. Line and column are where the span starts, which isn't
+    // always the line in the message: an unterminated string is reported
+    // on the line the source ends.
+    pub fn to_json(&self, source: &str) -> String {
+        let map = SourceMap::new(source);
+        let span = self
+            .span
+            .unwrap_or_else(|| map.line_span(self.line.unwrap_or(0)));
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        let file = match &self.file {
+            Some(file) => format!("\"file\":{},", quote(file)),
+            None => String::new(),
+        };
+        format!(
+            "{{{}\"code\":\"{}\",\"severity\":\"{}\",\"message\":{},\"line\":{},\"column\":{},\"span\":{{\"start\":{},\"end\":{}}}}}",
+            file,
+            self.code,
+            severity,
+            quote(&self.message),
+            map.line(span.start),
+            map.column(span.start),
+            span.start,
+            span.end
+        )
+    }
+}
+
+impl From<&Warning> for Diagnostic {
+    fn from(warning: &Warning) -> Self {
+        Self {
+            code: warning.code,
+            severity: Severity::Warning,
+            message: warning.message.clone(),
+            line: Some(warning.line),
+            span: None,
+            file: None,
+        }
+    }
+}
+
+// Collects what every stage found, so that it can all be shown in the order
+// it appears in the source instead of the order the stages ran in. Stages
+// push whatever they find, duplicates included. Each diagnostic comes with
+// the heading a terminal shows above it, which can say more than the
+// diagnostic does, like the rule a lint is from.
+#[derive(Debug, Default)]
+pub struct DiagnosticSink {
+    entries: Vec<(Diagnostic, String)>,
+}
+
+impl DiagnosticSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, diagnostic: Diagnostic, heading: String) {
+        self.entries.push((diagnostic, heading));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.entries
+            .iter()
+            .any(|(diagnostic, _)| diagnostic.severity == Severity::Error)
+    }
+
+    // Everything pushed so far, sorted by where it starts with errors before
+    // warnings at the same place, and with repeats of the same code at the
+    // same span left out. Without a span a diagnostic starts where its line
+    // does, and is only a repeat if the message is the same too.
+    pub fn take(&mut self, source: &str) -> Vec<(Diagnostic, String)> {
+        let map = SourceMap::new(source);
+        let mut entries = std::mem::take(&mut self.entries);
+        entries.sort_by_key(|(diagnostic, _)| {
+            let start = match diagnostic.span {
+                Some(span) => span.start,
+                None => map.line_span(diagnostic.line.unwrap_or(0)).start,
+            };
+            (start, diagnostic.severity == Severity::Warning)
+        });
+        let mut seen = HashSet::new();
+        entries.retain(|(diagnostic, _)| {
+            let line = match diagnostic.span {
+                Some(_) => None,
+                None => Some((diagnostic.line, diagnostic.message.clone())),
+            };
+            seen.insert((diagnostic.code, diagnostic.span, line))
+        });
+        entries
+    }
+}
+
+// Index of where every line starts, to turn offsets back into lines and
+// columns without rescanning the source.
+pub struct SourceMap<'a> {
+    source: &'a str,
+    line_starts: Vec<usize>,
+}
+
+impl<'a> SourceMap<'a> {
+    pub fn new(source: &'a str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        Self {
+            source,
+            line_starts,
+        }
+    }
+
+    // 1-based line of the offset.
+    pub fn line(&self, offset: usize) -> usize {
+        match self.line_starts.binary_search(&offset) {
+            Ok(i) => i + 1,
+            Err(i) => i,
+        }
+    }
+
+    // 1-based column of the offset, in bytes.
+    pub fn column(&self, offset: usize) -> usize {
+        offset - self.line_start(self.line(offset)) + 1
+    }
+
+    fn line_start(&self, line: usize) -> usize {
+        self.line_starts[line.clamp(1, self.line_starts.len()) - 1]
+    }
+
+    // The line without its line break.
+    pub fn line_text(&self, line: usize) -> &'a str {
+        if line == 0 || line > self.line_starts.len() {
+            return "";
+        }
+        let start = self.line_starts[line - 1];
+        let end = match self.line_starts.get(line) {
+            Some(next) => next - 1,
+            None => self.source.len(),
+        };
+        let text = self.source.get(start..end).unwrap_or("");
+        text.strip_suffix('\r').unwrap_or(text)
+    }
+
+    // The line without its indentation, used when an error only knows
+    // which line it happened on.
+    pub fn line_span(&self, line: usize) -> Span {
+        if line == 0 || line > self.line_starts.len() {
+            return Span::new(self.source.len(), self.source.len());
+        }
+        let text = self.line_text(line);
+        let start = self.line_starts[line - 1] + text.len() - text.trim_start().len();
+        Span::new(start, self.line_starts[line - 1] + text.len())
+    }
+}
+
+const TAB_WIDTH: usize = 4;
+
+fn display_width(text: &str) -> usize {
+    text.chars()
+        .map(|c| if c == '\t' { TAB_WIDTH } else { 1 })
+        .sum()
+}
+
+// Renders the message followed by the line the span starts on and a row of
+// carets under the span. Spans running past the end of that line only get
+// the first line underlined, with "..." marking the continuation.
+pub fn render_diagnostic(source: &str, span: Span, message: &str) -> String {
+    render_diagnostic_in(None, source, span, message)
+}
+
+// With a `--> file:line:column` line under the message when the file is known.
+pub fn render_diagnostic_in(file: Option<&str>, source: &str, span: Span, message: &str) -> String {
+    let map = SourceMap::new(source);
+    let start = span.start.min(source.len());
+    let end = span.end.clamp(start, source.len());
+    let line = map.line(start);
+    let text = map.line_text(line);
+    let column = (start - map.line_start(line)).min(text.len());
+    // A span ending right after a line break still ends on that line.
+    let last_line = map.line(end.saturating_sub(1).max(start));
+    let underline_end = if last_line == line {
+        (end - map.line_start(line)).min(text.len())
+    } else {
+        text.len()
+    };
+    let prefix = text.get(..column).unwrap_or(text);
+    let underlined = text.get(column..underline_end.max(column)).unwrap_or("");
+    let gutter = line.to_string().len();
+
+    let mut out = format!("{}\n", message);
+    if let Some(file) = file {
+        out.push_str(&format!(
+            "{}--> {}:{}:{}\n",
+            " ".repeat(gutter),
+            file,
+            line,
+            map.column(start)
+        ));
+    }
+    out.push_str(&format!("{} |", line));
+    if !text.is_empty() {
+        out.push(' ');
+        out.push_str(&text.replace('\t', &" ".repeat(TAB_WIDTH)));
+    }
+    out.push_str(&format!(
+        "\n{} | {}{}",
+        " ".repeat(gutter),
+        " ".repeat(display_width(prefix)),
+        "^".repeat(display_width(underlined).max(1))
+    ));
+    if last_line > line {
+        out.push_str(" ...");
+    }
+    out
+}
+
+#[cfg(test)]
+mod diagnostics_tests {
+    use std::collections::HashSet;
+
+    use super::{
+        render_diagnostic, render_diagnostic_in, Code, Diagnostic, DiagnosticSink, Severity,
+        SourceMap, Span, Stage,
+    };
+
+    #[test]
+    fn lines_and_columns() {
+        let map = SourceMap::new("ab\n\ncd");
+        assert_eq!((map.line(0), map.column(0)), (1, 1));
+        assert_eq!((map.line(2), map.column(2)), (1, 3));
+        assert_eq!((map.line(3), map.column(3)), (2, 1));
+        assert_eq!((map.line(5), map.column(5)), (3, 2));
+        assert_eq!(map.line_text(3), "cd");
+        assert_eq!(map.line_text(4), "");
+    }
+
+    #[test]
+    fn underlines_span() {
+        assert_eq!(
+            render_diagnostic("print 1;\nprint a + b;\n", Span::new(19, 20), "error"),
+            "error\n2 | print a + b;\n  |           ^"
+        );
+    }
+
+    #[test]
+    fn expands_tabs() {
+        assert_eq!(
+            render_diagnostic("\tx = 1;", Span::new(1, 2), "error"),
+            "error\n1 |     x = 1;\n  |     ^"
+        );
+    }
+
+    #[test]
+    fn multi_line_span() {
+        assert_eq!(
+            render_diagnostic("var s = \"one\ntwo", Span::new(8, 16), "error"),
+            "error\n1 | var s = \"one\n  |         ^^^^ ..."
+        );
+    }
+
+    #[test]
+    fn span_at_end() {
+        let source = "print 1\n";
+        assert_eq!(
+            render_diagnostic(source, Span::new(7, 7), "error"),
+            "error\n1 | print 1\n  |        ^"
+        );
+        assert_eq!(
+            render_diagnostic(source, Span::new(8, 8), "error"),
+            "error\n2 |\n  | ^"
+        );
+    }
+
+    #[test]
+    fn codes_are_unique_and_match_their_category() {
+        let mut seen = HashSet::new();
+        for code in Code::ALL {
+            let text = code.as_str();
+            assert!(seen.insert(text), "{} is used twice", text);
+            let letter = match code.severity() {
+                Severity::Error => "E",
+                Severity::Warning => "W",
+            };
+            let hundreds = match code.stage() {
+                Stage::Scan => "00",
+                Stage::Parse => "01",
+                Stage::Runtime => "02",
+                Stage::Check => "03",
+            };
+            assert_eq!(&text[..3], format!("{}{}", letter, hundreds), "{:?}", code);
+            assert_eq!(text.len(), 5);
+        }
+    }
+
+    #[test]
+    fn sink() {
+        let source = "var a = 1;\n  print a;\nprint b;\n";
+        let diagnostic = |code, line, span| Diagnostic {
+            code,
+            severity: Code::severity(&code),
+            message: format!("{:?}", code),
+            line: Some(line),
+            span,
+            file: None,
+        };
+        let mut sink = DiagnosticSink::new();
+        sink.push(
+            diagnostic(Code::ExpectSemicolon, 3, Some(Span::new(17, 18))),
+            "third".to_string(),
+        );
+        sink.push(
+            diagnostic(Code::UnusedVariable, 2, None),
+            "second".to_string(),
+        );
+        sink.push(
+            diagnostic(Code::ExpectSemicolon, 3, Some(Span::new(17, 18))),
+            "third again".to_string(),
+        );
+        sink.push(
+            diagnostic(Code::InexactNumber, 1, Some(Span::new(8, 9))),
+            "first warning".to_string(),
+        );
+        sink.push(
+            diagnostic(Code::NumberOutOfRange, 1, Some(Span::new(8, 9))),
+            "first error".to_string(),
+        );
+        sink.push(
+            diagnostic(Code::UnusedVariable, 2, None),
+            "second again".to_string(),
+        );
+        assert!(sink.has_errors());
+        let headings: Vec<String> = sink
+            .take(source)
+            .into_iter()
+            .map(|(_, heading)| heading)
+            .collect();
+        assert_eq!(
+            headings,
+            ["first error", "first warning", "second", "third"]
+        );
+        assert!(sink.is_empty());
+    }
+
+    // Tools depend on these, so changing one has to be deliberate.
+    #[test]
+    fn codes_are_stable() {
+        let codes: Vec<&str> = Code::ALL.iter().map(Code::as_str).collect();
+        assert_eq!(
+            codes,
+            [
+                "E0001", "E0002", "E0003", "E0004", "E0005", "E0006", "W0007", "E0008", "E0009",
+                "E0101", "E0102", "E0103", "E0104", "E0105", "E0106", "E0107", "E0108", "E0109",
+                "E0110", "E0111", "E0112", "E0113", "E0114", "E0201", "E0202", "E0203", "E0204",
+                "E0205", "E0206", "E0207", "E0208", "E0209", "E0210", "W0211", "E0212", "E0213",
+                "W0301", "W0302", "W0303", "W0304", "W0305", "W0306",
+            ]
+        );
+    }
+
+    #[test]
+    fn json() {
+        let source = "print 1;\n  print \"a\" + nil;";
+        let diagnostic = Diagnostic {
+            code: Code::TypeError,
+            severity: Severity::Error,
+            message: "Operands must be \"numbers\".".to_string(),
+            line: Some(2),
+            span: None,
+            file: None,
+        };
+        assert_eq!(
+            diagnostic.to_json(source),
+            r#"{"code":"E0202","severity":"error","message":"Operands must be \"numbers\".","line":2,"column":3,"span":{"start":11,"end":27}}"#
+        );
+        let diagnostic = Diagnostic {
+            file: Some("<stdin>".to_string()),
+            ..diagnostic
+        };
+        assert!(diagnostic
+            .to_json(source)
+            .starts_with(r#"{"file":"<stdin>","code":"E0202","#));
+    }
+
+    #[test]
+    fn render_with_file() {
+        assert_eq!(
+            render_diagnostic_in(
+                Some("<stdin>"),
+                "print 1;\nprint 1 +;",
+                Span::new(18, 19),
+                "error"
+            ),
+            "error\n --> <stdin>:2:10\n2 | print 1 +;\n  |          ^"
+        );
+    }
+}
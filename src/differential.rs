@@ -0,0 +1,238 @@
+// Random expressions run through every way of evaluating them: the tree
+// walker, the arena and the bytecode VM. They have to agree on the value, or
+// on the error and its message. Expressions stick to what the VM compiles.
+//
+// The seed is fixed so that failures reproduce. Set ROXY_DIFF_SEED to try
+// others, a failure prints the seed and the expression.
+
+use crate::{
+    arena::Ast,
+    compiler::Compiler,
+    diagnostics::{Code, Span},
+    interpreter::{Interpreter, RuntimeError, Value},
+    parser::{Binary, BinaryOperator, Expr, Grouping, Literal, Unary, UnaryOperator},
+    synthetic::Lcg,
+    vm::Vm,
+};
+
+const SEED: u64 = 0xd1ff;
+const CASES: usize = 3000;
+const DEPTH: u32 = 6;
+
+#[derive(Clone, Copy)]
+enum Type {
+    Number,
+    String,
+    Boolean,
+    Any,
+}
+
+struct Generator {
+    random: Lcg,
+    // One in this many operands gets the wrong type, 0 for never.
+    mistyped: u64,
+}
+
+impl Generator {
+    fn pick<T: Copy>(&mut self, choices: &[T]) -> T {
+        choices[self.random.below(choices.len() as u64) as usize]
+    }
+
+    fn expr(&mut self, ty: Type, depth: u32) -> Expr {
+        if self.mistyped != 0 && self.random.below(self.mistyped) == 0 {
+            let ty = self.pick(&[Type::Number, Type::String, Type::Boolean, Type::Any]);
+            return self.literal(ty);
+        }
+        if depth == 0 || self.random.below(4) == 0 {
+            return self.literal(ty);
+        }
+        let depth = depth - 1;
+        if self.random.below(8) == 0 {
+            return Expr::Grouping(Grouping {
+                expr: Box::new(self.expr(ty, depth)),
+            });
+        }
+        match ty {
+            Type::Number => {
+                if self.random.below(5) == 0 {
+                    return self.unary(UnaryOperator::Minus, Type::Number, depth);
+                }
+                let operator = self.pick(&[
+                    BinaryOperator::Plus,
+                    BinaryOperator::Minus,
+                    BinaryOperator::Multiply,
+                    BinaryOperator::Divide,
+                ]);
+                self.binary(operator, Type::Number, depth)
+            }
+            Type::String => self.binary(BinaryOperator::Plus, Type::String, depth),
+            Type::Boolean => match self.random.below(3) {
+                0 => self.unary(UnaryOperator::Not, Type::Any, depth),
+                1 => {
+                    let operator = self.pick(&[
+                        BinaryOperator::LessThan,
+                        BinaryOperator::LessThanEqual,
+                        BinaryOperator::GreaterThan,
+                        BinaryOperator::GreaterThanEqual,
+                    ]);
+                    self.binary(operator, Type::Number, depth)
+                }
+                _ => {
+                    let operator =
+                        self.pick(&[BinaryOperator::EqualEqual, BinaryOperator::NotEqual]);
+                    self.binary(operator, Type::Any, depth)
+                }
+            },
+            Type::Any => {
+                let ty = self.pick(&[Type::Number, Type::String, Type::Boolean]);
+                self.expr(ty, depth + 1)
+            }
+        }
+    }
+
+    fn unary(&mut self, operator: UnaryOperator, ty: Type, depth: u32) -> Expr {
+        Expr::Unary(Unary {
+            operator,
+            right: Box::new(self.expr(ty, depth)),
+        })
+    }
+
+    fn binary(&mut self, operator: BinaryOperator, ty: Type, depth: u32) -> Expr {
+        // Generated, so there is no source for a span to point into.
+        Expr::Binary(Binary {
+            left: Box::new(self.expr(ty, depth)),
+            operator,
+            right: Box::new(self.expr(ty, depth)),
+            span: Span::default(),
+        })
+    }
+
+    fn literal(&mut self, ty: Type) -> Expr {
+        let literal = match ty {
+            Type::Number => Literal::Number(self.pick(&[
+                0.0, 1.0, 2.0, 3.0, 0.5, 0.1, 10.0, 123.0, 1e21, 1e308, 5e-324,
+            ])),
+            Type::String => Literal::String(self.pick(&["", "a", "ab", " ", "lox"]).into()),
+            Type::Boolean => Literal::Boolean(self.random.below(2) == 0),
+            Type::Any => match self.random.below(4) {
+                0 => Literal::Nil,
+                1 => return self.literal(Type::Number),
+                2 => return self.literal(Type::String),
+                _ => return self.literal(Type::Boolean),
+            },
+        };
+        Expr::Literal(literal)
+    }
+}
+
+// What the paths have to agree on. Values are compared by type and repr, so
+// that NaN equals NaN and 0 doesn't equal -0.
+#[derive(Debug, PartialEq)]
+enum Outcome {
+    Value(&'static str, String),
+    Error(Code, String),
+}
+
+impl From<Result<Value, RuntimeError>> for Outcome {
+    fn from(result: Result<Value, RuntimeError>) -> Self {
+        match result {
+            Ok(value) => Outcome::Value(value.type_name(), value.repr()),
+            Err(err) => Outcome::Error(err.code(), err.to_string()),
+        }
+    }
+}
+
+fn every_path(expr: &Expr) -> [(&'static str, Outcome); 3] {
+    let tree_walker = Interpreter::new().eval(expr);
+    let mut ast = Ast::new();
+    let id = ast.lower_expr(expr);
+    let arena = Interpreter::new().eval_node(&ast, id);
+    let vm = Compiler::compile_expression(expr).and_then(|chunk| Vm::new().run(&chunk));
+    [
+        ("tree walker", tree_walker.into()),
+        ("arena", arena.into()),
+        ("vm", vm.into()),
+    ]
+}
+
+fn seed() -> u64 {
+    match std::env::var("ROXY_DIFF_SEED") {
+        Ok(seed) => seed.parse().expect("ROXY_DIFF_SEED should be a number"),
+        Err(_) => SEED,
+    }
+}
+
+// How many of the cases ended in an error.
+fn agree(seed: u64, mistyped: u64) -> usize {
+    let mut generator = Generator {
+        random: Lcg(seed),
+        mistyped,
+    };
+    let mut errors = 0;
+    for case in 0..CASES {
+        let ty = generator.pick(&[Type::Number, Type::String, Type::Boolean, Type::Any]);
+        let expr = generator.expr(ty, DEPTH);
+        let [(_, expected), rest @ ..] = every_path(&expr);
+        for (path, outcome) in rest {
+            assert_eq!(
+                outcome, expected,
+                "{} disagrees with the tree walker, seed {} case {}: {}",
+                path, seed, case, expr
+            );
+        }
+        errors += matches!(expected, Outcome::Error(..)) as usize;
+    }
+    errors
+}
+
+#[test]
+fn well_typed() {
+    assert_eq!(agree(seed(), 0), 0);
+}
+
+#[test]
+fn mistyped() {
+    assert!(agree(seed(), 6) > 0);
+}
+
+// Division by zero isn't an error anywhere, it follows IEEE 754.
+#[test]
+fn division_by_zero() {
+    let number = |n| Box::new(Expr::Literal(Literal::Number(n)));
+    let divide = |left, right| {
+        Expr::Binary(Binary {
+            left: number(left),
+            operator: BinaryOperator::Divide,
+            right: number(right),
+            span: Span::default(),
+        })
+    };
+    let negative_zero = Expr::Unary(Unary {
+        operator: UnaryOperator::Minus,
+        right: number(0.0),
+    });
+    let cases = [
+        (divide(1.0, 0.0), "inf"),
+        (divide(0.0, 0.0), "NaN"),
+        (
+            Expr::Binary(Binary {
+                left: number(1.0),
+                operator: BinaryOperator::Divide,
+                right: Box::new(negative_zero),
+                span: Span::default(),
+            }),
+            "-inf",
+        ),
+    ];
+    for (expr, expected) in cases {
+        for (path, outcome) in every_path(&expr) {
+            assert_eq!(
+                outcome,
+                Outcome::Value("number", expected.to_string()),
+                "{}: {}",
+                path,
+                expr
+            );
+        }
+    }
+}
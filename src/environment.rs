@@ -0,0 +1,243 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::interpreter::Value;
+
+// Copy of one scope for debuggers. Depth 0 is the global scope.
+#[derive(Debug, PartialEq, Clone)]
+pub struct ScopeSnapshot {
+    pub depth: usize,
+    pub label: String,
+    // Sorted by name.
+    pub variables: Vec<(String, Value)>,
+}
+
+// Scopes are kept as a stack, the first one holds the globals and every
+// block pushes a new one on top.
+#[derive(Debug, Clone)]
+pub struct Environment {
+    scopes: Vec<HashMap<String, Value>>,
+    // Scopes below this one are left alone while isolated, see `isolate`.
+    shared: usize,
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self {
+            scopes: vec![HashMap::new()],
+            shared: 0,
+        }
+    }
+}
+
+impl Environment {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn define(&mut self, name: &str, value: Value) {
+        self.scopes
+            .last_mut()
+            .expect("the global scope is never popped")
+            .insert(name.to_string(), value);
+    }
+
+    pub fn define_global(&mut self, name: &str, value: Value) {
+        self.scopes[0].insert(name.to_string(), value);
+    }
+
+    pub fn get_global(&self, name: &str) -> Option<&Value> {
+        self.scopes[0].get(name)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    // Every name visible from the innermost scope, shadowed ones only once.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        let mut seen = HashSet::new();
+        self.scopes
+            .iter()
+            .rev()
+            .flat_map(|scope| scope.keys())
+            .map(String::as_str)
+            .filter(move |name| seen.insert(*name))
+    }
+
+    // Returns false when the variable isn't defined in any scope.
+    pub fn assign(&mut self, name: &str, value: Value) -> bool {
+        let Some(depth) = (0..self.scopes.len())
+            .rev()
+            .find(|depth| self.scopes[*depth].contains_key(name))
+        else {
+            return false;
+        };
+        if depth < self.shared {
+            self.scopes[self.shared].insert(name.to_string(), value);
+        } else if let Some(slot) = self.scopes[depth].get_mut(name) {
+            *slot = value;
+        }
+        true
+    }
+
+    // Puts a scope on top that takes every change until `end_isolation`.
+    // Variables from below that are assigned to get a copy in it, so the
+    // scopes below look the same afterwards.
+    pub fn isolate(&mut self) {
+        self.push_scope();
+        self.shared = self.scopes.len() - 1;
+    }
+
+    pub fn end_isolation(&mut self) {
+        self.scopes.truncate(self.shared.max(1));
+        self.shared = 0;
+    }
+
+    pub fn globals(&self) -> impl Iterator<Item = (&str, &Value)> {
+        self.scopes[0]
+            .iter()
+            .map(|(name, value)| (name.as_str(), value))
+    }
+
+    pub fn locals_at_current_scope(&self) -> impl Iterator<Item = (&str, &Value)> {
+        self.scopes
+            .last()
+            .expect("the global scope is never popped")
+            .iter()
+            .map(|(name, value)| (name.as_str(), value))
+    }
+
+    // Every scope, innermost first.
+    pub fn snapshot(&self) -> Vec<ScopeSnapshot> {
+        self.scopes
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(depth, scope)| {
+                let mut variables: Vec<(String, Value)> = scope
+                    .iter()
+                    .map(|(name, value)| (name.clone(), value.clone()))
+                    .collect();
+                variables.sort_by(|a, b| a.0.cmp(&b.0));
+                ScopeSnapshot {
+                    depth,
+                    label: if depth == 0 {
+                        "globals".to_string()
+                    } else {
+                        format!("block {}", depth)
+                    },
+                    variables,
+                }
+            })
+            .collect()
+    }
+
+    pub fn push_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    pub fn pop_scope(&mut self) {
+        if self.scopes.len() > 1 {
+            self.scopes.pop();
+        }
+    }
+}
+
+#[cfg(test)]
+mod environment_tests {
+    use super::{Environment, ScopeSnapshot};
+    use crate::interpreter::Value;
+
+    #[test]
+    fn shadowing() {
+        let mut environment = Environment::new();
+        environment.define("a", Value::Number(1.0));
+        environment.push_scope();
+        environment.define("a", Value::Number(2.0));
+        assert_eq!(environment.get("a"), Some(&Value::Number(2.0)));
+        environment.pop_scope();
+        assert_eq!(environment.get("a"), Some(&Value::Number(1.0)));
+    }
+
+    #[test]
+    fn assign_to_enclosing_scope() {
+        let mut environment = Environment::new();
+        environment.define("a", Value::Number(1.0));
+        environment.push_scope();
+        assert!(environment.assign("a", Value::Nil));
+        assert!(!environment.assign("b", Value::Nil));
+        environment.pop_scope();
+        assert_eq!(environment.get("a"), Some(&Value::Nil));
+        assert_eq!(environment.get("b"), None);
+    }
+
+    #[test]
+    fn isolation() {
+        let mut environment = Environment::new();
+        environment.define("a", Value::Number(1.0));
+        environment.isolate();
+        environment.define("b", Value::Nil);
+        assert!(environment.assign("a", Value::Number(2.0)));
+        environment.push_scope();
+        assert!(environment.assign("a", Value::Number(3.0)));
+        environment.pop_scope();
+        assert_eq!(environment.get("a"), Some(&Value::Number(3.0)));
+        environment.end_isolation();
+        assert_eq!(environment.get("a"), Some(&Value::Number(1.0)));
+        assert_eq!(environment.get("b"), None);
+        // Back to normal.
+        assert!(environment.assign("a", Value::Nil));
+        assert_eq!(environment.get_global("a"), Some(&Value::Nil));
+    }
+
+    #[test]
+    fn globals_skip_inner_scopes() {
+        let mut environment = Environment::new();
+        environment.push_scope();
+        environment.define("a", Value::Number(1.0));
+        environment.define_global("a", Value::Number(2.0));
+        assert_eq!(environment.get("a"), Some(&Value::Number(1.0)));
+        assert_eq!(environment.get_global("a"), Some(&Value::Number(2.0)));
+    }
+
+    #[test]
+    fn visible_names() {
+        let mut environment = Environment::new();
+        environment.define("a", Value::Nil);
+        environment.push_scope();
+        environment.define("a", Value::Nil);
+        environment.define("b", Value::Nil);
+        let mut names: Vec<&str> = environment.names().collect();
+        names.sort();
+        assert_eq!(names, vec!["a", "b"]);
+    }
+
+    #[test]
+    fn snapshot() {
+        let mut environment = Environment::new();
+        environment.define("b", Value::Nil);
+        environment.define("a", Value::Number(1.0));
+        environment.push_scope();
+        environment.define("c", Value::Boolean(true));
+        assert_eq!(
+            environment.snapshot(),
+            vec![
+                ScopeSnapshot {
+                    depth: 1,
+                    label: "block 1".to_string(),
+                    variables: vec![("c".to_string(), Value::Boolean(true))],
+                },
+                ScopeSnapshot {
+                    depth: 0,
+                    label: "globals".to_string(),
+                    variables: vec![
+                        ("a".to_string(), Value::Number(1.0)),
+                        ("b".to_string(), Value::Nil)
+                    ],
+                },
+            ]
+        );
+        assert_eq!(environment.locals_at_current_scope().count(), 1);
+        assert_eq!(environment.globals().count(), 2);
+    }
+}
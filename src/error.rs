@@ -0,0 +1,190 @@
+use crate::{
+    diagnostics::{Diagnostic, Severity},
+    interpreter::RuntimeError,
+    parser::ParseError,
+    scanner::ScanError,
+};
+
+// Whatever stopped a program from running, by the stage that found it. The
+// scanner and parser keep going after an error, so they report all of them.
+#[derive(Debug, PartialEq, Clone)]
+pub enum RoxyError {
+    Scan(Vec<ScanError>),
+    Parse(Vec<ParseError>),
+    Runtime(RuntimeError),
+}
+
+impl RoxyError {
+    // The source ends in the middle of a statement or string.
+    pub fn at_end(&self) -> bool {
+        match self {
+            RoxyError::Scan(errors) => errors.iter().all(|err| err.at_end),
+            RoxyError::Parse(errors) => errors.iter().all(|err| err.at_end),
+            RoxyError::Runtime(_) => false,
+        }
+    }
+
+    // Every error on its own, in the order they were found.
+    pub fn errors(&self) -> impl Iterator<Item = Diagnostic> + '_ {
+        let error = |code, message: &str, line, span| Diagnostic {
+            code,
+            severity: Severity::Error,
+            message: message.to_string(),
+            line,
+            span,
+            file: None,
+        };
+        let diagnostics: Vec<Diagnostic> = match self {
+            RoxyError::Scan(errors) => errors
+                .iter()
+                .map(|err| error(err.code, &err.message, Some(err.line), Some(err.span)))
+                .collect(),
+            RoxyError::Parse(errors) => errors
+                .iter()
+                .map(|err| error(err.code, &err.message, Some(err.line), Some(err.span)))
+                .collect(),
+            RoxyError::Runtime(err) => vec![error(err.code(), &err.to_string(), err.line(), None)],
+        };
+        diagnostics.into_iter()
+    }
+}
+
+// One error per line, the way the CLI prints them minus the source snippets.
+impl std::fmt::Display for RoxyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let lines: Vec<String> = match self {
+            RoxyError::Scan(errors) => errors.iter().map(|err| err.to_string()).collect(),
+            RoxyError::Parse(errors) => errors.iter().map(|err| err.to_string()).collect(),
+            RoxyError::Runtime(err) => vec![err.to_string()],
+        };
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+// Only the first error of a stage is the source, `errors` has all of them.
+impl std::error::Error for RoxyError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RoxyError::Scan(errors) => errors.first().map(|err| err as _),
+            RoxyError::Parse(errors) => errors.first().map(|err| err as _),
+            RoxyError::Runtime(err) => Some(err),
+        }
+    }
+}
+
+impl From<Vec<ScanError>> for RoxyError {
+    fn from(errors: Vec<ScanError>) -> Self {
+        RoxyError::Scan(errors)
+    }
+}
+
+impl From<Vec<ParseError>> for RoxyError {
+    fn from(errors: Vec<ParseError>) -> Self {
+        RoxyError::Parse(errors)
+    }
+}
+
+impl From<RuntimeError> for RoxyError {
+    fn from(err: RuntimeError) -> Self {
+        RoxyError::Runtime(err)
+    }
+}
+
+#[cfg(test)]
+mod error_tests {
+    use std::error::Error;
+
+    use super::RoxyError;
+    use crate::{
+        diagnostics::{Code, Diagnostic, Severity, Span},
+        interpreter::RuntimeError,
+        parser::ParseError,
+        scanner::ScanError,
+    };
+
+    #[test]
+    fn display() {
+        let err = crate::run("print 1 +;\nprint (2;").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "[line 1] Error: Expect expression.\n[line 2] Error: Expect ')' after expression."
+        );
+        let err = RoxyError::from(RuntimeError::OutOfFuel);
+        assert_eq!(err.to_string(), "Out of fuel.");
+    }
+
+    #[test]
+    fn source() {
+        let err = crate::run("print @;").unwrap_err();
+        let source = err.source().unwrap();
+        assert_eq!(
+            source.downcast_ref::<ScanError>().unwrap().message,
+            "Unexpected character."
+        );
+        let err = crate::run("print;").unwrap_err();
+        assert!(err.source().unwrap().is::<ParseError>());
+        let err = crate::run("print -nil;").unwrap_err();
+        assert_eq!(
+            err.source().unwrap().downcast_ref::<RuntimeError>(),
+            Some(&RuntimeError::TypeError(
+                "You can only negate a number".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn from_stage_errors() {
+        fn parse() -> Result<(), RoxyError> {
+            Err(vec![ParseError {
+                code: Code::ExpectExpression,
+                message: "Expect expression.".to_string(),
+                line: 1,
+                span: Span::new(0, 1),
+                at_end: false,
+            }])?
+        }
+        assert!(matches!(parse(), Err(RoxyError::Parse(errors)) if errors.len() == 1));
+        fn run() -> Result<(), RoxyError> {
+            Err(RuntimeError::Cancelled)?
+        }
+        assert_eq!(run(), Err(RoxyError::Runtime(RuntimeError::Cancelled)));
+    }
+
+    #[test]
+    fn errors() {
+        let err = crate::run("print @ # 1;").unwrap_err();
+        assert_eq!(
+            err.errors().collect::<Vec<Diagnostic>>(),
+            vec![
+                Diagnostic {
+                    code: Code::UnexpectedCharacter,
+                    severity: Severity::Error,
+                    message: "Unexpected character.".to_string(),
+                    line: Some(1),
+                    span: Some(Span::new(6, 7)),
+                    file: None,
+                },
+                Diagnostic {
+                    code: Code::UnexpectedCharacter,
+                    severity: Severity::Error,
+                    message: "Unexpected character.".to_string(),
+                    line: Some(1),
+                    span: Some(Span::new(8, 9)),
+                    file: None,
+                },
+            ]
+        );
+        let err = crate::run("\"a\"[3];").unwrap_err();
+        assert_eq!(
+            err.errors().collect::<Vec<Diagnostic>>(),
+            vec![Diagnostic {
+                code: Code::IndexError,
+                severity: Severity::Error,
+                message: "Index 3 is out of range for a string of length 1.".to_string(),
+                line: Some(1),
+                span: None,
+                file: None,
+            }]
+        );
+    }
+}
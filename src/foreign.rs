@@ -0,0 +1,42 @@
+use std::{any::Any, sync::Arc};
+
+use crate::interpreter::Value;
+
+// A Rust value the host hands to scripts, e.g. a database handle. Scripts
+// can only store it, pass it around and compare it, which is by identity:
+// copies of one foreign value are equal, two separately made ones never are.
+#[derive(Clone)]
+pub struct Foreign {
+    // Shown when printed and in errors, `<foreign Db>`.
+    pub name: String,
+    value: Arc<dyn Any + Send + Sync>,
+}
+
+impl PartialEq for Foreign {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.value, &other.value)
+    }
+}
+
+impl std::fmt::Debug for Foreign {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<foreign {}>", self.name)
+    }
+}
+
+impl Value {
+    pub fn from_foreign<T: Any + Send + Sync>(value: T, name: &str) -> Value {
+        Value::Foreign(Foreign {
+            name: name.to_string(),
+            value: Arc::new(value),
+        })
+    }
+
+    // None for other values and for foreign values of another type.
+    pub fn downcast_foreign<T: Any>(&self) -> Option<&T> {
+        match self {
+            Value::Foreign(foreign) => foreign.value.downcast_ref(),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,326 @@
+use std::collections::VecDeque;
+
+use crate::{
+    parser::{Expr, Literal, ParseError, Parser, Stmt},
+    scanner::{Comment, Scanner, Token, TokenKind},
+};
+
+const INDENT: &str = "    ";
+
+// Prints the program back in one consistent style: a statement per line,
+// blocks indented by four spaces and expressions the way their Display impl
+// prints them. Formatting the output again gives the same output.
+pub fn format_source(source: &str) -> Result<String, Vec<ParseError>> {
+    let mut scanner = Scanner::new(source.to_string());
+    scanner.set_keep_comments(true);
+    let tokens = match scanner.scan_tokens() {
+        Ok(tokens) => tokens.clone(),
+        // Reported like parse errors, they print the same anyway.
+        Err(errors) => {
+            return Err(errors
+                .into_iter()
+                .map(|err| ParseError {
+                    code: err.code,
+                    message: err.message,
+                    line: err.line,
+                    span: err.span,
+                    at_end: err.at_end,
+                })
+                .collect())
+        }
+    };
+    let statements = Parser::new(tokens.clone()).parse()?;
+    let mut formatter = Formatter {
+        out: String::new(),
+        depth: 0,
+        comments: scanner.comments().iter().cloned().collect(),
+        marks: tokens
+            .into_iter()
+            .filter(|token| {
+                matches!(
+                    token.kind,
+                    TokenKind::Semicolon | TokenKind::LeftBrace | TokenKind::RightBrace
+                )
+            })
+            .collect(),
+        last_mark: None,
+    };
+    for stmt in &statements {
+        formatter.statement(stmt);
+    }
+    formatter.flush(usize::MAX);
+    Ok(formatter.out)
+}
+
+// The AST doesn't know where comments were, so they are put back by
+// position. Every `;`, `{` and `}` printed is matched with the next one in
+// the source. A comment after one of them on the same line stays at the end
+// of that line, any other comment goes on its own line before the statement
+// or closing brace that comes after it.
+struct Formatter {
+    out: String,
+    depth: usize,
+    comments: VecDeque<Comment>,
+    marks: VecDeque<Token>,
+    last_mark: Option<Token>,
+}
+
+impl Formatter {
+    fn statement(&mut self, stmt: &Stmt) {
+        self.begin_line();
+        self.write(stmt);
+        self.end_line();
+    }
+
+    // Writes the statement without the line break after it, so that an
+    // `else` or `catch` can follow a closing brace.
+    fn write(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Expression { expr, .. } => {
+                self.push(&expr.to_string());
+                self.mark(";");
+            }
+            Stmt::Print { exprs, .. } => {
+                let exprs: Vec<String> = exprs.iter().map(Expr::to_string).collect();
+                self.push(&format!("print {}", exprs.join(", ")));
+                self.mark(";");
+            }
+            Stmt::Throw { expr, .. } => {
+                self.push(&format!("throw {}", expr));
+                self.mark(";");
+            }
+            // The parser fills in nil for `var a;`.
+            Stmt::Var {
+                name,
+                initializer: Expr::Literal(Literal::Nil),
+                ..
+            } => {
+                self.push(&format!("var {}", name));
+                self.mark(";");
+            }
+            Stmt::Var {
+                name, initializer, ..
+            } => {
+                self.push(&format!("var {} = {}", name, initializer));
+                self.mark(";");
+            }
+            Stmt::Block { statements, .. } => {
+                self.mark("{");
+                self.end_line();
+                self.depth += 1;
+                for stmt in statements {
+                    self.statement(stmt);
+                }
+                let end = self.next_mark();
+                self.flush(end);
+                self.depth -= 1;
+                self.indent();
+                self.mark("}");
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.push(&format!("if ({})", condition));
+                self.body(then_branch);
+                if let Some(else_branch) = else_branch {
+                    if matches!(**then_branch, Stmt::Block { .. }) {
+                        self.push(" ");
+                    } else {
+                        self.end_line();
+                        self.begin_line();
+                    }
+                    self.push("else");
+                    match **else_branch {
+                        Stmt::If { .. } => {
+                            self.push(" ");
+                            self.write(else_branch);
+                        }
+                        _ => self.body(else_branch),
+                    }
+                }
+            }
+            Stmt::Try {
+                body,
+                catch,
+                finally,
+                ..
+            } => {
+                self.push("try ");
+                self.write(body);
+                if let Some((name, handler)) = catch {
+                    self.push(&format!(" catch ({}) ", name));
+                    self.write(handler);
+                }
+                if let Some(finally) = finally {
+                    self.push(" finally ");
+                    self.write(finally);
+                }
+            }
+            Stmt::ForIn {
+                name,
+                iterable,
+                body,
+                ..
+            } => {
+                self.push(&format!("for ({} in {})", name, iterable));
+                self.body(body);
+            }
+            Stmt::Error { .. } => unreachable!("only programs without errors are formatted"),
+        }
+    }
+
+    // Blocks open on the same line, anything else goes indented on the next.
+    fn body(&mut self, stmt: &Stmt) {
+        if let Stmt::Block { .. } = stmt {
+            self.push(" ");
+            self.write(stmt);
+        } else {
+            self.end_line();
+            self.depth += 1;
+            self.begin_line();
+            self.write(stmt);
+            self.depth -= 1;
+        }
+    }
+
+    fn begin_line(&mut self) {
+        let end = self.next_mark();
+        self.flush(end);
+        self.indent();
+    }
+
+    fn end_line(&mut self) {
+        let next = self.next_mark();
+        if let (Some(last), Some(comment)) = (&self.last_mark, self.comments.front()) {
+            if comment.line == last.line
+                && comment.span.start > last.span.start
+                && comment.span.start < next
+            {
+                let comment = self.comments.pop_front().unwrap();
+                self.push(&format!(" {}", comment.text));
+            }
+        }
+        self.out.push('\n');
+    }
+
+    // Puts the comments before `end` on lines of their own.
+    fn flush(&mut self, end: usize) {
+        while self
+            .comments
+            .front()
+            .is_some_and(|comment| comment.span.start < end)
+        {
+            let comment = self.comments.pop_front().unwrap();
+            self.indent();
+            self.push(&comment.text);
+            self.out.push('\n');
+        }
+    }
+
+    fn mark(&mut self, text: &str) {
+        self.push(text);
+        self.last_mark = self.marks.pop_front();
+    }
+
+    fn next_mark(&self) -> usize {
+        self.marks
+            .front()
+            .map_or(usize::MAX, |token| token.span.start)
+    }
+
+    fn indent(&mut self) {
+        for _ in 0..self.depth {
+            self.out.push_str(INDENT);
+        }
+    }
+
+    fn push(&mut self, text: &str) {
+        self.out.push_str(text);
+    }
+}
+
+#[cfg(test)]
+mod formatter_tests {
+    use super::format_source;
+
+    #[test]
+    fn style() {
+        assert_eq!(
+            format_source("var a=1;{var b;print(a+b)*2;}if(a)print a;else{a=-a;}").unwrap(),
+            "var a = 1;\n\
+             {\n\
+             \x20   var b;\n\
+             \x20   print (a + b) * 2;\n\
+             }\n\
+             if (a)\n\
+             \x20   print a;\n\
+             else {\n\
+             \x20   a = -a;\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn else_if_chain() {
+        assert_eq!(
+            format_source("if (a) { print 1; } else if (b) print 2; else { print 3; }").unwrap(),
+            "if (a) {\n\
+             \x20   print 1;\n\
+             } else if (b)\n\
+             \x20   print 2;\n\
+             else {\n\
+             \x20   print 3;\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn try_and_for() {
+        assert_eq!(
+            format_source("try{throw 1;}catch(e){print e;}finally{}\nfor(i in 0..=3)print i;")
+                .unwrap(),
+            "try {\n\
+             \x20   throw 1;\n\
+             } catch (e) {\n\
+             \x20   print e;\n\
+             } finally {\n\
+             }\n\
+             for (i in 0..=3)\n\
+             \x20   print i;\n"
+        );
+    }
+
+    #[test]
+    fn comments() {
+        let source = "// leading\nprint 1;   // trailing\n{\n// inside\nprint 2; // two\n  // before brace\n}\nprint 1 + // inner\n2;\n// last\n";
+        let formatted = format_source(source).unwrap();
+        assert_eq!(
+            formatted,
+            "// leading\n\
+             print 1; // trailing\n\
+             {\n\
+             \x20   // inside\n\
+             \x20   print 2; // two\n\
+             \x20   // before brace\n\
+             }\n\
+             // inner\n\
+             print 1 + 2;\n\
+             // last\n"
+        );
+        assert_eq!(format_source(&formatted).unwrap(), formatted);
+    }
+
+    #[test]
+    fn errors() {
+        let errors = format_source("print 1 +;\nprint @;").unwrap_err();
+        assert_eq!(
+            errors[0].to_string(),
+            "[line 2] Error: Unexpected character."
+        );
+        let errors = format_source("print 1 +;").unwrap_err();
+        assert_eq!(errors[0].to_string(), "[line 1] Error: Expect expression.");
+    }
+}
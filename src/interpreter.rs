@@ -1,174 +1,1774 @@
-use crate::parser::{BinaryOperator, Expr, Literal, UnaryOperator, Stmt};
+use std::{
+    collections::HashSet,
+    io::{self, Write},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Instant,
+};
+
+use crate::{
+    arena::{Ast, ExprId, ExprNode, StmtId, StmtNode},
+    callable::{Callable, NativeFunction},
+    coverage::Coverage,
+    diagnostics::{Code, Diagnostic, Severity, Span},
+    environment::{Environment, ScopeSnapshot},
+    error::RoxyError,
+    foreign::Foreign,
+    json::quote,
+    natives,
+    parser::{Binary, BinaryOperator, Expr, Literal, Stmt, UnaryOperator},
+    stats::Stats,
+};
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Value {
     Number(f64),
-    String(String),
+    String(Arc<str>),
     Boolean(bool),
     Nil,
+    NativeFunction(NativeFunction),
+    // Integers from `start` up to `end`, counting by one.
+    Range {
+        start: i64,
+        end: i64,
+        inclusive: bool,
+    },
+    Foreign(Foreign),
+}
+
+impl Value {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Number(_) => "number",
+            Value::String(_) => "string",
+            Value::Boolean(_) => "boolean",
+            Value::Nil => "nil",
+            Value::NativeFunction(_) => "function",
+            Value::Range { .. } => "range",
+            Value::Foreign(_) => "foreign",
+        }
+    }
+
+    // The numbers a `for` loop over the value goes through.
+    pub fn range_values(&self) -> Option<std::ops::Range<i64>> {
+        match *self {
+            Value::Range {
+                start,
+                end,
+                inclusive,
+            } => Some(
+                start..if inclusive {
+                    end.saturating_add(1)
+                } else {
+                    end
+                },
+            ),
+            _ => None,
+        }
+    }
+}
+
+// Shorthands for hosts passing arguments in.
+impl From<f64> for Value {
+    fn from(n: f64) -> Self {
+        Value::Number(n)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Boolean(b)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::String(s.into())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::String(s.into())
+    }
+}
+
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::String(v) => write!(f, "{}", v),
+            Value::Boolean(v) => write!(f, "{}", v),
+            Value::Nil => write!(f, "nil"),
+            Value::Number(v) => write!(f, "{}", format_number(*v)),
+            Value::NativeFunction(native) => write!(f, "<native {}>", native.name()),
+            Value::Range {
+                start,
+                end,
+                inclusive,
+            } => write!(
+                f,
+                "{}{}{}",
+                start,
+                if *inclusive { "..=" } else { ".." },
+                end
+            ),
+            Value::Foreign(foreign) => write!(f, "<foreign {}>", foreign.name),
+        }
+    }
+}
+
+impl Value {
+    // Like Display, but strings are quoted and escaped so that quotes,
+    // newlines and trailing spaces in them show. The REPL echoes values this
+    // way, `print` keeps showing the raw text.
+    pub fn repr(&self) -> String {
+        match self {
+            Value::String(s) => quote(s),
+            _ => self.to_string(),
+        }
+    }
+}
+
+// How much of a value an error message shows, in chars.
+pub const ERROR_VALUE_CHARS: usize = 120;
+
+// A value as error messages show it: escaped like `repr`, so it can't break
+// lines or send control codes to a terminal, and cut off so that a huge
+// string doesn't end up in a log whole. Every error that mentions a value
+// goes through here.
+pub fn fmt_value_for_error(value: &Value) -> String {
+    fmt_value_for_error_in(value, ERROR_VALUE_CHARS)
+}
+
+pub fn fmt_value_for_error_in(value: &Value, max_chars: usize) -> String {
+    let text = value.repr();
+    let chars = text.chars().count();
+    if chars <= max_chars {
+        return text;
+    }
+    // The length is the one `len` would give.
+    let length = match value {
+        Value::String(s) => s.chars().count(),
+        _ => chars,
+    };
+    let prefix: String = text.chars().take(max_chars).collect();
+    format!("{}… ({} chars)", prefix, length)
+}
+
+// Shortest text that reads back as the same number, which is what Rust's
+// formatting already produces. Very large and very small magnitudes switch to
+// exponent notation like JavaScript does, instead of printing hundreds of
+// digits.
+pub fn format_number(n: f64) -> String {
+    let magnitude = n.abs();
+    if n.is_finite() && magnitude != 0.0 && !(1e-7..1e21).contains(&magnitude) {
+        format!("{:e}", n)
+    } else {
+        format!("{}", n)
+    }
 }
 
-pub struct Interpreter {}
+// Exactly `digits` decimals and never an exponent. The exact binary value is
+// rounded with ties to even, so 0.125 becomes 0.12 while 0.375 becomes 0.38,
+// and 1.005 is 1.00 because it is really a bit less than that.
+pub fn format_fixed(n: f64, digits: usize) -> String {
+    format!("{:.*}", digits, n)
+}
+
+// `significant` digits, switching to exponent notation where JavaScript's
+// toPrecision does, when the exponent is below -6 or doesn't fit the digits.
+// Rounds like `format_fixed`.
+pub fn format_precision(n: f64, significant: usize) -> String {
+    let scientific = format!("{:.*e}", significant.saturating_sub(1), n);
+    let Some((_, exponent)) = scientific.split_once('e') else {
+        // inf and NaN
+        return scientific;
+    };
+    let exponent: i64 = exponent
+        .parse()
+        .expect("Rust writes the exponent as a number");
+    if exponent < -6 || exponent >= significant as i64 {
+        scientific
+    } else {
+        format_fixed(n, (significant as i64 - 1 - exponent) as usize)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum RuntimeError {
+    TypeError(String),
+    UndefinedVariable {
+        name: String,
+        // A visible name that is close enough to be what was meant.
+        suggestion: Option<String>,
+    },
+    OutOfFuel,
+    Cancelled,
+    // The program has error nodes from `Parser::parse_partial` in it, or is
+    // a chunk the VM can't run.
+    InvalidProgram,
+    // More was printed than `max_output_bytes` allows.
+    OutputLimit(usize),
+    // A string of `size` bytes would have gone over `max_string_len`.
+    SizeLimit {
+        size: usize,
+        limit: usize,
+    },
+    // The prelude failed to parse or run, the message says how.
+    Prelude(String),
+    InvalidJson(String),
+    // Calling something that isn't callable, with the wrong number of
+    // arguments, or a native function that failed.
+    CallError {
+        message: String,
+        line: usize,
+    },
+    // Indexing something that can't be indexed or with a bad index.
+    IndexError {
+        message: String,
+        line: usize,
+    },
+    // Raised by a `throw` statement, `catch` gets the value back as is.
+    Thrown {
+        value: Value,
+        line: usize,
+    },
+}
+
+impl RuntimeError {
+    // Limits set by the embedder can't be caught by the script.
+    pub fn is_catchable(&self) -> bool {
+        !matches!(
+            self,
+            RuntimeError::OutOfFuel
+                | RuntimeError::Cancelled
+                | RuntimeError::InvalidProgram
+                | RuntimeError::OutputLimit(_)
+                | RuntimeError::SizeLimit { .. }
+                | RuntimeError::Prelude(_)
+        )
+    }
+
+    // Errors that know better than the statement they happened in.
+    pub fn line(&self) -> Option<usize> {
+        match self {
+            RuntimeError::CallError { line, .. }
+            | RuntimeError::IndexError { line, .. }
+            | RuntimeError::Thrown { line, .. } => Some(*line),
+            _ => None,
+        }
+    }
+
+    pub fn code(&self) -> Code {
+        match self {
+            RuntimeError::TypeError(_) => Code::TypeError,
+            RuntimeError::UndefinedVariable { .. } => Code::UndefinedVariable,
+            RuntimeError::OutOfFuel => Code::OutOfFuel,
+            RuntimeError::Cancelled => Code::Cancelled,
+            RuntimeError::InvalidProgram => Code::InvalidProgram,
+            RuntimeError::OutputLimit(_) => Code::OutputLimit,
+            RuntimeError::SizeLimit { .. } => Code::SizeLimit,
+            RuntimeError::Prelude(_) => Code::PreludeError,
+            RuntimeError::InvalidJson(_) => Code::InvalidJson,
+            RuntimeError::CallError { .. } => Code::CallError,
+            RuntimeError::IndexError { .. } => Code::IndexError,
+            RuntimeError::Thrown { .. } => Code::UncaughtThrow,
+        }
+    }
+}
+
+impl std::fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RuntimeError::TypeError(message) => write!(f, "{}", message),
+            RuntimeError::UndefinedVariable { name, suggestion } => {
+                write!(f, "Undefined variable '{}'.", name)?;
+                if let Some(suggestion) = suggestion {
+                    write!(f, " Did you mean '{}'?", suggestion)?;
+                }
+                Ok(())
+            }
+            RuntimeError::OutOfFuel => write!(f, "Out of fuel."),
+            RuntimeError::Cancelled => write!(f, "Cancelled."),
+            RuntimeError::InvalidProgram => write!(f, "Program contains errors."),
+            RuntimeError::OutputLimit(limit) => {
+                write!(f, "Output limit of {} bytes exceeded.", limit)
+            }
+            RuntimeError::SizeLimit { size, limit } => write!(
+                f,
+                "Value exceeds the configured size limit: {} bytes, at most {} are allowed.",
+                size, limit
+            ),
+            RuntimeError::Prelude(message) => write!(f, "Error in prelude: {}", message),
+            RuntimeError::InvalidJson(message) => write!(f, "Invalid JSON: {}.", message),
+            RuntimeError::CallError { message, .. } | RuntimeError::IndexError { message, .. } => {
+                write!(f, "{}", message)
+            }
+            RuntimeError::Thrown { value, .. } => write!(f, "{}", value),
+        }
+    }
+}
+
+impl std::error::Error for RuntimeError {}
+
+// The prelude the CLI runs with, see `InterpreterOptions::prelude`.
+#[cfg(feature = "default-prelude")]
+pub const DEFAULT_PRELUDE: &str = include_str!("prelude.lox");
+
+// What a script is allowed to do, for hosts that run code they don't trust.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InterpreterOptions {
+    // Natives that read files or stdin. There are none yet, but anything
+    // added later has to check this.
+    pub allow_io: bool,
+    // A `clock` native reading the system time. `run_capture` turns it off
+    // and takes its clock from the host instead.
+    pub allow_clock: bool,
+    // A `sleep` native, which blocks the thread running the script.
+    // `run_capture` and the sandbox turn it off.
+    pub allow_sleep: bool,
+    // Seed for `random` and `randomInt`, the same seed gives the same
+    // numbers. Without one they are seeded from the system time.
+    pub rng_seed: Option<u64>,
+    // Runs the same way every time, for reproducing bug reports: `clock`
+    // counts a millisecond per call instead of reading the time, and
+    // `random` starts from a fixed seed unless `rng_seed` is set.
+    pub deterministic: bool,
+    // Bytes of printed values, line breaks not counted. The print that goes
+    // over is cut off at the limit and fails.
+    pub max_output_bytes: Option<usize>,
+    pub fuel: Option<u64>,
+    // How deeply calls can nest before the program fails.
+    pub max_call_depth: usize,
+    // Record which lines ran, see `coverage_report`.
+    pub coverage: bool,
+    // Lox source run before the first program, for globals written in Lox.
+    // Its statements don't use up fuel and aren't counted by profiling or
+    // coverage.
+    pub prelude: Option<String>,
+    // Warn when `==` or `!=` compares values of different types, which is
+    // never equal and usually a mistake. Comparisons with nil are left out.
+    // See `take_warnings`.
+    pub warn_cross_type_equality: bool,
+    // Longest string in bytes that concatenation and the string natives
+    // will make. Checked before the string is built, so a script doubling a
+    // string stops here instead of taking the host's memory.
+    pub max_string_len: usize,
+    // Only true and false can be `if` conditions, anything else is a type
+    // error instead of being truthy.
+    pub strict_conditions: bool,
+}
+
+impl Default for InterpreterOptions {
+    fn default() -> Self {
+        Self {
+            allow_io: true,
+            allow_clock: true,
+            allow_sleep: true,
+            rng_seed: None,
+            deterministic: false,
+            max_output_bytes: None,
+            fuel: None,
+            max_call_depth: 256,
+            coverage: false,
+            prelude: None,
+            warn_cross_type_equality: false,
+            strict_conditions: false,
+            max_string_len: 1 << 30,
+        }
+    }
+}
+
+impl InterpreterOptions {
+    // Limits for running scripts from people who aren't trusted: no I/O, no
+    // clock, no sleeping, and a bound on the steps, the output and the
+    // strings.
+    pub fn sandbox() -> Self {
+        Self {
+            allow_io: false,
+            allow_clock: false,
+            allow_sleep: false,
+            fuel: Some(10_000_000),
+            max_output_bytes: Some(1 << 20),
+            max_call_depth: 64,
+            max_string_len: 1 << 20,
+            ..Self::default()
+        }
+    }
+}
+
+pub struct Interpreter {
+    options: InterpreterOptions,
+    environment: Environment,
+    // Steps left before the program is aborted, `None` means unlimited.
+    fuel: Option<u64>,
+    // Counted against `max_output_bytes`.
+    output_bytes: usize,
+    call_depth: usize,
+    // Set from another thread to stop the program before its next statement.
+    // It stays set until the host clears it again.
+    cancelled: Arc<AtomicBool>,
+    // Line of the statement being executed.
+    line: usize,
+    trace: Option<Box<dyn Write + Send>>,
+    step_hook: Option<Box<StepHook>>,
+    print_handler: Option<Box<dyn FnMut(Value) + Send>>,
+    // Only kept while profiling, so a normal run doesn't count anything.
+    stats: Option<Box<Stats>>,
+    coverage: Option<Box<Coverage>>,
+    // Every native registered so far, `reset` defines them again.
+    natives: Vec<NativeFunction>,
+    // Set once the prelude ran, `reset` clears it to run it again.
+    prelude_loaded: bool,
+    warnings: Vec<Diagnostic>,
+    // Spans of the comparisons already warned about, so a loop doesn't warn
+    // on every iteration.
+    warned: HashSet<Span>,
+    // Source of the program being run, for `dbg` to print expressions as
+    // they were written.
+    source: Option<Arc<str>>,
+}
+
+type StepHook = dyn FnMut(&Stmt, &[ScopeSnapshot]) + Send;
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::with_options(InterpreterOptions::default())
+    }
+}
 
 impl Interpreter {
-    pub fn eval(expr: &Expr) -> Value {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_options(options: InterpreterOptions) -> Self {
+        let mut interpreter = Self {
+            environment: Environment::new(),
+            fuel: options.fuel,
+            output_bytes: 0,
+            call_depth: 0,
+            cancelled: Arc::default(),
+            line: 0,
+            trace: None,
+            step_hook: None,
+            print_handler: None,
+            stats: None,
+            coverage: options.coverage.then(Box::default),
+            natives: Vec::new(),
+            prelude_loaded: false,
+            warnings: Vec::new(),
+            warned: HashSet::new(),
+            source: None,
+            options,
+        };
+        natives::define(&mut interpreter);
+        interpreter
+    }
+
+    pub fn options(&self) -> &InterpreterOptions {
+        &self.options
+    }
+
+    // Traces statements and variable changes to stderr.
+    pub fn set_trace(&mut self, enabled: bool) {
+        self.trace = if enabled {
+            Some(Box::new(io::stderr()))
+        } else {
+            None
+        };
+    }
+
+    pub fn set_trace_writer(&mut self, writer: impl Write + Send + 'static) {
+        self.trace = Some(Box::new(writer));
+    }
+
+    // Called before every statement with the scopes it will run in, so a
+    // debugger can pause there and show what's in scope.
+    pub fn set_step_hook(&mut self, hook: impl FnMut(&Stmt, &[ScopeSnapshot]) + Send + 'static) {
+        self.step_hook = Some(Box::new(hook));
+    }
+
+    // The program's source, so `dbg` can print what it was given the way
+    // it was written. `run_with` attaches it on its own.
+    pub fn set_source(&mut self, source: &str) {
+        self.source = Some(Arc::from(source));
+    }
+
+    // Receives the value of every `print` statement instead of it being
+    // written to stdout, so the host can format it however it likes.
+    pub fn set_print_handler(&mut self, handler: impl FnMut(Value) + Send + 'static) {
+        self.print_handler = Some(Box::new(handler));
+    }
+
+    // Turning it on again starts the counts over.
+    pub fn set_profile(&mut self, enabled: bool) {
+        self.stats = enabled.then(Box::default);
+    }
+
+    // All zero unless profiling is on.
+    pub fn stats(&self) -> Stats {
+        self.stats.as_deref().cloned().unwrap_or_default()
+    }
+
+    // Warnings found while running since the last call. Comparisons that
+    // were warned about are forgotten too, spans in the REPL start over for
+    // every input.
+    pub fn take_warnings(&mut self) -> Vec<Diagnostic> {
+        self.warned.clear();
+        std::mem::take(&mut self.warnings)
+    }
+
+    // Empty unless `InterpreterOptions::coverage` is on.
+    pub fn coverage_report(&self) -> Coverage {
+        self.coverage.as_deref().cloned().unwrap_or_default()
+    }
+
+    fn count(&mut self, counter: impl FnOnce(&mut Stats)) {
+        if let Some(stats) = &mut self.stats {
+            counter(stats);
+        }
+    }
+
+    pub fn globals(&self) -> impl Iterator<Item = (&str, &Value)> {
+        self.environment.globals()
+    }
+
+    pub fn locals_at_current_scope(&self) -> impl Iterator<Item = (&str, &Value)> {
+        self.environment.locals_at_current_scope()
+    }
+
+    pub fn snapshot(&self) -> Vec<ScopeSnapshot> {
+        self.environment.snapshot()
+    }
+
+    fn trace(&mut self, event: std::fmt::Arguments) {
+        if let Some(writer) = &mut self.trace {
+            let _ = writeln!(writer, "[trace] line {}: {}", self.line, event);
+        }
+    }
+
+    pub fn define_native(
+        &mut self,
+        name: &str,
+        arity: usize,
+        function: impl Fn(&[Value]) -> Result<Value, String> + Send + Sync + 'static,
+    ) {
+        self.add_native(NativeFunction::new(name, arity, function));
+    }
+
+    // Like `define_native`, but extra arguments are passed along too.
+    pub fn define_variadic_native(
+        &mut self,
+        name: &str,
+        min_arity: usize,
+        function: impl Fn(&[Value]) -> Result<Value, String> + Send + Sync + 'static,
+    ) {
+        self.add_native(NativeFunction::variadic(name, min_arity, function));
+    }
+
+    pub(crate) fn add_native(&mut self, native: NativeFunction) {
+        self.environment
+            .define_global(&native.name, Value::NativeFunction(native.clone()));
+        self.natives.push(native);
+    }
+
+    // Forgets every variable the scripts defined, natives stay available.
+    pub fn reset(&mut self) {
+        self.environment = Environment::new();
+        self.prelude_loaded = false;
+        for native in &self.natives {
+            self.environment
+                .define_global(&native.name, Value::NativeFunction(native.clone()));
+        }
+    }
+
+    // Copy of the interpreter's variables that can run code without
+    // affecting this one. Tracing, profiling, the hooks, the cancel token and
+    // warnings are not carried over.
+    pub fn fork(&self) -> Interpreter {
+        Interpreter {
+            options: self.options.clone(),
+            environment: self.environment.clone(),
+            fuel: self.fuel,
+            output_bytes: self.output_bytes,
+            call_depth: 0,
+            cancelled: Arc::default(),
+            line: 0,
+            trace: None,
+            step_hook: None,
+            print_handler: None,
+            stats: None,
+            coverage: None,
+            natives: self.natives.clone(),
+            prelude_loaded: self.prelude_loaded,
+            warnings: Vec::new(),
+            warned: HashSet::new(),
+            source: self.source.clone(),
+        }
+    }
+
+    // Globals seeded by the host behave like a `var` at the top of the
+    // script, so the script can reassign them or declare them again.
+    pub fn define_global(&mut self, name: &str, value: Value) {
+        self.environment.define_global(name, value);
+    }
+
+    // Reads a global after the script ran, None if it was never defined.
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        self.environment.get_global(name).cloned()
+    }
+
+    // Calls a global function the way a call in the script would, for hosts
+    // that use functions in the script as callbacks.
+    pub fn call_function(
+        &mut self,
+        name: &str,
+        arguments: Vec<Value>,
+    ) -> Result<Value, RuntimeError> {
+        let callee = self
+            .get_global(name)
+            .ok_or_else(|| self.undefined_variable(name))?;
+        self.call(callee, arguments, self.line)
+    }
+
+    pub fn has_function(&self, name: &str) -> bool {
+        matches!(self.get_global(name), Some(Value::NativeFunction(_)))
+    }
+
+    pub fn with_fuel(fuel: u64) -> Self {
+        Self::with_options(InterpreterOptions {
+            fuel: Some(fuel),
+            ..InterpreterOptions::default()
+        })
+    }
+
+    pub fn cancel_token(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.cancelled)
+    }
+
+    pub(crate) fn check_cancelled(&self) -> Result<(), RuntimeError> {
+        if self.cancelled.load(Ordering::Relaxed) {
+            Err(RuntimeError::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+
+    // Line of the statement that was executing last, which is where a
+    // runtime error happened.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    pub fn fuel(&self) -> Option<u64> {
+        self.fuel
+    }
+
+    // Every statement and expression costs one unit of fuel.
+    pub(crate) fn burn_fuel(&mut self) -> Result<(), RuntimeError> {
+        self.burn(1)
+    }
+
+    // Concatenating also costs a unit per KiB of the result, otherwise a
+    // script that keeps doubling a string runs out of memory long before it
+    // runs out of fuel.
+    fn burn_string_fuel(&mut self, value: &Value) -> Result<(), RuntimeError> {
+        match value {
+            Value::String(s) => self.burn(s.len() as u64 / 1024),
+            _ => Ok(()),
+        }
+    }
+
+    fn burn(&mut self, amount: u64) -> Result<(), RuntimeError> {
+        match self.fuel {
+            Some(0) if amount > 0 => Err(RuntimeError::OutOfFuel),
+            Some(ref mut fuel) => {
+                *fuel = fuel.saturating_sub(amount);
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    }
+
+    // `1 + 2 + 3` nests to the left, and generated code can have chains far
+    // longer than the parser would allow. The left operands are walked with
+    // a loop instead of recursion so that those don't use up the stack, and
+    // each operator is applied on the way back up as `eval` would.
+    fn binary_chain(&mut self, outer: &Binary) -> Result<Value, RuntimeError> {
+        let mut chain = vec![outer];
+        let mut left = &*outer.left;
+        while let Expr::Binary(b) = left {
+            // What `eval` would have charged for the nested node.
+            self.burn_fuel()?;
+            self.count(|stats| stats.expressions += 1);
+            chain.push(b);
+            left = &b.left;
+        }
+        let mut value = self.eval(left)?;
+        for b in chain.into_iter().rev() {
+            let right = self.eval(&b.right)?;
+            self.check_equality(b.operator, b.span, &value, &right);
+            value = self.binary_checked(b.operator, value, right)?;
+        }
+        Ok(value)
+    }
+
+    // `binary_chain` for the arena, `outer` is the first operator, its right
+    // operand and the span of the whole expression.
+    fn binary_chain_node(
+        &mut self,
+        ast: &Ast,
+        mut left: ExprId,
+        outer: (BinaryOperator, ExprId, Span),
+    ) -> Result<Value, RuntimeError> {
+        let mut chain = vec![outer];
+        while let ExprNode::Binary {
+            left: next,
+            operator,
+            right,
+            span,
+        } = ast.expr(left)
+        {
+            // What `eval_node` would have charged for the nested node.
+            self.burn_fuel()?;
+            self.count(|stats| stats.expressions += 1);
+            chain.push((*operator, *right, *span));
+            left = *next;
+        }
+        let mut value = self.eval_node(ast, left)?;
+        for (operator, right, span) in chain.into_iter().rev() {
+            let right = self.eval_node(ast, right)?;
+            self.check_equality(operator, span, &value, &right);
+            value = self.binary_checked(operator, value, right)?;
+        }
+        Ok(value)
+    }
+
+    // `binary` within the limits of the options: a string over
+    // `max_string_len` is never made and long ones cost fuel.
+    pub(crate) fn binary_checked(
+        &mut self,
+        operator: BinaryOperator,
+        left: Value,
+        right: Value,
+    ) -> Result<Value, RuntimeError> {
+        self.check_size(operator, &left, &right)?;
+        let value = Interpreter::binary(operator, left, right)?;
+        self.burn_string_fuel(&value)?;
+        Ok(value)
+    }
+
+    // Subexpressions run left to right and each exactly once: a callee
+    // before its arguments, an index's object before the index, and both
+    // before the value of an index-set. A pipe is a call, so its function
+    // comes before the piped value. `eval_node` keeps to the same order,
+    // tests/programs/evaluation_order.lox checks both.
+    pub fn eval(&mut self, expr: &Expr) -> Result<Value, RuntimeError> {
+        self.burn_fuel()?;
+        self.count(|stats| stats.expressions += 1);
         match expr {
-            Expr::Binary(b) => {
-                let left = Interpreter::eval(&b.left);
-                let right = Interpreter::eval(&b.right);
-                match b.operator {
-                    BinaryOperator::Minus => {
-                        if let (Value::Number(n1), Value::Number(n2)) = (left, right) {
-                            Value::Number(n1 - n2)
-                        } else {
-                            panic!("You can only substract numbers")
-                        }
-                    }
-                    BinaryOperator::Plus => match (left, right) {
-                        (Value::Number(n1), Value::Number(n2)) => Value::Number(n1 + n2),
-                        (Value::String(s1), Value::String(s2)) => {
-                            Value::String(format!("{}{}", s1, s2))
-                        }
-                        _ => panic!("You can only add strings or numbers"),
-                    },
-                    BinaryOperator::Multiply => {
-                        if let (Value::Number(n1), Value::Number(n2)) = (left, right) {
-                            Value::Number(n1 * n2)
-                        } else {
-                            panic!("You can only multiply numbers")
-                        }
-                    }
-                    BinaryOperator::Divide => {
-                        if let (Value::Number(n1), Value::Number(n2)) = (left, right) {
-                            Value::Number(n1 / n2)
-                        } else {
-                            panic!("You can only multiply numbers")
-                        }
-                    }
-                    BinaryOperator::GreaterThan => {
-                        if let (Value::Number(n1), Value::Number(n2)) = (left, right) {
-                            Value::Boolean(n1 > n2)
-                        } else {
-                            panic!("You can only multiply numbers")
-                        }
-                    }
-                    BinaryOperator::LessThan => {
-                        if let (Value::Number(n1), Value::Number(n2)) = (left, right) {
-                            Value::Boolean(n1 < n2)
-                        } else {
-                            panic!("You can only multiply numbers")
-                        }
-                    }
-                    BinaryOperator::GreaterThanEqual => {
-                        if let (Value::Number(n1), Value::Number(n2)) = (left, right) {
-                            Value::Boolean(n1 >= n2)
-                        } else {
-                            panic!("You can only multiply numbers")
-                        }
-                    }
-                    BinaryOperator::LessThanEqual => {
-                        if let (Value::Number(n1), Value::Number(n2)) = (left, right) {
-                            Value::Boolean(n1 <= n2)
-                        } else {
-                            panic!("You can only multiply numbers")
-                        }
-                    }
-                    //What happens in the case of non-primitive values?
-                    BinaryOperator::EqualEqual => Value::Boolean(left == right),
-                    BinaryOperator::NotEqual => Value::Boolean(left != right),
-                }
-            }
-            Expr::Grouping(g) => Interpreter::eval(&g.expr),
-            Expr::Literal(l) => match l {
-                Literal::String(s) => Value::String(s.to_string()),
-                Literal::Number(n) => Value::Number(*n),
-                Literal::Boolean(b) => Value::Boolean(*b),
-                Literal::Nil => Value::Nil,
-            },
+            Expr::Binary(b) => self.binary_chain(b),
+            Expr::Grouping(g) => self.eval(&g.expr),
+            Expr::Literal(l) => Ok(Interpreter::literal(l)),
             Expr::Unary(u) => {
-                let right = Interpreter::eval(&u.right);
-                match u.operator {
-                    UnaryOperator::Minus => {
-                        if let Value::Number(n) = right {
-                            Value::Number(-n)
-                        } else {
-                            panic!("You can only negate a number")
+                let right = self.eval(&u.right)?;
+                Interpreter::unary(u.operator, right)
+            }
+            Expr::Variable(name) => self.lookup(name),
+            Expr::Assign(a) => {
+                let value = self.eval(&a.value)?;
+                self.assign(&a.name, value)
+            }
+            Expr::Call(c) => {
+                let callee = self.eval(&c.callee)?;
+                let arguments = c
+                    .arguments
+                    .iter()
+                    .map(|argument| self.eval(argument))
+                    .collect::<Result<Vec<_>, _>>()?;
+                self.call(callee, arguments, c.line)
+            }
+            Expr::Index(i) => {
+                let object = self.eval(&i.object)?;
+                let index = self.eval(&i.index)?;
+                Interpreter::index(object, index, i.line)
+            }
+            Expr::SetIndex(i) => {
+                let object = self.eval(&i.object)?;
+                self.eval(&i.index)?;
+                self.eval(&i.value)?;
+                Interpreter::set_index(object, i.line)
+            }
+            Expr::Range(r) => {
+                let start = self.eval(&r.start)?;
+                let end = self.eval(&r.end)?;
+                Interpreter::range(start, end, r.inclusive)
+            }
+            Expr::Dbg(d) => {
+                let value = self.eval(&d.expr)?;
+                self.print_dbg(d.span, d.line, &value, || d.expr.to_string())?;
+                Ok(value)
+            }
+            Expr::Error(_) => Err(RuntimeError::InvalidProgram),
+        }
+    }
+
+    pub(crate) fn range(start: Value, end: Value, inclusive: bool) -> Result<Value, RuntimeError> {
+        match (start, end) {
+            (Value::Number(start), Value::Number(end))
+                if start.fract() == 0.0 && end.fract() == 0.0 =>
+            {
+                Ok(Value::Range {
+                    start: start as i64,
+                    end: end as i64,
+                    inclusive,
+                })
+            }
+            (start, end) => Err(RuntimeError::TypeError(format!(
+                "Range bounds must be integers, got {} and {}.",
+                fmt_value_for_error(&start),
+                fmt_value_for_error(&end)
+            ))),
+        }
+    }
+
+    pub(crate) fn range_values(iterable: &Value) -> Result<std::ops::Range<i64>, RuntimeError> {
+        iterable.range_values().ok_or_else(|| {
+            RuntimeError::TypeError(format!(
+                "Can only iterate over ranges, got {}.",
+                fmt_value_for_error(iterable)
+            ))
+        })
+    }
+
+    // Strings are indexed by char, not by byte.
+    pub(crate) fn index(object: Value, index: Value, line: usize) -> Result<Value, RuntimeError> {
+        let error = |message: String| RuntimeError::IndexError { message, line };
+        let s = match object {
+            Value::String(s) => s,
+            _ => return Err(error("Only strings can be indexed.".to_string())),
+        };
+        let index = match index {
+            Value::Number(n) if n.fract() == 0.0 => n,
+            index => {
+                return Err(error(format!(
+                    "Index must be an integer, got {}.",
+                    fmt_value_for_error(&index)
+                )))
+            }
+        };
+        let length = s.chars().count();
+        if index < 0.0 || index >= length as f64 {
+            return Err(error(format!(
+                "Index {} is out of range for a string of length {}.",
+                index, length
+            )));
+        }
+        let c = s.chars().nth(index as usize).expect("index is in range");
+        Ok(Value::from(c.to_string()))
+    }
+
+    pub(crate) fn set_index(object: Value, line: usize) -> Result<Value, RuntimeError> {
+        let message = match object {
+            Value::String(_) => "Strings are immutable.",
+            _ => "Only strings can be indexed.",
+        };
+        Err(RuntimeError::IndexError {
+            message: message.to_string(),
+            line,
+        })
+    }
+
+    pub(crate) fn call(
+        &mut self,
+        callee: Value,
+        arguments: Vec<Value>,
+        line: usize,
+    ) -> Result<Value, RuntimeError> {
+        let error = |message: String| RuntimeError::CallError { message, line };
+        let function = match callee {
+            Value::NativeFunction(function) => function,
+            _ => return Err(error("Can only call functions and classes.".to_string())),
+        };
+        if function.variadic() && arguments.len() < function.arity() {
+            return Err(error(format!(
+                "Expected at least {} arguments but got {}.",
+                function.arity(),
+                arguments.len()
+            )));
+        }
+        if !function.variadic() && arguments.len() != function.arity() {
+            return Err(error(format!(
+                "Expected {} arguments but got {}.",
+                function.arity(),
+                arguments.len()
+            )));
+        }
+        if self.call_depth >= self.options.max_call_depth {
+            return Err(error("Stack overflow.".to_string()));
+        }
+        self.call_depth += 1;
+        let result = self.call_native(&function, &arguments);
+        self.call_depth -= 1;
+        result.map_err(error)
+    }
+
+    fn call_native(
+        &mut self,
+        function: &NativeFunction,
+        arguments: &[Value],
+    ) -> Result<Value, String> {
+        if self.stats.is_none() {
+            return function.call(self, arguments);
+        }
+        let start = Instant::now();
+        let result = function.call(self, arguments);
+        let time = start.elapsed();
+        self.count(|stats| {
+            let call = stats.calls.entry(function.name.clone()).or_default();
+            call.calls += 1;
+            call.time += time;
+        });
+        result
+    }
+
+    pub(crate) fn lookup(&self, name: &str) -> Result<Value, RuntimeError> {
+        self.environment
+            .get(name)
+            .cloned()
+            .ok_or_else(|| self.undefined_variable(name))
+    }
+
+    fn undefined_variable(&self, name: &str) -> RuntimeError {
+        RuntimeError::UndefinedVariable {
+            name: name.to_string(),
+            suggestion: self.suggest(name),
+        }
+    }
+
+    // The closest visible name within two edits. Ties are ambiguous so they
+    // get no suggestion, and single letter names are never suggested for
+    // each other.
+    fn suggest(&self, name: &str) -> Option<String> {
+        let mut best: Option<(usize, &str)> = None;
+        let mut tied = false;
+        for candidate in self.environment.names() {
+            let distance = edit_distance(name, candidate);
+            if distance > 2 || distance >= name.len().max(candidate.len()) {
+                continue;
+            }
+            match best {
+                Some((best_distance, _)) if distance > best_distance => {}
+                Some((best_distance, _)) if distance == best_distance => tied = true,
+                _ => {
+                    best = Some((distance, candidate));
+                    tied = false;
+                }
+            }
+        }
+        match best {
+            Some((_, candidate)) if !tied => Some(candidate.to_string()),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn assign(&mut self, name: &str, value: Value) -> Result<Value, RuntimeError> {
+        if !self.environment.assign(name, value.clone()) {
+            return Err(self.undefined_variable(name));
+        }
+        self.trace(format_args!("assign {} = {}", name, value));
+        Ok(value)
+    }
+
+    fn define(&mut self, name: &str, value: Value) {
+        self.trace(format_args!("define {} = {}", name, value));
+        self.environment.define(name, value);
+    }
+
+    pub fn eval_node(&mut self, ast: &Ast, id: ExprId) -> Result<Value, RuntimeError> {
+        self.burn_fuel()?;
+        self.count(|stats| stats.expressions += 1);
+        match ast.expr(id) {
+            ExprNode::Binary {
+                left,
+                operator,
+                right,
+                span,
+            } => self.binary_chain_node(ast, *left, (*operator, *right, *span)),
+            ExprNode::Grouping(expr) => self.eval_node(ast, *expr),
+            ExprNode::Constant(index) => Ok(ast.constant(*index).clone()),
+            ExprNode::Unary { operator, right } => {
+                let right = self.eval_node(ast, *right)?;
+                Interpreter::unary(*operator, right)
+            }
+            ExprNode::Variable(name) => self.lookup(name),
+            ExprNode::Assign { name, value } => {
+                let value = self.eval_node(ast, *value)?;
+                self.assign(name, value)
+            }
+            ExprNode::Call {
+                callee,
+                arguments,
+                line,
+            } => {
+                let callee = self.eval_node(ast, *callee)?;
+                let arguments = arguments
+                    .iter()
+                    .map(|argument| self.eval_node(ast, *argument))
+                    .collect::<Result<Vec<_>, _>>()?;
+                self.call(callee, arguments, *line)
+            }
+            ExprNode::Index {
+                object,
+                index,
+                line,
+            } => {
+                let object = self.eval_node(ast, *object)?;
+                let index = self.eval_node(ast, *index)?;
+                Interpreter::index(object, index, *line)
+            }
+            ExprNode::SetIndex {
+                object,
+                index,
+                value,
+                line,
+            } => {
+                let object = self.eval_node(ast, *object)?;
+                self.eval_node(ast, *index)?;
+                self.eval_node(ast, *value)?;
+                Interpreter::set_index(object, *line)
+            }
+            ExprNode::Range {
+                start,
+                end,
+                inclusive,
+            } => {
+                let start = self.eval_node(ast, *start)?;
+                let end = self.eval_node(ast, *end)?;
+                Interpreter::range(start, end, *inclusive)
+            }
+            ExprNode::Dbg { expr, span, line } => {
+                let value = self.eval_node(ast, *expr)?;
+                self.print_dbg(*span, *line, &value, || ast.to_expr(*expr).to_string())?;
+                Ok(value)
+            }
+            ExprNode::Error(_) => Err(RuntimeError::InvalidProgram),
+        }
+    }
+
+    fn check_equality(
+        &mut self,
+        operator: BinaryOperator,
+        span: Span,
+        left: &Value,
+        right: &Value,
+    ) {
+        if !self.options.warn_cross_type_equality
+            || !matches!(
+                operator,
+                BinaryOperator::EqualEqual | BinaryOperator::NotEqual
+            )
+            || *left == Value::Nil
+            || *right == Value::Nil
+        {
+            return;
+        }
+        let types = (left.type_name(), right.type_name());
+        if types.0 == types.1 || !self.warned.insert(span) {
+            return;
+        }
+        self.warnings.push(Diagnostic {
+            code: Code::CrossTypeEquality,
+            severity: Severity::Warning,
+            message: format!(
+                "'{}' compares a {} with a {}, values of different types are never equal.",
+                operator, types.0, types.1
+            ),
+            line: Some(self.line),
+            span: Some(span),
+            file: None,
+        });
+    }
+
+    // Before concatenating, so that the string over the limit is never made.
+    fn check_size(
+        &self,
+        operator: BinaryOperator,
+        left: &Value,
+        right: &Value,
+    ) -> Result<(), RuntimeError> {
+        if let (BinaryOperator::Plus, Value::String(s1), Value::String(s2)) =
+            (operator, left, right)
+        {
+            let size = s1.len().saturating_add(s2.len());
+            let limit = self.options.max_string_len;
+            if size > limit {
+                return Err(RuntimeError::SizeLimit { size, limit });
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn binary(
+        operator: BinaryOperator,
+        left: Value,
+        right: Value,
+    ) -> Result<Value, RuntimeError> {
+        // Foreign values only know how to be compared, and the host will
+        // want to see which of its types ended up in the arithmetic.
+        if let (Value::Foreign(foreign), _) | (_, Value::Foreign(foreign)) = (&left, &right) {
+            if !matches!(
+                operator,
+                BinaryOperator::EqualEqual | BinaryOperator::NotEqual
+            ) {
+                return Err(RuntimeError::TypeError(format!(
+                    "Can't use a foreign {} with '{}'.",
+                    foreign.name, operator
+                )));
+            }
+        }
+        match operator {
+            BinaryOperator::Minus => {
+                if let (Value::Number(n1), Value::Number(n2)) = (left, right) {
+                    Ok(Value::Number(n1 - n2))
+                } else {
+                    Err(RuntimeError::TypeError(
+                        "You can only substract numbers".to_string(),
+                    ))
+                }
+            }
+            BinaryOperator::Plus => match (left, right) {
+                (Value::Number(n1), Value::Number(n2)) => Ok(Value::Number(n1 + n2)),
+                (Value::String(s1), Value::String(s2)) => Ok(Value::from(format!("{}{}", s1, s2))),
+                _ => Err(RuntimeError::TypeError(
+                    "You can only add strings or numbers".to_string(),
+                )),
+            },
+            BinaryOperator::Multiply => {
+                if let (Value::Number(n1), Value::Number(n2)) = (left, right) {
+                    Ok(Value::Number(n1 * n2))
+                } else {
+                    Err(RuntimeError::TypeError(
+                        "You can only multiply numbers".to_string(),
+                    ))
+                }
+            }
+            BinaryOperator::Divide => {
+                if let (Value::Number(n1), Value::Number(n2)) = (left, right) {
+                    Ok(Value::Number(n1 / n2))
+                } else {
+                    Err(RuntimeError::TypeError(
+                        "You can only multiply numbers".to_string(),
+                    ))
+                }
+            }
+            BinaryOperator::GreaterThan => {
+                if let (Value::Number(n1), Value::Number(n2)) = (left, right) {
+                    Ok(Value::Boolean(n1 > n2))
+                } else {
+                    Err(RuntimeError::TypeError(
+                        "You can only multiply numbers".to_string(),
+                    ))
+                }
+            }
+            BinaryOperator::LessThan => {
+                if let (Value::Number(n1), Value::Number(n2)) = (left, right) {
+                    Ok(Value::Boolean(n1 < n2))
+                } else {
+                    Err(RuntimeError::TypeError(
+                        "You can only multiply numbers".to_string(),
+                    ))
+                }
+            }
+            BinaryOperator::GreaterThanEqual => {
+                if let (Value::Number(n1), Value::Number(n2)) = (left, right) {
+                    Ok(Value::Boolean(n1 >= n2))
+                } else {
+                    Err(RuntimeError::TypeError(
+                        "You can only multiply numbers".to_string(),
+                    ))
+                }
+            }
+            BinaryOperator::LessThanEqual => {
+                if let (Value::Number(n1), Value::Number(n2)) = (left, right) {
+                    Ok(Value::Boolean(n1 <= n2))
+                } else {
+                    Err(RuntimeError::TypeError(
+                        "You can only multiply numbers".to_string(),
+                    ))
+                }
+            }
+            //What happens in the case of non-primitive values?
+            BinaryOperator::EqualEqual => Ok(Value::Boolean(left == right)),
+            BinaryOperator::NotEqual => Ok(Value::Boolean(left != right)),
+        }
+    }
+
+    fn literal(literal: &Literal) -> Value {
+        match literal {
+            Literal::String(s) => Value::String(Arc::clone(s)),
+            Literal::Number(n) => Value::Number(*n),
+            Literal::Boolean(b) => Value::Boolean(*b),
+            Literal::Nil => Value::Nil,
+        }
+    }
+
+    pub(crate) fn unary(operator: UnaryOperator, right: Value) -> Result<Value, RuntimeError> {
+        match operator {
+            UnaryOperator::Minus => {
+                if let Value::Number(n) = right {
+                    Ok(Value::Number(-n))
+                } else if let Value::Foreign(foreign) = right {
+                    Err(RuntimeError::TypeError(format!(
+                        "Can't negate a foreign {}.",
+                        foreign.name
+                    )))
+                } else {
+                    Err(RuntimeError::TypeError(
+                        "You can only negate a number".to_string(),
+                    ))
+                }
+            }
+            UnaryOperator::Not => Ok(Value::Boolean(!Interpreter::is_truthy(&right))),
+        }
+    }
+
+    // Only nil and false are false. Public so that embedders and the `bool`
+    // native agree with the language.
+    pub fn is_truthy(right: &Value) -> bool {
+        match right {
+            Value::Nil => false,
+            Value::Boolean(b) => *b,
+            _ => true,
+        }
+    }
+    pub(crate) fn condition(&self, value: &Value) -> Result<bool, RuntimeError> {
+        match value {
+            Value::Boolean(b) => Ok(*b),
+            _ if self.options.strict_conditions => Err(RuntimeError::TypeError(format!(
+                "Condition must be true or false, got {}.",
+                fmt_value_for_error(value)
+            ))),
+            _ => Ok(Interpreter::is_truthy(value)),
+        }
+    }
+
+    // Runs a program without leaving anything behind: its variables and
+    // what it assigned to globals are gone afterwards, and fuel and the
+    // output limit start over. Natives and the prelude are shared, so this
+    // is much cheaper than a new interpreter for every program. Returns the
+    // value of the last statement when it's an expression.
+    pub fn run_isolated(&mut self, source: &str) -> Result<Option<Value>, RoxyError> {
+        let mut statements = crate::parse(source)?;
+        self.load_prelude()?;
+        self.set_source(source);
+        let last = match statements.last() {
+            Some(Stmt::Expression { .. }) => statements.pop(),
+            _ => None,
+        };
+        self.fuel = self.options.fuel;
+        self.output_bytes = 0;
+        self.environment.isolate();
+        let result = self
+            .interpret_ast(&Ast::from_stmts(&statements))
+            .and_then(|()| match last {
+                Some(Stmt::Expression { expr, line, .. }) => {
+                    self.line = line;
+                    self.eval(&expr).map(Some)
+                }
+                _ => Ok(None),
+            });
+        self.environment.end_isolation();
+        Ok(result?)
+    }
+
+    pub fn interpret(&mut self, statements: Vec<Stmt>) -> Result<(), RuntimeError> {
+        // Nothing runs, not even the statements before the first error.
+        if statements.iter().any(Stmt::has_errors) {
+            return Err(RuntimeError::InvalidProgram);
+        }
+        self.load_prelude()?;
+        if let Some(coverage) = &mut self.coverage {
+            coverage.add_program(&statements);
+        }
+        for stmt in &statements {
+            self.execute(stmt)?;
+        }
+        Ok(())
+    }
+
+    // Runs `InterpreterOptions::prelude` unless it already ran. `interpret`
+    // does this itself, hosts that only `eval` have to call it first. A
+    // prelude that fails is tried again on the next run.
+    pub fn load_prelude(&mut self) -> Result<(), RuntimeError> {
+        let Some(prelude) = self
+            .options
+            .prelude
+            .as_ref()
+            .filter(|_| !self.prelude_loaded)
+        else {
+            return Ok(());
+        };
+        let statements =
+            crate::parse(prelude).map_err(|err| RuntimeError::Prelude(err.to_string()))?;
+        let fuel = self.fuel.take();
+        let stats = self.stats.take();
+        let coverage = self.coverage.take();
+        let source = self.source.replace(Arc::from(prelude.as_str()));
+        let result = statements.iter().try_for_each(|stmt| self.execute(stmt));
+        self.fuel = fuel;
+        self.stats = stats;
+        self.coverage = coverage;
+        self.source = source;
+        result.map_err(|err| {
+            RuntimeError::Prelude(format!(
+                "{} [line {}]",
+                err,
+                err.line().unwrap_or(self.line)
+            ))
+        })?;
+        self.prelude_loaded = true;
+        Ok(())
+    }
+
+    fn execute(&mut self, stmt: &Stmt) -> Result<(), RuntimeError> {
+        self.check_cancelled()?;
+        self.burn_fuel()?;
+        self.count(|stats| stats.statements += 1);
+        self.line = stmt.line();
+        if let Some(coverage) = &mut self.coverage {
+            coverage.hit(stmt.span(), self.line);
+        }
+        if self.trace.is_some() {
+            let header = Interpreter::trace_header(stmt);
+            self.trace(format_args!("{}", header));
+        }
+        if let Some(hook) = &mut self.step_hook {
+            hook(stmt, &self.environment.snapshot());
+        }
+        match stmt {
+            Stmt::Print { exprs, .. } => {
+                let values = exprs
+                    .iter()
+                    .map(|expr| self.eval(expr))
+                    .collect::<Result<Vec<_>, _>>()?;
+                self.print_value(Interpreter::print_line(values))?;
+            }
+            Stmt::Expression { expr, .. } => {
+                //This has no side-effect as far as i can see
+                //so why do we do this?
+                let _ = self.eval(expr)?;
+            }
+            Stmt::Throw { expr, line, .. } => {
+                let value = self.eval(expr)?;
+                return Err(RuntimeError::Thrown { value, line: *line });
+            }
+            Stmt::Error { .. } => return Err(RuntimeError::InvalidProgram),
+            Stmt::ForIn {
+                name,
+                iterable,
+                body,
+                ..
+            } => {
+                let iterable = self.eval(iterable)?;
+                for i in Interpreter::range_values(&iterable)? {
+                    self.count(|stats| stats.loop_iterations += 1);
+                    self.environment.push_scope();
+                    self.define(name, Value::Number(i as f64));
+                    let result = self.execute(body);
+                    self.environment.pop_scope();
+                    result?;
+                }
+            }
+            Stmt::Var {
+                name, initializer, ..
+            } => {
+                let value = self.eval(initializer)?;
+                self.define(name, value);
+            }
+            Stmt::Block { statements, .. } => {
+                self.environment.push_scope();
+                let result = statements.iter().try_for_each(|stmt| self.execute(stmt));
+                self.environment.pop_scope();
+                result?;
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                let condition = self.eval(condition)?;
+                if self.condition(&condition)? {
+                    self.execute(then_branch)?;
+                } else if let Some(else_branch) = else_branch {
+                    self.execute(else_branch)?;
+                }
+            }
+            Stmt::Try {
+                body,
+                catch,
+                finally,
+                ..
+            } => {
+                let mut result = self.execute(body);
+                if let Some((name, handler)) = catch {
+                    result = match result {
+                        Err(err) if err.is_catchable() => {
+                            self.catch(name, err, |interpreter| interpreter.execute(handler))
                         }
-                    }
-                    UnaryOperator::Not => Value::Boolean(!Interpreter::is_truthy(&right)),
+                        result => result,
+                    };
+                }
+                if let Some(finally) = finally {
+                    // Errors still being raised are reported where they happened.
+                    let line = self.line;
+                    self.execute(finally)?;
+                    self.line = line;
+                }
+                result?;
+            }
+        };
+        Ok(())
+    }
+
+    // Compound statements only show their first line, their bodies are traced
+    // statement by statement.
+    fn trace_header(stmt: &Stmt) -> String {
+        match stmt {
+            Stmt::Expression { expr, .. } => format!("{};", expr),
+            Stmt::Print { exprs, .. } => {
+                let exprs: Vec<String> = exprs.iter().map(Expr::to_string).collect();
+                format!("print {};", exprs.join(", "))
+            }
+            Stmt::Throw { expr, .. } => format!("throw {};", expr),
+            Stmt::ForIn { name, iterable, .. } => format!("for ({} in {})", name, iterable),
+            Stmt::Var {
+                name, initializer, ..
+            } => format!("var {} = {};", name, initializer),
+            Stmt::Block { .. } => "{".to_string(),
+            Stmt::If { condition, .. } => format!("if ({})", condition),
+            Stmt::Try { .. } => "try".to_string(),
+            Stmt::Error { .. } => "<error>".to_string(),
+        }
+    }
+
+    fn trace_header_node(ast: &Ast, id: StmtId) -> String {
+        let expr = |id| ast.to_expr(id);
+        match ast.stmt(id) {
+            StmtNode::Expression { expr: e, .. } => format!("{};", expr(*e)),
+            StmtNode::Print { exprs, .. } => {
+                let exprs: Vec<String> = exprs.iter().map(|e| expr(*e).to_string()).collect();
+                format!("print {};", exprs.join(", "))
+            }
+            StmtNode::Throw { expr: e, .. } => format!("throw {};", expr(*e)),
+            StmtNode::ForIn { name, iterable, .. } => {
+                format!("for ({} in {})", name, expr(*iterable))
+            }
+            StmtNode::Var {
+                name, initializer, ..
+            } => format!("var {} = {};", name, expr(*initializer)),
+            StmtNode::Block { .. } => "{".to_string(),
+            StmtNode::If { condition, .. } => format!("if ({})", expr(*condition)),
+            StmtNode::Try { .. } => "try".to_string(),
+            StmtNode::Error { .. } => "<error>".to_string(),
+        }
+    }
+
+    // The error is bound to `name` in a scope of its own around the handler.
+    fn catch(
+        &mut self,
+        name: &str,
+        err: RuntimeError,
+        handler: impl FnOnce(&mut Self) -> Result<(), RuntimeError>,
+    ) -> Result<(), RuntimeError> {
+        self.environment.push_scope();
+        self.define(name, Interpreter::caught(err));
+        let result = handler(self);
+        self.environment.pop_scope();
+        result
+    }
+
+    // Thrown values are caught as they are, other errors as their message.
+    pub(crate) fn caught(err: RuntimeError) -> Value {
+        match err {
+            RuntimeError::Thrown { value, .. } => value,
+            err => Value::from(err.to_string()),
+        }
+    }
+
+    pub fn interpret_ast(&mut self, ast: &Ast) -> Result<(), RuntimeError> {
+        if ast.has_errors() {
+            return Err(RuntimeError::InvalidProgram);
+        }
+        self.load_prelude()?;
+        if let Some(coverage) = &mut self.coverage {
+            coverage.add_ast(ast);
+        }
+        for root in ast.roots() {
+            self.execute_node(ast, *root)?;
+        }
+        Ok(())
+    }
+
+    fn execute_node(&mut self, ast: &Ast, id: StmtId) -> Result<(), RuntimeError> {
+        self.check_cancelled()?;
+        self.burn_fuel()?;
+        self.count(|stats| stats.statements += 1);
+        self.line = ast.stmt(id).line();
+        if let Some(coverage) = &mut self.coverage {
+            coverage.hit(ast.stmt(id).span(), self.line);
+        }
+        if self.trace.is_some() {
+            let header = Interpreter::trace_header_node(ast, id);
+            self.trace(format_args!("{}", header));
+        }
+        if let Some(hook) = &mut self.step_hook {
+            // Hooks take the boxed statement, only built when there is one.
+            hook(&ast.to_stmt(id), &self.environment.snapshot());
+        }
+        match ast.stmt(id) {
+            StmtNode::Print { exprs, .. } => {
+                let values = exprs
+                    .iter()
+                    .map(|expr| self.eval_node(ast, *expr))
+                    .collect::<Result<Vec<_>, _>>()?;
+                self.print_value(Interpreter::print_line(values))?;
+            }
+            StmtNode::Expression { expr, .. } => {
+                let _ = self.eval_node(ast, *expr)?;
+            }
+            StmtNode::Throw { expr, line, .. } => {
+                let value = self.eval_node(ast, *expr)?;
+                return Err(RuntimeError::Thrown { value, line: *line });
+            }
+            StmtNode::Error { .. } => return Err(RuntimeError::InvalidProgram),
+            StmtNode::ForIn {
+                name,
+                iterable,
+                body,
+                ..
+            } => {
+                let iterable = self.eval_node(ast, *iterable)?;
+                for i in Interpreter::range_values(&iterable)? {
+                    self.count(|stats| stats.loop_iterations += 1);
+                    self.environment.push_scope();
+                    self.define(name, Value::Number(i as f64));
+                    let result = self.execute_node(ast, *body);
+                    self.environment.pop_scope();
+                    result?;
+                }
+            }
+            StmtNode::Var {
+                name, initializer, ..
+            } => {
+                let value = self.eval_node(ast, *initializer)?;
+                self.define(name, value);
+            }
+            StmtNode::Block { statements, .. } => {
+                self.environment.push_scope();
+                let result = statements
+                    .iter()
+                    .try_for_each(|stmt| self.execute_node(ast, *stmt));
+                self.environment.pop_scope();
+                result?;
+            }
+            StmtNode::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                let condition = self.eval_node(ast, *condition)?;
+                if self.condition(&condition)? {
+                    self.execute_node(ast, *then_branch)?;
+                } else if let Some(else_branch) = else_branch {
+                    self.execute_node(ast, *else_branch)?;
+                }
+            }
+            StmtNode::Try {
+                body,
+                catch,
+                finally,
+                ..
+            } => {
+                let mut result = self.execute_node(ast, *body);
+                if let Some((name, handler)) = catch {
+                    result = match result {
+                        Err(err) if err.is_catchable() => self.catch(name, err, |interpreter| {
+                            interpreter.execute_node(ast, *handler)
+                        }),
+                        result => result,
+                    };
                 }
+                if let Some(finally) = finally {
+                    let line = self.line;
+                    self.execute_node(ast, *finally)?;
+                    self.line = line;
+                }
+                result?;
             }
+        };
+        Ok(())
+    }
+
+    // What `print a, b;` prints, the values separated by spaces. A single
+    // value is passed on as it is, for print handlers that look at it.
+    pub(crate) fn print_line(mut values: Vec<Value>) -> Value {
+        if values.len() == 1 {
+            return values.remove(0);
         }
+        let line: Vec<String> = values.iter().map(Value::to_string).collect();
+        Value::from(line.join(" "))
     }
-    fn is_truthy(right: &Value) -> bool {
-        match right {
-            Value::Nil => false,
-            Value::Boolean(b) => *b,
-            _ => true,
+
+    // `[line 3] a + b = 5`, with the expression as it was written when the
+    // source is known and printed back from the AST otherwise.
+    pub(crate) fn print_dbg(
+        &mut self,
+        span: Span,
+        line: usize,
+        value: &Value,
+        unparse: impl FnOnce() -> String,
+    ) -> Result<(), RuntimeError> {
+        let text = self
+            .source
+            .as_deref()
+            .and_then(|source| source.get(span.start..span.end))
+            .map_or_else(unparse, str::to_string);
+        self.print_value(Value::from(format!(
+            "[line {}] {} = {}",
+            line,
+            text,
+            value.repr()
+        )))
+    }
+
+    pub(crate) fn print_value(&mut self, value: Value) -> Result<(), RuntimeError> {
+        let Some(limit) = self.options.max_output_bytes else {
+            self.emit(value);
+            return Ok(());
+        };
+        let text = value.to_string();
+        let left = limit.saturating_sub(self.output_bytes);
+        if text.len() <= left {
+            self.output_bytes += text.len();
+            self.emit(value);
+            return Ok(());
         }
+        let mut end = left;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        self.output_bytes = limit;
+        if end > 0 {
+            self.emit(Value::from(&text[..end]));
+        }
+        Err(RuntimeError::OutputLimit(limit))
     }
-    pub fn interpret(statements: Vec<Stmt>) {
-        for stmt in statements {
-            Interpreter::execute(stmt);
+
+    fn emit(&mut self, value: Value) {
+        match &mut self.print_handler {
+            Some(handler) => handler(value),
+            None => Interpreter::print(value),
         }
     }
 
-    fn execute(stmt: Stmt) {
-        match stmt {
-            Stmt::Print(expr) => {
-                let value = Interpreter::eval(&expr);
-                match value {
-                    Value::String(v) => println!("{}", v),
-                    Value::Boolean(v) => println!("{}", v),
-                    Value::Nil => println!("nil"),
-                    Value::Number(v) => println!("{}", v),
-                }
-            }
-            Stmt::Expression(expr) => {
-                //This has no side-effect as far as i can see
-                //so why do we do this?
-                let _ = Interpreter::eval(&expr);
-            }
-            Stmt::Var { name, initializer } => todo!(),
-            Stmt::Block(_) => todo!(),
-            _ => todo!(),
-        };
+    pub(crate) fn print(value: Value) {
+        println!("{}", value);
+    }
+}
+
+// Levenshtein distance, counted in chars.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
     }
+    previous[b.len()]
 }
 
 #[cfg(test)]
 mod interpreter_tests {
-    use super::{Interpreter, Value};
+    use std::{
+        io::Write,
+        sync::{atomic::Ordering, mpsc, Arc, Mutex},
+        thread,
+        time::Duration,
+    };
+
+    use super::{
+        fmt_value_for_error, fmt_value_for_error_in, format_fixed, format_precision, Interpreter,
+        InterpreterOptions, RoxyError, RuntimeError, Value,
+    };
     use crate::{
-        parser::{Expr, Literal, Parser, Unary, UnaryOperator},
+        arena::Ast,
+        diagnostics::Span,
+        parser::{Binary, BinaryOperator, Expr, Literal, Parser, Stmt, Unary, UnaryOperator},
         scanner::Scanner,
+        stats::Stats,
     };
 
     #[test]
     fn number_literal() {
         let expr = Expr::Literal(Literal::Number(123.2));
-        let value = Interpreter::eval(&expr);
+        let value = Interpreter::new().eval(&expr).unwrap();
         assert_eq!(value, Value::Number(123.2));
     }
 
     #[test]
     fn string_literal() {
-        let expr = Expr::Literal(Literal::String("string".to_string()));
-        let value = Interpreter::eval(&expr);
-        assert_eq!(value, Value::String("string".to_string()));
+        let expr = Expr::Literal(Literal::String("string".into()));
+        let value = Interpreter::new().eval(&expr).unwrap();
+        assert_eq!(value, Value::from("string"));
+    }
+
+    #[test]
+    fn repr() {
+        let values = [
+            Value::from("say \"hi\"\n"),
+            Value::from("tab\there\\"),
+            Value::Nil,
+            Value::Number(1.5),
+            Value::Range {
+                start: 0,
+                end: 3,
+                inclusive: true,
+            },
+        ];
+        assert_eq!(
+            values.iter().map(Value::repr).collect::<Vec<_>>(),
+            [
+                r#""say \"hi\"\n""#,
+                r#""tab\there\\""#,
+                "nil",
+                "1.5",
+                "0..=3"
+            ]
+        );
+        assert_eq!(values[0].to_string(), "say \"hi\"\n");
     }
 
     #[test]
     fn bool_literal() {
         let expr = Expr::Literal(Literal::Boolean(false));
-        let value = Interpreter::eval(&expr);
+        let value = Interpreter::new().eval(&expr).unwrap();
         assert_eq!(value, Value::Boolean(false));
     }
 
     #[test]
     fn nil_literal() {
         let expr = Expr::Literal(Literal::Nil);
-        let value = Interpreter::eval(&expr);
+        let value = Interpreter::new().eval(&expr).unwrap();
         assert_eq!(value, Value::Nil);
     }
 
@@ -179,7 +1779,7 @@ mod interpreter_tests {
             right: Box::new(Expr::Literal(Literal::Number(42.0))),
         });
 
-        let val = Interpreter::eval(&expr);
+        let val = Interpreter::new().eval(&expr).unwrap();
         assert_eq!(val, Value::Number(-42.0));
     }
 
@@ -190,7 +1790,7 @@ mod interpreter_tests {
             right: Box::new(Expr::Literal(Literal::Boolean(false))),
         });
 
-        let val = Interpreter::eval(&expr);
+        let val = Interpreter::new().eval(&expr).unwrap();
         assert_eq!(val, Value::Boolean(true));
     }
 
@@ -198,21 +1798,1337 @@ mod interpreter_tests {
     fn binary_expression() {
         let source = "(5 - (3 - 1)) + -1".to_string();
         let mut scanner = Scanner::new(source);
-        let tokens = scanner.scan_tokens();
+        let tokens = scanner.scan_tokens().unwrap();
         let mut parser = Parser::new(tokens.clone());
-        let expr = parser.expression();
-        let val = Interpreter::eval(&expr);
+        let expr = parser.expression().unwrap();
+        let val = Interpreter::new().eval(&expr).unwrap();
         assert_eq!(val, Value::Number(2.0));
     }
 
     #[test]
-    #[should_panic]
     fn incorrect_binary_expression() {
         let source = r#"2 * (3 / -"muffin")"#.to_string();
         let mut scanner = Scanner::new(source);
-        let tokens = scanner.scan_tokens();
+        let tokens = scanner.scan_tokens().unwrap();
+        let mut parser = Parser::new(tokens.clone());
+        let expr = parser.expression().unwrap();
+        assert_eq!(
+            Interpreter::new().eval(&expr),
+            Err(RuntimeError::TypeError(
+                "You can only negate a number".to_string()
+            ))
+        );
+    }
+
+    fn program(source: &str) -> Vec<Stmt> {
+        let mut scanner = Scanner::new(source.to_string());
+        let tokens = scanner.scan_tokens().unwrap();
         let mut parser = Parser::new(tokens.clone());
-        let expr = parser.expression();
-        let _ = Interpreter::eval(&expr);
+        parser.parse().unwrap()
+    }
+
+    #[test]
+    fn runs_out_of_fuel() {
+        let statements = program(&"1 + 2 * 3;".repeat(100));
+        let mut interpreter = Interpreter::with_fuel(100);
+        assert_eq!(
+            interpreter.interpret(statements),
+            Err(RuntimeError::OutOfFuel)
+        );
+        assert_eq!(interpreter.fuel(), Some(0));
+    }
+
+    #[test]
+    fn long_strings_cost_fuel() {
+        let source = r#"var s = "ab"; for (i in 0..64) s = s + s;"#;
+        let mut interpreter = Interpreter::with_fuel(10_000);
+        assert_eq!(
+            interpreter.interpret(program(source)),
+            Err(RuntimeError::OutOfFuel)
+        );
+        match interpreter.get_global("s") {
+            Some(Value::String(s)) => assert!(s.len() < 1 << 24, "{}", s.len()),
+            s => panic!("{:?}", s),
+        }
+    }
+
+    #[test]
+    fn fuel_to_spare() {
+        let statements = program("1 + 2 * 3; -4 == 4;");
+        let mut interpreter = Interpreter::with_fuel(100);
+        assert_eq!(interpreter.interpret(statements), Ok(()));
+        // 2 statements, 5 + 4 expressions
+        assert_eq!(interpreter.fuel(), Some(89));
+    }
+
+    #[test]
+    fn fuel_is_unlimited_by_default() {
+        let statements = program(&"1 + 2 * 3;".repeat(100));
+        let mut interpreter = Interpreter::new();
+        assert_eq!(interpreter.fuel(), None);
+        assert_eq!(interpreter.interpret(statements), Ok(()));
+        assert_eq!(interpreter.fuel(), None);
+    }
+
+    fn sandboxed(options: InterpreterOptions) -> (Interpreter, Arc<Mutex<Vec<Value>>>) {
+        let printed = Arc::new(Mutex::new(Vec::new()));
+        let mut interpreter = Interpreter::with_options(options);
+        let sink = Arc::clone(&printed);
+        interpreter.set_print_handler(move |value| sink.lock().unwrap().push(value));
+        (interpreter, printed)
+    }
+
+    #[test]
+    fn default_options() {
+        let interpreter = Interpreter::new();
+        assert_eq!(interpreter.options(), &InterpreterOptions::default());
+        assert!(interpreter.get_global("clock").is_some());
+        assert!(interpreter.get_global("sleep").is_some());
+        assert_eq!(interpreter.fuel(), None);
+    }
+
+    #[test]
+    fn options_fuel() {
+        let options = InterpreterOptions {
+            fuel: Some(20),
+            ..InterpreterOptions::default()
+        };
+        let mut interpreter = Interpreter::with_options(options.clone());
+        assert_eq!(
+            interpreter.interpret(program(&"1 + 2;".repeat(10))),
+            Err(RuntimeError::OutOfFuel)
+        );
+        // What was asked for, not what is left.
+        assert_eq!(interpreter.options(), &options);
+        assert_eq!(interpreter.fuel(), Some(0));
+    }
+
+    #[test]
+    fn options_string_size() {
+        let doubling = "var s = \"x\"; for (i in 0..100) { try { s = s + s; } catch (e) {} }";
+        let options = InterpreterOptions {
+            fuel: Some(1_000_000),
+            max_string_len: 1 << 20,
+            ..InterpreterOptions::default()
+        };
+        let limit = Err(RuntimeError::SizeLimit {
+            size: 1 << 21,
+            limit: 1 << 20,
+        });
+        let mut interpreter = Interpreter::with_options(options.clone());
+        assert_eq!(interpreter.interpret(program(doubling)), limit);
+        assert!(interpreter.fuel().unwrap() > 900_000);
+        let mut interpreter = Interpreter::with_options(options);
+        let ast = Ast::from_stmts(&program(doubling));
+        assert_eq!(interpreter.interpret_ast(&ast), limit);
+        let mut interpreter = Interpreter::with_options(InterpreterOptions::sandbox());
+        assert_eq!(interpreter.interpret(program(doubling)), limit);
+
+        let (mut interpreter, printed) = sandboxed(InterpreterOptions {
+            max_string_len: 4,
+            ..InterpreterOptions::default()
+        });
+        assert_eq!(
+            interpreter.interpret(program(r#"print "ab" + "cd";"#)),
+            Ok(())
+        );
+        assert_eq!(*printed.lock().unwrap(), [Value::from("abcd")]);
+        assert_eq!(
+            interpreter.interpret(program(r#"print "ab" + "cde";"#)),
+            Err(RuntimeError::SizeLimit { size: 5, limit: 4 })
+        );
+        let natives = [
+            (r#"repeat("ab", 2);"#, None),
+            (
+                r#"repeat("ab", 3);"#,
+                Some("repeat() would make a string of 6 bytes"),
+            ),
+            (r#"padStart("a", 4, "-");"#, None),
+            (
+                r#"padStart("a", 5, "-");"#,
+                Some("padStart() would make a string of 5 bytes"),
+            ),
+            (r#"replace("aa", "a", "bb");"#, None),
+            (
+                r#"replace("aa", "a", "bbb");"#,
+                Some("replace() would make a string of 6 bytes"),
+            ),
+            (r#"format("{}{}", "ab", "cd");"#, None),
+            (
+                r#"format("{}{}", "ab", "cde");"#,
+                Some("format() would make a string of 5 bytes"),
+            ),
+        ];
+        for (source, expected) in natives {
+            let result = interpreter.interpret(program(source));
+            match expected {
+                None => assert_eq!(result, Ok(()), "{}", source),
+                Some(message) => assert!(
+                    result
+                        .as_ref()
+                        .unwrap_err()
+                        .to_string()
+                        .starts_with(message),
+                    "{}: {:?}",
+                    source,
+                    result
+                ),
+            }
+        }
+    }
+
+    #[test]
+    fn options_clock() {
+        let (mut interpreter, printed) = sandboxed(InterpreterOptions::default());
+        interpreter
+            .interpret(program("print clock() > 0;"))
+            .unwrap();
+        assert_eq!(*printed.lock().unwrap(), [Value::Boolean(true)]);
+        // Still there after a reset, like the other natives.
+        interpreter.reset();
+        assert!(interpreter.get_global("clock").is_some());
+        let (interpreter, _) = sandboxed(InterpreterOptions::sandbox());
+        assert!(interpreter.get_global("clock").is_none());
+    }
+
+    #[test]
+    fn options_call_depth() {
+        let (mut interpreter, printed) = sandboxed(InterpreterOptions {
+            max_call_depth: 0,
+            ..InterpreterOptions::default()
+        });
+        assert_eq!(
+            interpreter.interpret(program("print 1; print len(\"ab\");")),
+            Err(RuntimeError::CallError {
+                message: "Stack overflow.".to_string(),
+                line: 1
+            })
+        );
+        assert_eq!(*printed.lock().unwrap(), [Value::Number(1.0)]);
+        let (mut interpreter, _) = sandboxed(InterpreterOptions {
+            max_call_depth: 1,
+            ..InterpreterOptions::default()
+        });
+        assert_eq!(
+            interpreter.interpret(program("len(str(len(\"ab\")));")),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn output_limit() {
+        let (mut interpreter, printed) = sandboxed(InterpreterOptions {
+            max_output_bytes: Some(8),
+            ..InterpreterOptions::default()
+        });
+        let result = interpreter.interpret(program(
+            "print \"abc\"; print 12; try { print \"defgh\"; } catch (e) {} print 4;",
+        ));
+        assert_eq!(result, Err(RuntimeError::OutputLimit(8)));
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Output limit of 8 bytes exceeded."
+        );
+        // The third print is cut off in the middle and nothing after it runs.
+        assert_eq!(
+            *printed.lock().unwrap(),
+            [Value::from("abc"), Value::Number(12.0), Value::from("def"),]
+        );
+        // Everything was used up, the next print fails straight away.
+        assert_eq!(
+            interpreter.interpret(program("print \"\"; print 1;")),
+            Err(RuntimeError::OutputLimit(8))
+        );
+        assert_eq!(printed.lock().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn output_limit_splits_on_chars() {
+        let (mut interpreter, printed) = sandboxed(InterpreterOptions {
+            max_output_bytes: Some(4),
+            ..InterpreterOptions::default()
+        });
+        let ast = Ast::from_stmts(&program("print \"aé€\";"));
+        assert_eq!(
+            interpreter.interpret_ast(&ast),
+            Err(RuntimeError::OutputLimit(4))
+        );
+        assert_eq!(*printed.lock().unwrap(), [Value::from("aé")]);
+    }
+
+    #[test]
+    fn cancelled_before_first_statement() {
+        let mut interpreter = Interpreter::with_fuel(100);
+        interpreter.cancel_token().store(true, Ordering::Relaxed);
+        assert_eq!(
+            interpreter.interpret(program("1 + 2;")),
+            Err(RuntimeError::Cancelled)
+        );
+        assert_eq!(interpreter.fuel(), Some(100));
+    }
+
+    #[test]
+    fn cancel_from_another_thread() {
+        let statements = program(&"1 + 2 * 3;".repeat(100));
+        let mut interpreter = Interpreter::new();
+        let token = interpreter.cancel_token();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || loop {
+            if let Err(err) = interpreter.interpret(statements.clone()) {
+                sender.send(err).unwrap();
+                break;
+            }
+        });
+        thread::sleep(Duration::from_millis(10));
+        token.store(true, Ordering::Relaxed);
+        assert_eq!(
+            receiver.recv_timeout(Duration::from_secs(5)),
+            Ok(RuntimeError::Cancelled)
+        );
+    }
+
+    #[test]
+    fn variables_and_scopes() {
+        let mut interpreter = Interpreter::new();
+        let statements = program(
+            r#"
+            var a = 1;
+            var b;
+            {
+                var a = 10;
+                b = a;
+            }
+            a = a + b;
+            "#,
+        );
+        assert_eq!(interpreter.interpret(statements), Ok(()));
+        let a = Expr::Variable("a".to_string());
+        assert_eq!(interpreter.eval(&a), Ok(Value::Number(11.0)));
+    }
+
+    #[test]
+    fn undefined_variable() {
+        let mut interpreter = Interpreter::new();
+        assert_eq!(
+            interpreter.interpret(program("{ var a = 1; } print a;")),
+            Err(RuntimeError::UndefinedVariable {
+                name: "a".to_string(),
+                suggestion: None
+            })
+        );
+        assert_eq!(
+            interpreter.interpret(program("b = 1;")),
+            Err(RuntimeError::UndefinedVariable {
+                name: "b".to_string(),
+                suggestion: None
+            })
+        );
+    }
+
+    #[test]
+    fn did_you_mean() {
+        let mut interpreter = Interpreter::new();
+        interpreter
+            .interpret(program("var count = 1; var total = 2;"))
+            .unwrap();
+        assert_eq!(
+            interpreter.interpret(program("{ var index = 0; print cuont + indx; }")),
+            Err(RuntimeError::UndefinedVariable {
+                name: "cuont".to_string(),
+                suggestion: Some("count".to_string())
+            })
+        );
+        assert_eq!(
+            interpreter
+                .interpret(program("{ var index = 0; indx = 1; }"))
+                .unwrap_err()
+                .to_string(),
+            "Undefined variable 'indx'. Did you mean 'index'?"
+        );
+        assert_eq!(
+            interpreter.interpret(program("print nothing;")),
+            Err(RuntimeError::UndefinedVariable {
+                name: "nothing".to_string(),
+                suggestion: None
+            })
+        );
+    }
+
+    #[test]
+    fn ambiguous_suggestion() {
+        let mut interpreter = Interpreter::new();
+        interpreter
+            .interpret(program("var cat = 1; var car = 2; var x = 3;"))
+            .unwrap();
+        assert_eq!(
+            interpreter
+                .interpret(program("print cab;"))
+                .unwrap_err()
+                .to_string(),
+            "Undefined variable 'cab'."
+        );
+        assert_eq!(
+            interpreter
+                .interpret(program("print y;"))
+                .unwrap_err()
+                .to_string(),
+            "Undefined variable 'y'."
+        );
+    }
+
+    fn with_double() -> Interpreter {
+        let mut interpreter = Interpreter::new();
+        interpreter.define_native("double", 1, |arguments| match &arguments[0] {
+            Value::Number(n) => Ok(Value::Number(n * 2.0)),
+            _ => Err("double() expects a number.".to_string()),
+        });
+        interpreter
+    }
+
+    #[test]
+    fn native_function() {
+        let mut interpreter = with_double();
+        interpreter
+            .interpret(program("var a = double(double(1) + 1);"))
+            .unwrap();
+        assert_eq!(
+            interpreter.eval(&Expr::Variable("a".to_string())),
+            Ok(Value::Number(6.0))
+        );
+        assert_eq!(
+            interpreter
+                .eval(&Expr::Variable("double".to_string()))
+                .unwrap()
+                .to_string(),
+            "<native double>"
+        );
+    }
+
+    #[test]
+    fn pipe() {
+        let mut interpreter = with_double();
+        interpreter
+            .interpret(program(
+                r#"var a = 4 |> double;
+var b = "{}-{}" |> format(1, 2);
+var c = "abc" |> len |> double |> str;"#,
+            ))
+            .unwrap();
+        assert_eq!(interpreter.get_global("a"), Some(Value::Number(8.0)));
+        assert_eq!(interpreter.get_global("b"), Some(Value::from("1-2")));
+        assert_eq!(interpreter.get_global("c"), Some(Value::from("6")));
+        assert_eq!(
+            interpreter.interpret(program("var d = 1\n  |> double\n  |> 2;")),
+            Err(RuntimeError::CallError {
+                message: "Can only call functions and classes.".to_string(),
+                line: 3
+            })
+        );
+    }
+
+    #[test]
+    fn native_function_errors() {
+        let mut interpreter = with_double();
+        assert_eq!(
+            interpreter.interpret(program("print 1;\ndouble(\n\"two\");")),
+            Err(RuntimeError::CallError {
+                message: "double() expects a number.".to_string(),
+                line: 3
+            })
+        );
+        assert_eq!(
+            interpreter.interpret(program("double(1, 2);")),
+            Err(RuntimeError::CallError {
+                message: "Expected 1 arguments but got 2.".to_string(),
+                line: 1
+            })
+        );
+        assert_eq!(
+            interpreter.interpret(program("\"double\"(1);")),
+            Err(RuntimeError::CallError {
+                message: "Can only call functions and classes.".to_string(),
+                line: 1
+            })
+        );
+    }
+
+    #[test]
+    fn seeded_globals() {
+        let mut interpreter = Interpreter::new();
+        interpreter.define_global("input", Value::Number(20.0));
+        interpreter
+            .interpret(program(
+                "var result = input + 1; input = nil; { var result = 0; }",
+            ))
+            .unwrap();
+        assert_eq!(interpreter.get_global("result"), Some(Value::Number(21.0)));
+        assert_eq!(interpreter.get_global("input"), Some(Value::Nil));
+        assert_eq!(interpreter.get_global("missing"), None);
+
+        interpreter
+            .interpret(program("var input = \"again\";"))
+            .unwrap();
+        assert_eq!(interpreter.get_global("input"), Some(Value::from("again")));
+    }
+
+    #[test]
+    fn call_function() {
+        let mut interpreter = Interpreter::new();
+        interpreter.define_native("double", 1, |arguments| match arguments[0] {
+            Value::Number(n) => Ok(Value::Number(n * 2.0)),
+            _ => Err("double() expects a number.".to_string()),
+        });
+        // Lox has no function declarations yet, so the script picks a native.
+        interpreter
+            .interpret(program("var handler = format; var answer = 42;"))
+            .unwrap();
+        assert!(interpreter.has_function("handler"));
+        assert!(!interpreter.has_function("answer"));
+        assert!(!interpreter.has_function("missing"));
+        assert_eq!(
+            interpreter.call_function(
+                "handler",
+                vec![Value::from("got {}"), Value::from("payload")]
+            ),
+            Ok(Value::from("got payload"))
+        );
+        assert_eq!(
+            interpreter.call_function("double", vec![Value::from(4.0)]),
+            Ok(Value::Number(8.0))
+        );
+        assert_eq!(
+            interpreter
+                .call_function("double", vec![Value::from(true)])
+                .unwrap_err()
+                .to_string(),
+            "double() expects a number."
+        );
+    }
+
+    #[test]
+    fn call_function_errors() {
+        let mut interpreter = Interpreter::new();
+        interpreter.interpret(program("var answer = 42;")).unwrap();
+        assert_eq!(
+            interpreter.call_function("lne", vec![]),
+            Err(RuntimeError::UndefinedVariable {
+                name: "lne".to_string(),
+                suggestion: Some("len".to_string()),
+            })
+        );
+        let message = |result: Result<Value, RuntimeError>| result.unwrap_err().to_string();
+        assert_eq!(
+            message(interpreter.call_function("len", vec![])),
+            "Expected 1 arguments but got 0."
+        );
+        assert_eq!(
+            message(interpreter.call_function("answer", vec![Value::Nil])),
+            "Can only call functions and classes."
+        );
+    }
+
+    #[test]
+    fn step_hook() {
+        let steps = Arc::new(Mutex::new(Vec::new()));
+        let mut interpreter = Interpreter::new();
+        let recorded = Arc::clone(&steps);
+        interpreter.set_step_hook(move |stmt, scopes| {
+            let names: Vec<String> = scopes
+                .iter()
+                .flat_map(|scope| &scope.variables)
+                .filter(|(_, value)| !matches!(value, Value::NativeFunction(_)))
+                .map(|(name, _)| name.clone())
+                .collect();
+            recorded.lock().unwrap().push((stmt.line(), names));
+        });
+        interpreter
+            .interpret(program(
+                "var a = 1;\n{\n  var b = 2;\n  print b;\n}\nprint a;",
+            ))
+            .unwrap();
+        let s = |names: &[&str]| names.iter().map(|name| name.to_string()).collect();
+        assert_eq!(
+            *steps.lock().unwrap(),
+            vec![
+                (1, s(&[])),
+                (2, s(&["a"])),
+                (3, s(&["a"])),
+                (4, s(&["b", "a"])),
+                (6, s(&["a"])),
+            ]
+        );
+    }
+
+    #[test]
+    fn number_formatting() {
+        let cases = [
+            (0.1 + 0.2, "0.30000000000000004"),
+            (1.0, "1"),
+            (-2.5, "-2.5"),
+            (-0.0, "-0"),
+            (100.0, "100"),
+            (123456789012345680000.0, "123456789012345680000"),
+            (1e21, "1e21"),
+            (-1.5e300, "-1.5e300"),
+            (f64::MAX, "1.7976931348623157e308"),
+            (1e-7, "0.0000001"),
+            (1.5e-8, "1.5e-8"),
+            (f64::MIN_POSITIVE, "2.2250738585072014e-308"),
+            (f64::INFINITY, "inf"),
+            (f64::NAN, "NaN"),
+        ];
+        for (number, expected) in cases {
+            assert_eq!(Value::Number(number).to_string(), expected);
+            if number.is_finite() {
+                assert_eq!(expected.parse::<f64>(), Ok(number));
+            }
+        }
+    }
+
+    #[test]
+    fn long_binary_chain() {
+        // The parser only takes `MAX_OPERATORS` in a statement, a host
+        // building its own tree has no such limit.
+        let source = (1..=100_000)
+            .map(|i| i.to_string())
+            .collect::<Vec<_>>()
+            .join(" + ");
+        let err = crate::parse(&format!("{};", source)).unwrap_err();
+        assert!(err
+            .errors()
+            .any(|err| err.message == "Too many operators in one statement."));
+        let mut sum = Expr::Literal(Literal::Number(1.0));
+        for i in 2..=100_000 {
+            sum = Expr::Binary(Binary {
+                left: Box::new(sum),
+                operator: BinaryOperator::Plus,
+                right: Box::new(Expr::Literal(Literal::Number(i as f64))),
+                span: Span::default(),
+            });
+        }
+        let mut interpreter = Interpreter::with_fuel(1_000_000);
+        assert_eq!(interpreter.eval(&sum), Ok(Value::Number(5_000_050_000.0)));
+        // One unit for every node, like with a short chain.
+        assert_eq!(interpreter.fuel(), Some(1_000_000 - 199_999));
+    }
+
+    #[test]
+    fn fixed_and_precision() {
+        let fixed = [
+            (0.125, 2, "0.12"),
+            (0.375, 2, "0.38"),
+            (2.5, 0, "2"),
+            (2.0, 0, "2"),
+            (-1.25, 1, "-1.2"),
+            (-0.001, 2, "-0.00"),
+            (1e21, 1, "1000000000000000000000.0"),
+            (1.5, 3, "1.500"),
+        ];
+        for (number, digits, expected) in fixed {
+            assert_eq!(format_fixed(number, digits), expected, "{}", number);
+        }
+        let precision = [
+            (123.456, 4, "123.5"),
+            (123.456, 2, "1.2e2"),
+            (0.000123, 2, "0.00012"),
+            (0.0000001234, 2, "1.2e-7"),
+            (99.96, 3, "100"),
+            (99.96, 2, "1.0e2"),
+            (-2.5, 1, "-2"),
+            (0.0, 3, "0.00"),
+            (1e21, 3, "1.00e21"),
+            (f64::INFINITY, 3, "inf"),
+        ];
+        for (number, significant, expected) in precision {
+            assert_eq!(
+                format_precision(number, significant),
+                expected,
+                "{} {}",
+                number,
+                significant
+            );
+        }
+    }
+
+    #[test]
+    fn reset() {
+        let mut interpreter = Interpreter::new();
+        interpreter.define_native("answer", 0, |_| Ok(Value::Number(42.0)));
+        interpreter
+            .interpret(program("var a = 1; var toJson = nil;"))
+            .unwrap();
+        interpreter.reset();
+        assert_eq!(interpreter.get_global("a"), None);
+        interpreter
+            .interpret(program("var a = answer(); var json = toJson(a);"))
+            .unwrap();
+        assert_eq!(interpreter.get_global("json"), Some(Value::from("42")));
+    }
+
+    #[test]
+    fn fork() {
+        let mut interpreter = Interpreter::new();
+        interpreter.interpret(program("var a = 1;")).unwrap();
+        let mut fork = interpreter.fork();
+        fork.interpret(program("a = 2; var b = 3;")).unwrap();
+        assert_eq!(fork.get_global("a"), Some(Value::Number(2.0)));
+        assert_eq!(interpreter.get_global("a"), Some(Value::Number(1.0)));
+        assert_eq!(interpreter.get_global("b"), None);
+    }
+
+    #[test]
+    fn print_handler() {
+        let printed = Arc::new(Mutex::new(Vec::new()));
+        let mut interpreter = Interpreter::new();
+        let sink = Arc::clone(&printed);
+        interpreter.set_print_handler(move |value| sink.lock().unwrap().push(value));
+        assert_eq!(
+            interpreter.interpret(program(
+                r#"print 1; { print "two"; } if (true) print nil; print -"four";"#
+            )),
+            Err(RuntimeError::TypeError(
+                "You can only negate a number".to_string()
+            ))
+        );
+        let ast = Ast::from_stmts(&program("print 1 == 1;"));
+        interpreter.interpret_ast(&ast).unwrap();
+        assert_eq!(
+            *printed.lock().unwrap(),
+            vec![
+                Value::Number(1.0),
+                Value::from("two"),
+                Value::Nil,
+                Value::Boolean(true)
+            ]
+        );
+    }
+
+    #[test]
+    fn string_index() {
+        let mut interpreter = Interpreter::new();
+        interpreter
+            .interpret(program(
+                r#"var s = "héllo"; var e = "a😀b"; var c = s[1] + e[1] + e[len(e) - 1];"#,
+            ))
+            .unwrap();
+        assert_eq!(interpreter.get_global("c"), Some(Value::from("é😀b")));
+        assert_eq!(
+            interpreter.interpret(program("s[5];")),
+            Err(RuntimeError::IndexError {
+                message: "Index 5 is out of range for a string of length 5.".to_string(),
+                line: 1
+            })
+        );
+        assert_eq!(
+            interpreter
+                .interpret(program("s[-1];"))
+                .unwrap_err()
+                .to_string(),
+            "Index -1 is out of range for a string of length 5."
+        );
+        assert_eq!(
+            interpreter
+                .interpret(program("s[0.5];"))
+                .unwrap_err()
+                .to_string(),
+            "Index must be an integer, got 0.5."
+        );
+        assert_eq!(
+            interpreter
+                .interpret(program("1[0];"))
+                .unwrap_err()
+                .to_string(),
+            "Only strings can be indexed."
+        );
+    }
+
+    #[test]
+    fn strings_are_immutable() {
+        let mut interpreter = Interpreter::new();
+        interpreter.interpret(program(r#"var s = "abc";"#)).unwrap();
+        assert_eq!(
+            interpreter.interpret(program("\ns[0] = \"z\";")),
+            Err(RuntimeError::IndexError {
+                message: "Strings are immutable.".to_string(),
+                line: 2
+            })
+        );
+        assert_eq!(interpreter.get_global("s"), Some(Value::from("abc")));
+    }
+
+    // Runs the program on both tree-walkers and returns what it printed.
+    fn printed(source: &str) -> (Result<(), RuntimeError>, Vec<Value>) {
+        let mut outputs = vec![];
+        let mut results = vec![];
+        for use_ast in [false, true] {
+            let printed = Arc::new(Mutex::new(Vec::new()));
+            let sink = Arc::clone(&printed);
+            let mut interpreter = Interpreter::new();
+            interpreter.set_print_handler(move |value| sink.lock().unwrap().push(value));
+            results.push(if use_ast {
+                interpreter.interpret_ast(&Ast::from_stmts(&program(source)))
+            } else {
+                interpreter.interpret(program(source))
+            });
+            outputs.push(printed.lock().unwrap().clone());
+        }
+        assert_eq!(results[0], results[1], "{}", source);
+        assert_eq!(outputs[0], outputs[1], "{}", source);
+        (results.remove(0), outputs.remove(0))
+    }
+
+    #[test]
+    fn try_catch() {
+        let (result, printed) = printed(
+            r#"
+            try {
+                print "before";
+                print -"one";
+                print "skipped";
+            } catch (e) {
+                print e;
+            }
+            print "after";
+            "#,
+        );
+        assert_eq!(result, Ok(()));
+        assert_eq!(
+            printed,
+            vec![
+                Value::from("before"),
+                Value::from("You can only negate a number"),
+                Value::from("after"),
+            ]
+        );
+    }
+
+    #[test]
+    fn nested_try() {
+        let (result, printed) = printed(
+            r#"
+            try {
+                try {
+                    missing;
+                } catch (e) {
+                    print "inner";
+                    "a"[5];
+                }
+            } catch (e) {
+                print e;
+            }
+            "#,
+        );
+        assert_eq!(result, Ok(()));
+        assert_eq!(
+            printed,
+            vec![
+                Value::from("inner"),
+                Value::from("Index 5 is out of range for a string of length 1."),
+            ]
+        );
+    }
+
+    #[test]
+    fn finally_always_runs() {
+        let (result, printed) = printed(
+            r#"
+            try { print 1; } finally { print 2; }
+            try { print -nil; } catch (e) { print 3; } finally { print 4; }
+            try { print -nil; } finally { print 5; }
+            print 6;
+            "#,
+        );
+        assert_eq!(
+            result,
+            Err(RuntimeError::TypeError(
+                "You can only negate a number".to_string()
+            ))
+        );
+        assert_eq!(
+            printed,
+            [1.0, 2.0, 3.0, 4.0, 5.0].map(Value::Number).to_vec()
+        );
+    }
+
+    #[test]
+    fn catch_scope() {
+        let (result, _) = printed("var e = 1; try { -nil; } catch (e) {} print e; print -e;");
+        assert_eq!(result, Ok(()));
+        let (result, _) = printed("try { -nil; } catch (e) {} print e;");
+        assert!(matches!(
+            result,
+            Err(RuntimeError::UndefinedVariable { .. })
+        ));
+    }
+
+    #[test]
+    fn throw_and_catch() {
+        let (result, printed) = printed(
+            r#"
+            try { throw "oops"; } catch (e) { print e; }
+            try { throw 1 + 2; } catch (e) { print type(e); print e * 2; }
+            try {
+                try { throw nil; } finally { print "finally"; }
+            } catch (e) {
+                print e;
+            }
+            "#,
+        );
+        assert_eq!(result, Ok(()));
+        assert_eq!(
+            printed,
+            vec![
+                Value::from("oops"),
+                Value::from("number"),
+                Value::Number(6.0),
+                Value::from("finally"),
+                Value::Nil,
+            ]
+        );
+    }
+
+    #[test]
+    fn uncaught_throw() {
+        let (result, printed) = printed("try { throw 1; } catch (e) {\n throw e + 1; }");
+        assert_eq!(
+            result,
+            Err(RuntimeError::Thrown {
+                value: Value::Number(2.0),
+                line: 2
+            })
+        );
+        assert!(printed.is_empty());
+    }
+
+    #[test]
+    fn for_in_range() {
+        let (result, printed) = printed(
+            r#"
+            for (i in 0..3) print i;
+            var n = 2;
+            for (i in n - 1..=n * 2) { var square = i * i; print square; }
+            for (i in 3..3) print "never";
+            print 1..5;
+            print 0..=-1;
+            "#,
+        );
+        assert_eq!(result, Ok(()));
+        assert_eq!(
+            printed,
+            vec![
+                Value::Number(0.0),
+                Value::Number(1.0),
+                Value::Number(2.0),
+                Value::Number(1.0),
+                Value::Number(4.0),
+                Value::Number(9.0),
+                Value::Number(16.0),
+                Value::Range {
+                    start: 1,
+                    end: 5,
+                    inclusive: false
+                },
+                Value::Range {
+                    start: 0,
+                    end: -1,
+                    inclusive: true
+                },
+            ]
+        );
+        assert_eq!(printed[7].to_string(), "1..5");
+        assert_eq!(printed[8].to_string(), "0..=-1");
+    }
+
+    #[test]
+    fn values_in_errors() {
+        let long = "ab".repeat(5000);
+        let (result, _) = printed(&format!("for (c in \"{}\") print c;", long));
+        let message = result.unwrap_err().to_string();
+        assert!(message.len() < 200, "{}", message);
+        assert_eq!(
+            message,
+            format!(
+                "Can only iterate over ranges, got \"{}… (10000 chars).",
+                &long[..119]
+            )
+        );
+
+        let (result, _) = printed("var s = \"a\n\u{1b}[31m\"; print \"abc\"[s];");
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Index must be an integer, got \"a\\n\\u001b[31m\"."
+        );
+
+        assert_eq!(
+            fmt_value_for_error_in(&Value::from("abcdef"), 3),
+            "\"ab… (6 chars)"
+        );
+        assert_eq!(
+            fmt_value_for_error_in(&Value::Number(12345.0), 3),
+            "123… (5 chars)"
+        );
+        assert_eq!(fmt_value_for_error(&Value::Nil), "nil");
+    }
+
+    #[test]
+    fn range_errors() {
+        let (result, _) = printed("var r = 1..2.5;");
+        assert_eq!(
+            result,
+            Err(RuntimeError::TypeError(
+                "Range bounds must be integers, got 1 and 2.5.".to_string()
+            ))
+        );
+        let (result, _) = printed(r#"for (c in "abc") print c;"#);
+        assert_eq!(
+            result,
+            Err(RuntimeError::TypeError(
+                "Can only iterate over ranges, got \"abc\".".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn refuses_programs_with_errors() {
+        let tokens = Scanner::new("print 1;\nprint f(1, +);".to_string())
+            .scan_tokens()
+            .unwrap()
+            .clone();
+        let (statements, errors) = Parser::new(tokens).parse_partial();
+        assert_eq!(errors.len(), 1);
+        let ast = Ast::from_stmts(&statements);
+        let printed = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&printed);
+        let mut interpreter = Interpreter::new();
+        interpreter.set_print_handler(move |value| sink.lock().unwrap().push(value));
+        let result = interpreter.interpret(statements);
+        assert_eq!(result, Err(RuntimeError::InvalidProgram));
+        assert_eq!(result.unwrap_err().to_string(), "Program contains errors.");
+        // Not even the statement before the error ran.
+        assert!(printed.lock().unwrap().is_empty());
+        assert_eq!(
+            Interpreter::new().interpret_ast(&ast),
+            Err(RuntimeError::InvalidProgram)
+        );
+    }
+
+    #[test]
+    fn profiling() {
+        let source = "for (i in 0..10) { len(\"ab\"); if (i < 3) print format(\"{}\", i); }";
+        let mut interpreter = Interpreter::new();
+        interpreter.set_print_handler(|_| {});
+        interpreter.interpret(program(source)).unwrap();
+        assert_eq!(interpreter.stats(), Stats::default());
+
+        for use_ast in [false, true] {
+            let mut interpreter = Interpreter::new();
+            interpreter.set_print_handler(|_| {});
+            interpreter.set_profile(true);
+            if use_ast {
+                interpreter.interpret_ast(&Ast::from_stmts(&program(source)))
+            } else {
+                interpreter.interpret(program(source))
+            }
+            .unwrap();
+            let stats = interpreter.stats();
+            assert_eq!(stats.calls["len"].calls, 10);
+            assert_eq!(stats.calls["format"].calls, 3);
+            assert_eq!(stats.calls.len(), 2);
+            assert_eq!(stats.loop_iterations, 10);
+            // The loop, its body 10 times, what's in it and the 3 prints.
+            assert_eq!(stats.statements, 1 + 10 + 20 + 3);
+            assert!(stats.expressions > stats.statements);
+        }
+    }
+
+    #[test]
+    fn out_of_fuel_is_not_caught() {
+        let mut interpreter = Interpreter::with_fuel(20);
+        let source = "try { var a = 0; a = a + 1; a = a + 1; a = a + 1; a = a + 1; a = a + 1; } \
+                      catch (e) { print e; }";
+        assert_eq!(
+            interpreter.interpret(program(source)),
+            Err(RuntimeError::OutOfFuel)
+        );
+    }
+
+    #[test]
+    fn if_else() {
+        let mut interpreter = Interpreter::new();
+        let statements = program(
+            r#"
+            var a = "unset";
+            var b = "unset";
+            if (1 < 2) a = "then"; else a = "else";
+            if (nil) b = "then"; else b = "else";
+            "#,
+        );
+        assert_eq!(interpreter.interpret(statements), Ok(()));
+        assert_eq!(
+            interpreter.eval(&Expr::Variable("a".to_string())),
+            Ok(Value::from("then"))
+        );
+        assert_eq!(
+            interpreter.eval(&Expr::Variable("b".to_string())),
+            Ok(Value::from("else"))
+        );
+    }
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn trace() {
+        let statements = program(
+            r#"var i = 0;
+{
+    i = i + 1;
+}
+if (i == 1) i = -i;"#,
+        );
+        for use_ast in [false, true] {
+            let buffer = SharedBuffer::default();
+            let mut interpreter = Interpreter::new();
+            interpreter.set_trace_writer(buffer.clone());
+            let result = if use_ast {
+                interpreter.interpret_ast(&Ast::from_stmts(&statements))
+            } else {
+                interpreter.interpret(statements.clone())
+            };
+            assert_eq!(result, Ok(()));
+            let trace = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+            assert_eq!(
+                trace,
+                r#"[trace] line 1: var i = 0;
+[trace] line 1: define i = 0
+[trace] line 2: {
+[trace] line 3: i = i + 1;
+[trace] line 3: assign i = 1
+[trace] line 5: if (i == 1)
+[trace] line 5: i = -i;
+[trace] line 5: assign i = -1
+"#
+            );
+        }
+    }
+
+    #[test]
+    fn trace_is_off_by_default() {
+        let buffer = SharedBuffer::default();
+        let mut interpreter = Interpreter::new();
+        interpreter.set_trace_writer(buffer.clone());
+        interpreter.set_trace(false);
+        assert_eq!(interpreter.interpret(program("var i = 0;")), Ok(()));
+        assert!(buffer.0.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn strict_conditions() {
+        let run = |strict, source: &str| {
+            let options = InterpreterOptions {
+                strict_conditions: strict,
+                ..InterpreterOptions::default()
+            };
+            let mut interpreter = Interpreter::with_options(options.clone());
+            interpreter.set_print_handler(|_| {});
+            let tree_walker = interpreter.interpret(program(source));
+            let mut interpreter = Interpreter::with_options(options);
+            interpreter.set_print_handler(|_| {});
+            let arena = interpreter.interpret_ast(&Ast::from_stmts(&program(source)));
+            assert_eq!(tree_walker, arena, "{}", source);
+            tree_walker
+        };
+        assert_eq!(run(false, "if (1) print 1;"), Ok(()));
+        assert_eq!(run(false, "if (nil) print 1;"), Ok(()));
+        assert_eq!(run(true, "if (1 < 2) print 1; else print 2;"), Ok(()));
+        assert_eq!(
+            run(true, "if (1) print 1;"),
+            Err(RuntimeError::TypeError(
+                "Condition must be true or false, got 1.".to_string()
+            ))
+        );
+        assert_eq!(
+            run(true, "if (nil) print 1;"),
+            Err(RuntimeError::TypeError(
+                "Condition must be true or false, got nil.".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn cross_type_equality_warnings() {
+        let source = r#"
+            for (i in 0..3) {
+                print i == "1";
+                print i != 1;
+                print "a" == nil;
+            }
+            print true != "true", false != "false";
+        "#;
+        let mut interpreter = Interpreter::with_options(InterpreterOptions {
+            warn_cross_type_equality: true,
+            ..InterpreterOptions::default()
+        });
+        interpreter.set_print_handler(|_| {});
+        assert_eq!(interpreter.interpret(program(source)), Ok(()));
+        let warnings: Vec<(Option<usize>, &str, String)> = interpreter
+            .take_warnings()
+            .into_iter()
+            .map(|warning| {
+                let span = warning.span.unwrap();
+                (warning.line, &source[span.start..span.end], warning.message)
+            })
+            .collect();
+        assert_eq!(
+            warnings,
+            [
+                (
+                    Some(3),
+                    r#"i == "1""#,
+                    "'==' compares a number with a string, values of different types are never \
+                     equal."
+                        .to_string()
+                ),
+                (
+                    Some(7),
+                    r#"true != "true""#,
+                    "'!=' compares a boolean with a string, values of different types are never \
+                     equal."
+                        .to_string()
+                ),
+                // Once for each comparison, even on the same line.
+                (
+                    Some(7),
+                    r#"false != "false""#,
+                    "'!=' compares a boolean with a string, values of different types are never \
+                     equal."
+                        .to_string()
+                ),
+            ]
+        );
+        assert!(interpreter.take_warnings().is_empty());
+
+        let mut interpreter = Interpreter::new();
+        interpreter.set_print_handler(|_| {});
+        assert_eq!(interpreter.interpret(program(source)), Ok(()));
+        assert!(interpreter.take_warnings().is_empty());
+    }
+
+    #[test]
+    fn prelude() {
+        let printed = Arc::new(Mutex::new(Vec::new()));
+        let with_prelude = |prelude: &str, fuel| {
+            let sink = Arc::clone(&printed);
+            let mut interpreter = Interpreter::with_options(InterpreterOptions {
+                prelude: Some(prelude.to_string()),
+                fuel,
+                ..InterpreterOptions::default()
+            });
+            interpreter.set_print_handler(move |value| sink.lock().unwrap().push(value));
+            interpreter
+        };
+
+        let mut interpreter = with_prelude("var answer = 40;", None);
+        assert_eq!(interpreter.interpret(program("print answer + 2;")), Ok(()));
+        // Only once, the program's changes stay.
+        assert_eq!(interpreter.interpret(program("answer = 1;")), Ok(()));
+        assert_eq!(interpreter.interpret(program("print answer;")), Ok(()));
+        interpreter.reset();
+        assert_eq!(interpreter.interpret(program("print answer;")), Ok(()));
+        assert_eq!(
+            *printed.lock().unwrap(),
+            [Value::Number(42.0), Value::Number(1.0), Value::Number(40.0)]
+        );
+        printed.lock().unwrap().clear();
+
+        // Thousands of steps, none of them taken from the program's 10.
+        let mut interpreter = with_prelude("var n = 0; for (i in 0..1000) n = n + i;", Some(10));
+        assert_eq!(interpreter.interpret(program("print n;")), Ok(()));
+        assert_eq!(interpreter.fuel(), Some(8));
+
+        let mut interpreter = with_prelude("var a = 1;\nvar b = -nil;", None);
+        let err = interpreter.interpret(program("print 1;")).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Error in prelude: You can only negate a number [line 2]"
+        );
+        assert_eq!(err.code(), crate::diagnostics::Code::PreludeError);
+        let mut interpreter = with_prelude("var = 1;", None);
+        assert_eq!(
+            interpreter.interpret(program("print 1;")),
+            Err(RuntimeError::Prelude(
+                "[line 1] Error: Expect variable name.".to_string()
+            ))
+        );
+        assert_eq!(*printed.lock().unwrap(), [Value::Number(499500.0)]);
+    }
+
+    #[test]
+    fn dbg_without_source() {
+        let printed = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&printed);
+        let mut interpreter = Interpreter::new();
+        interpreter.set_print_handler(move |value| sink.lock().unwrap().push(value.to_string()));
+        let source = "var y = 4;\nvar x = dbg(y*(y+1))  -  1;";
+        assert_eq!(interpreter.interpret(program(source)), Ok(()));
+        assert_eq!(interpreter.get_global("x"), Some(Value::Number(19.0)));
+        assert_eq!(
+            interpreter.interpret_ast(&Ast::from_stmts(&program("dbg(\"a\");"))),
+            Ok(())
+        );
+        assert_eq!(
+            *printed.lock().unwrap(),
+            ["[line 2] y * (y + 1) = 20", "[line 1] \"a\" = \"a\""]
+        );
+    }
+
+    #[test]
+    fn run_isolated() {
+        let mut interpreter = Interpreter::with_options(InterpreterOptions {
+            fuel: Some(100),
+            prelude: Some("var base = 10;".to_string()),
+            ..InterpreterOptions::default()
+        });
+        interpreter.set_print_handler(|_| {});
+        interpreter.define_global("rate", Value::Number(2.0));
+
+        assert_eq!(
+            interpreter.run_isolated("var x = 1; rate = 3; base + rate * x;"),
+            Ok(Some(Value::Number(13.0)))
+        );
+        // Nothing of the first run is left for the second.
+        assert_eq!(
+            interpreter.run_isolated("base + rate;"),
+            Ok(Some(Value::Number(12.0)))
+        );
+        assert_eq!(interpreter.get_global("rate"), Some(Value::Number(2.0)));
+        assert!(matches!(
+            interpreter.run_isolated("print x;"),
+            Err(RoxyError::Runtime(RuntimeError::UndefinedVariable { .. }))
+        ));
+        assert_eq!(interpreter.run_isolated("var y = 1;"), Ok(None));
+
+        // Every run gets all of the fuel, and a failed one cleans up too.
+        for _ in 0..3 {
+            assert!(interpreter
+                .run_isolated("var n = 0; for (i in 0..1000) n = n + i;")
+                .is_err());
+            assert_eq!(
+                interpreter.run_isolated("var n = 0; for (i in 0..10) n = n + i; n;"),
+                Ok(Some(Value::Number(45.0)))
+            );
+        }
+        assert_eq!(interpreter.get_global("n"), None);
+        assert!(interpreter.run_isolated("print 1 +;").is_err());
     }
 }
@@ -0,0 +1,189 @@
+use std::{iter::Peekable, str::Chars};
+
+use crate::interpreter::{fmt_value_for_error, RuntimeError, Value};
+
+// Only scalars can be converted for now, there are no arrays or maps in the
+// language yet. Numbers are written the same way `print` shows them.
+impl Value {
+    pub fn to_json(&self) -> Result<String, RuntimeError> {
+        match self {
+            Value::Nil => Ok("null".to_string()),
+            Value::Boolean(b) => Ok(b.to_string()),
+            Value::Number(n) if n.is_finite() => Ok(self.to_string()),
+            Value::Number(n) => Err(RuntimeError::TypeError(format!(
+                "Can't convert {} to JSON.",
+                n
+            ))),
+            Value::String(s) => Ok(quote(s)),
+            Value::NativeFunction(_) => Err(RuntimeError::TypeError(
+                "Can't convert a function to JSON.".to_string(),
+            )),
+            Value::Range { .. } => Err(RuntimeError::TypeError(
+                "Can't convert a range to JSON.".to_string(),
+            )),
+            Value::Foreign(foreign) => Err(RuntimeError::TypeError(format!(
+                "Can't convert a foreign {} to JSON.",
+                foreign.name
+            ))),
+        }
+    }
+
+    pub fn from_json(json: &str) -> Result<Value, RuntimeError> {
+        let mut chars = json.trim().chars().peekable();
+        let value = parse_value(&mut chars)?;
+        match chars.next() {
+            None => Ok(value),
+            Some(c) => Err(invalid(&format!("unexpected '{}' after the value", c))),
+        }
+    }
+}
+
+fn invalid(message: &str) -> RuntimeError {
+    RuntimeError::InvalidJson(message.to_string())
+}
+
+pub(crate) fn quote(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn parse_value(chars: &mut Peekable<Chars>) -> Result<Value, RuntimeError> {
+    match chars.peek() {
+        Some('n') => keyword(chars, "null", Value::Nil),
+        Some('t') => keyword(chars, "true", Value::Boolean(true)),
+        Some('f') => keyword(chars, "false", Value::Boolean(false)),
+        Some('"') => parse_string(chars).map(Value::from),
+        Some(c) if *c == '-' || c.is_ascii_digit() => parse_number(chars),
+        Some('[') | Some('{') => Err(invalid("arrays and objects are not supported")),
+        Some(c) => Err(invalid(&format!("unexpected '{}'", c))),
+        None => Err(invalid("expected a value")),
+    }
+}
+
+fn keyword(chars: &mut Peekable<Chars>, word: &str, value: Value) -> Result<Value, RuntimeError> {
+    for expected in word.chars() {
+        if chars.next() != Some(expected) {
+            return Err(invalid(&format!("expected '{}'", word)));
+        }
+    }
+    Ok(value)
+}
+
+fn parse_number(chars: &mut Peekable<Chars>) -> Result<Value, RuntimeError> {
+    let mut text = String::new();
+    while let Some(c) = chars.peek() {
+        if c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E') {
+            text.push(*c);
+            chars.next();
+        } else {
+            break;
+        }
+    }
+    text.parse::<f64>().map(Value::Number).map_err(|_| {
+        let text = fmt_value_for_error(&Value::from(text));
+        invalid(&format!("invalid number {}", text))
+    })
+}
+
+fn parse_string(chars: &mut Peekable<Chars>) -> Result<String, RuntimeError> {
+    chars.next();
+    let mut out = String::new();
+    loop {
+        match chars.next() {
+            Some('"') => return Ok(out),
+            Some('\\') => match chars.next() {
+                Some('"') => out.push('"'),
+                Some('\\') => out.push('\\'),
+                Some('/') => out.push('/'),
+                Some('n') => out.push('\n'),
+                Some('r') => out.push('\r'),
+                Some('t') => out.push('\t'),
+                Some('b') => out.push('\u{8}'),
+                Some('f') => out.push('\u{c}'),
+                Some('u') => {
+                    let hex: String = chars.by_ref().take(4).collect();
+                    let c = u32::from_str_radix(&hex, 16)
+                        .ok()
+                        .and_then(char::from_u32)
+                        .ok_or_else(|| invalid(&format!("invalid escape '\\u{}'", hex)))?;
+                    out.push(c);
+                }
+                _ => return Err(invalid("invalid escape")),
+            },
+            Some(c) => out.push(c),
+            None => return Err(invalid("unterminated string")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod json_tests {
+    use crate::{
+        callable::NativeFunction,
+        interpreter::{RuntimeError, Value},
+    };
+
+    #[test]
+    fn round_trip() {
+        let values = [
+            Value::Nil,
+            Value::Boolean(true),
+            Value::Boolean(false),
+            Value::Number(3.0),
+            Value::Number(-0.25),
+            Value::from("say \"hi\"\n\tback\\slash \u{1}"),
+        ];
+        for value in values {
+            let json = value.to_json().unwrap();
+            assert_eq!(Value::from_json(&json), Ok(value), "{}", json);
+        }
+    }
+
+    #[test]
+    fn numbers_match_print() {
+        assert_eq!(Value::Number(3.0).to_json().unwrap(), "3");
+        assert_eq!(Value::Number(1.5).to_json().unwrap(), "1.5");
+        assert_eq!(Value::Number(1e21).to_json().unwrap(), "1e21");
+        assert_eq!(
+            Value::Number(f64::NAN).to_json(),
+            Err(RuntimeError::TypeError(
+                "Can't convert NaN to JSON.".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn functions_are_not_serializable() {
+        let native = NativeFunction::new("f", 0, |_| Ok(Value::Nil));
+        assert_eq!(
+            Value::NativeFunction(native).to_json(),
+            Err(RuntimeError::TypeError(
+                "Can't convert a function to JSON.".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn invalid_json() {
+        assert_eq!(Value::from_json(" \"a\\u0041\" "), Ok(Value::from("aA")));
+        for json in ["", "nul", "1 2", "\"open", "[1]", "{}", "--1"] {
+            assert!(
+                matches!(Value::from_json(json), Err(RuntimeError::InvalidJson(_))),
+                "{}",
+                json
+            );
+        }
+    }
+}
@@ -1,3 +1,180 @@
+pub mod arena;
+pub mod ast_printer;
+pub mod callable;
+pub mod capture;
+pub mod compiler;
+pub mod coverage;
+pub mod diagnostics;
+#[cfg(test)]
+mod differential;
+pub mod environment;
+pub mod error;
+pub mod foreign;
+pub mod formatter;
 pub mod interpreter;
+pub mod json;
+pub mod lint;
+pub mod natives;
 pub mod parser;
+pub mod pipeline;
+pub mod resolver;
+pub mod runner;
 pub mod scanner;
+pub mod state;
+pub mod stats;
+#[cfg(any(test, feature = "bench-helpers"))]
+pub mod synthetic;
+pub mod vm;
+pub mod walk;
+
+// The types an embedder needs, so they don't have to know the module layout.
+pub use capture::{run_capture, ExitKind, RunOptions, RunOutcome};
+pub use error::RoxyError;
+pub use formatter::format_source;
+pub use interpreter::{Interpreter, InterpreterOptions, RuntimeError, Value};
+pub use pipeline::{Pipeline, PipelineReport};
+pub use runner::Runner;
+pub use state::StateSnapshot;
+pub use walk::{walk, NodeKind, NodeRef};
+
+use std::time::Instant;
+
+use arena::Ast;
+use compiler::Compiler;
+use diagnostics::{Diagnostic, Span};
+use parser::{Expr, Parser, Stmt};
+use resolver::Resolver;
+use scanner::{Scanner, Token};
+use vm::Vm;
+
+pub fn scan(source: &str) -> Result<Vec<Token>, RoxyError> {
+    let mut scanner = Scanner::new(source.to_string());
+    Ok(scanner.scan_tokens()?.clone())
+}
+
+// Stops after scanning if the scanner found errors.
+pub fn parse(source: &str) -> Result<Vec<Stmt>, RoxyError> {
+    Ok(Parser::new(scan(source)?).parse()?)
+}
+
+// For editors: a single expression and the span it covers, surrounding
+// whitespace left out. Every diagnostic has a span.
+pub fn parse_expression_with_spans(source: &str) -> Result<(Expr, Span), Vec<Diagnostic>> {
+    let tokens = scan(source).map_err(|err| err.errors().collect::<Vec<_>>())?;
+    Parser::new(tokens)
+        .standalone_expression()
+        .map_err(|err| RoxyError::Parse(vec![err]).errors().collect())
+}
+
+// Everything short of running the program, so it is also safe on programs
+// that never end. The warnings come back when there are no errors.
+pub fn check(source: &str) -> Result<Vec<Diagnostic>, RoxyError> {
+    let mut scanner = Scanner::new(source.to_string());
+    let tokens = scanner.scan_tokens()?.clone();
+    let statements = Parser::new(tokens).parse()?;
+    let mut warnings = scanner.warnings().to_vec();
+    warnings.extend(Resolver::resolve(&statements).iter().map(Diagnostic::from));
+    Ok(warnings)
+}
+
+// `check` with the sizes of the program and how long each stage took. The
+// counts are left at 0 when it doesn't parse.
+pub fn check_with_report(source: &str) -> (Result<Vec<Diagnostic>, RoxyError>, PipelineReport) {
+    let mut report = PipelineReport::default();
+    let result = front_end(source, &mut report).map(|(_, warnings)| warnings);
+    (result, report)
+}
+
+// `run_with`, reporting on the program like `check_with_report` does.
+pub fn run_with_report(
+    source: &str,
+    interpreter: &mut Interpreter,
+) -> (Result<(), RoxyError>, PipelineReport) {
+    let mut report = PipelineReport::default();
+    let result = front_end(source, &mut report).and_then(|(statements, _)| {
+        let start = Instant::now();
+        interpreter.set_source(source);
+        let result = interpreter.interpret_ast(&Ast::from_stmts(&statements));
+        report.run = start.elapsed();
+        Ok(result?)
+    });
+    (result, report)
+}
+
+fn front_end(
+    source: &str,
+    report: &mut PipelineReport,
+) -> Result<(Vec<Stmt>, Vec<Diagnostic>), RoxyError> {
+    let start = Instant::now();
+    let mut scanner = Scanner::new(source.to_string());
+    let tokens = scanner.scan_tokens().cloned();
+    report.scan = start.elapsed();
+    let tokens = tokens?;
+
+    let counted = tokens.clone();
+    let start = Instant::now();
+    let statements = Parser::new(tokens).parse();
+    report.parse = start.elapsed();
+    let statements = statements?;
+    report.count(&counted, &statements);
+
+    let start = Instant::now();
+    let mut warnings = scanner.warnings().to_vec();
+    warnings.extend(Resolver::resolve(&statements).iter().map(Diagnostic::from));
+    report.resolve = start.elapsed();
+    Ok((statements, warnings))
+}
+
+// Runs a program on a fresh interpreter.
+pub fn run(source: &str) -> Result<(), RoxyError> {
+    run_with(source, &mut Interpreter::new())
+}
+
+// Runs a program on an existing interpreter, so globals from earlier runs
+// are still around.
+pub fn run_with(source: &str, interpreter: &mut Interpreter) -> Result<(), RoxyError> {
+    let statements = parse(source)?;
+    interpreter.set_source(source);
+    Ok(interpreter.interpret_ast(&Ast::from_stmts(&statements))?)
+}
+
+// Scans, parses, resolves and runs a program on a fresh interpreter, and
+// never panics whatever the source is: every problem comes back as an
+// error. Only fuel makes it return for programs that loop forever.
+// tests/hostile.rs holds it to this, an input that panics goes in
+// tests/hostile/. Returns the same warnings as `check`.
+pub fn run_checked(
+    source: &str,
+    options: InterpreterOptions,
+) -> Result<Vec<Diagnostic>, RoxyError> {
+    let mut scanner = Scanner::new(source.to_string());
+    let tokens = scanner.scan_tokens()?.clone();
+    let statements = Parser::new(tokens).parse()?;
+    let mut warnings = scanner.warnings().to_vec();
+    warnings.extend(Resolver::resolve(&statements).iter().map(Diagnostic::from));
+    let mut interpreter = Interpreter::with_options(options);
+    interpreter.set_source(source);
+    interpreter.interpret_ast(&Ast::from_stmts(&statements))?;
+    Ok(warnings)
+}
+
+// Entry point for fuzzers. Whatever the bytes are, running them has to end in
+// a value or an error, never a panic, on the tree-walker and on the VM. Fuel
+// keeps loops short and makes long strings expensive, and printed values are
+// thrown away.
+pub fn fuzz_pipeline(data: &[u8]) {
+    let source = String::from_utf8_lossy(data);
+    let Ok(statements) = parse(&source) else {
+        return;
+    };
+    let fuzzed = || {
+        let mut interpreter = Interpreter::with_fuel(10_000);
+        interpreter.set_print_handler(|_| {});
+        interpreter.set_source(&source);
+        interpreter
+    };
+    if let Ok(chunk) = Compiler::compile(&statements) {
+        let _ = Vm::new().run_with(&chunk, &mut fuzzed());
+    }
+    let _ = fuzzed().interpret(statements);
+}
@@ -0,0 +1,411 @@
+use crate::{
+    diagnostics::{Code, Diagnostic, Severity, SourceMap, Span},
+    parser::{BinaryOperator, Expr, Literal, Stmt},
+    resolver::Resolver,
+};
+
+// Style checks on a parsed program, nothing is run. They are warnings, a
+// program that trips one still works the way it is written.
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Rule {
+    UnusedVariable,
+    ShadowedVariable,
+    SelfComparison,
+    ConstantCondition,
+    AssignmentInCondition,
+    EmptyBlock,
+}
+
+impl Rule {
+    pub const ALL: [Rule; 6] = [
+        Rule::UnusedVariable,
+        Rule::ShadowedVariable,
+        Rule::SelfComparison,
+        Rule::ConstantCondition,
+        Rule::AssignmentInCondition,
+        Rule::EmptyBlock,
+    ];
+
+    pub fn id(&self) -> &'static str {
+        match self {
+            Rule::UnusedVariable => "unused-variable",
+            Rule::ShadowedVariable => "shadowed-variable",
+            Rule::SelfComparison => "self-comparison",
+            Rule::ConstantCondition => "constant-condition",
+            Rule::AssignmentInCondition => "assignment-in-condition",
+            Rule::EmptyBlock => "empty-block",
+        }
+    }
+
+    pub fn code(&self) -> Code {
+        match self {
+            Rule::UnusedVariable => Code::UnusedVariable,
+            Rule::ShadowedVariable => Code::ShadowedVariable,
+            Rule::SelfComparison => Code::SelfComparison,
+            Rule::ConstantCondition => Code::ConstantCondition,
+            Rule::AssignmentInCondition => Code::AssignmentInCondition,
+            Rule::EmptyBlock => Code::EmptyBlock,
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<Rule> {
+        Rule::ALL.into_iter().find(|rule| rule.id() == id)
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct LintOptions {
+    pub disabled: Vec<Rule>,
+}
+
+impl LintOptions {
+    pub fn enabled(&self, rule: Rule) -> bool {
+        !self.disabled.contains(&rule)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Lint {
+    pub rule: Rule,
+    pub message: String,
+    pub line: usize,
+    // Statements don't keep their spans, so this is the whole line.
+    pub span: Span,
+}
+
+impl std::fmt::Display for Lint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "[line {}] Warning ({}): {}",
+            self.line,
+            self.rule.id(),
+            self.message
+        )
+    }
+}
+
+impl From<&Lint> for Diagnostic {
+    fn from(lint: &Lint) -> Self {
+        Self {
+            code: lint.rule.code(),
+            severity: Severity::Warning,
+            message: lint.message.clone(),
+            line: Some(lint.line),
+            span: Some(lint.span),
+            file: None,
+        }
+    }
+}
+
+// The source is only used to find the lines again.
+pub fn lint(source: &str, statements: &[Stmt], options: &LintOptions) -> Vec<Lint> {
+    let mut linter = Linter {
+        options,
+        map: SourceMap::new(source),
+        scopes: vec![Vec::new()],
+        line: 0,
+        lints: Vec::new(),
+    };
+    if options.enabled(Rule::UnusedVariable) {
+        for warning in Resolver::resolve(statements) {
+            linter.line = warning.line;
+            linter.report(Rule::UnusedVariable, warning.message);
+        }
+    }
+    for stmt in statements {
+        linter.statement(stmt);
+    }
+    linter.lints.sort_by_key(|lint| lint.line);
+    linter.lints
+}
+
+struct Linter<'a> {
+    options: &'a LintOptions,
+    map: SourceMap<'a>,
+    // Names declared in every scope, the first one holds the globals.
+    scopes: Vec<Vec<String>>,
+    // Line of the statement being checked.
+    line: usize,
+    lints: Vec<Lint>,
+}
+
+impl Linter<'_> {
+    fn statement(&mut self, stmt: &Stmt) {
+        self.line = stmt.line();
+        match stmt {
+            Stmt::Expression { expr, .. } | Stmt::Throw { expr, .. } => self.expression(expr),
+            Stmt::Print { exprs, .. } => exprs.iter().for_each(|expr| self.expression(expr)),
+            Stmt::Error { .. } => {}
+            Stmt::Var {
+                name, initializer, ..
+            } => {
+                self.expression(initializer);
+                self.declare(name);
+            }
+            Stmt::Block { statements, .. } => {
+                if statements.is_empty() {
+                    self.report(Rule::EmptyBlock, "Empty block.".to_string());
+                }
+                self.scoped(None, statements);
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.condition(condition);
+                self.statement(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.statement(else_branch);
+                }
+            }
+            Stmt::ForIn {
+                name,
+                iterable,
+                body,
+                ..
+            } => {
+                self.expression(iterable);
+                self.scoped(Some(name), std::slice::from_ref(body));
+            }
+            Stmt::Try {
+                body,
+                catch,
+                finally,
+                ..
+            } => {
+                self.statement(body);
+                if let Some((name, handler)) = catch {
+                    // An empty handler is how an error is deliberately ignored.
+                    if let Stmt::Block { statements, .. } = &**handler {
+                        self.scoped(Some(name), statements);
+                    }
+                }
+                if let Some(finally) = finally {
+                    self.statement(finally);
+                }
+            }
+        }
+    }
+
+    // Runs the statements in a new scope, which starts out with the loop or
+    // catch variable if there is one.
+    fn scoped(&mut self, name: Option<&String>, statements: &[Stmt]) {
+        self.scopes.push(Vec::new());
+        if let Some(name) = name {
+            self.declare(name);
+        }
+        for stmt in statements {
+            self.statement(stmt);
+        }
+        self.scopes.pop();
+    }
+
+    fn declare(&mut self, name: &str) {
+        let (scope, outer) = self.scopes.split_last().expect("globals are a scope");
+        let shadows = outer.iter().any(|names| names.iter().any(|n| n == name));
+        let already_declared = scope.iter().any(|n| n == name);
+        if shadows && !already_declared {
+            self.report(
+                Rule::ShadowedVariable,
+                format!(
+                    "Variable '{}' shadows a variable from an outer scope.",
+                    name
+                ),
+            );
+        }
+        self.scopes
+            .last_mut()
+            .expect("globals are a scope")
+            .push(name.to_string());
+    }
+
+    // Extra parens around the condition keep these quiet, like in C.
+    fn condition(&mut self, condition: &Expr) {
+        match condition {
+            Expr::Assign(_) | Expr::SetIndex(_) => self.report(
+                Rule::AssignmentInCondition,
+                "Assignment used as a condition, did you mean '=='?".to_string(),
+            ),
+            Expr::Literal(literal) => {
+                let truthy = !matches!(literal, Literal::Nil | Literal::Boolean(false));
+                self.report(
+                    Rule::ConstantCondition,
+                    format!("Condition is always {}.", truthy),
+                );
+            }
+            _ => {}
+        }
+        self.expression(condition);
+    }
+
+    fn expression(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Binary(b) => {
+                if is_comparison(b.operator) && b.left == b.right && is_pure(&b.left) {
+                    self.report(
+                        Rule::SelfComparison,
+                        format!("'{}' compares a value to itself.", expr),
+                    );
+                }
+                self.expression(&b.left);
+                self.expression(&b.right);
+            }
+            Expr::Unary(u) => self.expression(&u.right),
+            Expr::Literal(_) | Expr::Variable(_) | Expr::Error(_) => {}
+            Expr::Grouping(g) => self.expression(&g.expr),
+            Expr::Assign(a) => self.expression(&a.value),
+            Expr::Call(c) => {
+                self.expression(&c.callee);
+                c.arguments
+                    .iter()
+                    .for_each(|argument| self.expression(argument));
+            }
+            Expr::Index(i) => {
+                self.expression(&i.object);
+                self.expression(&i.index);
+            }
+            Expr::SetIndex(i) => {
+                self.expression(&i.object);
+                self.expression(&i.index);
+                self.expression(&i.value);
+            }
+            Expr::Range(r) => {
+                self.expression(&r.start);
+                self.expression(&r.end);
+            }
+            Expr::Dbg(d) => self.expression(&d.expr),
+        }
+    }
+
+    fn report(&mut self, rule: Rule, message: String) {
+        if self.options.enabled(rule) {
+            self.lints.push(Lint {
+                rule,
+                message,
+                line: self.line,
+                span: self.map.line_span(self.line),
+            });
+        }
+    }
+}
+
+fn is_comparison(operator: BinaryOperator) -> bool {
+    matches!(
+        operator,
+        BinaryOperator::EqualEqual
+            | BinaryOperator::NotEqual
+            | BinaryOperator::LessThan
+            | BinaryOperator::LessThanEqual
+            | BinaryOperator::GreaterThan
+            | BinaryOperator::GreaterThanEqual
+    )
+}
+
+// Evaluating it twice gives the same value, so `f() == f()` isn't reported.
+fn is_pure(expr: &Expr) -> bool {
+    match expr {
+        Expr::Literal(_) | Expr::Variable(_) => true,
+        Expr::Grouping(g) => is_pure(&g.expr),
+        Expr::Unary(u) => is_pure(&u.right),
+        Expr::Binary(b) => is_pure(&b.left) && is_pure(&b.right),
+        Expr::Index(i) => is_pure(&i.object) && is_pure(&i.index),
+        Expr::Range(r) => is_pure(&r.start) && is_pure(&r.end),
+        // It prints.
+        Expr::Assign(_) | Expr::SetIndex(_) | Expr::Call(_) | Expr::Dbg(_) | Expr::Error(_) => {
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod lint_tests {
+    use super::{lint, Lint, LintOptions, Rule};
+
+    fn lints(source: &str, options: &LintOptions) -> Vec<Lint> {
+        lint(source, &crate::parse(source).unwrap(), options)
+    }
+
+    fn rules(source: &str) -> Vec<Rule> {
+        lints(source, &LintOptions::default())
+            .iter()
+            .map(|lint| lint.rule)
+            .collect()
+    }
+
+    // Every rule with a program that trips only that rule.
+    const TRIGGERS: [(Rule, &str); 6] = [
+        (Rule::UnusedVariable, "{ var a = 1; }"),
+        (Rule::ShadowedVariable, "var a = 1; { var a = 2; print a; }"),
+        (Rule::SelfComparison, "var a = 1; print a == a;"),
+        (Rule::ConstantCondition, "if (true) print 1;"),
+        (Rule::AssignmentInCondition, "var a; if (a = 1) print a;"),
+        (Rule::EmptyBlock, "if (clock) {}"),
+    ];
+
+    #[test]
+    fn each_rule() {
+        for (rule, source) in TRIGGERS {
+            assert_eq!(rules(source), vec![rule], "{}", source);
+            let options = LintOptions {
+                disabled: vec![rule],
+            };
+            assert!(lints(source, &options).is_empty(), "{}", source);
+        }
+    }
+
+    #[test]
+    fn ids() {
+        for rule in Rule::ALL {
+            assert_eq!(Rule::from_id(rule.id()), Some(rule));
+        }
+        assert_eq!(Rule::from_id("no-such-rule"), None);
+    }
+
+    #[test]
+    fn lint_details() {
+        let source = "var x = 1;\n{\n  for (x in 0..3) print x;\n}\nif (nil) print x != x;";
+        let found = lints(source, &LintOptions::default());
+        assert_eq!(
+            found.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            [
+                "[line 3] Warning (shadowed-variable): Variable 'x' shadows a variable from an outer scope.",
+                "[line 5] Warning (constant-condition): Condition is always false.",
+                "[line 5] Warning (self-comparison): 'x != x' compares a value to itself.",
+            ]
+        );
+        assert_eq!(
+            &source[found[0].span.start..found[0].span.end],
+            "for (x in 0..3) print x;"
+        );
+    }
+
+    #[test]
+    fn not_reported() {
+        let clean = [
+            "var a = 1; var a = 2; print a;",
+            "print f() == f();",
+            "var a = 1; var b = 2; print a == b;",
+            "if ((1)) print 1;",
+            "try { throw 1; } catch (_e) {}",
+            "{ var _unused = 1; }",
+        ];
+        for source in clean {
+            let found = lints(source, &LintOptions::default());
+            assert!(found.is_empty(), "{}: {:?}", source, found);
+        }
+    }
+
+    #[test]
+    fn does_not_run_or_change_the_program() {
+        let source = "var a = 1; { var a = -nil; a = a + 1; } if (a = 2) {}";
+        let statements = crate::parse(source).unwrap();
+        let before = statements.clone();
+        // Running this would fail on `-nil` straight away.
+        assert!(!lint(source, &statements, &LintOptions::default()).is_empty());
+        assert_eq!(statements, before);
+    }
+}
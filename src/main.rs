@@ -1,10 +1,360 @@
-use roxy::{interpreter::Interpreter, parser::Parser, scanner::*};
+use std::{
+    fs,
+    io::{self, BufRead, Read, Write},
+    process,
+    time::Instant,
+};
+
+use roxy::{
+    arena::Ast,
+    ast_printer,
+    compiler::Compiler,
+    diagnostics::{render_diagnostic_in, Diagnostic, DiagnosticSink, SourceMap},
+    lint::{self, LintOptions},
+    parser::{Parser, Stmt},
+    resolver::Resolver,
+    scanner::Scanner,
+    vm::Vm,
+    Interpreter, InterpreterOptions, PipelineReport, RoxyError,
+};
+
+// Exit codes from sysexits.h, which is what the Lox test suites expect.
+const EX_USAGE: i32 = 64;
+const EX_DATAERR: i32 = 65;
+const EX_NOINPUT: i32 = 66;
+const EX_SOFTWARE: i32 = 70;
+
+// Anything else starting with `--` is a usage error.
+const FLAGS: [&str; 14] = [
+    "--coverage",
+    "--deterministic",
+    "--trace",
+    "--profile",
+    "--vm",
+    "--dump-tokens",
+    "--dump-ast",
+    "--run",
+    "--format",
+    "--lint",
+    "--check",
+    "--diagnostics=json",
+    "--deny-warnings",
+    "--timings",
+];
+
+struct Options {
+    use_vm: bool,
+    dump_tokens: bool,
+    dump_ast: bool,
+    // Still execute the program after dumping it.
+    run: bool,
+    format: bool,
+    // Only check the program, without running it.
+    lint: bool,
+    // Report errors and warnings, without running the program.
+    check: bool,
+    // Print diagnostics as JSON objects, one per line, for editors.
+    json: bool,
+    // Show which lines ran afterwards.
+    coverage: bool,
+    // Warnings stop the program like errors do.
+    deny_warnings: bool,
+    // Sizes of the program and time spent in each stage, afterwards.
+    timings: bool,
+}
+
+impl Options {
+    fn execute(&self) -> bool {
+        !self.check && (self.run || !(self.dump_tokens || self.dump_ast))
+    }
+}
+
+// Scan and parse errors point at their spans, runtime errors get the line of
+// the statement that failed unless they know better. Returns the exit code
+// for the error.
+fn push_error(sink: &mut DiagnosticSink, err: RoxyError, line: usize) -> i32 {
+    let headings: Vec<String> = match &err {
+        RoxyError::Scan(errors) => errors.iter().map(ToString::to_string).collect(),
+        RoxyError::Parse(errors) => errors.iter().map(ToString::to_string).collect(),
+        RoxyError::Runtime(err) => vec![format!("{}\n[line {}]", err, err.line().unwrap_or(line))],
+    };
+    for (mut diagnostic, heading) in err.errors().zip(headings) {
+        diagnostic.line = diagnostic.line.or(Some(line));
+        sink.push(diagnostic, heading);
+    }
+    match err {
+        RoxyError::Runtime(_) => EX_SOFTWARE,
+        _ => EX_DATAERR,
+    }
+}
+
+fn push_warning(sink: &mut DiagnosticSink, diagnostic: Diagnostic) {
+    let heading = format!(
+        "[line {}] Warning: {}",
+        diagnostic.line.unwrap_or(0),
+        diagnostic.message
+    );
+    sink.push(diagnostic, heading);
+}
+
+// Everything the CLI reports is printed here, in source order. `file` is
+// only set for programs that don't come from a file, which would otherwise
+// go unnamed. Runtime errors only know their line, so the whole line is
+// underlined.
+fn flush(sink: &mut DiagnosticSink, source: &str, file: Option<&str>, json: bool) {
+    for (mut diagnostic, heading) in sink.take(source) {
+        if json {
+            diagnostic.file = file.map(str::to_string);
+            eprintln!("{}", diagnostic.to_json(source));
+        } else {
+            let span = diagnostic
+                .span
+                .unwrap_or_else(|| SourceMap::new(source).line_span(diagnostic.line.unwrap_or(0)));
+            eprintln!("{}", render_diagnostic_in(file, source, span, &heading));
+        }
+    }
+}
+
+fn report(source: &str, file: Option<&str>, err: RoxyError, line: usize, json: bool) -> i32 {
+    let mut sink = DiagnosticSink::new();
+    let code = push_error(&mut sink, err, line);
+    flush(&mut sink, source, file, json);
+    code
+}
+
+fn run(
+    interpreter: &mut Interpreter,
+    statements: Vec<Stmt>,
+    use_vm: bool,
+    sink: &mut DiagnosticSink,
+) -> Result<(), i32> {
+    if use_vm {
+        let mut vm = Vm::new();
+        Compiler::compile(&statements)
+            .and_then(|chunk| vm.run_with(&chunk, interpreter))
+            .map(|_| ())
+            .map_err(|err| push_error(sink, RoxyError::Runtime(err), vm.line()))
+    } else {
+        interpreter
+            .interpret_ast(&Ast::from_stmts(&statements))
+            .map_err(|err| push_error(sink, RoxyError::Runtime(err), interpreter.line()))
+    }
+}
+
+// `-` reads the whole program from stdin, e.g. `cat gen.lox | roxy -`.
+fn run_file(interpreter: &mut Interpreter, path: &str, options: &Options) -> Result<(), i32> {
+    let (source, file) = if path == "-" {
+        let mut source = String::new();
+        io::stdin().read_to_string(&mut source).map_err(|err| {
+            eprintln!("Could not read stdin: {}", err);
+            EX_NOINPUT
+        })?;
+        (source, Some("<stdin>"))
+    } else {
+        let source = fs::read_to_string(path).map_err(|err| {
+            eprintln!("Could not read '{}': {}", path, err);
+            EX_NOINPUT
+        })?;
+        (source, None)
+    };
+    if options.format {
+        let formatted = roxy::format_source(&source)
+            .map_err(|errors| report(&source, file, RoxyError::Parse(errors), 0, options.json))?;
+        print!("{}", formatted);
+        return Ok(());
+    }
+    let mut sink = DiagnosticSink::new();
+    let mut timings = PipelineReport::default();
+    let start = Instant::now();
+    let mut scanner = Scanner::new(source.clone());
+    let tokens = scanner.scan_tokens().cloned();
+    timings.scan = start.elapsed();
+    let mut warnings = scanner.warnings().len();
+    for warning in scanner.warnings() {
+        push_warning(&mut sink, warning.clone());
+    }
+    let tokens = tokens.map_err(|errors| {
+        push_error(&mut sink, RoxyError::Scan(errors), 0);
+        flush(&mut sink, &source, file, options.json);
+        EX_DATAERR
+    })?;
+    if options.dump_tokens {
+        tokens.iter().for_each(|token| println!("{}", token));
+    }
+    let start = Instant::now();
+    let (statements, errors) = Parser::new(tokens.clone()).parse_partial();
+    timings.parse = start.elapsed();
+    if !errors.is_empty() {
+        // What did parse is still checked, so that everything wrong with the
+        // program shows at once.
+        for warning in Resolver::resolve(&statements) {
+            push_warning(&mut sink, Diagnostic::from(&warning));
+        }
+        push_error(&mut sink, RoxyError::Parse(errors), 0);
+        flush(&mut sink, &source, file, options.json);
+        return Err(EX_DATAERR);
+    }
+    if options.timings {
+        timings.count(&tokens, &statements);
+    }
+    if options.dump_ast {
+        print!("{}", ast_printer::print(&statements));
+    }
+    if options.lint {
+        let lints = lint::lint(&source, &statements, &LintOptions::default());
+        warnings += lints.len();
+        for lint in lints {
+            sink.push(Diagnostic::from(&lint), lint.to_string());
+        }
+        flush(&mut sink, &source, file, options.json);
+        return denied(warnings, options);
+    }
+    // The REPL skips this, a local there is usually typed to be printed later.
+    let start = Instant::now();
+    let resolved = Resolver::resolve(&statements);
+    timings.resolve = start.elapsed();
+    warnings += resolved.len();
+    for warning in resolved {
+        push_warning(&mut sink, Diagnostic::from(&warning));
+    }
+    flush(&mut sink, &source, file, options.json);
+    denied(warnings, options)?;
+    interpreter.set_source(&source);
+    if options.execute() {
+        let start = Instant::now();
+        let result = run(interpreter, statements, options.use_vm, &mut sink);
+        timings.run = start.elapsed();
+        flush(&mut sink, &source, file, options.json);
+        // Also when the program failed, up to where it did.
+        if options.coverage {
+            eprint!("{}", interpreter.coverage_report().render(&source));
+        }
+        if options.timings {
+            eprint!("{}", timings.table());
+        }
+        result?;
+    } else if options.timings {
+        eprint!("{}", timings.table());
+    }
+    Ok(())
+}
+
+fn denied(warnings: usize, options: &Options) -> Result<(), i32> {
+    if options.deny_warnings && warnings > 0 {
+        if !options.json {
+            eprintln!(
+                "Stopping because of {} warning(s), --deny-warnings is on.",
+                warnings
+            );
+        }
+        return Err(EX_DATAERR);
+    }
+    Ok(())
+}
+
+// Keeps going after errors. When the input is piped in, the exit code is the
+// one of the last error so scripts can still tell that something failed.
+fn repl(interpreter: &mut Interpreter, use_vm: bool) -> Result<(), i32> {
+    // Lone expressions are evaluated without `interpret`, which would load it.
+    interpreter.load_prelude().map_err(|err| {
+        eprintln!("{}", err);
+        EX_SOFTWARE
+    })?;
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    let mut source = String::new();
+    let mut result = Ok(());
+    loop {
+        print!("{}", if source.is_empty() { "> " } else { "... " });
+        let _ = io::stdout().flush();
+        let line = match lines.next() {
+            Some(Ok(line)) => line,
+            _ => break,
+        };
+        if source.is_empty() && line.trim() == "exit" {
+            return result;
+        }
+        source.push_str(&line);
+        source.push('\n');
+        interpreter.set_source(&source);
+        let mut sink = DiagnosticSink::new();
+        let outcome = match roxy::parse(&source) {
+            Ok(statements) => match statements.as_slice() {
+                // A lone expression is echoed back like in most REPLs.
+                [Stmt::Expression { expr, line, .. }] if !use_vm => interpreter
+                    .eval(expr)
+                    .map(|value| println!("{}", value.repr()))
+                    .map_err(|err| push_error(&mut sink, RoxyError::Runtime(err), *line)),
+                _ => run(interpreter, statements, use_vm, &mut sink),
+            },
+            Err(err) if err.at_end() => continue,
+            Err(err) => Err(push_error(&mut sink, err, 0)),
+        };
+        for warning in interpreter.take_warnings() {
+            push_warning(&mut sink, warning);
+        }
+        flush(&mut sink, &source, None, false);
+        if outcome.is_err() {
+            result = outcome;
+        }
+        source.clear();
+    }
+    // Only an unfinished statement is left over when the input ends.
+    if let Err(err) = roxy::parse(&source) {
+        result = Err(report(&source, None, err, 0, false));
+    }
+    println!();
+    result
+}
 
 fn main() {
-    let source = r#"print 1 + 2;"#.to_string();
-    let mut scanner = Scanner::new(source);
-    let tokens = scanner.scan_tokens();
-    let mut parser = Parser::new(tokens.clone());
-    let stmts = parser.parse();
-    Interpreter::interpret(stmts);
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let (flags, paths): (Vec<&String>, Vec<&String>) =
+        args.iter().partition(|arg| arg.starts_with("--"));
+    if let Some(flag) = flags.iter().find(|flag| !FLAGS.contains(&flag.as_str())) {
+        eprintln!("Unknown flag '{}'.", flag);
+        eprintln!("Usage: roxy [script | -]");
+        process::exit(EX_USAGE);
+    }
+    let coverage = flags.iter().any(|arg| *arg == "--coverage");
+    let mut interpreter = Interpreter::with_options(InterpreterOptions {
+        coverage,
+        // Scripts are checked before they run, the REPL never is.
+        warn_cross_type_equality: paths.is_empty(),
+        deterministic: flags.iter().any(|arg| *arg == "--deterministic"),
+        #[cfg(feature = "default-prelude")]
+        prelude: Some(roxy::interpreter::DEFAULT_PRELUDE.to_string()),
+        ..InterpreterOptions::default()
+    });
+    interpreter.set_trace(flags.iter().any(|arg| *arg == "--trace"));
+    let profile = flags.iter().any(|arg| *arg == "--profile");
+    interpreter.set_profile(profile);
+    let options = Options {
+        use_vm: flags.iter().any(|arg| *arg == "--vm"),
+        dump_tokens: flags.iter().any(|arg| *arg == "--dump-tokens"),
+        dump_ast: flags.iter().any(|arg| *arg == "--dump-ast"),
+        run: flags.iter().any(|arg| *arg == "--run"),
+        format: flags.iter().any(|arg| *arg == "--format"),
+        lint: flags.iter().any(|arg| *arg == "--lint"),
+        check: flags.iter().any(|arg| *arg == "--check"),
+        json: flags.iter().any(|arg| *arg == "--diagnostics=json"),
+        coverage,
+        deny_warnings: flags.iter().any(|arg| *arg == "--deny-warnings"),
+        timings: flags.iter().any(|arg| *arg == "--timings"),
+    };
+    let result = match paths.as_slice() {
+        [] => repl(&mut interpreter, options.use_vm),
+        [path] => run_file(&mut interpreter, path, &options),
+        _ => {
+            eprintln!("Usage: roxy [script | -]");
+            Err(EX_USAGE)
+        }
+    };
+    // Also when the program failed, up to where it did.
+    if profile {
+        eprint!("{}", interpreter.stats().table());
+    }
+    if let Err(code) = result {
+        process::exit(code);
+    }
 }
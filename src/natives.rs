@@ -0,0 +1,815 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    callable::{Callable, NativeFunction},
+    interpreter::{format_fixed, format_precision, Interpreter, Value},
+    scanner::parse_number,
+};
+
+// What `random` starts from in deterministic mode, and in `run_capture`
+// when the host gives no seed.
+pub(crate) const DETERMINISTIC_SEED: u64 = 0x5eed;
+
+// Functions every interpreter starts with.
+pub fn define(interpreter: &mut Interpreter) {
+    // Strings the natives build are checked against it before they are.
+    let max = interpreter.options().max_string_len;
+    // Counts chars so it agrees with indexing.
+    interpreter.define_native("len", 1, |arguments| match &arguments[0] {
+        Value::String(s) => Ok(Value::Number(s.chars().count() as f64)),
+        // Counted without walking the range, they can be huge.
+        range @ Value::Range { .. } => {
+            let values = range.range_values().expect("value is a range");
+            Ok(Value::Number(
+                values.end.saturating_sub(values.start).max(0) as f64,
+            ))
+        }
+        _ => Err("len() expects a string or a range.".to_string()),
+    });
+    interpreter.define_native("type", 1, |arguments| {
+        Ok(Value::from(arguments[0].type_name()))
+    });
+    // Only nil and false are false, 0 and "" are true.
+    interpreter.define_native("bool", 1, |arguments| {
+        Ok(Value::Boolean(Interpreter::is_truthy(&arguments[0])))
+    });
+    // Same text `print` shows.
+    interpreter.define_native("str", 1, |arguments| {
+        Ok(Value::from(arguments[0].to_string()))
+    });
+    // nil instead of an error so scripts can check the input.
+    interpreter.define_native("parseNumber", 1, |arguments| match &arguments[0] {
+        Value::String(s) => Ok(parse_number(s).map_or(Value::Nil, Value::Number)),
+        _ => Err("parseNumber() expects a string.".to_string()),
+    });
+    interpreter.define_variadic_native("format", 1, move |arguments| match &arguments[0] {
+        Value::String(template) => format(template, &arguments[1..], max).map(Value::from),
+        _ => Err("format() expects a string template.".to_string()),
+    });
+    // Prints what `format` returns, through the print handler and the
+    // output limit like `print`.
+    interpreter.add_native(NativeFunction::variadic_with_interpreter(
+        "printf",
+        1,
+        move |interpreter, arguments| match &arguments[0] {
+            Value::String(template) => {
+                let text = format(template, &arguments[1..], max)?;
+                interpreter
+                    .print_value(Value::from(text))
+                    .map_err(|err| err.to_string())?;
+                Ok(Value::Nil)
+            }
+            _ => Err("printf() expects a string template.".to_string()),
+        },
+    ));
+    interpreter.define_native("replace", 3, move |arguments| match arguments {
+        [Value::String(s), Value::String(from), Value::String(to)] => {
+            if from.is_empty() {
+                return Err("replace() can't replace an empty string.".to_string());
+            }
+            let matches = s.matches(&**from).count();
+            let bytes =
+                (s.len() - matches * from.len()).saturating_add(matches.saturating_mul(to.len()));
+            check_length("replace", bytes, max)?;
+            Ok(Value::from(s.replace(&**from, to)))
+        }
+        _ => Err("replace() expects three strings.".to_string()),
+    });
+    interpreter.define_native("startsWith", 2, |arguments| match arguments {
+        [Value::String(s), Value::String(prefix)] => Ok(Value::Boolean(s.starts_with(&**prefix))),
+        _ => Err("startsWith() expects two strings.".to_string()),
+    });
+    interpreter.define_native("endsWith", 2, |arguments| match arguments {
+        [Value::String(s), Value::String(suffix)] => Ok(Value::Boolean(s.ends_with(&**suffix))),
+        _ => Err("endsWith() expects two strings.".to_string()),
+    });
+    interpreter.define_native("repeat", 2, move |arguments| match arguments {
+        [Value::String(s), count] => {
+            let count = whole_number("repeat", count)?;
+            check_length("repeat", s.len().saturating_mul(count), max)?;
+            Ok(Value::from(s.repeat(count)))
+        }
+        _ => Err("repeat() expects a string and a count.".to_string()),
+    });
+    // Widths are in chars like `len`, and the pad is cut off where the width
+    // is reached. Longer strings are left alone.
+    interpreter.define_native("padStart", 3, move |arguments| match arguments {
+        [Value::String(s), width, Value::String(pad)] => {
+            let width = whole_number("padStart", width)?;
+            if pad.is_empty() {
+                return Err("padStart() can't pad with an empty string.".to_string());
+            }
+            let missing = width.saturating_sub(s.chars().count());
+            check_length(
+                "padStart",
+                s.len().saturating_add(missing.saturating_mul(pad.len())),
+                max,
+            )?;
+            let mut padded: String = pad.chars().cycle().take(missing).collect();
+            padded.push_str(s);
+            Ok(Value::from(padded))
+        }
+        _ => Err("padStart() expects a string, a width and a pad string.".to_string()),
+    });
+    // Unlike indexing it never fails on the bounds: negative ones count from
+    // the end, nil is the start or the end, and anything past either end is
+    // cut back to it. Counted in chars like `len`.
+    interpreter.define_native("slice", 3, |arguments| match arguments {
+        [Value::String(s), start, end] => {
+            let length = s.chars().count();
+            let start = bound("slice", start, 0, length)?;
+            let end = bound("slice", end, length, length)?;
+            Ok(Value::from(
+                s.chars()
+                    .skip(start)
+                    .take(end.saturating_sub(start))
+                    .collect::<String>(),
+            ))
+        }
+        _ => Err("slice() expects a string, a start and an end.".to_string()),
+    });
+    // Digits are limited like in JavaScript, more is never what was meant.
+    interpreter.define_native("toFixed", 2, |arguments| match arguments {
+        [Value::Number(n), Value::Number(digits)] if is_digits(*digits, 0.0) => {
+            Ok(Value::from(format_fixed(*n, *digits as usize)))
+        }
+        _ => Err("toFixed() expects a number and 0 to 100 digits.".to_string()),
+    });
+    interpreter.define_native("toPrecision", 2, |arguments| match arguments {
+        [Value::Number(n), Value::Number(digits)] if is_digits(*digits, 1.0) => {
+            Ok(Value::from(format_precision(*n, *digits as usize)))
+        }
+        _ => Err("toPrecision() expects a number and 1 to 100 significant digits.".to_string()),
+    });
+    // Variadic natives give the least they take, there is no pair to return
+    // a range of arities in yet.
+    interpreter.define_native("arity", 1, |arguments| match &arguments[0] {
+        Value::NativeFunction(native) => Ok(Value::Number(native.arity() as f64)),
+        _ => Err("arity() expects a function.".to_string()),
+    });
+    interpreter.define_native("name", 1, |arguments| match &arguments[0] {
+        Value::NativeFunction(native) => Ok(Value::from(native.name())),
+        _ => Err("name() expects a function.".to_string()),
+    });
+    interpreter.define_native("toJson", 1, |arguments| {
+        arguments[0]
+            .to_json()
+            .map(Value::from)
+            .map_err(|err| err.to_string())
+    });
+    interpreter.define_native("fromJson", 1, |arguments| match &arguments[0] {
+        Value::String(json) => Value::from_json(json).map_err(|err| err.to_string()),
+        _ => Err("fromJson() expects a string.".to_string()),
+    });
+    if interpreter.options().deterministic {
+        let calls = AtomicU64::new(0);
+        interpreter.define_native("clock", 0, move |_| {
+            let calls = calls.fetch_add(1, Ordering::Relaxed);
+            Ok(Value::Number(calls as f64 / 1000.0))
+        });
+    } else if interpreter.options().allow_clock {
+        // Seconds since the Unix epoch, like clock() in the book.
+        interpreter.define_native("clock", 0, |_| {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_err(|err| err.to_string())?;
+            Ok(Value::Number(now.as_secs_f64()))
+        });
+    }
+    if interpreter.options().allow_sleep {
+        interpreter.define_native("sleep", 1, |arguments| match &arguments[0] {
+            Value::Number(seconds) if *seconds >= 0.0 && seconds.is_finite() => {
+                let duration = Duration::try_from_secs_f64(*seconds)
+                    .map_err(|_| "sleep duration out of range".to_string())?;
+                thread::sleep(duration);
+                Ok(Value::Nil)
+            }
+            _ => Err("sleep() expects a number of seconds of at least 0.".to_string()),
+        });
+    }
+    let options = interpreter.options();
+    let seed = options.rng_seed.unwrap_or_else(|| {
+        if options.deterministic {
+            return DETERMINISTIC_SEED;
+        }
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |now| now.as_nanos() as u64)
+    });
+    let rng = Arc::new(Mutex::new(Rng(seed)));
+    let shared = Arc::clone(&rng);
+    // In [0, 1), with all 53 bits of precision.
+    interpreter.define_native("random", 0, move |_| {
+        let bits = shared.lock().unwrap().next() >> 11;
+        Ok(Value::Number(bits as f64 / (1u64 << 53) as f64))
+    });
+    // Both bounds are included.
+    interpreter.define_native("randomInt", 2, move |arguments| {
+        let (low, high) = match arguments {
+            [Value::Number(low), Value::Number(high)] if is_int(*low) && is_int(*high) => {
+                (*low as i64, *high as i64)
+            }
+            _ => return Err("randomInt() expects two whole numbers.".to_string()),
+        };
+        if low > high {
+            return Err("randomInt() expects the lower bound first.".to_string());
+        }
+        let span = (high - low) as u64 + 1;
+        let offset = rng.lock().unwrap().next() % span;
+        Ok(Value::Number((low + offset as i64) as f64))
+    });
+}
+
+// splitmix64, small and good enough for scripts. Not for anything that
+// has to be unpredictable.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+fn is_digits(n: f64, min: f64) -> bool {
+    n.fract() == 0.0 && (min..=100.0).contains(&n)
+}
+
+// Whole and small enough that every integer up to it is a number too.
+fn is_int(n: f64) -> bool {
+    n.fract() == 0.0 && n.abs() <= (1u64 << 53) as f64
+}
+
+fn whole_number(native: &str, value: &Value) -> Result<usize, String> {
+    match value {
+        Value::Number(n) if *n >= 0.0 && n.fract() == 0.0 => Ok(*n as usize),
+        _ => Err(format!(
+            "{}() expects a whole number of at least 0.",
+            native
+        )),
+    }
+}
+
+// A `slice` bound as an offset from the start, at most `length`.
+fn bound(native: &str, value: &Value, default: usize, length: usize) -> Result<usize, String> {
+    match value {
+        Value::Nil => Ok(default),
+        Value::Number(n) if n.fract() == 0.0 => {
+            let from_end = length as f64 + n;
+            Ok(if *n < 0.0 {
+                from_end.max(0.0)
+            } else {
+                n.min(length as f64)
+            } as usize)
+        }
+        _ => Err(format!(
+            "{}() expects whole numbers or nil as bounds.",
+            native
+        )),
+    }
+}
+
+// Strings built by natives are capped by `max_string_len`, a typo in a count
+// would otherwise take all the memory there is.
+fn check_length(native: &str, bytes: usize, max: usize) -> Result<(), String> {
+    if bytes > max {
+        return Err(format!(
+            "{}() would make a string of {} bytes, over the size limit of {}.",
+            native, bytes, max
+        ));
+    }
+    Ok(())
+}
+
+// `{}` takes the next argument and `{1}` a given one, `{{` and `}}` are
+// literal braces. Arguments no placeholder refers to are ignored.
+fn format(template: &str, arguments: &[Value], max: usize) -> Result<String, String> {
+    let mut out = String::new();
+    let mut chars = template.chars().peekable();
+    let mut next = 0;
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                out.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                out.push('}');
+            }
+            '{' => {
+                let mut digits = String::new();
+                loop {
+                    match chars.next() {
+                        Some('}') => break,
+                        Some(c) if c.is_ascii_digit() => digits.push(c),
+                        _ => return Err("Invalid placeholder in format string.".to_string()),
+                    }
+                }
+                let index = if digits.is_empty() {
+                    next += 1;
+                    next - 1
+                } else {
+                    digits
+                        .parse()
+                        .map_err(|_| "Invalid placeholder in format string.".to_string())?
+                };
+                match arguments.get(index) {
+                    Some(value) => {
+                        let text = value.to_string();
+                        check_length("format", out.len().saturating_add(text.len()), max)?;
+                        out.push_str(&text);
+                    }
+                    None => return Err(format!("No argument for placeholder {}.", index)),
+                }
+            }
+            '}' => return Err("Unmatched '}' in format string.".to_string()),
+            c => out.push(c),
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod natives_tests {
+    use crate::{
+        interpreter::{Interpreter, InterpreterOptions, RuntimeError, Value},
+        parser::Parser,
+        scanner::Scanner,
+        Runner,
+    };
+
+    fn run(source: &str) -> Result<Option<Value>, RuntimeError> {
+        let mut scanner = Scanner::new(source.to_string());
+        let tokens = scanner.scan_tokens().unwrap();
+        let statements = Parser::new(tokens.clone()).parse().unwrap();
+        let mut interpreter = Interpreter::new();
+        interpreter.interpret(statements)?;
+        Ok(interpreter.get_global("result"))
+    }
+
+    fn result(source: &str) -> Value {
+        run(source).unwrap().unwrap()
+    }
+
+    #[test]
+    fn type_names() {
+        let cases = [
+            ("1", "number"),
+            (r#""s""#, "string"),
+            ("true", "boolean"),
+            ("nil", "nil"),
+            ("type", "function"),
+            ("1..2", "range"),
+        ];
+        for (value, name) in cases {
+            assert_eq!(
+                result(&format!("var result = type({});", value)),
+                Value::from(name)
+            );
+        }
+    }
+
+    #[test]
+    fn callable_metadata() {
+        let mut interpreter = Interpreter::new();
+        interpreter.define_variadic_native("log", 2, |_| Ok(Value::Nil));
+        crate::run_with(
+            "var a = arity(log); var n = name(log); var s = str(log);",
+            &mut interpreter,
+        )
+        .unwrap();
+        assert_eq!(interpreter.get_global("a"), Some(Value::Number(2.0)));
+        assert_eq!(interpreter.get_global("n"), Some(Value::from("log")));
+        assert_eq!(
+            interpreter.get_global("s"),
+            Some(Value::from("<native log>"))
+        );
+        assert_eq!(
+            run("arity(nil);").unwrap_err().to_string(),
+            "arity() expects a function."
+        );
+    }
+
+    #[test]
+    fn bool() {
+        assert_eq!(
+            result(
+                r#"var result = str(bool(0)) + str(bool("")) + str(bool(nil)) + str(bool(false));"#
+            ),
+            Value::from("truetruefalsefalse")
+        );
+        assert_eq!(
+            run("bool();").unwrap_err().to_string(),
+            "Expected 1 arguments but got 0."
+        );
+    }
+
+    #[test]
+    fn slice() {
+        let cases = [
+            (r#""hello", 0, nil"#, "hello"),
+            (r#""hello", nil, nil"#, "hello"),
+            (r#""hello", 1, 3"#, "el"),
+            (r#""hello", -3, nil"#, "llo"),
+            (r#""hello", -3, -1"#, "ll"),
+            (r#""hello", -10, 2"#, "he"),
+            (r#""hello", 2, 100"#, "llo"),
+            (r#""hello", 3, 3"#, ""),
+            (r#""hello", 4, 1"#, ""),
+            (r#""hello", 5, nil"#, ""),
+            (r#""hello", 100, nil"#, ""),
+            (r#""hello", nil, -5"#, ""),
+            (r#""", 0, nil"#, ""),
+            (r#""héllo wörld", 1, 8"#, "éllo wö"),
+            (r#""日本語", -2, nil"#, "本語"),
+        ];
+        for (arguments, expected) in cases {
+            assert_eq!(
+                result(&format!("var result = slice({});", arguments)),
+                Value::from(expected),
+                "{}",
+                arguments
+            );
+        }
+        for arguments in [r#""a", "0", nil"#, "1, 0, nil"] {
+            assert!(
+                run(&format!("slice({});", arguments)).is_err(),
+                "{}",
+                arguments
+            );
+        }
+        assert_eq!(
+            run(r#"slice("a", 1.5, nil);"#).unwrap_err().to_string(),
+            "slice() expects whole numbers or nil as bounds."
+        );
+    }
+
+    #[test]
+    fn str() {
+        assert_eq!(
+            result(r#"var result = str(2.0) + str(nil) + str(1 < 2) + str("s") + str(str);"#),
+            Value::from("2niltrues<native str>")
+        );
+        assert_eq!(
+            run("str();").unwrap_err().to_string(),
+            "Expected 1 arguments but got 0."
+        );
+        assert_eq!(
+            run("type(1, 2);").unwrap_err().to_string(),
+            "Expected 1 arguments but got 2."
+        );
+    }
+
+    #[test]
+    fn range_len() {
+        Runner::new()
+            .source("print len(1..5); print len(1..=5); print len(5..1);")
+            .expect_output("4\n5\n0\n")
+            .run();
+        Runner::new()
+            .source("len(1);")
+            .expect_runtime_error("len() expects a string or a range.")
+            .run();
+    }
+
+    #[test]
+    fn format() {
+        let cases = [
+            (r#"format("{} + {} = {}", 1, 2, 1 + 2)"#, "1 + 2 = 3"),
+            (r#"format("{1}{0}{1}", "a", "b")"#, "bab"),
+            (r#"format("{}, {0}, {}", "x", "y")"#, "x, x, y"),
+            (r#"format("{{{}}} }}", nil)"#, "{nil} }"),
+            (
+                r#"format("{} {}", 0.1 + 0.2, 1000000000 * 1000000000000)"#,
+                "0.30000000000000004 1e21",
+            ),
+            (r#"format("no placeholders", 1, 2)"#, "no placeholders"),
+        ];
+        for (call, expected) in cases {
+            assert_eq!(
+                result(&format!("var result = {};", call)),
+                Value::from(expected),
+                "{}",
+                call
+            );
+        }
+    }
+
+    #[test]
+    fn format_errors() {
+        let cases = [
+            (r#"format("{} {}", 1);"#, "No argument for placeholder 1."),
+            (r#"format("{2}", 1, 2);"#, "No argument for placeholder 2."),
+            (r#"format("{x}");"#, "Invalid placeholder in format string."),
+            (r#"format("{");"#, "Invalid placeholder in format string."),
+            (r#"format("}");"#, "Unmatched '}' in format string."),
+            ("format(1);", "format() expects a string template."),
+            ("format();", "Expected at least 1 arguments but got 0."),
+        ];
+        for (source, message) in cases {
+            assert_eq!(run(source).unwrap_err().to_string(), message, "{}", source);
+        }
+    }
+
+    #[test]
+    fn printf() {
+        Runner::new()
+            .source(
+                r#"printf("{} + {} = {}", 1, 2.5, 1 + 2.5); print 0.1 + 0.2; printf("{}", 0.1 + 0.2);"#,
+            )
+            .expect_output("1 + 2.5 = 3.5\n0.30000000000000004\n0.30000000000000004\n")
+            .run();
+        Runner::new()
+            .source(r#"printf("{} {}", 1);"#)
+            .expect_runtime_error("No argument for placeholder 1.")
+            .run();
+        Runner::new()
+            .source("printf(1);")
+            .expect_runtime_error("printf() expects a string template.")
+            .run();
+        let mut interpreter = Interpreter::with_options(InterpreterOptions {
+            max_output_bytes: Some(4),
+            ..InterpreterOptions::default()
+        });
+        interpreter.set_print_handler(|_| {});
+        assert_eq!(
+            interpreter
+                .call_function(
+                    "printf",
+                    vec![Value::from("{}{}"), Value::from("abc"), Value::from("def")]
+                )
+                .unwrap_err()
+                .to_string(),
+            RuntimeError::OutputLimit(4).to_string()
+        );
+    }
+
+    #[test]
+    fn parse_number() {
+        assert_eq!(
+            result(r#"var result = parseNumber(" 42.5 ") + 1;"#),
+            Value::Number(43.5)
+        );
+        assert_eq!(result(r#"var result = parseNumber("abc");"#), Value::Nil);
+        assert_eq!(
+            run("parseNumber(1);").unwrap_err().to_string(),
+            "parseNumber() expects a string."
+        );
+    }
+
+    #[test]
+    fn replace() {
+        Runner::new()
+            .source(r#"print replace("aaa-aaa", "aa", "b"); print replace("abc", "x", "y");"#)
+            .expect_output("ba-ba\nabc\n")
+            .run();
+        Runner::new()
+            .source(r#"replace("abc", "", "y");"#)
+            .expect_runtime_error("replace() can't replace an empty string.")
+            .run();
+        Runner::new()
+            .source(r#"replace("abc", 1, "y");"#)
+            .expect_runtime_error("replace() expects three strings.")
+            .run();
+    }
+
+    #[test]
+    fn starts_and_ends_with() {
+        Runner::new()
+            .source(
+                r#"
+                print startsWith("roxy", "ro");
+                print startsWith("roxy", "xy");
+                print endsWith("roxy", "xy");
+                print endsWith("roxy", "");
+                "#,
+            )
+            .expect_output("true\nfalse\ntrue\ntrue\n")
+            .run();
+        Runner::new()
+            .source(r#"endsWith(nil, "a");"#)
+            .expect_runtime_error("endsWith() expects two strings.")
+            .run();
+    }
+
+    #[test]
+    fn repeat() {
+        assert_eq!(
+            result(r#"var result = repeat("ab", 3);"#),
+            Value::from("ababab")
+        );
+        assert_eq!(result(r#"var result = repeat("ab", 0);"#), Value::from(""));
+        for count in ["-1", "1.5", "\"2\""] {
+            assert_eq!(
+                run(&format!(r#"repeat("ab", {});"#, count))
+                    .unwrap_err()
+                    .to_string(),
+                "repeat() expects a whole number of at least 0.",
+                "{}",
+                count
+            );
+        }
+        assert_eq!(
+            run(r#"repeat("ab", 1000000000);"#).unwrap_err().to_string(),
+            "repeat() would make a string of 2000000000 bytes, over the size limit of 1073741824."
+        );
+        assert_eq!(
+            run("repeat(1, 2);").unwrap_err().to_string(),
+            "repeat() expects a string and a count."
+        );
+    }
+
+    #[test]
+    fn pad_start() {
+        let cases = [
+            (r#"padStart("7", 3, "0")"#, "007"),
+            (r#"padStart("7", 6, "ab")"#, "ababa7"),
+            (r#"padStart("long", 2, " ")"#, "long"),
+            (r#"padStart("é", 2, "·")"#, "·é"),
+        ];
+        for (call, expected) in cases {
+            assert_eq!(
+                result(&format!("var result = {};", call)),
+                Value::from(expected),
+                "{}",
+                call
+            );
+        }
+        assert_eq!(
+            run(r#"padStart("7", 3, "");"#).unwrap_err().to_string(),
+            "padStart() can't pad with an empty string."
+        );
+        assert_eq!(
+            run(r#"padStart(7, 3, " ");"#).unwrap_err().to_string(),
+            "padStart() expects a string, a width and a pad string."
+        );
+    }
+
+    fn seeded(seed: u64) -> Interpreter {
+        Interpreter::with_options(InterpreterOptions {
+            rng_seed: Some(seed),
+            ..InterpreterOptions::default()
+        })
+    }
+
+    fn draws(interpreter: &mut Interpreter, call: &str, count: usize) -> Vec<f64> {
+        (0..count)
+            .map(
+                |_| match interpreter.call_function(call, Vec::new()).unwrap() {
+                    Value::Number(n) => n,
+                    other => panic!("expected a number, got {:?}", other),
+                },
+            )
+            .collect()
+    }
+
+    #[test]
+    fn deterministic() {
+        let run = |options: InterpreterOptions| {
+            let mut interpreter = Interpreter::with_options(options);
+            draws(&mut interpreter, "clock", 3)
+                .into_iter()
+                .chain(draws(&mut interpreter, "random", 3))
+                .collect::<Vec<_>>()
+        };
+        let options = InterpreterOptions {
+            deterministic: true,
+            ..InterpreterOptions::default()
+        };
+        let first = run(options.clone());
+        assert_eq!(first, run(options.clone()));
+        assert_eq!(first[..3], [0.0, 0.001, 0.002]);
+        // An explicit seed still counts.
+        assert_ne!(
+            first,
+            run(InterpreterOptions {
+                rng_seed: Some(1),
+                ..options
+            })
+        );
+    }
+
+    #[test]
+    fn seeded_random() {
+        let first = draws(&mut seeded(7), "random", 100);
+        assert_eq!(first, draws(&mut seeded(7), "random", 100));
+        assert_ne!(first, draws(&mut seeded(8), "random", 100));
+        assert!(first.iter().all(|n| (0.0..1.0).contains(n)));
+    }
+
+    #[test]
+    fn random_int() {
+        let mut interpreter = seeded(1);
+        let mut seen = [false; 6];
+        for _ in 0..1000 {
+            let n = match interpreter
+                .call_function("randomInt", vec![Value::from(-2.0), Value::from(3.0)])
+                .unwrap()
+            {
+                Value::Number(n) => n,
+                other => panic!("expected a number, got {:?}", other),
+            };
+            assert!((-2.0..=3.0).contains(&n) && n.fract() == 0.0, "{}", n);
+            seen[(n + 2.0) as usize] = true;
+        }
+        assert_eq!(seen, [true; 6]);
+        assert_eq!(result("var result = randomInt(4, 4);"), Value::Number(4.0));
+        assert_eq!(
+            run("randomInt(1, 0);").unwrap_err().to_string(),
+            "randomInt() expects the lower bound first."
+        );
+        assert_eq!(
+            run("randomInt(0, 1.5);").unwrap_err().to_string(),
+            "randomInt() expects two whole numbers."
+        );
+    }
+
+    #[test]
+    fn sleep() {
+        let mut interpreter = Interpreter::new();
+        assert_eq!(
+            interpreter.call_function("sleep", vec![Value::from(0.001)]),
+            Ok(Value::Nil)
+        );
+        assert_eq!(
+            interpreter
+                .call_function("sleep", vec![Value::from(-1.0)])
+                .unwrap_err()
+                .to_string(),
+            "sleep() expects a number of seconds of at least 0."
+        );
+        assert_eq!(
+            interpreter
+                .call_function("sleep", vec![Value::from(1e36)])
+                .unwrap_err()
+                .to_string(),
+            "sleep duration out of range"
+        );
+        // Not there in the sandbox.
+        let interpreter = Interpreter::with_options(InterpreterOptions::sandbox());
+        assert!(interpreter.get_global("sleep").is_none());
+    }
+
+    #[test]
+    fn fixed_and_precision() {
+        Runner::new()
+            .source(
+                r#"
+                print toFixed(2.0, 0);
+                print toFixed(0.125, 2);
+                print toFixed(-3.14159, 3);
+                print toFixed(1000000000000000000000, 2);
+                print toPrecision(123.456, 4);
+                print toPrecision(1000000000000000000000, 2);
+                "#,
+            )
+            .expect_output("2\n0.12\n-3.142\n1000000000000000000000.00\n123.5\n1.0e21\n")
+            .run();
+        for call in [
+            "toFixed(1, -1)",
+            "toFixed(1, 101)",
+            "toFixed(1, 1.5)",
+            "toFixed(\"1\", 2)",
+        ] {
+            Runner::new()
+                .source(&format!("{};", call))
+                .expect_runtime_error("toFixed() expects a number and 0 to 100 digits.")
+                .run();
+        }
+        Runner::new()
+            .source("toPrecision(1, 0);")
+            .expect_runtime_error("toPrecision() expects a number and 1 to 100 significant digits.")
+            .run();
+    }
+
+    #[test]
+    fn json() {
+        assert_eq!(
+            run(r#"var result = toJson("a" + "b") + toJson(1);"#),
+            Ok(Some(Value::from("\"ab\"1")))
+        );
+        assert_eq!(
+            run(r#"var result = fromJson(" true ");"#),
+            Ok(Some(Value::Boolean(true)))
+        );
+        assert_eq!(
+            run("toJson(toJson);").unwrap_err().to_string(),
+            "Can't convert a function to JSON."
+        );
+        assert_eq!(
+            run(r#"fromJson("nope");"#).unwrap_err().to_string(),
+            "Invalid JSON: expected 'null'."
+        );
+    }
+}
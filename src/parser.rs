@@ -1,32 +1,245 @@
-use crate::scanner::{Token, TokenKind};
+use std::sync::Arc;
+
+use crate::{
+    diagnostics::{Code, Diagnostic, Span},
+    scanner::{ScanError, Scanner, Token, TokenKind},
+};
+
+// Deeper code would overflow the stack, in the parser and in every pass that
+// walks the tree after it.
+const MAX_DEPTH: usize = 128;
+// Binary operators don't nest in the source, but a chain of them nests to the
+// left in the tree and the passes after the parser recurse through that. So
+// a statement can only have so many.
+const MAX_OPERATORS: usize = 256;
 
 pub struct Parser {
     tokens: Vec<Token>,
+    // Whether a line break came before each token. `Newline` tokens are
+    // taken out, they only matter where a statement can end.
+    newline_before: Vec<bool>,
+    // A line break was scanned while streaming, the next token gets it.
+    pending_newline: bool,
     current: usize,
+    // Nesting of the statement or expression being parsed.
+    depth: usize,
+    // Binary operators in the statement being parsed.
+    operators: usize,
+    // Set when parsing while scanning, tokens are then pulled from it as the
+    // parser gets to them and dropped after every statement.
+    scanner: Option<Scanner>,
+    scan_errors: Vec<ScanError>,
+    finished: bool,
+    // Set by `parse_partial`, which keeps the errors here and goes on.
+    recover: bool,
+    errors: Vec<ParseError>,
+    // Where the errors of the statement being recovered start. Only its
+    // first error is kept, the rest tend to follow from it.
+    statement_errors: usize,
+    // Blocks the parser is in, a recovering block keeps its closing brace.
+    blocks: usize,
+    // Blocks left by an error before their closing brace.
+    unclosed: usize,
+    // Keywords that were used as names. The error is reported where the
+    // name is declared, uses of it are read as variables.
+    reserved: Vec<TokenKind>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ParseError {
+    pub code: Code,
+    pub message: String,
+    pub line: usize,
+    pub span: Span,
+    // The parser ran out of tokens, more input could still make the
+    // program valid. The REPL uses it to ask for a continuation line.
+    pub at_end: bool,
 }
 
+impl ParseError {
+    fn new(token: &Token, code: Code, message: &str) -> Self {
+        Self {
+            code,
+            message: message.to_string(),
+            line: token.line,
+            span: token.span,
+            at_end: token.kind == TokenKind::EOF,
+        }
+    }
+}
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.at_end {
+            write!(f, "[line {}] Error at end: {}", self.line, self.message)
+        } else {
+            write!(f, "[line {}] Error: {}", self.line, self.message)
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum Stmt {
-    Expression(Expr),
-    Print(Expr),
+    Expression {
+        expr: Expr,
+        span: Span,
+        line: usize,
+    },
+    // `print a, b;` prints both on one line.
+    Print {
+        exprs: Vec<Expr>,
+        span: Span,
+        line: usize,
+    },
     Var {
         name: String,
         initializer: Expr,
+        span: Span,
+        line: usize,
+    },
+    Block {
+        statements: Vec<Stmt>,
+        span: Span,
+        line: usize,
     },
-    Block(Vec<Stmt>),
     If {
         condition: Expr,
         then_branch: Box<Stmt>,
         else_branch: Option<Box<Stmt>>,
+        span: Span,
+        line: usize,
+    },
+    // The body, handler and finally block are all block statements.
+    Try {
+        body: Box<Stmt>,
+        // Name the error is bound to and the handler's body.
+        catch: Option<(String, Box<Stmt>)>,
+        finally: Option<Box<Stmt>>,
+        span: Span,
+        line: usize,
+    },
+    Throw {
+        expr: Expr,
+        span: Span,
+        line: usize,
+    },
+    // `for (name in iterable) body`
+    ForIn {
+        name: String,
+        iterable: Expr,
+        body: Box<Stmt>,
+        span: Span,
+        line: usize,
+    },
+    // Stands in for tokens that didn't parse, see `parse_partial`.
+    Error {
+        span: Span,
+        line: usize,
     },
 }
 
+impl Stmt {
+    // From the statement's first token to its last, nested statements
+    // included.
+    pub fn span(&self) -> Span {
+        match self {
+            Stmt::Expression { span, .. }
+            | Stmt::Print { span, .. }
+            | Stmt::Var { span, .. }
+            | Stmt::Block { span, .. }
+            | Stmt::If { span, .. }
+            | Stmt::Try { span, .. }
+            | Stmt::Throw { span, .. }
+            | Stmt::ForIn { span, .. }
+            | Stmt::Error { span, .. } => *span,
+        }
+    }
+
+    // Line of the token the statement starts with.
+    pub fn line(&self) -> usize {
+        match self {
+            Stmt::Expression { line, .. }
+            | Stmt::Print { line, .. }
+            | Stmt::Var { line, .. }
+            | Stmt::Block { line, .. }
+            | Stmt::If { line, .. }
+            | Stmt::Try { line, .. }
+            | Stmt::Throw { line, .. }
+            | Stmt::ForIn { line, .. }
+            | Stmt::Error { line, .. } => *line,
+        }
+    }
+
+    // Whether a part of the statement failed to parse.
+    pub fn has_errors(&self) -> bool {
+        match self {
+            Stmt::Expression { expr, .. } | Stmt::Throw { expr, .. } => expr.has_errors(),
+            Stmt::Print { exprs, .. } => exprs.iter().any(Expr::has_errors),
+            Stmt::Var { initializer, .. } => initializer.has_errors(),
+            Stmt::Block { statements, .. } => statements.iter().any(Stmt::has_errors),
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                condition.has_errors()
+                    || then_branch.has_errors()
+                    || else_branch.as_ref().is_some_and(|stmt| stmt.has_errors())
+            }
+            Stmt::Try {
+                body,
+                catch,
+                finally,
+                ..
+            } => {
+                body.has_errors()
+                    || catch.as_ref().is_some_and(|(_, stmt)| stmt.has_errors())
+                    || finally.as_ref().is_some_and(|stmt| stmt.has_errors())
+            }
+            Stmt::ForIn { iterable, body, .. } => iterable.has_errors() || body.has_errors(),
+            Stmt::Error { .. } => true,
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum Expr {
     Binary(Binary),
     Unary(Unary),
     Literal(Literal),
     Grouping(Grouping),
+    Variable(String),
+    Assign(Assign),
+    Call(Call),
+    Index(Index),
+    SetIndex(SetIndex),
+    Range(Range),
+    Dbg(Dbg),
+    // An expression that didn't parse, covering the tokens skipped over.
+    Error(Span),
+}
+
+impl Expr {
+    pub fn has_errors(&self) -> bool {
+        match self {
+            Expr::Binary(b) => b.left.has_errors() || b.right.has_errors(),
+            Expr::Unary(u) => u.right.has_errors(),
+            Expr::Literal(_) | Expr::Variable(_) => false,
+            Expr::Grouping(g) => g.expr.has_errors(),
+            Expr::Assign(a) => a.value.has_errors(),
+            Expr::Call(c) => c.callee.has_errors() || c.arguments.iter().any(Expr::has_errors),
+            Expr::Index(i) => i.object.has_errors() || i.index.has_errors(),
+            Expr::SetIndex(i) => {
+                i.object.has_errors() || i.index.has_errors() || i.value.has_errors()
+            }
+            Expr::Range(r) => r.start.has_errors() || r.end.has_errors(),
+            Expr::Dbg(d) => d.expr.has_errors(),
+            Expr::Error(_) => true,
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
@@ -48,6 +261,20 @@ pub struct Binary {
     pub left: Box<Expr>,
     pub operator: BinaryOperator,
     pub right: Box<Expr>,
+    // From the left operand to the right one.
+    pub span: Span,
+}
+
+// Dropping takes the left operands apart in a loop, a long chain of them
+// would overflow the stack otherwise. See `Interpreter::binary_chain`. The
+// operands are moved out of their boxes, which keep a nil until they go.
+impl Drop for Binary {
+    fn drop(&mut self) {
+        let mut left = std::mem::replace(&mut *self.left, Expr::Literal(Literal::Nil));
+        while let Expr::Binary(b) = &mut left {
+            left = std::mem::replace(&mut *b.left, Expr::Literal(Literal::Nil));
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
@@ -64,7 +291,8 @@ pub struct Unary {
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Literal {
-    String(String),
+    // Shared with the values it evaluates to.
+    String(Arc<str>),
     Number(f64),
     Boolean(bool),
     Nil,
@@ -75,27 +303,415 @@ pub struct Grouping {
     pub expr: Box<Expr>,
 }
 
+#[derive(Debug, PartialEq, Clone)]
+pub struct Assign {
+    pub name: String,
+    pub value: Box<Expr>,
+}
+
+// `object[index]`, line is the one of the closing bracket.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Index {
+    pub object: Box<Expr>,
+    pub index: Box<Expr>,
+    pub line: usize,
+}
+
+// `object[index] = value`
+#[derive(Debug, PartialEq, Clone)]
+pub struct SetIndex {
+    pub object: Box<Expr>,
+    pub index: Box<Expr>,
+    pub value: Box<Expr>,
+    pub line: usize,
+}
+
+// `start..end`, or `start..=end` when inclusive.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Range {
+    pub start: Box<Expr>,
+    pub end: Box<Expr>,
+    pub inclusive: bool,
+}
+
+// `dbg(expr)`, which prints the expression's source along with its value.
+// The span is the argument's, without the parens.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Dbg {
+    pub expr: Box<Expr>,
+    pub span: Span,
+    pub line: usize,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Call {
+    pub callee: Box<Expr>,
+    pub arguments: Vec<Expr>,
+    // Line of the closing paren, runtime errors in the call are reported there.
+    pub line: usize,
+}
+
+impl std::fmt::Display for BinaryOperator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let operator = match self {
+            BinaryOperator::EqualEqual => "==",
+            BinaryOperator::NotEqual => "!=",
+            BinaryOperator::LessThan => "<",
+            BinaryOperator::LessThanEqual => "<=",
+            BinaryOperator::GreaterThan => ">",
+            BinaryOperator::GreaterThanEqual => ">=",
+            BinaryOperator::Plus => "+",
+            BinaryOperator::Minus => "-",
+            BinaryOperator::Multiply => "*",
+            BinaryOperator::Divide => "/",
+        };
+        write!(f, "{}", operator)
+    }
+}
+
+impl std::fmt::Display for UnaryOperator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UnaryOperator::Minus => write!(f, "-"),
+            UnaryOperator::Not => write!(f, "!"),
+        }
+    }
+}
+
+impl std::fmt::Display for Literal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Literal::String(s) => write!(f, "\"{}\"", s),
+            Literal::Number(n) => write!(f, "{}", n),
+            Literal::Boolean(b) => write!(f, "{}", b),
+            Literal::Nil => write!(f, "nil"),
+        }
+    }
+}
+
+// Prints the expression back as Lox source.
+impl std::fmt::Display for Expr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Expr::Binary(b) => write!(f, "{} {} {}", b.left, b.operator, b.right),
+            Expr::Unary(u) => write!(f, "{}{}", u.operator, u.right),
+            Expr::Literal(l) => write!(f, "{}", l),
+            Expr::Grouping(g) => write!(f, "({})", g.expr),
+            Expr::Variable(name) => write!(f, "{}", name),
+            Expr::Assign(a) => write!(f, "{} = {}", a.name, a.value),
+            Expr::Call(c) => {
+                write!(f, "{}(", c.callee)?;
+                for (i, argument) in c.arguments.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", argument)?;
+                }
+                write!(f, ")")
+            }
+            Expr::Index(i) => write!(f, "{}[{}]", i.object, i.index),
+            Expr::SetIndex(i) => write!(f, "{}[{}] = {}", i.object, i.index, i.value),
+            Expr::Range(r) => {
+                let operator = if r.inclusive { "..=" } else { ".." };
+                write!(f, "{}{}{}", r.start, operator, r.end)
+            }
+            Expr::Dbg(d) => write!(f, "dbg({})", d.expr),
+            Expr::Error(_) => write!(f, "<error>"),
+        }
+    }
+}
+
 impl Parser {
     pub fn new(tokens: Vec<Token>) -> Self {
-        Self { tokens, current: 0 }
+        let mut parser = Self {
+            tokens: Vec::new(),
+            newline_before: Vec::new(),
+            pending_newline: false,
+            current: 0,
+            depth: 0,
+            operators: 0,
+            scanner: None,
+            scan_errors: Vec::new(),
+            finished: false,
+            recover: false,
+            errors: Vec::new(),
+            statement_errors: 0,
+            blocks: 0,
+            unclosed: 0,
+            reserved: Vec::new(),
+        };
+        for token in tokens {
+            parser.push(token);
+        }
+        parser
     }
 
+    fn push(&mut self, token: Token) {
+        if token.kind == TokenKind::Newline {
+            self.pending_newline = true;
+        } else {
+            self.tokens.push(token);
+            self.newline_before.push(self.pending_newline);
+            self.pending_newline = false;
+        }
+    }
+
+    pub fn streaming(scanner: Scanner) -> Self {
+        let mut parser = Self {
+            scanner: Some(scanner),
+            ..Self::new(Vec::new())
+        };
+        parser.fill();
+        parser
+    }
+
+    // Makes sure the current token has been scanned.
+    fn fill(&mut self) {
+        while self.tokens.len() <= self.current {
+            let Some(scanner) = &mut self.scanner else {
+                return;
+            };
+            match scanner.next_token() {
+                Ok(token) => self.push(token),
+                Err(err) => self.scan_errors.push(err),
+            }
+        }
+    }
+
+    // The next top level declaration, or `None` at the end of the source.
+    // After an error the parser skips to where the next statement probably
+    // starts, so calling it again goes on with the rest of the program.
+    pub fn next_declaration(&mut self) -> Option<Result<Stmt, ParseError>> {
+        // Statements inside a block recover on their own, each can leave an
+        // error behind. They come out one at a time.
+        if !self.errors.is_empty() {
+            return Some(Err(self.errors.remove(0)));
+        }
+        self.statement_errors = 0;
+        // What's left of a block with an error in it was parsed as top
+        // level statements, its closing brace isn't a stray one.
+        while self.unclosed > 0 && self.check(TokenKind::RightBrace) {
+            self.advance();
+            self.unclosed -= 1;
+        }
+        if self.is_at_end() {
+            self.finished = true;
+            return None;
+        }
+        let start = self.current;
+        let mut result = self.declaration();
+        if result.is_err() {
+            self.depth = 0;
+            self.synchronize(start);
+            self.unclosed += self.blocks;
+            self.blocks = 0;
+        }
+        // Reported without stopping, they came before anything that did.
+        if !self.errors.is_empty() {
+            result = Err(self.errors.remove(0));
+        }
+        if self.scanner.is_some() {
+            // Only `previous` still looks back, at the last token.
+            let consumed = self.current.saturating_sub(1);
+            self.tokens.drain(..consumed);
+            self.newline_before.drain(..consumed);
+            self.current -= consumed;
+        }
+        Some(result)
+    }
+
+    // Errors the scanner found in the statements parsed so far, when
+    // streaming. The scanner runs a token ahead, so errors past the last
+    // consumed token are kept for the next statement.
+    pub fn take_scan_errors(&mut self) -> Vec<ScanError> {
+        let end = match self.current {
+            _ if self.finished => usize::MAX,
+            0 => 0,
+            _ => self.previous().span.end,
+        };
+        let (errors, rest) = std::mem::take(&mut self.scan_errors)
+            .into_iter()
+            .partition(|err| err.span.start < end);
+        self.scan_errors = rest;
+        errors
+    }
+
+    // Warnings the scanner found so far, when streaming. Unlike errors they
+    // aren't tied to a statement, nothing is dropped because of them.
+    pub fn take_scan_warnings(&mut self) -> Vec<Diagnostic> {
+        self.scanner
+            .as_mut()
+            .map_or_else(Vec::new, Scanner::take_warnings)
+    }
+
+    #[allow(clippy::result_unit_err)]
     pub fn literal(&mut self) -> Result<Literal, ()> {
         let token = self.tokens[self.current].clone();
         let token = match token.kind {
-            TokenKind::StringLiteral(s) => Ok(Literal::String(s)),
+            TokenKind::StringLiteral(s) => Ok(Literal::String(s.into())),
+            TokenKind::CharLiteral(c) => Ok(Literal::String(c.to_string().into())),
             TokenKind::NumberLiteral(n) => Ok(Literal::Number(n)),
             TokenKind::True => Ok(Literal::Boolean(true)),
             TokenKind::False => Ok(Literal::Boolean(false)),
             TokenKind::Nil => Ok(Literal::Nil),
             _ => Err(()),
         };
-        self.current += 1;
+        self.advance();
         token
     }
 
-    pub fn expression(&mut self) -> Expr {
-        self.equality()
+    pub fn expression(&mut self) -> Result<Expr, ParseError> {
+        self.nest()?;
+        let expr = self.assignment()?;
+        self.depth -= 1;
+        Ok(expr)
+    }
+
+    // An expression that has to make up all of the input, e.g. the one under
+    // an editor's cursor. Also returns the span it covers.
+    pub fn standalone_expression(&mut self) -> Result<(Expr, Span), ParseError> {
+        let start = self.peek().span.start;
+        let expr = self.expression()?;
+        if !self.is_at_end() {
+            return Err(ParseError::new(
+                self.peek(),
+                Code::TrailingInput,
+                "Expect end of expression.",
+            ));
+        }
+        Ok((expr, Span::new(start, self.previous().span.end)))
+    }
+
+    // Errors don't unwind the depth, `parse` resets it before going on with
+    // the next statement.
+    fn nest(&mut self) -> Result<(), ParseError> {
+        if self.depth == MAX_DEPTH {
+            return Err(ParseError::new(
+                self.peek(),
+                Code::TooMuchNesting,
+                "Too much nesting.",
+            ));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+
+    // Counts an operator of a binary chain, like `nest` counts nesting.
+    fn chain(&mut self) -> Result<(), ParseError> {
+        if self.operators == MAX_OPERATORS {
+            return Err(ParseError::new(
+                &self.previous(),
+                Code::TooMuchNesting,
+                "Too many operators in one statement.",
+            ));
+        }
+        self.operators += 1;
+        Ok(())
+    }
+
+    // `x |> f |> g(2)` is `g(f(x), 2)`, there is no node of its own. Both
+    // the pipes and the assignment are parsed out of line, every nested
+    // expression goes through here and its stack frame adds up.
+    fn assignment(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.range()?;
+        if self.check(TokenKind::PipeGreater) {
+            expr = self.pipes(expr)?;
+        }
+        if self.match_tokens(vec![TokenKind::Equal]) {
+            return self.assign(expr);
+        }
+        Ok(expr)
+    }
+
+    fn assign(&mut self, target: Expr) -> Result<Expr, ParseError> {
+        let equals = self.previous();
+        // Where a literal target is, they are always a single token.
+        let before_equals = self.current - 2;
+        self.nest()?;
+        let value = self.assignment()?;
+        self.depth -= 1;
+        match target {
+            Expr::Variable(name) => Ok(Expr::Assign(Assign {
+                name,
+                value: Box::new(value),
+            })),
+            Expr::Index(i) => Ok(Expr::SetIndex(SetIndex {
+                object: i.object,
+                index: i.index,
+                value: Box::new(value),
+                line: i.line,
+            })),
+            Expr::Literal(literal) => Err(ParseError::new(
+                &self.tokens[before_equals],
+                Code::InvalidAssignmentTarget,
+                &Parser::assign_to_literal(&literal),
+            )),
+            _ => Err(ParseError::new(
+                &equals,
+                Code::InvalidAssignmentTarget,
+                "Invalid assignment target.",
+            )),
+        }
+    }
+
+    // `true = 1` is common enough among beginners to say why it can't work.
+    fn assign_to_literal(literal: &Literal) -> String {
+        match literal {
+            Literal::Boolean(_) | Literal::Nil => {
+                format!("Cannot assign to '{}'.", literal)
+            }
+            Literal::Number(_) => "Cannot assign to a number.".to_string(),
+            Literal::String(_) => "Cannot assign to a string.".to_string(),
+        }
+    }
+
+    // The stage is parsed like a callee, a call gets the value as its first
+    // argument and anything else is called with just the value. Kept out of
+    // `assignment` so that its locals don't take stack space there.
+    fn pipes(&mut self, mut expr: Expr) -> Result<Expr, ParseError> {
+        let depth = self.depth;
+        while self.match_tokens(vec![TokenKind::PipeGreater]) {
+            self.nest()?;
+            expr = self.pipe_stage(expr)?;
+        }
+        self.depth = depth;
+        Ok(expr)
+    }
+
+    fn pipe_stage(&mut self, value: Expr) -> Result<Expr, ParseError> {
+        let pipe = self.previous();
+        match self.call()? {
+            Expr::Call(mut call) => {
+                if call.arguments.len() >= 255 {
+                    return Err(ParseError::new(
+                        &pipe,
+                        Code::TooManyArguments,
+                        "Can't have more than 255 arguments.",
+                    ));
+                }
+                call.arguments.insert(0, value);
+                Ok(Expr::Call(call))
+            }
+            callee => Ok(Expr::Call(Call {
+                callee: Box::new(callee),
+                arguments: vec![value],
+                line: pipe.line,
+            })),
+        }
+    }
+
+    fn range(&mut self) -> Result<Expr, ParseError> {
+        let start = self.binary(0)?;
+        if self.match_tokens(vec![TokenKind::DotDot, TokenKind::DotDotEqual]) {
+            let inclusive = self.previous().kind == TokenKind::DotDotEqual;
+            let end = self.binary(0)?;
+            return Ok(Expr::Range(Range {
+                start: Box::new(start),
+                end: Box::new(end),
+                inclusive,
+            }));
+        }
+        Ok(start)
     }
 
     fn is_at_end(&self) -> bool {
@@ -106,49 +722,45 @@ impl Parser {
         &self.tokens[self.current]
     }
 
-    fn equality(&mut self) -> Expr {
-        let mut expr = self.comparison();
-        while self.match_tokens(vec![TokenKind::BangEqual, TokenKind::EqualEqual]) {
-            let operator = self.previous();
-            let operator = match operator.kind {
-                TokenKind::BangEqual => BinaryOperator::NotEqual,
-                TokenKind::EqualEqual => BinaryOperator::EqualEqual,
-                _ => panic!("only != and == is allowed"),
-            };
-            let right = self.comparison();
+    // The binary operators by precedence climbing: one frame per operand
+    // rather than one per precedence level, so deeply nested parens don't
+    // run out of stack. Only operators binding at least as tightly as
+    // `min` are taken here, the rest are left to the caller.
+    fn binary(&mut self, min: usize) -> Result<Expr, ParseError> {
+        let start = self.current;
+        let mut expr = self.unary()?;
+        while let Some((operator, precedence)) = Parser::binary_operator(&self.peek().kind) {
+            if precedence < min {
+                break;
+            }
+            self.advance();
+            self.chain()?;
+            let right = self.binary(precedence + 1)?;
             expr = Expr::Binary(Binary {
-                left: Box::new(expr.clone()),
+                left: Box::new(expr),
                 operator,
-                right: Box::new(right.clone()),
+                right: Box::new(right),
+                span: self.span_from(start),
             })
         }
-        expr
+        Ok(expr)
     }
 
-    fn comparison(&mut self) -> Expr {
-        let mut expr = self.term();
-        while self.match_tokens(vec![
-            TokenKind::Greater,
-            TokenKind::GreaterEqual,
-            TokenKind::Less,
-            TokenKind::LessEqual,
-        ]) {
-            let operator = self.previous();
-            let operator = match operator.kind {
-                TokenKind::Greater => BinaryOperator::GreaterThan,
-                TokenKind::GreaterEqual => BinaryOperator::GreaterThanEqual,
-                TokenKind::Less => BinaryOperator::LessThan,
-                TokenKind::LessEqual => BinaryOperator::LessThanEqual,
-                _ => panic!("only >, >=, < and <= is allowed as an operator"),
-            };
-            let right = self.term();
-            expr = Expr::Binary(Binary {
-                left: Box::new(expr.clone()),
-                operator,
-                right: Box::new(right.clone()),
-            })
+    // Higher binds tighter: equality, comparison, terms, factors.
+    fn binary_operator(kind: &TokenKind) -> Option<(BinaryOperator, usize)> {
+        match kind {
+            TokenKind::BangEqual => Some((BinaryOperator::NotEqual, 0)),
+            TokenKind::EqualEqual => Some((BinaryOperator::EqualEqual, 0)),
+            TokenKind::Greater => Some((BinaryOperator::GreaterThan, 1)),
+            TokenKind::GreaterEqual => Some((BinaryOperator::GreaterThanEqual, 1)),
+            TokenKind::Less => Some((BinaryOperator::LessThan, 1)),
+            TokenKind::LessEqual => Some((BinaryOperator::LessThanEqual, 1)),
+            TokenKind::Minus => Some((BinaryOperator::Minus, 2)),
+            TokenKind::Plus => Some((BinaryOperator::Plus, 2)),
+            TokenKind::Slash => Some((BinaryOperator::Divide, 3)),
+            TokenKind::Star => Some((BinaryOperator::Multiply, 3)),
+            _ => None,
         }
-        expr
     }
 
     fn match_tokens(&mut self, tokens: Vec<TokenKind>) -> bool {
@@ -172,52 +784,42 @@ impl Parser {
         self.peek().kind == token
     }
 
-    fn advance(&mut self) -> Token {
-        if !self.is_at_end() {
-            self.current += 1;
+    // Strings with only whitespace and comments between them are one string,
+    // like in C, so that a long one can be split over lines. They become one
+    // token spanning all the pieces. A line break that ends the statement
+    // ends the string too.
+    fn string(&mut self) -> String {
+        let first = self.current;
+        self.advance();
+        while let TokenKind::StringLiteral(piece) = &self.peek().kind {
+            if self.at_line_break() {
+                break;
+            }
+            let piece = piece.clone();
+            let next = self.tokens.remove(self.current);
+            self.newline_before.remove(self.current);
+            let merged = &mut self.tokens[first];
+            if let TokenKind::StringLiteral(value) = &mut merged.kind {
+                value.push_str(&piece);
+            }
+            merged.span.end = next.span.end;
+            self.fill();
         }
-        self.previous()
-    }
-
-    fn term(&mut self) -> Expr {
-        let mut expr = self.factor();
-        while self.match_tokens(vec![TokenKind::Minus, TokenKind::Plus]) {
-            let operator = self.previous();
-            let operator = match operator.kind {
-                TokenKind::Minus => BinaryOperator::Minus,
-                TokenKind::Plus => BinaryOperator::Plus,
-                _ => panic!("Only - and + operators are allowed"),
-            };
-            let right = self.factor();
-            expr = Expr::Binary(Binary {
-                left: Box::new(expr.clone()),
-                operator,
-                right: Box::new(right.clone()),
-            })
+        match &self.tokens[first].kind {
+            TokenKind::StringLiteral(value) => value.clone(),
+            _ => unreachable!("only called on a string"),
         }
-        expr
     }
 
-    fn factor(&mut self) -> Expr {
-        let mut expr = self.unary();
-        while self.match_tokens(vec![TokenKind::Slash, TokenKind::Star]) {
-            let operator = self.previous();
-            let operator = match operator.kind {
-                TokenKind::Slash => BinaryOperator::Divide,
-                TokenKind::Star => BinaryOperator::Multiply,
-                _ => panic!("only / and * is allowed as an operator"),
-            };
-            let right = self.unary();
-            expr = Expr::Binary(Binary {
-                left: Box::new(expr.clone()),
-                operator,
-                right: Box::new(right.clone()),
-            })
+    fn advance(&mut self) -> Token {
+        if !self.is_at_end() {
+            self.current += 1;
+            self.fill();
         }
-        expr
+        self.previous()
     }
 
-    fn unary(&mut self) -> Expr {
+    fn unary(&mut self) -> Result<Expr, ParseError> {
         if self.match_tokens(vec![TokenKind::Bang, TokenKind::Minus]) {
             let operator = self.previous();
             let operator = match operator.kind {
@@ -225,103 +827,471 @@ impl Parser {
                 TokenKind::Minus => UnaryOperator::Minus,
                 _ => panic!("Only ! and - operator is allowed"),
             };
-            let right = self.unary();
-            return Expr::Unary(Unary {
+            self.nest()?;
+            let right = self.unary()?;
+            self.depth -= 1;
+            return Ok(Expr::Unary(Unary {
                 operator,
                 right: Box::new(right),
-            });
+            }));
         }
 
-        self.primary()
+        self.call()
+    }
+
+    fn call(&mut self) -> Result<Expr, ParseError> {
+        let depth = self.depth;
+        let mut expr = self.primary()?;
+        loop {
+            if self.match_tokens(vec![TokenKind::LeftParen]) {
+                expr = self.finish_call(expr)?;
+            } else if self.match_tokens(vec![TokenKind::LeftBracket]) {
+                let index = self.expression()?;
+                let bracket = self.consume(TokenKind::RightBracket, "Expect ']' after index.")?;
+                expr = Expr::Index(Index {
+                    object: Box::new(expr),
+                    index: Box::new(index),
+                    line: bracket.line,
+                });
+            } else {
+                break;
+            }
+            self.nest()?;
+        }
+        self.depth = depth;
+        Ok(expr)
     }
 
-    fn primary(&mut self) -> Expr {
+    fn finish_call(&mut self, callee: Expr) -> Result<Expr, ParseError> {
+        let open = self.current;
+        let mut arguments = Vec::new();
+        if !self.check(TokenKind::RightParen) {
+            loop {
+                if arguments.len() >= 255 {
+                    return Err(ParseError::new(
+                        self.peek(),
+                        Code::TooManyArguments,
+                        "Can't have more than 255 arguments.",
+                    ));
+                }
+                arguments.push(if self.recover {
+                    self.argument_or_error()
+                } else {
+                    self.expression()?
+                });
+                if !self.match_tokens(vec![TokenKind::Comma]) {
+                    break;
+                }
+            }
+        }
+        let paren = self.consume(TokenKind::RightParen, "Expect ')' after arguments.")?;
+        // Not a call, the interpreter needs to know where the argument is in
+        // the source to print it.
+        if matches!(&callee, Expr::Variable(name) if name == "dbg") && arguments.len() == 1 {
+            let span = Span::new(
+                self.tokens[open].span.start,
+                self.tokens[self.current - 2].span.end,
+            );
+            return Ok(Expr::Dbg(Dbg {
+                expr: Box::new(arguments.remove(0)),
+                span,
+                line: paren.line,
+            }));
+        }
+        Ok(Expr::Call(Call {
+            callee: Box::new(callee),
+            arguments,
+            line: paren.line,
+        }))
+    }
+
+    fn primary(&mut self) -> Result<Expr, ParseError> {
         if self.match_tokens(vec![TokenKind::False]) {
-            Expr::Literal(Literal::Boolean(false))
+            Ok(Expr::Literal(Literal::Boolean(false)))
         } else if self.match_tokens(vec![TokenKind::True]) {
-            Expr::Literal(Literal::Boolean(true))
+            Ok(Expr::Literal(Literal::Boolean(true)))
         } else if self.match_tokens(vec![TokenKind::Nil]) {
-            Expr::Literal(Literal::Nil)
+            Ok(Expr::Literal(Literal::Nil))
         } else if matches!(
             self.tokens[self.current].clone().kind,
             TokenKind::NumberLiteral(_),
         ) | matches!(
             self.tokens[self.current].clone().kind,
-            TokenKind::StringLiteral(_),
+            TokenKind::StringLiteral(_) | TokenKind::CharLiteral(_),
         ) {
             let token = self.tokens[self.current].clone();
-            return match token.kind {
+            match token.kind {
                 TokenKind::NumberLiteral(n) => {
                     self.advance();
-                    Expr::Literal(Literal::Number(n))
+                    Ok(Expr::Literal(Literal::Number(n)))
                 }
-                TokenKind::StringLiteral(s) => {
+                TokenKind::StringLiteral(_) => {
+                    Ok(Expr::Literal(Literal::String(self.string().into())))
+                }
+                // There is no char type, a char is a one-character string.
+                TokenKind::CharLiteral(c) => {
                     self.advance();
-                    Expr::Literal(Literal::String(s))
+                    Ok(Expr::Literal(Literal::String(c.to_string().into())))
                 }
                 _ => panic!("Only strings or numbers allowed"),
-            };
+            }
+        } else if let TokenKind::Identifier(name) = &self.peek().kind {
+            let name = name.clone();
+            self.advance();
+            Ok(Expr::Variable(name))
+        } else if self.match_tokens(vec![TokenKind::LeftParen]) {
+            if self.check(TokenKind::RightParen) {
+                let start = self.previous().span.start;
+                let mut err = ParseError::new(
+                    self.peek(),
+                    Code::ExpectExpression,
+                    "Expect expression inside parentheses.",
+                );
+                err.span = Span::new(start, self.peek().span.end);
+                return Err(err);
+            }
+            let expr = self.expression()?;
+            self.consume(TokenKind::RightParen, "Expect ')' after expression.")?;
+            Ok(Expr::Grouping(Grouping {
+                expr: Box::new(expr),
+            }))
+        } else if let Some(name) = self.reserved_name() {
+            Ok(Expr::Variable(name))
         } else {
-            match self.match_tokens(vec![TokenKind::LeftParen]) {
-                true => {
-                    let expr = self.expression();
-                    self.consume(TokenKind::RightParen, "Expect ')' after expression");
-                    Expr::Grouping(Grouping {
-                        expr: Box::new(expr),
-                    })
+            Err(self.expect_expression())
+        }
+    }
+
+    // Out of `primary` like `expect_expression`.
+    fn reserved_name(&mut self) -> Option<String> {
+        let kind = &self.peek().kind;
+        if !self.reserved.contains(kind) {
+            return None;
+        }
+        let name = kind.to_string();
+        self.advance();
+        Some(name)
+    }
+
+    // Out of `primary` to keep its frame small.
+    fn expect_expression(&self) -> ParseError {
+        let token = self.peek();
+        let assigned = self
+            .tokens
+            .get(self.current + 1)
+            .is_some_and(|next| next.kind == TokenKind::Equal);
+        match token.kind {
+            // Keywords that are expressions in full Lox.
+            TokenKind::This | TokenKind::Super if assigned => ParseError::new(
+                token,
+                Code::InvalidAssignmentTarget,
+                &format!("Cannot assign to '{}'.", token.kind),
+            ),
+            _ => ParseError::new(token, Code::ExpectExpression, "Expect expression."),
+        }
+    }
+
+    fn consume(&mut self, token: TokenKind, err_msg: &str) -> Result<Token, ParseError> {
+        let code = match token {
+            TokenKind::Semicolon => Code::ExpectSemicolon,
+            TokenKind::LeftParen | TokenKind::LeftBrace => Code::ExpectOpening,
+            TokenKind::In => Code::ExpectIn,
+            // Everything else consumed is a closing paren, brace or bracket.
+            _ => Code::ExpectClosing,
+        };
+        if self.check(token.clone()) {
+            Ok(self.advance())
+        } else if token == TokenKind::Semicolon && self.at_line_break() {
+            Ok(self.previous())
+        } else if token == TokenKind::Semicolon
+            && (self.check(TokenKind::RightParen)
+                || self.blocks == 0 && self.check(TokenKind::RightBrace))
+        {
+            // Where a statement ends, no paren is open.
+            Err(self.unmatched_closing())
+        } else {
+            Err(ParseError::new(self.peek(), code, err_msg))
+        }
+    }
+
+    // With newlines scanned, a line break ends a statement where a `;`
+    // could. Anywhere else it is skipped, so an expression goes on over
+    // the next line after an operator or inside parens. That also means a
+    // line starting with `-` or `(` continues the one before it.
+    fn at_line_break(&self) -> bool {
+        self.newline_before[self.current]
+    }
+
+    // Skips tokens until the start of the next statement so a single
+    // mistake doesn't produce an error for every token after it. A paren
+    // the statement opened is skipped to its end first, `;` in there doesn't
+    // end the statement, e.g. in `for (var i = 0; i < 3; i = i + 1)`. In
+    // case the paren is never closed, a brace or anything on a later line
+    // than the paren stops the skipping.
+    fn synchronize(&mut self, start: usize) {
+        if self.blocks > 0 && self.check(TokenKind::RightBrace) {
+            return;
+        }
+        let mut open = 0usize;
+        let mut line = 0;
+        for token in &self.tokens[start..=self.current] {
+            match token.kind {
+                TokenKind::LeftParen => {
+                    open += 1;
+                    line = token.line;
+                }
+                TokenKind::RightParen => open = open.saturating_sub(1),
+                _ => {}
+            }
+        }
+        self.advance();
+        while open > 0 && !self.is_at_end() {
+            match self.peek().kind {
+                _ if self.at_line_break() || self.peek().line > line => return,
+                TokenKind::LeftBrace | TokenKind::RightBrace => return,
+                TokenKind::LeftParen => open += 1,
+                TokenKind::RightParen => open -= 1,
+                _ => {}
+            }
+            self.advance();
+        }
+        while !self.is_at_end() {
+            if self.previous().kind == TokenKind::Semicolon || self.at_line_break() {
+                return;
+            }
+            match self.peek().kind {
+                TokenKind::RightBrace if self.blocks > 0 => return,
+                // Always a block, its statements are still worth checking.
+                TokenKind::LeftBrace
+                | TokenKind::Class
+                | TokenKind::Fun
+                | TokenKind::Var
+                | TokenKind::For
+                | TokenKind::If
+                | TokenKind::While
+                | TokenKind::Print
+                | TokenKind::Try
+                | TokenKind::Throw
+                | TokenKind::Return => return,
+                _ => {
+                    self.advance();
                 }
-                false => panic!("is this part unreachable?"),
             }
         }
     }
 
-    fn consume(&mut self, token: TokenKind, err_msg: &str) -> Token {
-        if self.check(token) {
-            self.advance()
+    // Parses the whole program even when parts of it don't, for tools that
+    // want the structure around the errors. A statement or call argument
+    // that doesn't parse becomes an error node covering the tokens skipped
+    // to get past it. The interpreter refuses to run the result if there is
+    // one.
+    pub fn parse_partial(&mut self) -> (Vec<Stmt>, Vec<ParseError>) {
+        self.recover = true;
+        let mut statements = vec![];
+        while !self.is_at_end() {
+            statements.push(self.declaration_or_error());
+        }
+        self.recover = false;
+        (statements, std::mem::take(&mut self.errors))
+    }
+
+    fn declaration_or_error(&mut self) -> Stmt {
+        let (start, depth, blocks) = (self.current, self.depth, self.blocks);
+        self.statement_errors = self.errors.len();
+        let stmt = self.declaration().unwrap_or_else(|err| {
+            self.report(err);
+            self.depth = depth;
+            self.blocks = blocks;
+            self.synchronize(start);
+            Stmt::Error {
+                span: self.span_from(start),
+                line: self.tokens[start].line,
+            }
+        });
+        // A statement it is nested in starts over after it.
+        self.statement_errors = self.errors.len();
+        stmt
+    }
+
+    fn report(&mut self, err: ParseError) {
+        if self.errors.len() == self.statement_errors {
+            self.errors.push(err);
+        }
+    }
+
+    fn argument_or_error(&mut self) -> Expr {
+        let (start, depth) = (self.current, self.depth);
+        self.expression().unwrap_or_else(|err| {
+            self.report(err);
+            self.depth = depth;
+            // Up to the next argument or the closing paren, whichever is
+            // at the same level of nesting.
+            let mut nesting = 0;
+            while !self.is_at_end() {
+                match self.peek().kind {
+                    TokenKind::LeftParen | TokenKind::LeftBracket => nesting += 1,
+                    TokenKind::RightParen | TokenKind::RightBracket if nesting > 0 => nesting -= 1,
+                    TokenKind::Comma if nesting > 0 => {}
+                    TokenKind::Comma
+                    | TokenKind::RightParen
+                    | TokenKind::RightBracket
+                    | TokenKind::Semicolon
+                    | TokenKind::RightBrace => break,
+                    _ => {}
+                }
+                self.advance();
+            }
+            Expr::Error(self.span_from(start))
+        })
+    }
+
+    // Span of the tokens from `start` up to the current one.
+    fn span_from(&self, start: usize) -> Span {
+        let from = self.tokens[start].span.start;
+        if self.current > start {
+            Span::new(from, self.previous().span.end)
         } else {
-            panic!("{:#?} {}", self.peek(), err_msg);
+            Span::new(from, from)
         }
     }
 
-    pub fn parse(&mut self) -> Vec<Stmt> {
+    pub fn parse(&mut self) -> Result<Vec<Stmt>, Vec<ParseError>> {
         let mut statements: Vec<Stmt> = vec![];
-        while !self.is_at_end() {
-            statements.push(self.declaration());
+        let mut errors = vec![];
+        while let Some(result) = self.next_declaration() {
+            match result {
+                Ok(stmt) => statements.push(stmt),
+                Err(err) => errors.push(err),
+            }
+        }
+        if errors.is_empty() {
+            Ok(statements)
+        } else {
+            Err(errors)
         }
-        statements
     }
 
-    fn statement(&mut self) -> Stmt {
-        match self.tokens[self.current].kind {
+    fn statement(&mut self) -> Result<Stmt, ParseError> {
+        self.nest()?;
+        self.operators = 0;
+        let stmt = match self.tokens[self.current].kind {
             TokenKind::Print => {
                 self.advance();
                 self.print_statement()
             }
+            // Lox has no empty statement, a stray `;` is most likely a typo.
+            TokenKind::Semicolon => Err(ParseError::new(
+                self.peek(),
+                Code::EmptyStatement,
+                "Empty statement, remove the extra ';'.",
+            )),
             TokenKind::LeftBrace => {
                 self.advance();
                 self.block()
             }
+            // A `}` in a block closes it, whatever came before.
+            TokenKind::RightBrace if self.blocks == 0 => Err(self.unmatched_closing()),
+            TokenKind::RightParen => Err(self.unmatched_closing()),
             TokenKind::If => {
                 self.advance();
                 self.if_statement()
             }
+            TokenKind::Try => {
+                self.advance();
+                self.try_statement()
+            }
+            TokenKind::Throw => {
+                self.advance();
+                self.throw_statement()
+            }
+            TokenKind::For => {
+                self.advance();
+                self.for_statement()
+            }
             _ => self.expression_statement(),
+        }?;
+        self.depth -= 1;
+        Ok(stmt)
+    }
+
+    fn print_statement(&mut self) -> Result<Stmt, ParseError> {
+        let (start, line) = (self.current - 1, self.previous().line);
+        if self.check(TokenKind::Semicolon) {
+            return Err(ParseError::new(
+                self.peek(),
+                Code::ExpectExpression,
+                "Expect a value after 'print'.",
+            ));
         }
+        let mut exprs = vec![self.expression()?];
+        while self.match_tokens(vec![TokenKind::Comma]) {
+            if self.check(TokenKind::Semicolon) {
+                return Err(ParseError::new(
+                    self.peek(),
+                    Code::ExpectExpression,
+                    "Expect a value after ','.",
+                ));
+            }
+            exprs.push(self.expression()?);
+        }
+        self.consume(TokenKind::Semicolon, "Expect ';' after value.")?;
+        Ok(Stmt::Print {
+            exprs,
+            span: self.span_from(start),
+            line,
+        })
     }
 
-    fn print_statement(&mut self) -> Stmt {
-        let value = self.expression();
-        self.consume(TokenKind::Semicolon, "Expect ';' after value.");
-        Stmt::Print(value)
+    fn expression_statement(&mut self) -> Result<Stmt, ParseError> {
+        let (start, line) = (self.current, self.peek().line);
+        let expr = self.expression()?;
+        self.consume(TokenKind::Semicolon, "Expect ';' after expression.")?;
+        Ok(Stmt::Expression {
+            expr,
+            span: self.span_from(start),
+            line,
+        })
     }
 
-    fn expression_statement(&mut self) -> Stmt {
-        let expr = self.expression();
-        self.consume(TokenKind::Semicolon, "Expect ';' after expression.");
-        Stmt::Expression(expr)
+    // A keyword where a name should be is reported, but parsing goes on as
+    // if it were a name so that's the only error.
+    fn name(&mut self, message: &str) -> Result<String, ParseError> {
+        let token = self.peek();
+        let name = match &token.kind {
+            TokenKind::Identifier(name) => name.clone(),
+            kind if kind.is_keyword() => {
+                let name = kind.to_string();
+                let message = format!(
+                    "'{}' is a reserved word and cannot be used as a name.",
+                    name
+                );
+                let kind = kind.clone();
+                self.report(ParseError::new(token, Code::ReservedName, &message));
+                self.reserved.push(kind);
+                name
+            }
+            _ => return Err(ParseError::new(token, Code::ExpectName, message)),
+        };
+        self.advance();
+        Ok(name)
     }
 
-    fn declaration(&mut self) -> Stmt {
+    // Usually one too many, left over from an edit. Synchronizing skips it
+    // and the rest of the program is still checked.
+    fn unmatched_closing(&self) -> ParseError {
+        let token = self.peek();
+        let opening = match token.kind {
+            TokenKind::RightParen => "'('",
+            _ => "block",
+        };
+        let message = format!(
+            "Unexpected '{}', there is no open {} to close.",
+            token.kind, opening
+        );
+        ParseError::new(token, Code::UnmatchedClosing, &message)
+    }
+
+    fn declaration(&mut self) -> Result<Stmt, ParseError> {
         if self.match_tokens(vec![TokenKind::Var]) {
             self.var_declaration()
         } else {
@@ -329,65 +1299,146 @@ impl Parser {
         }
     }
 
-    fn var_declaration(&mut self) -> Stmt {
-        // i am not sure how to remove this `.clone()` call.
-        let name = self.tokens[self.current].clone();
-        self.advance();
+    fn var_declaration(&mut self) -> Result<Stmt, ParseError> {
+        self.operators = 0;
+        let (start, line) = (self.current - 1, self.previous().line);
+        let name = self.name("Expect variable name.")?;
         let mut initializer = Expr::Literal(Literal::Nil);
         if self.match_tokens(vec![TokenKind::Equal]) {
-            initializer = self.expression();
+            initializer = self.expression()?;
         }
 
         self.consume(
             TokenKind::Semicolon,
-            "Expect ';' after variable declaration",
-        );
-        if let TokenKind::Identifier(name) = name.kind {
-            Stmt::Var { name, initializer }
-        } else {
-            panic!("Variable declarations require an identifier")
-        }
+            "Expect ';' after variable declaration.",
+        )?;
+        Ok(Stmt::Var {
+            name,
+            initializer,
+            span: self.span_from(start),
+            line,
+        })
     }
 
-    fn block(&mut self) -> Stmt {
+    fn block(&mut self) -> Result<Stmt, ParseError> {
+        let (start, line) = (self.current - 1, self.previous().line);
         let mut statements = vec![];
+        self.blocks += 1;
+        // A statement that doesn't parse is skipped up to the next one in
+        // the block, `synchronize` leaves the closing brace alone. The
+        // statements after it, and after the block, are parsed as usual.
         while !self.check(TokenKind::RightBrace) && !self.is_at_end() {
-            statements.push(self.declaration());
+            statements.push(self.declaration_or_error());
         }
+        self.blocks -= 1;
 
-        self.consume(TokenKind::RightBrace, "Expected a '}' after block");
-        Stmt::Block(statements)
+        self.consume(TokenKind::RightBrace, "Expect '}' after block.")?;
+        Ok(Stmt::Block {
+            statements,
+            span: self.span_from(start),
+            line,
+        })
     }
 
-    fn if_statement(&mut self) -> Stmt {
-        self.consume(TokenKind::LeftParen, "Expect '(' after 'if'");
-        let condition = self.expression();
-        self.consume(TokenKind::RightParen, "Expect ')' after if condtion");
+    fn if_statement(&mut self) -> Result<Stmt, ParseError> {
+        let (start, line) = (self.current - 1, self.previous().line);
+        self.consume(TokenKind::LeftParen, "Expect '(' after 'if'.")?;
+        let condition = self.expression()?;
+        self.consume(TokenKind::RightParen, "Expect ')' after if condition.")?;
 
-        let then_branch = self.statement();
+        let then_branch = self.statement()?;
         let mut else_branch: Option<Box<Stmt>> = None;
         if self.match_tokens(vec![TokenKind::Else]) {
-            else_branch = Some(Box::new(self.statement()));
+            else_branch = Some(Box::new(self.statement()?));
         }
 
-        Stmt::If {
+        Ok(Stmt::If {
             condition,
             then_branch: Box::new(then_branch),
             else_branch,
+            span: self.span_from(start),
+            line,
+        })
+    }
+
+    fn try_statement(&mut self) -> Result<Stmt, ParseError> {
+        let (start, line) = (self.current - 1, self.previous().line);
+        let body = self.block_after("Expect '{' after 'try'.")?;
+        let mut catch = None;
+        if self.match_tokens(vec![TokenKind::Catch]) {
+            self.consume(TokenKind::LeftParen, "Expect '(' after 'catch'.")?;
+            let name = self.name("Expect error name.")?;
+            self.consume(TokenKind::RightParen, "Expect ')' after error name.")?;
+            catch = Some((name, self.block_after("Expect '{' after catch clause.")?));
         }
+        let mut finally = None;
+        if self.match_tokens(vec![TokenKind::Finally]) {
+            finally = Some(self.block_after("Expect '{' after 'finally'.")?);
+        }
+        if catch.is_none() && finally.is_none() {
+            return Err(ParseError::new(
+                self.peek(),
+                Code::ExpectCatchOrFinally,
+                "Expect 'catch' or 'finally' after try block.",
+            ));
+        }
+        Ok(Stmt::Try {
+            body,
+            catch,
+            finally,
+            span: self.span_from(start),
+            line,
+        })
+    }
+
+    fn throw_statement(&mut self) -> Result<Stmt, ParseError> {
+        let (start, line) = (self.current - 1, self.previous().line);
+        let expr = self.expression()?;
+        self.consume(TokenKind::Semicolon, "Expect ';' after thrown value.")?;
+        Ok(Stmt::Throw {
+            expr,
+            span: self.span_from(start),
+            line,
+        })
+    }
+
+    fn for_statement(&mut self) -> Result<Stmt, ParseError> {
+        let (start, line) = (self.current - 1, self.previous().line);
+        self.consume(TokenKind::LeftParen, "Expect '(' after 'for'.")?;
+        let name = self.name("Expect loop variable name.")?;
+        self.consume(TokenKind::In, "Expect 'in' after loop variable.")?;
+        let iterable = self.expression()?;
+        self.consume(TokenKind::RightParen, "Expect ')' after for clauses.")?;
+        let body = self.statement()?;
+        Ok(Stmt::ForIn {
+            name,
+            iterable,
+            body: Box::new(body),
+            span: self.span_from(start),
+            line,
+        })
+    }
+
+    fn block_after(&mut self, message: &str) -> Result<Box<Stmt>, ParseError> {
+        self.consume(TokenKind::LeftBrace, message)?;
+        Ok(Box::new(self.block()?))
     }
 }
 
 #[cfg(test)]
 mod parser_tests {
-    use super::{Binary, BinaryOperator, Expr, Parser, Stmt};
-    use crate::{parser::Literal, scanner::Scanner};
+    use super::{Assign, Binary, BinaryOperator, Expr, ParseError, Parser, Stmt};
+    use crate::{
+        diagnostics::{Code, Span},
+        parser::Literal,
+        scanner::{Scanner, ScannerOptions, TokenKind},
+    };
 
     #[test]
     fn parsing_literals() {
-        let source = r#"123.456 "a string literal" nil true false"#.to_string();
+        let source = r#"123.456 "a string literal" nil true false 'c'"#.to_string();
         let mut scanner = Scanner::new(source);
-        let tokens = scanner.scan_tokens();
+        let tokens = scanner.scan_tokens().unwrap();
         let (_, tail) = tokens.split_last().unwrap();
         let mut parser = Parser::new(tail.to_vec());
         let mut literals: Vec<Literal> = vec![];
@@ -398,10 +1449,11 @@ mod parser_tests {
             literals,
             vec![
                 Literal::Number(123.456),
-                Literal::String("a string literal".to_string()),
+                Literal::String("a string literal".into()),
                 Literal::Nil,
                 Literal::Boolean(true),
-                Literal::Boolean(false)
+                Literal::Boolean(false),
+                Literal::String("c".into()),
             ]
         );
     }
@@ -410,9 +1462,9 @@ mod parser_tests {
     fn binary_expr() {
         let source = r#"-1 - 2 * (4 - 2)"#.to_string();
         let mut scanner = Scanner::new(source);
-        let tokens = scanner.scan_tokens();
+        let tokens = scanner.scan_tokens().unwrap();
         let mut parser = Parser::new(tokens.clone());
-        let expr = parser.expression();
+        let expr = parser.expression().unwrap();
         println!("{:#?}", expr);
     }
 
@@ -420,14 +1472,16 @@ mod parser_tests {
     fn var_declaration() {
         let source = r#"var age = 26;"#.to_string();
         let mut scanner = Scanner::new(source);
-        let tokens = scanner.scan_tokens();
+        let tokens = scanner.scan_tokens().unwrap();
         let mut parser = Parser::new(tokens.clone());
-        let program = parser.parse();
+        let program = parser.parse().unwrap();
         assert_eq!(
             program,
             vec![Stmt::Var {
                 name: "age".to_string(),
-                initializer: Expr::Literal(Literal::Number(26.0))
+                initializer: Expr::Literal(Literal::Number(26.0)),
+                span: Span::new(0, 13),
+                line: 1,
             }]
         )
     }
@@ -444,21 +1498,33 @@ mod parser_tests {
         "#
         .to_string();
         let mut scanner = Scanner::new(source);
-        let tokens = scanner.scan_tokens();
+        let tokens = scanner.scan_tokens().unwrap();
         let mut parser = Parser::new(tokens.clone());
-        let program = parser.parse();
+        let program = parser.parse().unwrap();
         assert_eq!(
             program,
-            vec![Stmt::Block(vec![
-                Stmt::Var {
-                    name: "a".to_string(),
-                    initializer: Expr::Literal(Literal::Number(12.0))
-                },
-                Stmt::Block(vec![Stmt::Var {
-                    name: "b".to_string(),
-                    initializer: Expr::Literal(Literal::Number(12.0))
-                }])
-            ])]
+            vec![Stmt::Block {
+                statements: vec![
+                    Stmt::Var {
+                        name: "a".to_string(),
+                        initializer: Expr::Literal(Literal::Number(12.0)),
+                        span: Span::new(23, 34),
+                        line: 3,
+                    },
+                    Stmt::Block {
+                        statements: vec![Stmt::Var {
+                            name: "b".to_string(),
+                            initializer: Expr::Literal(Literal::Number(12.0)),
+                            span: Span::new(65, 76),
+                            line: 5,
+                        }],
+                        span: Span::new(47, 90),
+                        line: 4,
+                    }
+                ],
+                span: Span::new(9, 100),
+                line: 2,
+            }]
         )
     }
 
@@ -473,26 +1539,593 @@ mod parser_tests {
         "#
         .to_string();
         let mut scanner = Scanner::new(source);
-        let tokens = scanner.scan_tokens();
+        let tokens = scanner.scan_tokens().unwrap();
         let mut parser = Parser::new(tokens.clone());
-        let program = parser.parse();
+        let program = parser.parse().unwrap();
         assert_eq!(
             program,
             vec![Stmt::If {
                 condition: Expr::Binary(Binary {
                     left: Box::new(Expr::Literal(Literal::Number(2.0))),
                     right: Box::new(Expr::Literal(Literal::Number(2.0))),
-                    operator: BinaryOperator::EqualEqual
+                    operator: BinaryOperator::EqualEqual,
+                    span: Span::new(17, 23),
+                }),
+                then_branch: Box::new(Stmt::Block {
+                    statements: vec![Stmt::Var {
+                        name: "x".to_string(),
+                        initializer: Expr::Literal(Literal::Number(23.0)),
+                        span: Span::new(43, 54),
+                        line: 3,
+                    }],
+                    span: Span::new(25, 68),
+                    line: 2,
+                }),
+                else_branch: Some(Box::new(Stmt::Block {
+                    statements: vec![Stmt::Var {
+                        name: "y".to_string(),
+                        initializer: Expr::Literal(Literal::Number(23.0)),
+                        span: Span::new(92, 103),
+                        line: 5,
+                    }],
+                    span: Span::new(74, 117),
+                    line: 4,
+                })),
+                span: Span::new(13, 117),
+                line: 2,
+            }]
+        )
+    }
+
+    #[test]
+    fn assignment() {
+        let source = r#"a = b = 1;"#.to_string();
+        let mut scanner = Scanner::new(source);
+        let tokens = scanner.scan_tokens().unwrap();
+        let mut parser = Parser::new(tokens.clone());
+        let program = parser.parse().unwrap();
+        assert_eq!(
+            program,
+            vec![Stmt::Expression {
+                expr: Expr::Assign(Assign {
+                    name: "a".to_string(),
+                    value: Box::new(Expr::Assign(Assign {
+                        name: "b".to_string(),
+                        value: Box::new(Expr::Literal(Literal::Number(1.0)))
+                    }))
                 }),
-                then_branch: Box::new(Stmt::Block(vec![Stmt::Var {
-                    name: "x".to_string(),
-                    initializer: Expr::Literal(Literal::Number(23.0))
-                }])),
-                else_branch: Some(Box::new(Stmt::Block(vec![Stmt::Var {
-                    name: "y".to_string(),
-                    initializer: Expr::Literal(Literal::Number(23.0))
-                }])))
+                span: Span::new(0, 10),
+                line: 1,
             }]
         )
     }
+
+    #[test]
+    fn invalid_assignment_target() {
+        let source = r#"1 + a = 2;"#.to_string();
+        let mut scanner = Scanner::new(source);
+        let tokens = scanner.scan_tokens().unwrap();
+        let mut parser = Parser::new(tokens.clone());
+        assert_eq!(
+            parser.parse(),
+            Err(vec![ParseError {
+                code: Code::InvalidAssignmentTarget,
+                message: "Invalid assignment target.".to_string(),
+                line: 1,
+                span: Span::new(6, 7),
+                at_end: false,
+            }])
+        );
+    }
+
+    #[test]
+    fn assignment_to_keywords_and_literals() {
+        let source = "true = 5;\nthis = a;\nprint 1;";
+        let mut scanner = Scanner::new(source.to_string());
+        let mut parser = Parser::new(scanner.scan_tokens().unwrap().clone());
+        let errors = parser.parse().unwrap_err();
+        let errors: Vec<(Code, &str, Span)> = errors
+            .iter()
+            .map(|err| (err.code, err.message.as_str(), err.span))
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                (
+                    Code::InvalidAssignmentTarget,
+                    "Cannot assign to 'true'.",
+                    Span::new(0, 4)
+                ),
+                (
+                    Code::InvalidAssignmentTarget,
+                    "Cannot assign to 'this'.",
+                    Span::new(10, 14)
+                ),
+            ]
+        );
+    }
+
+    // An error inside a block doesn't take the closing brace with it, and
+    // what follows the block parses as if nothing happened.
+    #[test]
+    fn recovery_inside_blocks() {
+        let cases = [
+            (
+                "{\n  {\n    var = 1;\n    print 2;\n  }\n  print 3;\n}\nprint 4;",
+                1,
+            ),
+            (
+                "if (true) {\n  print 1 2;\n} else {\n  print 3;\n}\nprint 4;",
+                1,
+            ),
+            ("try {\n  throw;\n} catch (e) {\n  print e;\n}\nprint 4;", 1),
+            (
+                "for (i in 0..3) {\n  {\n    print i i;\n  }\n}\nprint 4;",
+                1,
+            ),
+            ("{ { { print 1 } } }\nprint 4;", 1),
+            ("{\n  var = 1;\n  { print 2; }\n  print (3;\n}\nprint 4;", 2),
+        ];
+        for (source, errors) in cases {
+            let mut scanner = Scanner::new(source.to_string());
+            let mut parser = Parser::new(scanner.scan_tokens().unwrap().clone());
+            let mut results = Vec::new();
+            while let Some(result) = parser.next_declaration() {
+                results.push(result);
+            }
+            let last = results.pop().unwrap();
+            assert_eq!(printed(&last.unwrap()), "4", "{}", source);
+            assert_eq!(results.len(), errors, "{}: {:?}", source, results);
+            assert!(results.iter().all(Result::is_err), "{}", source);
+        }
+    }
+
+    #[test]
+    fn keywords_as_names() {
+        let parse = |source: &str| {
+            let mut scanner = Scanner::new(source.to_string());
+            Parser::new(scanner.scan_tokens().unwrap().clone()).parse()
+        };
+        let errors = parse(
+            "var class = 1;\nfor (if in 0..3) {\n  print if;\n}\ntry {} catch (this) {}\nvar = 2;",
+        )
+        .unwrap_err();
+        let errors: Vec<(Code, &str, Span)> = errors
+            .iter()
+            .map(|err| (err.code, err.message.as_str(), err.span))
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                (
+                    Code::ReservedName,
+                    "'class' is a reserved word and cannot be used as a name.",
+                    Span::new(4, 9)
+                ),
+                (
+                    Code::ReservedName,
+                    "'if' is a reserved word and cannot be used as a name.",
+                    Span::new(20, 22)
+                ),
+                (
+                    Code::ReservedName,
+                    "'this' is a reserved word and cannot be used as a name.",
+                    Span::new(62, 66)
+                ),
+                (Code::ExpectName, "Expect variable name.", Span::new(75, 76)),
+            ]
+        );
+        assert!(parse("var classes = 1; for (iffy in 0..3) {}").is_ok());
+
+        // The statement still parses around it.
+        let mut scanner = Scanner::new("var true = 1 + 2;".to_string());
+        let (statements, errors) =
+            Parser::new(scanner.scan_tokens().unwrap().clone()).parse_partial();
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(&statements[..], [Stmt::Var { name, .. }] if name == "true"));
+    }
+
+    #[test]
+    fn unmatched_closing() {
+        let errors = |source: &str| -> Vec<(Code, String, Span)> {
+            let mut scanner = Scanner::new(source.to_string());
+            match Parser::new(scanner.scan_tokens().unwrap().clone()).parse() {
+                Ok(_) => Vec::new(),
+                Err(errors) => errors
+                    .into_iter()
+                    .map(|err| (err.code, err.message, err.span))
+                    .collect(),
+            }
+        };
+        // Everything after it is still checked.
+        assert_eq!(
+            errors("if (a) {\n  print a;\n}\n}\nprint a +;\nprint (a));\n"),
+            [
+                (
+                    Code::UnmatchedClosing,
+                    "Unexpected '}', there is no open block to close.".to_string(),
+                    Span::new(22, 23)
+                ),
+                (
+                    Code::ExpectExpression,
+                    "Expect expression.".to_string(),
+                    Span::new(33, 34)
+                ),
+                (
+                    Code::UnmatchedClosing,
+                    "Unexpected ')', there is no open '(' to close.".to_string(),
+                    Span::new(44, 45)
+                ),
+            ]
+        );
+        assert_eq!(errors("print 1)\nprint 2;")[0].2, Span::new(7, 8));
+        // A missing `;` in a block is still reported as one.
+        assert_eq!(
+            errors("{ print 1 }")[0].1,
+            "Expect ';' after value.".to_string()
+        );
+        assert_eq!(errors("{ if (a) { print (a); } }\n(a);\n{}\n"), []);
+    }
+
+    #[test]
+    fn reports_every_statement_with_errors() {
+        let source = r#"
+            print 1 +;
+            var = 2;
+            print 3;
+            print (4
+        "#
+        .to_string();
+        let mut scanner = Scanner::new(source);
+        let tokens = scanner.scan_tokens().unwrap();
+        let mut parser = Parser::new(tokens.clone());
+        let errors = parser.parse().unwrap_err();
+        assert_eq!(
+            errors
+                .iter()
+                .map(|err| err.to_string())
+                .collect::<Vec<String>>(),
+            vec![
+                "[line 2] Error: Expect expression.",
+                "[line 3] Error: Expect variable name.",
+                "[line 6] Error at end: Expect ')' after expression.",
+            ]
+        );
+        assert!(errors[2].at_end);
+    }
+
+    #[test]
+    fn unclosed_paren_recovers_on_the_next_line() {
+        let source = "print (1 + ;\nprint 2 +;\nprint 3 +;\nprint 4;".to_string();
+        let mut scanner = Scanner::new(source);
+        let tokens = scanner.scan_tokens().unwrap();
+        let errors = Parser::new(tokens.clone()).parse().unwrap_err();
+        assert_eq!(
+            errors.iter().map(|err| err.to_string()).collect::<Vec<_>>(),
+            [
+                "[line 1] Error: Expect expression.",
+                "[line 2] Error: Expect expression.",
+                "[line 3] Error: Expect expression.",
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_parens_and_statements() {
+        let source = "print ();\nprint 1;;\nprint;\nprint 2;".to_string();
+        let mut scanner = Scanner::new(source);
+        let tokens = scanner.scan_tokens().unwrap();
+        let errors = Parser::new(tokens.clone()).parse().unwrap_err();
+        assert_eq!(
+            errors.iter().map(|err| err.to_string()).collect::<Vec<_>>(),
+            [
+                "[line 1] Error: Expect expression inside parentheses.",
+                "[line 2] Error: Empty statement, remove the extra ';'.",
+                "[line 3] Error: Expect a value after 'print'.",
+            ]
+        );
+        assert_eq!(errors[0].span, Span::new(6, 8));
+        assert_eq!(errors[1].code, Code::EmptyStatement);
+        assert_eq!(errors[1].span, Span::new(18, 19));
+    }
+
+    fn parse_lines(source: &str) -> Result<Vec<Stmt>, Vec<ParseError>> {
+        let mut scanner = Scanner::new(source.to_string());
+        scanner.set_newlines(true);
+        Parser::new(scanner.scan_tokens().unwrap().clone()).parse()
+    }
+
+    #[test]
+    fn newlines_end_statements() {
+        let statements =
+            parse_lines("print 1\n\nvar a = 2; print a\n{\n  a = 3\n}\nprint 4").unwrap();
+        assert_eq!(
+            statements
+                .iter()
+                .map(|stmt| format!("{:?}", stmt).split(' ').next().unwrap().to_string())
+                .collect::<Vec<_>>(),
+            ["Print", "Var", "Print", "Block", "Print"]
+        );
+        let mut scanner = Scanner::new("print 1\nprint 2\n".to_string());
+        scanner.set_newlines(true);
+        let mut parser = Parser::streaming(scanner);
+        assert!(parser.next_declaration().unwrap().is_ok());
+        assert!(parser.next_declaration().unwrap().is_ok());
+        assert!(parser.next_declaration().is_none());
+    }
+
+    #[test]
+    fn truncated_scan() {
+        let options = ScannerOptions {
+            max_tokens: 5,
+            ..ScannerOptions::default()
+        };
+        let scanner = Scanner::with_options("print 1; print 2; print 3;".to_string(), options);
+        let mut parser = Parser::streaming(scanner);
+        assert!(parser.next_declaration().unwrap().is_ok());
+        assert!(parser.next_declaration().unwrap().is_err());
+        assert!(parser.next_declaration().is_none());
+        let errors = parser.take_scan_errors();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, Code::ScanAborted);
+    }
+
+    #[test]
+    fn newlines_inside_expressions() {
+        let statements =
+            parse_lines("var a = 1 +\n  2\nprint (a\n  * 3)\nprint a ==\n\n a").unwrap();
+        assert_eq!(
+            statements
+                .iter()
+                .map(|stmt| match stmt {
+                    Stmt::Var { initializer, .. } => initializer.to_string(),
+                    Stmt::Print { .. } => printed(stmt),
+                    other => panic!("unexpected {:?}", other),
+                })
+                .collect::<Vec<_>>(),
+            ["1 + 2", "(a * 3)", "a == a"]
+        );
+        let errors = parse_lines("print 1 2\nprint 3").unwrap_err();
+        assert_eq!(
+            errors.iter().map(|err| err.to_string()).collect::<Vec<_>>(),
+            ["[line 1] Error: Expect ';' after value."]
+        );
+    }
+
+    #[test]
+    fn newlines_need_the_option() {
+        let mut scanner = Scanner::new("print 1\nprint 2".to_string());
+        let tokens = scanner.scan_tokens().unwrap();
+        assert!(tokens.iter().all(|token| token.kind != TokenKind::Newline));
+        let errors = Parser::new(tokens.clone()).parse().unwrap_err();
+        assert_eq!(
+            errors[0].to_string(),
+            "[line 2] Error: Expect ';' after value."
+        );
+    }
+
+    #[test]
+    fn try_statement() {
+        let source =
+            "try { 1; } catch (e) { 2; }\ntry {} finally {}\ntry {} catch {}\ntry {}".to_string();
+        let mut scanner = Scanner::new(source);
+        let tokens = scanner.scan_tokens().unwrap();
+        let mut parser = Parser::new(tokens.clone());
+        let errors = parser.parse().unwrap_err();
+        assert_eq!(
+            errors
+                .iter()
+                .map(|err| err.to_string())
+                .collect::<Vec<String>>(),
+            vec![
+                "[line 3] Error: Expect '(' after 'catch'.",
+                "[line 4] Error at end: Expect 'catch' or 'finally' after try block.",
+            ]
+        );
+
+        let mut scanner = Scanner::new("try { 1; } catch (e) { 2; }".to_string());
+        let tokens = scanner.scan_tokens().unwrap();
+        let mut parser = Parser::new(tokens.clone());
+        // `{ n; }` starting at `start`.
+        let block = |value, start| {
+            Box::new(Stmt::Block {
+                statements: vec![Stmt::Expression {
+                    expr: Expr::Literal(Literal::Number(value)),
+                    span: Span::new(start + 2, start + 4),
+                    line: 1,
+                }],
+                span: Span::new(start, start + 6),
+                line: 1,
+            })
+        };
+        assert_eq!(
+            parser.parse().unwrap(),
+            vec![Stmt::Try {
+                body: block(1.0, 4),
+                catch: Some(("e".to_string(), block(2.0, 21))),
+                finally: None,
+                span: Span::new(0, 27),
+                line: 1,
+            }]
+        );
+    }
+
+    #[test]
+    fn unparse_expression() {
+        let source = r#"a = -(1 + f(b)(c, 3)[0]) * 2 >= !"s"[i = 1]..=n"#.to_string();
+        let mut scanner = Scanner::new(source.clone());
+        let tokens = scanner.scan_tokens().unwrap();
+        let mut parser = Parser::new(tokens.clone());
+        assert_eq!(parser.expression().unwrap().to_string(), source);
+    }
+
+    // The printed expressions.
+    fn printed(stmt: &Stmt) -> String {
+        match stmt {
+            Stmt::Print { exprs, .. } => exprs
+                .iter()
+                .map(Expr::to_string)
+                .collect::<Vec<_>>()
+                .join(", "),
+            other => panic!("expected a print, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn adjacent_strings() {
+        let source = "print \"ab\"\n  \"cd\" // more\n  \"ef\";\nprint \"x\" \"y\";";
+        let mut scanner = Scanner::new(source.to_string());
+        let mut parser = Parser::new(scanner.scan_tokens().unwrap().clone());
+        let statements = parser.parse().unwrap();
+        assert_eq!(
+            statements[0],
+            Stmt::Print {
+                exprs: vec![Expr::Literal(Literal::String("abcdef".into()))],
+                span: Span::new(0, 33),
+                line: 1,
+            }
+        );
+        assert_eq!(printed(&statements[1]), "\"xy\"");
+        assert_eq!(parser.tokens[1].span, Span::new(6, 32));
+        assert_eq!(
+            parser.tokens[1].kind,
+            TokenKind::StringLiteral("abcdef".to_string())
+        );
+        // The same while streaming.
+        let streamed: Vec<Stmt> = crate::Pipeline::new(source).map(Result::unwrap).collect();
+        assert_eq!(streamed, statements);
+
+        // Only strings are merged.
+        assert_eq!(
+            printed(&crate::parse("print \"a\" + \"b\";").unwrap()[0]),
+            "\"a\" + \"b\""
+        );
+        for source in ["print \"a\" 1;", "print \"a\" b;", "print 'a' \"b\";"] {
+            assert!(crate::parse(source).is_err(), "{}", source);
+        }
+    }
+
+    #[test]
+    fn adjacent_strings_and_newlines() {
+        let mut scanner = Scanner::new("print \"a\"\n\"b\"".to_string());
+        scanner.set_newlines(true);
+        let statements = Parser::new(scanner.scan_tokens().unwrap().clone())
+            .parse()
+            .unwrap();
+        assert_eq!(statements.len(), 2);
+        assert_eq!(printed(&statements[0]), "\"a\"");
+    }
+
+    #[test]
+    fn pipe_desugars_to_calls() {
+        let source = "a = x + 1 |> f |> g(2) |> h()[0]".to_string();
+        let mut scanner = Scanner::new(source);
+        let tokens = scanner.scan_tokens().unwrap();
+        let mut parser = Parser::new(tokens.clone());
+        assert_eq!(
+            parser.expression().unwrap().to_string(),
+            "a = h()[0](g(f(x + 1), 2))"
+        );
+    }
+
+    #[test]
+    fn partial_call_arguments() {
+        let source = "f(1, + 2 +, 3);".to_string();
+        let mut scanner = Scanner::new(source);
+        let tokens = scanner.scan_tokens().unwrap();
+        let (statements, errors) = Parser::new(tokens.clone()).parse_partial();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "Expect expression.");
+        let Stmt::Expression {
+            expr: Expr::Call(call),
+            ..
+        } = &statements[0]
+        else {
+            panic!("expected a call, got {:?}", statements);
+        };
+        assert_eq!(
+            call.arguments,
+            vec![
+                Expr::Literal(Literal::Number(1.0)),
+                Expr::Error(Span::new(5, 10)),
+                Expr::Literal(Literal::Number(3.0)),
+            ]
+        );
+    }
+
+    #[test]
+    fn one_error_per_statement() {
+        let source = "f(1 + *, 2 + /) + ;\n{\n  print 1 +;\n  print 2;\n}\ng(*);".to_string();
+        let mut scanner = Scanner::new(source);
+        let tokens = scanner.scan_tokens().unwrap();
+        let (statements, errors) = Parser::new(tokens.clone()).parse_partial();
+        assert_eq!(
+            errors.iter().map(|err| err.to_string()).collect::<Vec<_>>(),
+            [
+                "[line 1] Error: Expect expression.",
+                "[line 3] Error: Expect expression.",
+                "[line 6] Error: Expect expression.",
+            ]
+        );
+        assert_eq!(errors[0].span, Span::new(6, 7));
+        let Stmt::Block {
+            statements: inner, ..
+        } = &statements[1]
+        else {
+            panic!("expected a block, got {:?}", statements[1]);
+        };
+        assert!(matches!(inner[0], Stmt::Error { .. }));
+        assert!(!inner[1].has_errors());
+    }
+
+    #[test]
+    fn partial_statements() {
+        let source = "print 1;\nprint 1 + ;\n{\n  print 2\n}\nprint 3;".to_string();
+        let mut scanner = Scanner::new(source);
+        let tokens = scanner.scan_tokens().unwrap();
+        let (statements, errors) = Parser::new(tokens.clone()).parse_partial();
+        assert_eq!(
+            errors.iter().map(|err| err.to_string()).collect::<Vec<_>>(),
+            [
+                "[line 2] Error: Expect expression.",
+                "[line 5] Error: Expect ';' after value."
+            ]
+        );
+        assert_eq!(
+            statements,
+            vec![
+                Stmt::Print {
+                    exprs: vec![Expr::Literal(Literal::Number(1.0))],
+                    span: Span::new(0, 8),
+                    line: 1
+                },
+                Stmt::Error {
+                    span: Span::new(9, 20),
+                    line: 2
+                },
+                Stmt::Block {
+                    statements: vec![Stmt::Error {
+                        span: Span::new(25, 32),
+                        line: 4
+                    }],
+                    span: Span::new(21, 34),
+                    line: 3
+                },
+                Stmt::Print {
+                    exprs: vec![Expr::Literal(Literal::Number(3.0))],
+                    span: Span::new(35, 43),
+                    line: 6
+                },
+            ]
+        );
+        // Without errors it's the same as `parse`.
+        let tokens = Scanner::new("print 1; { print 2; }".to_string())
+            .scan_tokens()
+            .unwrap()
+            .clone();
+        assert_eq!(
+            Parser::new(tokens.clone()).parse_partial(),
+            (Parser::new(tokens).parse().unwrap(), vec![])
+        );
+    }
 }
@@ -0,0 +1,228 @@
+use std::time::Duration;
+
+use crate::{
+    diagnostics::Diagnostic,
+    error::RoxyError,
+    parser::{Parser, Stmt},
+    scanner::{Scanner, Token, TokenKind},
+    walk::walk,
+};
+
+// Scans and parses a program one top level statement at a time, so the first
+// statements can run before the rest of a long source has been looked at.
+// Only the tokens of the statement being parsed are kept around. An error is
+// yielded in place of the statement it was found in and the statements after
+// it still come through.
+pub struct Pipeline {
+    parser: Parser,
+}
+
+impl Pipeline {
+    pub fn new(source: &str) -> Self {
+        Self {
+            parser: Parser::streaming(Scanner::new(source.to_string())),
+        }
+    }
+
+    // Warnings found since the last call. They don't hold statements back,
+    // so callers that want them collect them next to the statements.
+    pub fn take_warnings(&mut self) -> Vec<Diagnostic> {
+        self.parser.take_scan_warnings()
+    }
+}
+
+impl Iterator for Pipeline {
+    type Item = Result<Stmt, RoxyError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let result = self.parser.next_declaration();
+        let scan_errors = self.parser.take_scan_errors();
+        match result {
+            // Without the bad characters the statement probably means
+            // something else, so it is dropped like `parse` would.
+            Some(_) if !scan_errors.is_empty() => Some(Err(RoxyError::Scan(scan_errors))),
+            Some(result) => Some(result.map_err(|err| RoxyError::Parse(vec![err]))),
+            None if !scan_errors.is_empty() => Some(Err(RoxyError::Scan(scan_errors))),
+            None => None,
+        }
+    }
+}
+
+// Sizes of a program and how long each stage took on it, for build tools.
+// Only `run_with_report` and `check_with_report` fill it in, the other
+// entry points don't count or time anything. A stage that didn't run took
+// no time.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PipelineReport {
+    // Without the EOF token.
+    pub tokens: usize,
+    // Top level ones.
+    pub statements: usize,
+    // Statements and expressions at any depth.
+    pub nodes: usize,
+    // Top level statements are at depth 0.
+    pub max_depth: usize,
+    pub scan: Duration,
+    pub parse: Duration,
+    pub resolve: Duration,
+    pub run: Duration,
+}
+
+impl PipelineReport {
+    pub fn count(&mut self, tokens: &[Token], statements: &[Stmt]) {
+        self.tokens = tokens
+            .iter()
+            .filter(|token| token.kind != TokenKind::EOF)
+            .count();
+        self.statements = statements.len();
+        self.nodes = 0;
+        self.max_depth = 0;
+        for node in walk(statements) {
+            self.nodes += 1;
+            self.max_depth = self.max_depth.max(node.depth);
+        }
+    }
+
+    pub fn total(&self) -> Duration {
+        self.scan + self.parse + self.resolve + self.run
+    }
+
+    pub fn table(&self) -> String {
+        let mut out = String::new();
+        for (name, count) in [
+            ("tokens", self.tokens),
+            ("statements", self.statements),
+            ("nodes", self.nodes),
+            ("max depth", self.max_depth),
+        ] {
+            out.push_str(&format!("{:<10}  {:>10}\n", name, count));
+        }
+        for (name, time) in [
+            ("scan", self.scan),
+            ("parse", self.parse),
+            ("resolve", self.resolve),
+            ("run", self.run),
+            ("total", self.total()),
+        ] {
+            out.push_str(&format!(
+                "{:<10}  {:>10.3} ms\n",
+                name,
+                time.as_secs_f64() * 1000.0
+            ));
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod pipeline_tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::{Pipeline, PipelineReport};
+    use crate::{diagnostics::Severity, error::RoxyError, interpreter::Interpreter};
+
+    #[test]
+    fn runs_each_statement_as_it_is_parsed() {
+        let printed = Arc::new(Mutex::new(Vec::new()));
+        let mut interpreter = Interpreter::new();
+        let sink = printed.clone();
+        interpreter.set_print_handler(move |value| sink.lock().unwrap().push(value.to_string()));
+        let mut pipeline = Pipeline::new("var a = 1; print a; a = a + 1; print a;");
+        for expected in [vec![], vec!["1"], vec!["1"], vec!["1", "2"]] {
+            let stmt = pipeline.next().unwrap().unwrap();
+            interpreter.interpret(vec![stmt]).unwrap();
+            assert_eq!(*printed.lock().unwrap(), expected);
+        }
+        assert!(pipeline.next().is_none());
+        assert!(pipeline.next().is_none());
+    }
+
+    #[test]
+    fn keeps_going_after_errors() {
+        let results: Vec<_> = Pipeline::new("print 1;\nprint 1 +;\nprint @ 2;\nprint 3;").collect();
+        assert_eq!(results.len(), 4);
+        assert!(results[0].is_ok());
+        match &results[1] {
+            Err(RoxyError::Parse(errors)) => {
+                assert_eq!(errors[0].to_string(), "[line 2] Error: Expect expression.")
+            }
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+        match &results[2] {
+            Err(RoxyError::Scan(errors)) => assert_eq!(errors[0].line, 3),
+            other => panic!("expected a scan error, got {:?}", other),
+        }
+        assert!(results[3].is_ok());
+    }
+
+    #[test]
+    fn one_error_for_a_bad_expression() {
+        let results: Vec<_> = Pipeline::new("print 1 + * 2 + & 3;\nprint 4;").collect();
+        assert_eq!(results.len(), 2);
+        match &results[0] {
+            Err(err) => assert_eq!(err.errors().count(), 1),
+            other => panic!("expected an error, got {:?}", other),
+        }
+        assert!(results[1].is_ok());
+    }
+
+    #[test]
+    fn matches_parse() {
+        let source = "var a = 1;\n{ var b = a; print b; }\ntry { throw 1; } catch (e) { print e; }\nfor (i in 0..3) print i;";
+        let streamed: Vec<_> = Pipeline::new(source).map(Result::unwrap).collect();
+        assert_eq!(streamed, crate::parse(source).unwrap());
+    }
+
+    #[test]
+    fn warnings_do_not_block() {
+        let mut pipeline = Pipeline::new("print 9007199254740993;\nprint 2;");
+        assert!(pipeline.next().unwrap().is_ok());
+        let warnings = pipeline.take_warnings();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].severity, Severity::Warning);
+        assert!(pipeline.next().unwrap().is_ok());
+        assert!(pipeline.next().is_none());
+        assert!(pipeline.take_warnings().is_empty());
+    }
+
+    #[test]
+    fn error_after_last_statement() {
+        let results: Vec<_> = Pipeline::new("print 1; @").collect();
+        assert!(results[0].is_ok());
+        assert!(matches!(results[1], Err(RoxyError::Scan(_))));
+        assert_eq!(results.len(), 2);
+    }
+
+    #[test]
+    fn report() {
+        let source = "var a = 1;\nif (a < 2) {\n  print -a;\n}\n";
+        let (result, report) = crate::run_with_report(source, &mut Interpreter::new());
+        assert_eq!(result, Ok(()));
+        // The deepest is `a` in the negation, under the print, the block
+        // and the `if`.
+        assert_eq!(
+            (
+                report.tokens,
+                report.statements,
+                report.nodes,
+                report.max_depth
+            ),
+            (17, 2, 10, 4)
+        );
+        let stages = [report.scan, report.parse, report.resolve, report.run];
+        assert!(stages.iter().all(|stage| *stage <= report.total()));
+        assert_eq!(stages.iter().sum::<std::time::Duration>(), report.total());
+
+        let (result, checked) = crate::check_with_report(source);
+        assert_eq!(result, Ok(Vec::new()));
+        assert_eq!(checked.nodes, 10);
+        assert_eq!(checked.run, std::time::Duration::ZERO);
+
+        // Not counted when it doesn't parse, but scanning was timed.
+        let (result, report) = crate::check_with_report("print 1 +;");
+        assert!(result.is_err());
+        assert_eq!(report.tokens, 0);
+        assert_eq!(report.resolve, std::time::Duration::ZERO);
+        assert_eq!(PipelineReport::default().total(), std::time::Duration::ZERO);
+    }
+}
@@ -0,0 +1,212 @@
+use crate::{
+    diagnostics::{Code, Warning},
+    parser::{Expr, Stmt},
+};
+
+struct Local {
+    name: String,
+    line: usize,
+    read: bool,
+}
+
+// Static pass over the program before it runs. For now it only looks for
+// locals that are never read; globals are skipped since the REPL or another
+// script may still use them.
+#[derive(Default)]
+pub struct Resolver {
+    scopes: Vec<Vec<Local>>,
+    warnings: Vec<Warning>,
+}
+
+impl Resolver {
+    pub fn resolve(statements: &[Stmt]) -> Vec<Warning> {
+        let mut resolver = Resolver::default();
+        for stmt in statements {
+            resolver.statement(stmt);
+        }
+        resolver.warnings
+    }
+
+    fn statement(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Expression { expr, .. } | Stmt::Throw { expr, .. } => self.expression(expr),
+            Stmt::Print { exprs, .. } => exprs.iter().for_each(|expr| self.expression(expr)),
+            Stmt::Error { .. } => {}
+            Stmt::Var {
+                name,
+                initializer,
+                line,
+                ..
+            } => {
+                self.expression(initializer);
+                if let Some(scope) = self.scopes.last_mut() {
+                    scope.push(Local {
+                        name: name.clone(),
+                        line: *line,
+                        read: false,
+                    });
+                }
+            }
+            Stmt::Block { statements, .. } => {
+                self.scopes.push(Vec::new());
+                for stmt in statements {
+                    self.statement(stmt);
+                }
+                self.end_scope();
+            }
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.expression(condition);
+                self.statement(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.statement(else_branch);
+                }
+            }
+            Stmt::ForIn {
+                name,
+                iterable,
+                body,
+                line,
+                ..
+            } => {
+                self.expression(iterable);
+                self.scopes.push(vec![Local {
+                    name: name.clone(),
+                    line: *line,
+                    read: false,
+                }]);
+                self.statement(body);
+                self.end_scope();
+            }
+            Stmt::Try {
+                body,
+                catch,
+                finally,
+                line,
+                ..
+            } => {
+                self.statement(body);
+                if let Some((name, handler)) = catch {
+                    self.scopes.push(vec![Local {
+                        name: name.clone(),
+                        line: *line,
+                        read: false,
+                    }]);
+                    self.statement(handler);
+                    self.end_scope();
+                }
+                if let Some(finally) = finally {
+                    self.statement(finally);
+                }
+            }
+        }
+    }
+
+    fn end_scope(&mut self) {
+        let scope = self.scopes.pop().expect("scopes are balanced");
+        for local in scope {
+            if !local.read && !local.name.starts_with('_') {
+                self.warnings.push(Warning {
+                    code: Code::UnusedVariable,
+                    message: format!("Local variable '{}' is never used.", local.name),
+                    line: local.line,
+                });
+            }
+        }
+    }
+
+    fn expression(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Binary(b) => {
+                self.expression(&b.left);
+                self.expression(&b.right);
+            }
+            Expr::Unary(u) => self.expression(&u.right),
+            Expr::Literal(_) | Expr::Error(_) => {}
+            Expr::Grouping(g) => self.expression(&g.expr),
+            Expr::Variable(name) => self.read(name),
+            // Writing to a variable doesn't count as using it.
+            Expr::Assign(a) => self.expression(&a.value),
+            Expr::Call(c) => {
+                self.expression(&c.callee);
+                c.arguments
+                    .iter()
+                    .for_each(|argument| self.expression(argument));
+            }
+            Expr::Index(i) => {
+                self.expression(&i.object);
+                self.expression(&i.index);
+            }
+            Expr::SetIndex(i) => {
+                self.expression(&i.object);
+                self.expression(&i.index);
+                self.expression(&i.value);
+            }
+            Expr::Range(r) => {
+                self.expression(&r.start);
+                self.expression(&r.end);
+            }
+            Expr::Dbg(d) => self.expression(&d.expr),
+        }
+    }
+
+    fn read(&mut self, name: &str) {
+        let local = self
+            .scopes
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.iter_mut().rev().find(|local| local.name == name));
+        if let Some(local) = local {
+            local.read = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod resolver_tests {
+    use super::Resolver;
+    use crate::{
+        diagnostics::{Code, Warning},
+        parser::Parser,
+        scanner::Scanner,
+    };
+
+    fn warnings(source: &str) -> Vec<Warning> {
+        let mut scanner = Scanner::new(source.to_string());
+        let tokens = scanner.scan_tokens().unwrap();
+        let statements = Parser::new(tokens.clone()).parse().unwrap();
+        Resolver::resolve(&statements)
+    }
+
+    #[test]
+    fn unused_local() {
+        assert_eq!(
+            warnings("{\n  var tmp = 1;\n  var used = 2;\n  print used;\n}"),
+            vec![Warning {
+                code: Code::UnusedVariable,
+                message: "Local variable 'tmp' is never used.".to_string(),
+                line: 2
+            }]
+        );
+    }
+
+    #[test]
+    fn written_but_never_read() {
+        assert_eq!(
+            warnings("{ var a = 1; a = 2; }")[0].message,
+            "Local variable 'a' is never used."
+        );
+    }
+
+    #[test]
+    fn no_warnings() {
+        assert!(warnings("var global = 1; { var _scratch = 1; }").is_empty());
+        // The outer `a` is read by the inner one's initializer.
+        assert!(warnings("{ var a = 1; { var a = a; print a; } }").is_empty());
+        assert!(warnings("{ var s = \"x\"; if (true) s[0]; }").is_empty());
+    }
+}
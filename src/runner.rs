@@ -0,0 +1,248 @@
+use std::sync::{Arc, Mutex};
+
+use crate::{
+    error::RoxyError,
+    interpreter::{Interpreter, InterpreterOptions, Value},
+};
+
+// Runs a program and checks what came of it, for tests here and in crates
+// embedding Roxy:
+//
+//     Runner::new()
+//         .source("var x = 1; print x;")
+//         .expect_output("1\n")
+//         .run();
+//
+// Output is compared as a whole, errors by a part of their message. Without
+// an expected error, any error is a failure.
+#[derive(Default)]
+pub struct Runner {
+    source: String,
+    globals: Vec<(String, Value)>,
+    fuel: Option<u64>,
+    output: Option<String>,
+    runtime_error: Option<String>,
+    // Scan errors count as parse errors.
+    parse_error: Option<String>,
+}
+
+impl Runner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn source(mut self, source: &str) -> Self {
+        self.source = source.to_string();
+        self
+    }
+
+    pub fn with_global(mut self, name: &str, value: impl Into<Value>) -> Self {
+        self.globals.push((name.to_string(), value.into()));
+        self
+    }
+
+    pub fn with_fuel(mut self, fuel: u64) -> Self {
+        self.fuel = Some(fuel);
+        self
+    }
+
+    // Every printed value followed by a line break.
+    pub fn expect_output(mut self, output: &str) -> Self {
+        self.output = Some(output.to_string());
+        self
+    }
+
+    pub fn expect_runtime_error(mut self, message: &str) -> Self {
+        self.runtime_error = Some(message.to_string());
+        self
+    }
+
+    pub fn expect_parse_error(mut self, message: &str) -> Self {
+        self.parse_error = Some(message.to_string());
+        self
+    }
+
+    // Panics when the program didn't do what was expected, otherwise
+    // returns what it printed.
+    pub fn run(self) -> String {
+        match self.check() {
+            Ok(output) => output,
+            Err(failure) => panic!("{}", failure),
+        }
+    }
+
+    // Like `run`, with the failure as an error instead of a panic.
+    pub fn check(self) -> Result<String, String> {
+        let printed = Arc::new(Mutex::new(String::new()));
+        let sink = Arc::clone(&printed);
+        let mut interpreter = Interpreter::with_options(InterpreterOptions {
+            fuel: self.fuel,
+            ..InterpreterOptions::default()
+        });
+        interpreter.set_print_handler(move |value| {
+            let mut printed = sink.lock().unwrap();
+            printed.push_str(&value.to_string());
+            printed.push('\n');
+        });
+        for (name, value) in self.globals {
+            interpreter.define_global(&name, value);
+        }
+        let result = crate::run_with(&self.source, &mut interpreter);
+        let output = printed.lock().unwrap().clone();
+
+        let mut failures = Vec::new();
+        let (kind, expected, err) = match &result {
+            Ok(()) => ("", None, None),
+            Err(RoxyError::Runtime(err)) => (
+                "runtime",
+                self.runtime_error.as_ref(),
+                Some(err.to_string()),
+            ),
+            Err(err) => ("parse", self.parse_error.as_ref(), Some(err.to_string())),
+        };
+        match (expected, err) {
+            (Some(expected), Some(err)) if !err.contains(expected.as_str()) => {
+                failures.push(format!(
+                    "Expected a {} error containing {:?}, got:\n{}",
+                    kind, expected, err
+                ))
+            }
+            (None, Some(err)) => failures.push(format!("Unexpected {} error:\n{}", kind, err)),
+            _ => {}
+        }
+        for (kind, expected) in [
+            ("runtime", &self.runtime_error),
+            ("parse", &self.parse_error),
+        ] {
+            if let (Some(expected), Ok(())) = (expected, &result) {
+                failures.push(format!(
+                    "Expected a {} error containing {:?}, but the program ran without errors.",
+                    kind, expected
+                ));
+            }
+        }
+        if let Some(expected) = &self.output {
+            if *expected != output {
+                failures.push(format!(
+                    "Output differs (- expected, + actual):\n{}",
+                    diff(expected, &output)
+                ));
+            }
+        }
+        if failures.is_empty() {
+            Ok(output)
+        } else {
+            Err(failures.join("\n\n"))
+        }
+    }
+}
+
+// Line by line, without looking for inserted or removed lines.
+fn diff(expected: &str, actual: &str) -> String {
+    let expected: Vec<&str> = expected.split('\n').collect();
+    let actual: Vec<&str> = actual.split('\n').collect();
+    let mut out = Vec::new();
+    for i in 0..expected.len().max(actual.len()) {
+        match (expected.get(i), actual.get(i)) {
+            (Some(e), Some(a)) if e == a => out.push(format!("  {}", e)),
+            (e, a) => {
+                if let Some(e) = e {
+                    out.push(format!("- {}", e));
+                }
+                if let Some(a) = a {
+                    out.push(format!("+ {}", a));
+                }
+            }
+        }
+    }
+    out.join("\n")
+}
+
+#[cfg(test)]
+mod runner_tests {
+    use super::Runner;
+
+    #[test]
+    fn passes() {
+        let output = Runner::new()
+            .source("var x = 1; print x + y;")
+            .with_global("y", 2.0)
+            .expect_output("3\n")
+            .run();
+        assert_eq!(output, "3\n");
+        Runner::new()
+            .source("print 1;\nprint -nil;")
+            .expect_output("1\n")
+            .expect_runtime_error("negate")
+            .run();
+        Runner::new()
+            .source("print 1 +;")
+            .expect_parse_error("Expect expression.")
+            .run();
+        Runner::new()
+            .source("for (i in 0..1000000000) {}")
+            .with_fuel(100)
+            .expect_runtime_error("Out of fuel.")
+            .run();
+    }
+
+    #[test]
+    fn output_diff() {
+        let failure = Runner::new()
+            .source("print 1; print 2; print 3;")
+            .expect_output("1\n4\n3\n")
+            .check()
+            .unwrap_err();
+        assert_eq!(
+            failure,
+            "Output differs (- expected, + actual):\n  1\n- 4\n+ 2\n  3\n  "
+        );
+        let failure = Runner::new()
+            .source("print 1;")
+            .expect_output("1\n2\n")
+            .check()
+            .unwrap_err();
+        assert_eq!(
+            failure,
+            "Output differs (- expected, + actual):\n  1\n- 2\n+ \n- "
+        );
+    }
+
+    #[test]
+    fn error_mismatches() {
+        let failure = Runner::new()
+            .source("print -nil;")
+            .expect_runtime_error("Undefined")
+            .check()
+            .unwrap_err();
+        assert_eq!(
+            failure,
+            "Expected a runtime error containing \"Undefined\", got:\nYou can only negate a number"
+        );
+        let failure = Runner::new().source("print 1 +;").check().unwrap_err();
+        assert_eq!(
+            failure,
+            "Unexpected parse error:\n[line 1] Error: Expect expression."
+        );
+        let failure = Runner::new()
+            .source("print 1;")
+            .expect_parse_error("Expect")
+            .expect_output("2\n")
+            .check()
+            .unwrap_err();
+        assert_eq!(
+            failure,
+            "Expected a parse error containing \"Expect\", but the program ran without errors.\n\n\
+             Output differs (- expected, + actual):\n- 2\n+ 1\n  "
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Unexpected runtime error:\nOut of fuel.")]
+    fn run_panics() {
+        Runner::new()
+            .source("for (i in 0..1000000) {}")
+            .with_fuel(10)
+            .run();
+    }
+}
@@ -1,3 +1,5 @@
+use crate::diagnostics::{Code, Diagnostic, Severity, Span};
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum TokenKind {
     Bang,
@@ -12,8 +14,13 @@ pub enum TokenKind {
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
     Comma,
     Dot,
+    DotDot,
+    DotDotEqual,
+    PipeGreater,
     Minus,
     Plus,
     Semicolon,
@@ -26,6 +33,7 @@ pub enum TokenKind {
     Fun,
     For,
     If,
+    In,
     Nil,
     Or,
     Print,
@@ -35,105 +43,468 @@ pub enum TokenKind {
     True,
     Var,
     While,
+    Try,
+    Catch,
+    Finally,
+    Throw,
     StringLiteral(String),
+    CharLiteral(char),
     NumberLiteral(f64),
     Identifier(String),
+    // Only with `set_newlines`.
+    Newline,
     EOF,
 }
 
+impl TokenKind {
+    // Every keyword with its spelling, the scanner recognizes keywords from
+    // this.
+    pub const KEYWORDS: &'static [(&'static str, TokenKind)] = &[
+        ("and", TokenKind::And),
+        ("class", TokenKind::Class),
+        ("else", TokenKind::Else),
+        ("false", TokenKind::False),
+        ("fun", TokenKind::Fun),
+        ("for", TokenKind::For),
+        ("if", TokenKind::If),
+        ("in", TokenKind::In),
+        ("nil", TokenKind::Nil),
+        ("or", TokenKind::Or),
+        ("print", TokenKind::Print),
+        ("return", TokenKind::Return),
+        ("super", TokenKind::Super),
+        ("this", TokenKind::This),
+        ("true", TokenKind::True),
+        ("var", TokenKind::Var),
+        ("while", TokenKind::While),
+        ("try", TokenKind::Try),
+        ("catch", TokenKind::Catch),
+        ("finally", TokenKind::Finally),
+        ("throw", TokenKind::Throw),
+    ];
+
+    pub fn is_keyword(&self) -> bool {
+        Self::KEYWORDS.iter().any(|(_, kind)| kind == self)
+    }
+
+    // Tokens that make a `Binary` expression. `|>` and `..` go between two
+    // operands too, but make calls and ranges.
+    pub fn is_binary_operator(&self) -> bool {
+        matches!(
+            self,
+            TokenKind::BangEqual
+                | TokenKind::EqualEqual
+                | TokenKind::Greater
+                | TokenKind::GreaterEqual
+                | TokenKind::Less
+                | TokenKind::LessEqual
+                | TokenKind::Minus
+                | TokenKind::Plus
+                | TokenKind::Slash
+                | TokenKind::Star
+        )
+    }
+
+    // Tokens that are a value on their own, see `Parser::literal`.
+    pub fn is_literal(&self) -> bool {
+        matches!(
+            self,
+            TokenKind::StringLiteral(_)
+                | TokenKind::CharLiteral(_)
+                | TokenKind::NumberLiteral(_)
+                | TokenKind::True
+                | TokenKind::False
+                | TokenKind::Nil
+        )
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TokenKind::Bang => "Bang",
+            TokenKind::BangEqual => "BangEqual",
+            TokenKind::Equal => "Equal",
+            TokenKind::EqualEqual => "EqualEqual",
+            TokenKind::Greater => "Greater",
+            TokenKind::GreaterEqual => "GreaterEqual",
+            TokenKind::Less => "Less",
+            TokenKind::LessEqual => "LessEqual",
+            TokenKind::LeftParen => "LeftParen",
+            TokenKind::RightParen => "RightParen",
+            TokenKind::LeftBrace => "LeftBrace",
+            TokenKind::RightBrace => "RightBrace",
+            TokenKind::LeftBracket => "LeftBracket",
+            TokenKind::RightBracket => "RightBracket",
+            TokenKind::Comma => "Comma",
+            TokenKind::Dot => "Dot",
+            TokenKind::DotDot => "DotDot",
+            TokenKind::DotDotEqual => "DotDotEqual",
+            TokenKind::PipeGreater => "PipeGreater",
+            TokenKind::Minus => "Minus",
+            TokenKind::Plus => "Plus",
+            TokenKind::Semicolon => "Semicolon",
+            TokenKind::Slash => "Slash",
+            TokenKind::Star => "Star",
+            TokenKind::And => "And",
+            TokenKind::Class => "Class",
+            TokenKind::Else => "Else",
+            TokenKind::False => "False",
+            TokenKind::Fun => "Fun",
+            TokenKind::For => "For",
+            TokenKind::If => "If",
+            TokenKind::In => "In",
+            TokenKind::Nil => "Nil",
+            TokenKind::Or => "Or",
+            TokenKind::Print => "Print",
+            TokenKind::Return => "Return",
+            TokenKind::Super => "Super",
+            TokenKind::This => "This",
+            TokenKind::True => "True",
+            TokenKind::Var => "Var",
+            TokenKind::While => "While",
+            TokenKind::Try => "Try",
+            TokenKind::Catch => "Catch",
+            TokenKind::Finally => "Finally",
+            TokenKind::Throw => "Throw",
+            TokenKind::StringLiteral(_) => "StringLiteral",
+            TokenKind::CharLiteral(_) => "CharLiteral",
+            TokenKind::NumberLiteral(_) => "NumberLiteral",
+            TokenKind::Identifier(_) => "Identifier",
+            TokenKind::Newline => "Newline",
+            TokenKind::EOF => "EOF",
+        }
+    }
+}
+
+// Prints the token's lexeme.
+impl std::fmt::Display for TokenKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let lexeme = match self {
+            TokenKind::Bang => "!",
+            TokenKind::BangEqual => "!=",
+            TokenKind::Equal => "=",
+            TokenKind::EqualEqual => "==",
+            TokenKind::Greater => ">",
+            TokenKind::GreaterEqual => ">=",
+            TokenKind::Less => "<",
+            TokenKind::LessEqual => "<=",
+            TokenKind::LeftParen => "(",
+            TokenKind::RightParen => ")",
+            TokenKind::LeftBrace => "{",
+            TokenKind::RightBrace => "}",
+            TokenKind::LeftBracket => "[",
+            TokenKind::RightBracket => "]",
+            TokenKind::Comma => ",",
+            TokenKind::Dot => ".",
+            TokenKind::DotDot => "..",
+            TokenKind::DotDotEqual => "..=",
+            TokenKind::PipeGreater => "|>",
+            TokenKind::Minus => "-",
+            TokenKind::Plus => "+",
+            TokenKind::Semicolon => ";",
+            TokenKind::Slash => "/",
+            TokenKind::Star => "*",
+            TokenKind::And => "and",
+            TokenKind::Class => "class",
+            TokenKind::Else => "else",
+            TokenKind::False => "false",
+            TokenKind::Fun => "fun",
+            TokenKind::For => "for",
+            TokenKind::If => "if",
+            TokenKind::In => "in",
+            TokenKind::Nil => "nil",
+            TokenKind::Or => "or",
+            TokenKind::Print => "print",
+            TokenKind::Return => "return",
+            TokenKind::Super => "super",
+            TokenKind::This => "this",
+            TokenKind::True => "true",
+            TokenKind::Var => "var",
+            TokenKind::While => "while",
+            TokenKind::Try => "try",
+            TokenKind::Catch => "catch",
+            TokenKind::Finally => "finally",
+            TokenKind::Throw => "throw",
+            TokenKind::StringLiteral(value) => return write!(f, "\"{}\"", value),
+            TokenKind::CharLiteral(c) => {
+                return match ESCAPES.iter().find(|(_, escaped)| escaped == c) {
+                    Some((name, _)) => write!(f, "'\\{}'", name),
+                    None => write!(f, "'{}'", c),
+                }
+            }
+            TokenKind::NumberLiteral(value) => return write!(f, "{}", value),
+            TokenKind::Identifier(name) => name,
+            TokenKind::Newline | TokenKind::EOF => "",
+        };
+        write!(f, "{}", lexeme)
+    }
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct Token {
     pub kind: TokenKind,
     pub line: usize,
+    // Byte offset of the token's first character, the same as `span.start`.
     pub pos: usize,
+    // 1-based column of the token's first character.
+    pub column: usize,
+    pub span: Span,
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{} {}", self.line, self.column, self.kind.name())?;
+        if self.kind != TokenKind::EOF {
+            write!(f, " {}", self.kind)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct ScanError {
+    pub code: Code,
+    pub message: String,
+    pub line: usize,
+    pub span: Span,
+    // The error was caused by the source ending too early, e.g. an
+    // unterminated string. The REPL uses it to ask for more input.
+    pub at_end: bool,
+}
+
+impl std::fmt::Display for ScanError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[line {}] Error: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ScanError {}
+
+// A `//` comment, the text starts with the slashes.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Comment {
+    pub text: String,
+    pub line: usize,
+    pub span: Span,
+}
+
+// Past these the scan gives up, with an error saying so where it stopped.
+// Nothing written by hand comes close, they are there so that megabytes of
+// `((((` or of garbage don't pile up without end.
+#[derive(Debug, Clone, Copy)]
+pub struct ScannerOptions {
+    pub max_tokens: usize,
+    pub max_errors: usize,
+}
+
+impl Default for ScannerOptions {
+    fn default() -> Self {
+        ScannerOptions {
+            max_tokens: 1_000_000,
+            max_errors: 1_000,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct Scanner {
     source: String,
     tokens: Vec<Token>,
+    errors: Vec<ScanError>,
+    // Don't stop the scan, `warnings` hands them out.
+    warnings: Vec<Diagnostic>,
     start: usize,
     current: usize,
     line: usize,
+    // Offset of the first character of the current line.
+    line_start: usize,
+    // Comments are skipped unless asked for, only the formatter wants them.
+    keep_comments: bool,
+    comments: Vec<Comment>,
+    // Line breaks become tokens, and so does the end of the source.
+    newlines: bool,
+    ended: bool,
+    options: ScannerOptions,
+    // Counted apart from `tokens` and `errors`, `next_token` takes those.
+    token_count: usize,
+    error_count: usize,
+    aborted: bool,
 }
 
 impl Scanner {
     pub fn new(source: String) -> Scanner {
+        Scanner::with_options(source, ScannerOptions::default())
+    }
+
+    pub fn with_options(source: String, options: ScannerOptions) -> Scanner {
         Scanner {
             source,
             tokens: Vec::new(),
+            errors: Vec::new(),
+            warnings: Vec::new(),
             start: 0,
             current: 0,
             line: 1,
+            line_start: 0,
+            keep_comments: false,
+            comments: Vec::new(),
+            newlines: false,
+            ended: false,
+            options,
+            token_count: 0,
+            error_count: 0,
+            aborted: false,
         }
     }
+
+    pub fn set_keep_comments(&mut self, keep: bool) {
+        self.keep_comments = keep;
+    }
+
+    // For sources where a line break can end a statement, see
+    // `Parser::consume`.
+    pub fn set_newlines(&mut self, newlines: bool) {
+        self.newlines = newlines;
+    }
+
+    pub fn comments(&self) -> &[Comment] {
+        &self.comments
+    }
+
+    pub fn warnings(&self) -> &[Diagnostic] {
+        &self.warnings
+    }
+
+    pub fn take_warnings(&mut self) -> Vec<Diagnostic> {
+        std::mem::take(&mut self.warnings)
+    }
     fn scan_token(&mut self) {
         let c = self.advance();
         match c {
             '(' => self.add_token(Token {
                 kind: TokenKind::LeftParen,
                 line: self.line,
-                pos: self.current,
+                pos: self.start,
+                column: self.column(),
+                span: self.span(),
             }),
             ')' => self.add_token(Token {
                 kind: TokenKind::RightParen,
                 line: self.line,
-                pos: self.current,
+                pos: self.start,
+                column: self.column(),
+                span: self.span(),
             }),
             '{' => self.add_token(Token {
                 kind: TokenKind::LeftBrace,
                 line: self.line,
-                pos: self.current,
+                pos: self.start,
+                column: self.column(),
+                span: self.span(),
             }),
             '}' => self.add_token(Token {
                 kind: TokenKind::RightBrace,
                 line: self.line,
-                pos: self.current,
+                pos: self.start,
+                column: self.column(),
+                span: self.span(),
+            }),
+            '[' => self.add_token(Token {
+                kind: TokenKind::LeftBracket,
+                line: self.line,
+                pos: self.start,
+                column: self.column(),
+                span: self.span(),
+            }),
+            ']' => self.add_token(Token {
+                kind: TokenKind::RightBracket,
+                line: self.line,
+                pos: self.start,
+                column: self.column(),
+                span: self.span(),
             }),
             ',' => self.add_token(Token {
                 kind: TokenKind::Comma,
                 line: self.line,
-                pos: self.current,
+                pos: self.start,
+                column: self.column(),
+                span: self.span(),
             }),
-            '.' => self.add_token(Token {
-                kind: TokenKind::Dot,
+            '.' => {
+                let kind = if !self.match_char('.') {
+                    TokenKind::Dot
+                } else if self.match_char('=') {
+                    TokenKind::DotDotEqual
+                } else {
+                    TokenKind::DotDot
+                };
+                self.add_token(Token {
+                    kind,
+                    line: self.line,
+                    pos: self.start,
+                    column: self.column(),
+                    span: self.span(),
+                })
+            }
+            // There is no `|` on its own.
+            '|' if self.match_char('>') => self.add_token(Token {
+                kind: TokenKind::PipeGreater,
                 line: self.line,
-                pos: self.current,
+                pos: self.start,
+                column: self.column(),
+                span: self.span(),
             }),
             '-' => self.add_token(Token {
                 kind: TokenKind::Minus,
                 line: self.line,
-                pos: self.current,
+                pos: self.start,
+                column: self.column(),
+                span: self.span(),
             }),
             '+' => self.add_token(Token {
                 kind: TokenKind::Plus,
                 line: self.line,
-                pos: self.current,
+                pos: self.start,
+                column: self.column(),
+                span: self.span(),
             }),
             ';' => self.add_token(Token {
                 kind: TokenKind::Semicolon,
                 line: self.line,
-                pos: self.current,
+                pos: self.start,
+                column: self.column(),
+                span: self.span(),
             }),
             '*' => self.add_token(Token {
                 kind: TokenKind::Star,
                 line: self.line,
-                pos: self.current,
+                pos: self.start,
+                column: self.column(),
+                span: self.span(),
             }),
             ' ' | '\r' | '\t' => (),
-            '\n' => self.line += 1,
+            '\n' => {
+                if self.newlines {
+                    self.add_newline();
+                }
+                self.line += 1;
+                self.line_start = self.current;
+            }
             '!' => {
                 if self.match_char('=') {
                     self.add_token(Token {
                         kind: TokenKind::BangEqual,
                         line: self.line,
-                        pos: self.current,
+                        pos: self.start,
+                        column: self.column(),
+                        span: self.span(),
                     })
                 } else {
                     self.add_token(Token {
                         kind: TokenKind::Bang,
                         line: self.line,
-                        pos: self.current,
+                        pos: self.start,
+                        column: self.column(),
+                        span: self.span(),
                     })
                 }
             }
@@ -142,13 +513,17 @@ impl Scanner {
                     self.add_token(Token {
                         kind: TokenKind::EqualEqual,
                         line: self.line,
-                        pos: self.current,
+                        pos: self.start,
+                        column: self.column(),
+                        span: self.span(),
                     })
                 } else {
                     self.add_token(Token {
                         kind: TokenKind::Equal,
                         line: self.line,
-                        pos: self.current,
+                        pos: self.start,
+                        column: self.column(),
+                        span: self.span(),
                     })
                 }
             }
@@ -157,13 +532,17 @@ impl Scanner {
                     self.add_token(Token {
                         kind: TokenKind::LessEqual,
                         line: self.line,
-                        pos: self.current,
+                        pos: self.start,
+                        column: self.column(),
+                        span: self.span(),
                     })
                 } else {
                     self.add_token(Token {
                         kind: TokenKind::Less,
                         line: self.line,
-                        pos: self.current,
+                        pos: self.start,
+                        column: self.column(),
+                        span: self.span(),
                     })
                 }
             }
@@ -172,13 +551,17 @@ impl Scanner {
                     self.add_token(Token {
                         kind: TokenKind::GreaterEqual,
                         line: self.line,
-                        pos: self.current,
+                        pos: self.start,
+                        column: self.column(),
+                        span: self.span(),
                     })
                 } else {
                     self.add_token(Token {
                         kind: TokenKind::Greater,
                         line: self.line,
-                        pos: self.current,
+                        pos: self.start,
+                        column: self.column(),
+                        span: self.span(),
                     })
                 }
             }
@@ -187,71 +570,194 @@ impl Scanner {
                     while self.peek() != '\n' && !self.is_at_end() {
                         self.advance();
                     }
+                    if self.keep_comments {
+                        self.comments.push(Comment {
+                            text: self.source[self.start..self.current].trim_end().to_string(),
+                            line: self.line,
+                            span: self.span(),
+                        });
+                    }
                 } else {
                     self.add_token(Token {
                         kind: TokenKind::Slash,
                         line: self.line,
-                        pos: self.current,
+                        pos: self.start,
+                        column: self.column(),
+                        span: self.span(),
                     })
                 }
             }
             '"' => self.string(),
+            '\'' => self.char_literal(),
             _ if Scanner::is_lox_digit(c) => self.number(),
             _ if Scanner::is_lox_alphabetic(c) => {
                 self.identifier();
             }
-            _ => todo!(),
+            // Usually pasted from somewhere, e.g. full-width digits.
+            _ if c.is_numeric() => self.error(
+                Code::UnexpectedCharacter,
+                &format!("Non-ASCII digit '{}', numbers are written with 0-9.", c),
+                false,
+            ),
+            _ => self.error(Code::UnexpectedCharacter, "Unexpected character.", false),
         }
     }
-    pub fn scan_tokens(&mut self) -> &Vec<Token> {
+    // Scanning doesn't stop at the first error, every error in the source
+    // is reported.
+    pub fn scan_tokens(&mut self) -> Result<&Vec<Token>, Vec<ScanError>> {
         while !self.is_at_end() {
+            if self.over_limit() {
+                break;
+            }
+            self.start = self.current;
+            self.scan_token();
+        }
+        self.end_source();
+        let eof = self.eof();
+        self.tokens.push(eof);
+        if self.errors.is_empty() {
+            Ok(&self.tokens)
+        } else {
+            Err(self.errors.clone())
+        }
+    }
+
+    // Scans only as far as the next token, for parsing a long source while it
+    // is being scanned. Errors come back one at a time and scanning picks up
+    // after them. Once the source is used up every call returns EOF.
+    pub fn next_token(&mut self) -> Result<Token, ScanError> {
+        loop {
+            if let Some(err) = self.errors.pop() {
+                return Err(err);
+            }
+            if let Some(token) = self.tokens.pop() {
+                return Ok(token);
+            }
+            if self.is_at_end() {
+                if !self.ended && self.newlines {
+                    self.end_source();
+                    continue;
+                }
+                return Ok(self.eof());
+            }
+            if self.over_limit() {
+                continue;
+            }
             self.start = self.current;
             self.scan_token();
         }
-        self.tokens.push(Token {
+    }
+
+    // Stops the scan for good once there are too many tokens or errors. The
+    // rest of the source is treated as if it wasn't there, so EOF still
+    // comes, right where the scan stopped.
+    fn over_limit(&mut self) -> bool {
+        let what = if self.token_count >= self.options.max_tokens {
+            "tokens"
+        } else if self.error_count >= self.options.max_errors {
+            "errors"
+        } else {
+            return false;
+        };
+        self.start = self.current;
+        self.error(
+            Code::ScanAborted,
+            &format!("Too many {}; aborting scan.", what),
+            false,
+        );
+        self.aborted = true;
+        true
+    }
+
+    // The last line ends with the source, whether or not it has a line break.
+    fn end_source(&mut self) {
+        if self.newlines && !self.ended {
+            self.start = self.current;
+            self.add_newline();
+        }
+        self.ended = true;
+    }
+
+    fn add_newline(&mut self) {
+        self.add_token(Token {
+            kind: TokenKind::Newline,
+            line: self.line,
+            pos: self.start,
+            column: self.column(),
+            span: self.span(),
+        });
+    }
+
+    fn eof(&mut self) -> Token {
+        self.start = self.current;
+        Token {
             kind: TokenKind::EOF,
             line: self.line,
-            pos: self.current,
+            pos: self.start,
+            column: self.column(),
+            span: self.span(),
+        }
+    }
+
+    fn error(&mut self, code: Code, message: &str, at_end: bool) {
+        self.error_count += 1;
+        self.errors.push(ScanError {
+            code,
+            message: message.to_string(),
+            line: self.line,
+            span: self.span(),
+            at_end,
         });
-        &self.tokens
     }
+    // Positions are byte offsets, so a multi-byte char moves `current` by
+    // more than one.
     fn advance(&mut self) -> char {
-        self.current += 1;
-        self.source.chars().nth(self.current - 1).unwrap()
+        let c = self.peek();
+        self.current += c.len_utf8();
+        c
+    }
+
+    fn column(&self) -> usize {
+        self.start - self.line_start + 1
+    }
+
+    fn span(&self) -> Span {
+        Span::new(self.start, self.current)
     }
 
     fn add_token(&mut self, token: Token) {
+        self.token_count += 1;
         self.tokens.push(token);
     }
 
     fn is_at_end(&self) -> bool {
-        self.current >= self.source.len()
+        self.aborted || self.current >= self.source.len()
     }
 
     fn match_char(&mut self, c: char) -> bool {
-        if self.is_at_end() || self.source.chars().nth(self.current).unwrap() != c {
+        if self.is_at_end() || self.peek() != c {
             return false;
         }
-        self.current += 1;
+        self.current += c.len_utf8();
         true
     }
 
     fn peek(&self) -> char {
-        if self.is_at_end() {
-            return '\0';
-        }
-        self.source.chars().nth(self.current).unwrap()
+        self.source[self.current..].chars().next().unwrap_or('\0')
     }
 
     fn string(&mut self) {
+        // Multi-line strings are reported where they start.
+        let (line, column) = (self.line, self.column());
         while self.peek() != '"' && !self.is_at_end() {
-            if self.peek() != '\n' {
+            if self.advance() == '\n' {
                 self.line += 1;
+                self.line_start = self.current;
             }
-            self.advance();
         }
         if self.is_at_end() {
-            panic!("[line {}] Error: Unterminated string literal", self.line);
+            self.error(Code::UnterminatedString, "Unterminated string.", true);
+            return;
         }
 
         self.advance();
@@ -264,153 +770,220 @@ impl Scanner {
         let value = String::from(&self.source[self.start + 1..self.current - 1]);
         self.add_token(Token {
             kind: TokenKind::StringLiteral(value),
-            line: self.line,
-            pos: self.current,
+            line,
+            pos: self.start,
+            column,
+            span: self.span(),
         });
     }
 
-    fn number(&mut self) {
-        while Scanner::is_lox_digit(self.peek()) {
-            self.advance();
+    fn char_literal(&mut self) {
+        let after_name = self.source[..self.start]
+            .chars()
+            .next_back()
+            .is_some_and(Scanner::is_lox_alphanumeric);
+        let mut value = None;
+        let mut chars = 0;
+        let mut invalid_escape = false;
+        while !self.is_at_end() && self.peek() != '\'' && self.peek() != '\n' {
+            let c = match self.advance() {
+                '\\' => match self.escape() {
+                    Some(c) => c,
+                    None => {
+                        invalid_escape = true;
+                        continue;
+                    }
+                },
+                c => c,
+            };
+            value.get_or_insert(c);
+            chars += 1;
         }
-        if self.peek() == '.' && Scanner::is_lox_digit(self.peek_next()) {
-            self.advance();
+        if !self.match_char('\'') {
+            let at_end = self.is_at_end();
+            self.error(
+                Code::UnterminatedChar,
+                "Unterminated character literal.",
+                at_end,
+            );
+            return;
+        }
+        // `x'y'` is more likely a typo than a name and a char next to
+        // each other.
+        if after_name {
+            self.error(
+                Code::UnexpectedCharacter,
+                "Character literal right after a name or number, put a space before the quote.",
+                false,
+            );
+            return;
+        }
+        if invalid_escape {
+            self.error(Code::InvalidEscape, "Invalid escape sequence.", false);
+            return;
+        }
+        match (chars, value) {
+            (1, Some(c)) => self.add_token(Token {
+                kind: TokenKind::CharLiteral(c),
+                line: self.line,
+                pos: self.start,
+                column: self.column(),
+                span: self.span(),
+            }),
+            (0, _) => self.error(Code::EmptyCharLiteral, "Empty character literal.", false),
+            _ => self.error(
+                Code::CharLiteralTooLong,
+                "Character literal must be a single character, use double quotes for strings.",
+                false,
+            ),
+        }
+    }
 
-            while Scanner::is_lox_digit(self.peek()) {
-                self.advance();
-            }
+    // The part after a backslash. Strings don't have escapes yet, when they
+    // get them they should go through here too.
+    fn escape(&mut self) -> Option<char> {
+        if self.is_at_end() || self.peek() == '\n' {
+            return None;
         }
+        let name = self.advance();
+        ESCAPES
+            .iter()
+            .find(|(escape, _)| *escape == name)
+            .map(|(_, c)| *c)
+    }
+
+    fn number(&mut self) {
+        self.current = self.start + number_literal_len(&self.source[self.start..]);
         let raw = String::from(&self.source[self.start..self.current]);
-        let value = raw.parse::<f64>().unwrap();
+        let Some(value) = literal_value(&raw) else {
+            return self.error(
+                Code::NumberOutOfRange,
+                "Number literal out of range.",
+                false,
+            );
+        };
+        // Fractions are rounded all the time and nobody expects `0.1` to be
+        // exact, but a whole number turning into a different one is a bug
+        // waiting to happen, e.g. with IDs.
+        if !raw.contains('.') {
+            let stored = format!("{:.0}", value);
+            let digits = raw.trim_start_matches('0');
+            if stored != if digits.is_empty() { "0" } else { digits } {
+                self.warnings.push(Diagnostic {
+                    code: Code::InexactNumber,
+                    severity: Severity::Warning,
+                    message: format!(
+                        "Number literal cannot be represented exactly; stored as {}.",
+                        stored
+                    ),
+                    line: Some(self.line),
+                    span: Some(self.span()),
+                    file: None,
+                });
+            }
+        }
         self.add_token(Token {
             kind: TokenKind::NumberLiteral(value),
             line: self.line,
-            pos: self.current,
+            pos: self.start,
+            column: self.column(),
+            span: self.span(),
         })
     }
 
-    fn peek_next(&self) -> char {
-        if self.current + 1 >= self.source.len() {
-            '\0'
-        } else {
-            self.source.chars().nth(self.current + 1).unwrap()
-        }
-    }
-
     fn identifier(&mut self) {
         while Scanner::is_lox_alphanumeric(self.peek()) {
             self.advance();
         }
         let text = &self.source[self.start..self.current];
-        let token = match text {
-            "and" => Token {
-                kind: TokenKind::And,
-                line: self.line,
-                pos: self.current,
-            },
-            "class" => Token {
-                kind: TokenKind::Class,
-                line: self.line,
-                pos: self.current,
-            },
-            "else" => Token {
-                kind: TokenKind::Else,
-                line: self.line,
-                pos: self.current,
-            },
-            "false" => Token {
-                kind: TokenKind::False,
-                line: self.line,
-                pos: self.current,
-            },
-            "for" => Token {
-                kind: TokenKind::For,
-                line: self.line,
-                pos: self.current,
-            },
-            "fun" => Token {
-                kind: TokenKind::Fun,
-                line: self.line,
-                pos: self.current,
-            },
-            "if" => Token {
-                kind: TokenKind::If,
-                line: self.line,
-                pos: self.current,
-            },
-            "nil" => Token {
-                kind: TokenKind::Nil,
-                line: self.line,
-                pos: self.current,
-            },
-            "or" => Token {
-                kind: TokenKind::Or,
-                line: self.line,
-                pos: self.current,
-            },
-            "print" => Token {
-                kind: TokenKind::Print,
-                line: self.line,
-                pos: self.current,
-            },
-            "return" => Token {
-                kind: TokenKind::Return,
-                line: self.line,
-                pos: self.current,
-            },
-            "super" => Token {
-                kind: TokenKind::Super,
-                line: self.line,
-                pos: self.current,
-            },
-            "this" => Token {
-                kind: TokenKind::This,
-                line: self.line,
-                pos: self.current,
-            },
-            "true" => Token {
-                kind: TokenKind::True,
-                line: self.line,
-                pos: self.current,
-            },
-            "var" => Token {
-                kind: TokenKind::Var,
-                line: self.line,
-                pos: self.current,
-            },
-            "while" => Token {
-                kind: TokenKind::While,
-                line: self.line,
-                pos: self.current,
-            },
-            _ => Token {
-                kind: TokenKind::Identifier(String::from(text)),
-                line: self.line,
-                pos: self.current,
-            },
+        let kind = TokenKind::KEYWORDS
+            .iter()
+            .find(|(keyword, _)| *keyword == text)
+            .map_or_else(
+                || TokenKind::Identifier(text.to_string()),
+                |(_, kind)| kind.clone(),
+            );
+        let token = Token {
+            kind,
+            line: self.line,
+            pos: self.start,
+            column: self.column(),
+            span: self.span(),
         };
         self.add_token(token)
     }
     fn is_lox_digit(c: char) -> bool {
-        ('0'..='9').contains(&c)
+        c.is_ascii_digit()
     }
     fn is_lox_alphabetic(c: char) -> bool {
-        ('a'..='z').contains(&c) || ('A'..='Z').contains(&c) || c == '_'
+        c.is_ascii_alphabetic() || c == '_'
     }
     fn is_lox_alphanumeric(c: char) -> bool {
         Scanner::is_lox_alphabetic(c) || Scanner::is_lox_digit(c)
     }
 }
 
+// What follows the backslash and the char it stands for.
+const ESCAPES: [(char, char); 7] = [
+    ('n', '\n'),
+    ('t', '\t'),
+    ('r', '\r'),
+    ('0', '\0'),
+    ('\\', '\\'),
+    ('\'', '\''),
+    ('"', '"'),
+];
+
+// Length of the number literal at the start of `text`, 0 if there is none.
+// A literal is digits with an optional fraction, `1.` and `.5` are not
+// numbers.
+fn number_literal_len(text: &str) -> usize {
+    let digits = |from: usize| {
+        text[from..]
+            .bytes()
+            .take_while(|b| b.is_ascii_digit())
+            .count()
+    };
+    let integer = digits(0);
+    if integer > 0 && text[integer..].starts_with('.') {
+        let fraction = digits(integer + 1);
+        if fraction > 0 {
+            return integer + 1 + fraction;
+        }
+    }
+    integer
+}
+
+// The value of a literal `number_literal_len` accepted, for the scanner and
+// `parse_number` alike. None when it is too large for an f64, instead of
+// infinity.
+fn literal_value(literal: &str) -> Option<f64> {
+    literal.parse::<f64>().ok().filter(|n| n.is_finite())
+}
+
+// Reads a number the way it would be written in source, ignoring surrounding
+// whitespace.
+pub fn parse_number(text: &str) -> Option<f64> {
+    let text = text.trim();
+    if text.is_empty() || number_literal_len(text) != text.len() {
+        return None;
+    }
+    literal_value(text)
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::scanner::{Scanner, Token, TokenKind};
+    use crate::{
+        diagnostics::{Code, Span},
+        scanner::{parse_number, Comment, ScanError, Scanner, ScannerOptions, Token, TokenKind},
+    };
     #[test]
     fn single_character_tokens() {
-        let source = "(){},-*;".to_string();
+        let source = "(){}[],-*;".to_string();
         let mut scanner = Scanner::new(source);
         let tokens = scanner
             .scan_tokens()
+            .unwrap()
             .iter()
             .map(|token| token.kind.clone())
             .collect::<Vec<TokenKind>>();
@@ -421,6 +994,8 @@ mod tests {
                 TokenKind::RightParen,
                 TokenKind::LeftBrace,
                 TokenKind::RightBrace,
+                TokenKind::LeftBracket,
+                TokenKind::RightBracket,
                 TokenKind::Comma,
                 TokenKind::Minus,
                 TokenKind::Star,
@@ -437,6 +1012,7 @@ mod tests {
         let mut scanner = Scanner::new(source);
         let tokens = scanner
             .scan_tokens()
+            .unwrap()
             .iter()
             .map(|token| token.kind.clone())
             .collect::<Vec<TokenKind>>();
@@ -461,6 +1037,7 @@ mod tests {
         let mut scanner = Scanner::new(source);
         let tokens = scanner
             .scan_tokens()
+            .unwrap()
             .iter()
             .map(|token| token.kind.clone())
             .collect::<Vec<TokenKind>>();
@@ -492,6 +1069,7 @@ mod tests {
         let mut scanner = Scanner::new(source);
         let tokens = scanner
             .scan_tokens()
+            .unwrap()
             .iter()
             .map(|token| token.kind.clone())
             .collect::<Vec<TokenKind>>();
@@ -506,6 +1084,30 @@ mod tests {
         )
     }
     #[test]
+    fn keep_comments() {
+        let mut scanner = Scanner::new("// one\nprint 1; // two  \n".to_string());
+        scanner.set_keep_comments(true);
+        scanner.scan_tokens().unwrap();
+        assert_eq!(
+            scanner.comments(),
+            [
+                Comment {
+                    text: "// one".to_string(),
+                    line: 1,
+                    span: Span { start: 0, end: 6 },
+                },
+                Comment {
+                    text: "// two".to_string(),
+                    line: 2,
+                    span: Span { start: 16, end: 24 },
+                },
+            ]
+        );
+        let mut scanner = Scanner::new("// skipped".to_string());
+        scanner.scan_tokens().unwrap();
+        assert!(scanner.comments().is_empty());
+    }
+    #[test]
     fn string_literal() {
         let source = r#""This is a string literal"
         "This is a
@@ -515,6 +1117,7 @@ literal""#
         let mut scanner = Scanner::new(source);
         let tokens = scanner
             .scan_tokens()
+            .unwrap()
             .iter()
             .map(|token| token.kind.clone())
             .collect::<Vec<TokenKind>>();
@@ -533,30 +1136,293 @@ literal"
         )
     }
     #[test]
-    #[should_panic]
+    fn non_ascii_source() {
+        let source = "\"héllo\" é".to_string();
+        let mut scanner = Scanner::new(source);
+        let errors = scanner.scan_tokens().unwrap_err();
+        assert_eq!(errors[0].span, Span::new(9, 11));
+        assert_eq!(
+            scanner.tokens[0].kind,
+            TokenKind::StringLiteral("héllo".to_string())
+        );
+    }
+
+    #[test]
     fn unterminated_string_literal() {
         let source = r#""This is an unterminated string literal"#.to_string();
         let mut scanner = Scanner::new(source);
-        scanner.scan_tokens();
+        let errors = scanner.scan_tokens().unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].at_end);
+        assert_eq!(errors[0].message, "Unterminated string.");
+    }
+
+    #[test]
+    fn char_literal() {
+        let source = r#"'a' '\n' '\'' 'é' "s""#.to_string();
+        let mut scanner = Scanner::new(source);
+        let tokens = scanner.scan_tokens().unwrap().clone();
+        assert_eq!(
+            tokens
+                .iter()
+                .map(|token| token.kind.clone())
+                .collect::<Vec<_>>(),
+            [
+                TokenKind::CharLiteral('a'),
+                TokenKind::CharLiteral('\n'),
+                TokenKind::CharLiteral('\''),
+                TokenKind::CharLiteral('é'),
+                TokenKind::StringLiteral("s".to_string()),
+                TokenKind::EOF,
+            ]
+        );
+        assert_eq!(tokens[1].span, Span::new(4, 8));
+        assert_eq!(tokens[1].to_string(), r"1:5 CharLiteral '\n'");
+    }
+
+    #[test]
+    fn char_literal_errors() {
+        let error = |source: &str| {
+            let errors = Scanner::new(source.to_string()).scan_tokens().unwrap_err();
+            assert_eq!(errors.len(), 1, "{}", source);
+            errors[0].clone()
+        };
+        let empty = error("print '';");
+        assert_eq!(empty.code, Code::EmptyCharLiteral);
+        assert_eq!(empty.message, "Empty character literal.");
+        assert_eq!(empty.span, Span::new(6, 8));
+
+        let long = error("print 'ab';");
+        assert_eq!(long.code, Code::CharLiteralTooLong);
+        assert_eq!(long.span, Span::new(6, 10));
+
+        let unterminated = error("print 'a");
+        assert_eq!(unterminated.code, Code::UnterminatedChar);
+        assert!(unterminated.at_end);
+        // Chars don't span lines, the next line scans normally.
+        let unterminated = error("print 'a;\nprint 1;");
+        assert_eq!(unterminated.code, Code::UnterminatedChar);
+        assert!(!unterminated.at_end);
+        assert_eq!(unterminated.span, Span::new(6, 9));
+
+        let escape = error(r"print '\q';");
+        assert_eq!(escape.code, Code::InvalidEscape);
+        assert_eq!(escape.message, "Invalid escape sequence.");
+    }
+
+    #[test]
+    fn char_literal_after_name() {
+        let mut scanner = Scanner::new("x'y' 1'y'".to_string());
+        let errors = scanner.scan_tokens().unwrap_err();
+        assert_eq!(
+            errors
+                .iter()
+                .map(|err| (err.span, err.message.as_str()))
+                .collect::<Vec<_>>(),
+            [
+                (
+                    Span::new(1, 4),
+                    "Character literal right after a name or number, put a space before the quote."
+                ),
+                (
+                    Span::new(6, 9),
+                    "Character literal right after a name or number, put a space before the quote."
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn unexpected_characters() {
+        let source = "(@)\n#".to_string();
+        let mut scanner = Scanner::new(source);
+        assert_eq!(
+            scanner.scan_tokens(),
+            Err(vec![
+                ScanError {
+                    code: Code::UnexpectedCharacter,
+                    message: "Unexpected character.".to_string(),
+                    line: 1,
+                    span: Span::new(1, 2),
+                    at_end: false,
+                },
+                ScanError {
+                    code: Code::UnexpectedCharacter,
+                    message: "Unexpected character.".to_string(),
+                    line: 2,
+                    span: Span::new(4, 5),
+                    at_end: false,
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn token_display() {
+        let source = "var name = \"lox\";\n  print 1.5;".to_string();
+        let mut scanner = Scanner::new(source);
+        let tokens = scanner.scan_tokens().unwrap();
+        let lines: Vec<String> = tokens.iter().map(|token| token.to_string()).collect();
+        assert_eq!(
+            lines,
+            vec![
+                "1:1 Var var",
+                "1:5 Identifier name",
+                "1:10 Equal =",
+                "1:12 StringLiteral \"lox\"",
+                "1:17 Semicolon ;",
+                "2:3 Print print",
+                "2:9 NumberLiteral 1.5",
+                "2:12 Semicolon ;",
+                "2:13 EOF",
+            ]
+        );
+    }
+
+    #[test]
+    fn ranges() {
+        let source = "1..5 1..=5 1.5..2 a.b".to_string();
+        let mut scanner = Scanner::new(source);
+        let tokens = scanner
+            .scan_tokens()
+            .unwrap()
+            .iter()
+            .map(|token| token.kind.clone())
+            .collect::<Vec<TokenKind>>();
+        assert_eq!(
+            tokens,
+            vec![
+                TokenKind::NumberLiteral(1.0),
+                TokenKind::DotDot,
+                TokenKind::NumberLiteral(5.0),
+                TokenKind::NumberLiteral(1.0),
+                TokenKind::DotDotEqual,
+                TokenKind::NumberLiteral(5.0),
+                TokenKind::NumberLiteral(1.5),
+                TokenKind::DotDot,
+                TokenKind::NumberLiteral(2.0),
+                TokenKind::Identifier("a".to_string()),
+                TokenKind::Dot,
+                TokenKind::Identifier("b".to_string()),
+                TokenKind::EOF,
+            ]
+        );
+    }
+
+    #[test]
+    fn pipe() {
+        let mut scanner = Scanner::new("a |> b".to_string());
+        let tokens = scanner.scan_tokens().unwrap();
+        assert_eq!(tokens[1].kind, TokenKind::PipeGreater);
+        assert_eq!(tokens[1].span, Span::new(2, 4));
+        let mut scanner = Scanner::new("a | b".to_string());
+        let errors = scanner.scan_tokens().unwrap_err();
+        assert_eq!(errors[0].message, "Unexpected character.");
+        assert_eq!(errors[0].span, Span::new(2, 3));
+    }
+
+    #[test]
+    fn parse_numbers() {
+        let accepted = [
+            ("42.5", 42.5),
+            (" 1 ", 1.0),
+            ("\t007\n", 7.0),
+            ("0.25", 0.25),
+        ];
+        for (text, number) in accepted {
+            assert_eq!(parse_number(text), Some(number), "{:?}", text);
+        }
+        let huge = "9".repeat(400);
+        let rejected = [
+            "", " ", "abc", "1.", ".5", "-1", "+1", "1e5", "1e999", "0x10", "1 2", "1.2.3", "inf",
+            "NaN", &huge,
+        ];
+        for text in rejected {
+            assert_eq!(parse_number(text), None, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn inexact_number_literals() {
+        let warnings = |source: &str| {
+            let mut scanner = Scanner::new(source.to_string());
+            scanner.scan_tokens().unwrap();
+            scanner.warnings().to_vec()
+        };
+        let found = warnings("print 9007199254740993 == 9007199254740992;");
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].code, Code::InexactNumber);
+        assert_eq!(
+            found[0].message,
+            "Number literal cannot be represented exactly; stored as 9007199254740992."
+        );
+        assert_eq!(found[0].span, Some(Span::new(6, 22)));
+        assert_eq!(found[0].line, Some(1));
+        let exact = [
+            "0.1",
+            "9007199254740992",
+            "18446744073709551616",
+            "007",
+            "0",
+            "123456789.123456789123",
+        ];
+        for source in exact {
+            assert!(warnings(source).is_empty(), "{}", source);
+        }
+    }
+
+    #[test]
+    fn number_literal_out_of_range() {
+        let source = format!("print {} + 1;", "9".repeat(400));
+        let errors = Scanner::new(source).scan_tokens().unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, Code::NumberOutOfRange);
+        assert_eq!(errors[0].message, "Number literal out of range.");
+        assert_eq!(errors[0].span, Span::new(6, 406));
+        assert_eq!(parse_number(&"9".repeat(400)), None);
+        // The largest finite f64 still fits.
+        assert!(Scanner::new(format!("{:.0}", f64::MAX))
+            .scan_tokens()
+            .is_ok());
+    }
+
+    #[test]
+    fn non_ascii_digits() {
+        let errors = Scanner::new("var a = 1\u{ff12};".to_string())
+            .scan_tokens()
+            .unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].code, Code::UnexpectedCharacter);
+        assert_eq!(
+            errors[0].message,
+            "Non-ASCII digit '\u{ff12}', numbers are written with 0-9."
+        );
+        // The full-width digit is three bytes long.
+        assert_eq!(errors[0].span, Span::new(9, 12));
+        assert_eq!(parse_number("\u{ff11}\u{ff12}"), None);
     }
 
     #[test]
     fn number_literal() {
         let source = "123.456".to_string();
         let mut scanner = Scanner::new(source);
-        let tokens = scanner.scan_tokens();
+        let tokens = scanner.scan_tokens().unwrap();
         assert_eq!(
             *tokens,
             vec![
                 Token {
                     kind: TokenKind::NumberLiteral(123.456),
                     line: 1,
-                    pos: 7 //Why does this equal to 7? There seems to be a bug.
+                    pos: 0,
+                    column: 1,
+                    span: Span::new(0, 7),
                 },
                 Token {
                     kind: TokenKind::EOF,
                     line: 1,
-                    pos: 7
+                    pos: 7,
+                    column: 8,
+                    span: Span::new(7, 7),
                 }
             ]
         )
@@ -564,10 +1430,12 @@ literal"
     #[test]
     fn keywords() {
         let source =
-            "and class false fun if nil or print return super this true var while".to_string();
+            "and class false fun if in nil or print return super this true var while try catch finally throw"
+                .to_string();
         let mut scanner = Scanner::new(source);
         let tokens = scanner
             .scan_tokens()
+            .unwrap()
             .iter()
             .map(|token| token.kind.clone())
             .collect::<Vec<TokenKind>>();
@@ -579,6 +1447,7 @@ literal"
                 TokenKind::False,
                 TokenKind::Fun,
                 TokenKind::If,
+                TokenKind::In,
                 TokenKind::Nil,
                 TokenKind::Or,
                 TokenKind::Print,
@@ -588,16 +1457,190 @@ literal"
                 TokenKind::True,
                 TokenKind::Var,
                 TokenKind::While,
+                TokenKind::Try,
+                TokenKind::Catch,
+                TokenKind::Finally,
+                TokenKind::Throw,
                 TokenKind::EOF,
             ]
         )
     }
+    // Every kind of token, with one of each for those holding a value. The
+    // match has no catch-all so that a new kind doesn't compile until it is
+    // added here.
+    fn every_kind() -> Vec<TokenKind> {
+        let kinds = vec![
+            TokenKind::Bang,
+            TokenKind::BangEqual,
+            TokenKind::Equal,
+            TokenKind::EqualEqual,
+            TokenKind::Greater,
+            TokenKind::GreaterEqual,
+            TokenKind::Less,
+            TokenKind::LessEqual,
+            TokenKind::LeftParen,
+            TokenKind::RightParen,
+            TokenKind::LeftBrace,
+            TokenKind::RightBrace,
+            TokenKind::LeftBracket,
+            TokenKind::RightBracket,
+            TokenKind::Comma,
+            TokenKind::Dot,
+            TokenKind::DotDot,
+            TokenKind::DotDotEqual,
+            TokenKind::PipeGreater,
+            TokenKind::Minus,
+            TokenKind::Plus,
+            TokenKind::Semicolon,
+            TokenKind::Slash,
+            TokenKind::Star,
+            TokenKind::And,
+            TokenKind::Class,
+            TokenKind::Else,
+            TokenKind::False,
+            TokenKind::Fun,
+            TokenKind::For,
+            TokenKind::If,
+            TokenKind::In,
+            TokenKind::Nil,
+            TokenKind::Or,
+            TokenKind::Print,
+            TokenKind::Return,
+            TokenKind::Super,
+            TokenKind::This,
+            TokenKind::True,
+            TokenKind::Var,
+            TokenKind::While,
+            TokenKind::Try,
+            TokenKind::Catch,
+            TokenKind::Finally,
+            TokenKind::Throw,
+            TokenKind::StringLiteral("s".to_string()),
+            TokenKind::CharLiteral('c'),
+            TokenKind::NumberLiteral(1.0),
+            TokenKind::Identifier("name".to_string()),
+            TokenKind::Newline,
+            TokenKind::EOF,
+        ];
+        for kind in &kinds {
+            match kind {
+                TokenKind::Bang
+                | TokenKind::BangEqual
+                | TokenKind::Equal
+                | TokenKind::EqualEqual
+                | TokenKind::Greater
+                | TokenKind::GreaterEqual
+                | TokenKind::Less
+                | TokenKind::LessEqual
+                | TokenKind::LeftParen
+                | TokenKind::RightParen
+                | TokenKind::LeftBrace
+                | TokenKind::RightBrace
+                | TokenKind::LeftBracket
+                | TokenKind::RightBracket
+                | TokenKind::Comma
+                | TokenKind::Dot
+                | TokenKind::DotDot
+                | TokenKind::DotDotEqual
+                | TokenKind::PipeGreater
+                | TokenKind::Minus
+                | TokenKind::Plus
+                | TokenKind::Semicolon
+                | TokenKind::Slash
+                | TokenKind::Star
+                | TokenKind::And
+                | TokenKind::Class
+                | TokenKind::Else
+                | TokenKind::False
+                | TokenKind::Fun
+                | TokenKind::For
+                | TokenKind::If
+                | TokenKind::In
+                | TokenKind::Nil
+                | TokenKind::Or
+                | TokenKind::Print
+                | TokenKind::Return
+                | TokenKind::Super
+                | TokenKind::This
+                | TokenKind::True
+                | TokenKind::Var
+                | TokenKind::While
+                | TokenKind::Try
+                | TokenKind::Catch
+                | TokenKind::Finally
+                | TokenKind::Throw
+                | TokenKind::StringLiteral(_)
+                | TokenKind::CharLiteral(_)
+                | TokenKind::NumberLiteral(_)
+                | TokenKind::Identifier(_)
+                | TokenKind::Newline
+                | TokenKind::EOF => {}
+            }
+        }
+        kinds
+    }
+
+    #[test]
+    fn keyword_table() {
+        for (keyword, kind) in TokenKind::KEYWORDS {
+            let tokens = Scanner::new(keyword.to_string())
+                .scan_tokens()
+                .unwrap()
+                .clone();
+            let kinds: Vec<TokenKind> = tokens.into_iter().map(|token| token.kind).collect();
+            assert_eq!(kinds, [kind.clone(), TokenKind::EOF], "{}", keyword);
+            assert_eq!(kind.to_string(), *keyword);
+        }
+        for kind in &every_kind() {
+            // Anything spelled with letters that isn't a keyword is a name.
+            let text = kind.to_string();
+            if !kind.is_keyword()
+                && text.chars().all(|c| c.is_ascii_alphabetic())
+                && !text.is_empty()
+            {
+                let tokens = Scanner::new(text.clone()).scan_tokens().unwrap().clone();
+                assert_eq!(tokens[0].kind, TokenKind::Identifier(text), "{:?}", kind);
+            }
+        }
+    }
+
+    #[test]
+    fn token_classes() {
+        let kinds = every_kind();
+        let binary: Vec<String> = kinds
+            .iter()
+            .filter(|kind| kind.is_binary_operator())
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            binary,
+            ["!=", "==", ">", ">=", "<", "<=", "-", "+", "/", "*"]
+        );
+        let literals: Vec<&str> = kinds
+            .iter()
+            .filter(|kind| kind.is_literal())
+            .map(TokenKind::name)
+            .collect();
+        assert_eq!(
+            literals,
+            [
+                "False",
+                "Nil",
+                "True",
+                "StringLiteral",
+                "CharLiteral",
+                "NumberLiteral"
+            ]
+        );
+    }
+
     #[test]
     fn identifiers() {
         let source = "variable iffy classy snake_case_variable".to_string();
         let mut scanner = Scanner::new(source);
         let tokens = scanner
             .scan_tokens()
+            .unwrap()
             .iter()
             .map(|token| token.kind.clone())
             .collect::<Vec<TokenKind>>();
@@ -612,4 +1655,71 @@ literal"
             ]
         );
     }
+
+    #[test]
+    fn limits() {
+        let options = ScannerOptions {
+            max_tokens: 4,
+            ..ScannerOptions::default()
+        };
+        let mut scanner = Scanner::with_options("print 1; print 2;".to_string(), options);
+        let errors = scanner.scan_tokens().unwrap_err();
+        assert_eq!(
+            errors,
+            [ScanError {
+                code: Code::ScanAborted,
+                message: "Too many tokens; aborting scan.".to_string(),
+                line: 1,
+                span: Span::new(14, 14),
+                at_end: false,
+            }]
+        );
+
+        let options = ScannerOptions {
+            max_errors: 3,
+            ..ScannerOptions::default()
+        };
+        let errors = Scanner::with_options("@@@@@@".to_string(), options)
+            .scan_tokens()
+            .unwrap_err();
+        assert_eq!(errors.len(), 4);
+        assert_eq!(errors[3].code, Code::ScanAborted);
+        assert_eq!(errors[3].message, "Too many errors; aborting scan.");
+
+        // Streaming still ends with EOF, and a line break before it.
+        let options = ScannerOptions {
+            max_tokens: 2,
+            ..ScannerOptions::default()
+        };
+        let mut scanner = Scanner::with_options("1 2 3 4 5".to_string(), options);
+        scanner.set_newlines(true);
+        let mut kinds = Vec::new();
+        let mut codes = Vec::new();
+        loop {
+            match scanner.next_token() {
+                Ok(token) if token.kind == TokenKind::EOF => break,
+                Ok(token) => kinds.push(token.kind),
+                Err(err) => codes.push(err.code),
+            }
+        }
+        assert_eq!(
+            kinds,
+            [
+                TokenKind::NumberLiteral(1.0),
+                TokenKind::NumberLiteral(2.0),
+                TokenKind::Newline
+            ]
+        );
+        assert_eq!(codes, [Code::ScanAborted]);
+    }
+
+    #[test]
+    fn default_limits() {
+        let errors = Scanner::new("@".repeat(100_000)).scan_tokens().unwrap_err();
+        assert_eq!(errors.len(), ScannerOptions::default().max_errors + 1);
+        let source = "(".repeat(ScannerOptions::default().max_tokens + 10);
+        let errors = Scanner::new(source).scan_tokens().unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].message, "Too many tokens; aborting scan.");
+    }
 }
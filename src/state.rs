@@ -0,0 +1,112 @@
+use crate::interpreter::{Interpreter, Value};
+
+// The globals of an interpreter at some point, for hosts that want to go
+// back to it later. Functions aren't saved, the natives are registered again
+// on restore and anything else holding one is left out with a warning.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StateSnapshot {
+    globals: Vec<(String, Value)>,
+    warnings: Vec<String>,
+}
+
+impl StateSnapshot {
+    // Sorted by name.
+    pub fn globals(&self) -> &[(String, Value)] {
+        &self.globals
+    }
+
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+}
+
+impl Interpreter {
+    pub fn save_state(&self) -> StateSnapshot {
+        let mut globals = Vec::new();
+        let mut warnings = Vec::new();
+        for (name, value) in self.globals() {
+            match value {
+                // The native itself, `reset` brings it back.
+                Value::NativeFunction(native) if native.name == name => {}
+                Value::NativeFunction(_) => warnings.push(format!(
+                    "Global '{}' holds a function and was not saved.",
+                    name
+                )),
+                _ => globals.push((name.to_string(), value.clone())),
+            }
+        }
+        globals.sort_by(|(a, _), (b, _)| a.cmp(b));
+        warnings.sort();
+        StateSnapshot { globals, warnings }
+    }
+
+    // Globals defined since the snapshot are gone afterwards.
+    pub fn restore_state(&mut self, snapshot: StateSnapshot) {
+        self.reset();
+        for (name, value) in snapshot.globals {
+            self.define_global(&name, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod state_tests {
+    use crate::{Interpreter, Value};
+
+    fn run(interpreter: &mut Interpreter, source: &str) {
+        interpreter
+            .interpret(crate::parse(source).unwrap())
+            .unwrap();
+    }
+
+    #[test]
+    fn save_and_restore() {
+        let mut interpreter = Interpreter::new();
+        run(
+            &mut interpreter,
+            "var a = 1; var b = \"two\"; var r = 0..3;",
+        );
+        let snapshot = interpreter.save_state();
+        run(
+            &mut interpreter,
+            "a = a + 1; b = nil; var c = true; var len = 0;",
+        );
+        let later = interpreter.save_state();
+
+        interpreter.restore_state(snapshot.clone());
+        assert_eq!(interpreter.get_global("a"), Some(Value::Number(1.0)));
+        assert_eq!(interpreter.get_global("b"), Some(Value::from("two")));
+        assert_eq!(interpreter.get_global("c"), None);
+        // The native the script overwrote is back.
+        run(&mut interpreter, "var n = len(\"abc\") + len(r);");
+        assert_eq!(interpreter.get_global("n"), Some(Value::Number(6.0)));
+        assert_eq!(
+            snapshot
+                .globals()
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>(),
+            ["a", "b", "r"]
+        );
+
+        interpreter.restore_state(later);
+        assert_eq!(interpreter.get_global("a"), Some(Value::Number(2.0)));
+        assert_eq!(interpreter.get_global("len"), Some(Value::Number(0.0)));
+        assert!(snapshot.warnings().is_empty());
+    }
+
+    #[test]
+    fn functions_are_not_saved() {
+        let mut interpreter = Interpreter::new();
+        run(&mut interpreter, "var f = format; var x = 1;");
+        let snapshot = interpreter.save_state();
+        assert_eq!(
+            snapshot.warnings(),
+            ["Global 'f' holds a function and was not saved."]
+        );
+        interpreter.restore_state(snapshot);
+        assert_eq!(interpreter.get_global("f"), None);
+        assert_eq!(interpreter.get_global("x"), Some(Value::Number(1.0)));
+        assert!(interpreter.has_function("format"));
+    }
+}
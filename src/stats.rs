@@ -0,0 +1,84 @@
+use std::{collections::HashMap, time::Duration};
+
+// Counters gathered while the interpreter runs with profiling on.
+#[derive(Debug, Default, PartialEq, Clone)]
+pub struct Stats {
+    pub statements: u64,
+    pub expressions: u64,
+    pub loop_iterations: u64,
+    // By the name of the function called.
+    pub calls: HashMap<String, CallStats>,
+}
+
+#[derive(Debug, Default, PartialEq, Clone, Copy)]
+pub struct CallStats {
+    pub calls: u64,
+    // Including everything the function called in turn.
+    pub time: Duration,
+}
+
+impl Stats {
+    // The functions called the most first, then by name so that the order
+    // doesn't change between runs.
+    pub fn table(&self) -> String {
+        let mut calls: Vec<_> = self.calls.iter().collect();
+        calls.sort_by(|(a_name, a), (b_name, b)| b.calls.cmp(&a.calls).then(a_name.cmp(b_name)));
+        let width = calls
+            .iter()
+            .map(|(name, _)| name.len())
+            .chain(std::iter::once("function".len()))
+            .max()
+            .unwrap_or(0);
+        let mut out = format!(
+            "{:<width$}  {:>8}  {:>10}\n",
+            "function", "calls", "time (ms)"
+        );
+        for (name, stats) in calls {
+            out.push_str(&format!(
+                "{:<width$}  {:>8}  {:>10.3}\n",
+                name,
+                stats.calls,
+                stats.time.as_secs_f64() * 1000.0
+            ));
+        }
+        out.push_str(&format!(
+            "{} statements, {} expressions, {} loop iterations\n",
+            self.statements, self.expressions, self.loop_iterations
+        ));
+        out
+    }
+}
+
+#[cfg(test)]
+mod stats_tests {
+    use std::time::Duration;
+
+    use super::{CallStats, Stats};
+
+    #[test]
+    fn table() {
+        let mut stats = Stats {
+            statements: 4,
+            expressions: 9,
+            loop_iterations: 2,
+            ..Stats::default()
+        };
+        for (name, calls) in [("len", 2), ("format", 3), ("clock", 2)] {
+            stats.calls.insert(
+                name.to_string(),
+                CallStats {
+                    calls,
+                    time: Duration::from_micros(1500),
+                },
+            );
+        }
+        assert_eq!(
+            stats.table(),
+            "function     calls   time (ms)\n\
+             format           3       1.500\n\
+             clock            2       1.500\n\
+             len              2       1.500\n\
+             4 statements, 9 expressions, 2 loop iterations\n"
+        );
+    }
+}
@@ -0,0 +1,106 @@
+// Programs generated for the benchmarks. They only depend on the arguments,
+// so timings from different machines measure the same work.
+
+// Linear congruential generator, good enough to shuffle statement shapes.
+pub(crate) struct Lcg(pub(crate) u64);
+
+impl Lcg {
+    pub(crate) fn below(&mut self, bound: u64) -> u64 {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (self.0 >> 33) % bound
+    }
+}
+
+// Straight-line code with at least `tokens` tokens, counting EOF. Every
+// statement is valid and runs without errors.
+pub fn program(tokens: usize) -> String {
+    let mut random = Lcg(0x5eed);
+    let mut source = String::new();
+    let mut count = 1;
+    let mut variables = 0;
+    let mut strings = 0;
+    while count < tokens {
+        let a = random.below(variables.max(1));
+        let b = random.below(variables.max(1));
+        let n = random.below(99) + 1;
+        let shape = if variables == 0 { 0 } else { random.below(5) };
+        let (statement, length) = match shape {
+            0 => {
+                variables += 1;
+                let m = random.below(99) + 1;
+                (
+                    format!("var v{} = {} + {} * {};", variables - 1, n, m, n),
+                    9,
+                )
+            }
+            1 => (format!("v{} = v{} - {};", a, b, n), 6),
+            2 => (format!("{{ var t = v{} / {}; v{} = t; }}", a, n, b), 13),
+            3 => (
+                format!(
+                    "if (v{} < v{}) v{} = v{}; else v{} = v{};",
+                    a, b, a, b, b, a
+                ),
+                15,
+            ),
+            _ => {
+                strings += 1;
+                (
+                    format!("var s{} = \"text{}\" + \"more\";", strings - 1, n),
+                    7,
+                )
+            }
+        };
+        source.push_str(&statement);
+        source.push('\n');
+        count += length;
+    }
+    source
+}
+
+// The `n`th Fibonacci number, computed `rounds` times. There are no
+// functions yet, so it's a loop instead of the usual recursive fib.
+pub fn fib(n: usize, rounds: usize) -> String {
+    format!(
+        "for (round in 0..{}) {{\n  var a = 0;\n  var b = 1;\n  for (i in 0..{}) {{\n    \
+         var t = a + b;\n    a = b;\n    b = t;\n  }}\n}}\n",
+        rounds, n
+    )
+}
+
+// Builds a string one character at a time.
+pub fn concat(n: usize) -> String {
+    format!("var s = \"\";\nfor (i in 0..{}) s = s + \"x\";\n", n)
+}
+
+#[cfg(test)]
+mod synthetic_tests {
+    use super::{concat, fib, program};
+    use crate::{scanner::Scanner, Interpreter, Value};
+
+    #[test]
+    fn deterministic() {
+        assert_eq!(program(5_000), program(5_000));
+        assert!(program(5_000).starts_with(&program(500)));
+    }
+
+    #[test]
+    fn token_count() {
+        for tokens in [1, 10, 1_000, 10_000] {
+            let mut scanner = Scanner::new(program(tokens));
+            let scanned = scanner.scan_tokens().unwrap().len();
+            assert!(scanned >= tokens && scanned < tokens + 15, "{}", tokens);
+        }
+    }
+
+    #[test]
+    fn programs_run() {
+        crate::run(&program(10_000)).unwrap();
+        crate::run(&fib(20, 2)).unwrap();
+        let mut interpreter = Interpreter::new();
+        crate::run_with(&concat(5), &mut interpreter).unwrap();
+        assert_eq!(interpreter.get_global("s"), Some(Value::from("xxxxx")));
+    }
+}
@@ -0,0 +1,486 @@
+use crate::{
+    compiler::{Chunk, Compiler, OpCode},
+    interpreter::{Interpreter, RuntimeError, Value},
+    parser::{Stmt, UnaryOperator},
+};
+
+// Stack machine executing chunks produced by the compiler. Operators are
+// evaluated by the same functions the tree-walker uses so both backends
+// print the same values and fail with the same messages. Globals, natives,
+// printing and the limits are the interpreter's the chunk runs with.
+#[derive(Default)]
+pub struct Vm {
+    // Locals are at the bottom, in the slots the compiler gave them.
+    stack: Vec<Value>,
+    handlers: Vec<Handler>,
+    // Errors raised in the `try` of a finally block that is running, with
+    // the line they were raised on. `None` for a block reached without one.
+    unwinding: Vec<Option<(RuntimeError, usize)>>,
+    // Line of the instruction being executed.
+    line: usize,
+}
+
+// A `try` the program is in, see `OpCode::PushCatch`.
+struct Handler {
+    target: usize,
+    finally: bool,
+    // What to cut the stacks back to when it takes an error.
+    stack_len: usize,
+    unwinding_len: usize,
+}
+
+impl Vm {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn interpret(statements: &[Stmt]) -> Result<(), RuntimeError> {
+        let chunk = Compiler::compile(statements)?;
+        Vm::new().run(&chunk)?;
+        Ok(())
+    }
+
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    // Runs the chunk with an interpreter of its own.
+    pub fn run(&mut self, chunk: &Chunk) -> Result<Value, RuntimeError> {
+        self.run_with(chunk, &mut Interpreter::new())
+    }
+
+    pub fn run_with(
+        &mut self,
+        chunk: &Chunk,
+        interpreter: &mut Interpreter,
+    ) -> Result<Value, RuntimeError> {
+        interpreter.load_prelude()?;
+        let mut ip = 0;
+        loop {
+            match self.execute(chunk, interpreter, &mut ip) {
+                Ok(value) => return Ok(value),
+                Err(err) => match self.unwind(err) {
+                    Ok(target) => ip = target,
+                    Err(err) => {
+                        self.stack.clear();
+                        self.handlers.clear();
+                        self.unwinding.clear();
+                        return Err(err);
+                    }
+                },
+            }
+        }
+    }
+
+    // Hands the error to the innermost handler that takes it and returns
+    // where to go on from.
+    fn unwind(&mut self, err: RuntimeError) -> Result<usize, RuntimeError> {
+        while let Some(handler) = self.handlers.pop() {
+            if !handler.finally && !err.is_catchable() {
+                continue;
+            }
+            self.stack.truncate(handler.stack_len);
+            self.unwinding.truncate(handler.unwinding_len);
+            if handler.finally {
+                self.unwinding.push(Some((err, self.line)));
+            } else {
+                self.stack.push(Interpreter::caught(err));
+            }
+            return Ok(handler.target);
+        }
+        Err(err)
+    }
+
+    fn execute(
+        &mut self,
+        chunk: &Chunk,
+        interpreter: &mut Interpreter,
+        ip: &mut usize,
+    ) -> Result<Value, RuntimeError> {
+        while let Some(op) = chunk.code.get(*ip) {
+            self.line = chunk.lines[*ip];
+            *ip += 1;
+            interpreter.check_cancelled()?;
+            interpreter.burn_fuel()?;
+            match *op {
+                OpCode::Constant(index) => self.stack.push(chunk.constants[index].clone()),
+                OpCode::Nil => self.stack.push(Value::Nil),
+                OpCode::True => self.stack.push(Value::Boolean(true)),
+                OpCode::False => self.stack.push(Value::Boolean(false)),
+                OpCode::Negate => {
+                    let right = self.pop()?;
+                    self.stack
+                        .push(Interpreter::unary(UnaryOperator::Minus, right)?);
+                }
+                OpCode::Not => {
+                    let right = self.pop()?;
+                    self.stack
+                        .push(Interpreter::unary(UnaryOperator::Not, right)?);
+                }
+                OpCode::Binary(operator) => {
+                    let right = self.pop()?;
+                    let left = self.pop()?;
+                    self.stack
+                        .push(interpreter.binary_checked(operator, left, right)?);
+                }
+                OpCode::Print(count) => {
+                    let values = self.pop_many(count)?;
+                    interpreter.print_value(Interpreter::print_line(values))?;
+                }
+                OpCode::Pop => {
+                    self.pop()?;
+                }
+                OpCode::DefineGlobal(index) => {
+                    let value = self.pop()?;
+                    interpreter.define_global(&chunk.names[index], value);
+                }
+                OpCode::GetGlobal(index) => {
+                    self.stack.push(interpreter.lookup(&chunk.names[index])?);
+                }
+                OpCode::SetGlobal(index) => {
+                    let value = self.peek()?.clone();
+                    interpreter.assign(&chunk.names[index], value)?;
+                }
+                OpCode::GetLocal(slot) => self.stack.push(self.stack[slot].clone()),
+                OpCode::SetLocal(slot) => self.stack[slot] = self.peek()?.clone(),
+                OpCode::Jump(target) => *ip = target,
+                OpCode::JumpIfFalse(target) => {
+                    let condition = self.pop()?;
+                    if !interpreter.condition(&condition)? {
+                        *ip = target;
+                    }
+                }
+                OpCode::Call { arguments, line } => {
+                    let arguments = self.pop_many(arguments)?;
+                    let callee = self.pop()?;
+                    self.stack.push(interpreter.call(callee, arguments, line)?);
+                }
+                OpCode::Index { line } => {
+                    let index = self.pop()?;
+                    let object = self.pop()?;
+                    self.stack.push(Interpreter::index(object, index, line)?);
+                }
+                OpCode::SetIndex { line } => {
+                    self.pop_many(2)?;
+                    let object = self.pop()?;
+                    self.stack.push(Interpreter::set_index(object, line)?);
+                }
+                OpCode::Range { inclusive } => {
+                    let end = self.pop()?;
+                    let start = self.pop()?;
+                    self.stack.push(Interpreter::range(start, end, inclusive)?);
+                }
+                OpCode::Dbg(index) => {
+                    let (span, line, text) = &chunk.dbg[index];
+                    let value = self.peek()?.clone();
+                    interpreter.print_dbg(*span, *line, &value, || text.clone())?;
+                }
+                OpCode::ForInit => {
+                    let values = Interpreter::range_values(self.peek()?)?;
+                    self.stack.pop();
+                    self.stack.push(Value::Range {
+                        start: values.start,
+                        end: values.end,
+                        inclusive: false,
+                    });
+                }
+                OpCode::ForNext { slot, exit } => {
+                    let mut values = self.stack[slot]
+                        .range_values()
+                        .ok_or(RuntimeError::InvalidProgram)?;
+                    match values.next() {
+                        Some(i) => {
+                            self.stack[slot] = Value::Range {
+                                start: values.start,
+                                end: values.end,
+                                inclusive: false,
+                            };
+                            self.stack.push(Value::Number(i as f64));
+                        }
+                        None => *ip = exit,
+                    }
+                }
+                OpCode::PushCatch(target) | OpCode::PushFinally(target) => {
+                    self.handlers.push(Handler {
+                        target,
+                        finally: matches!(op, OpCode::PushFinally(_)),
+                        stack_len: self.stack.len(),
+                        unwinding_len: self.unwinding.len(),
+                    });
+                }
+                OpCode::PopHandler => {
+                    self.handlers.pop();
+                }
+                OpCode::EnterFinally => self.unwinding.push(None),
+                OpCode::EndFinally => {
+                    if let Some((err, line)) = self.unwinding.pop().flatten() {
+                        // Reported where it was raised, not in the finally block.
+                        self.line = line;
+                        return Err(err);
+                    }
+                }
+                OpCode::Throw => {
+                    let value = self.pop()?;
+                    return Err(RuntimeError::Thrown {
+                        value,
+                        line: self.line,
+                    });
+                }
+                OpCode::Return => return self.pop(),
+            }
+        }
+        Ok(Value::Nil)
+    }
+
+    // A chunk that pops more than it pushed wasn't made by the compiler.
+    fn pop(&mut self) -> Result<Value, RuntimeError> {
+        self.stack.pop().ok_or(RuntimeError::InvalidProgram)
+    }
+
+    fn pop_many(&mut self, count: usize) -> Result<Vec<Value>, RuntimeError> {
+        let start = self
+            .stack
+            .len()
+            .checked_sub(count)
+            .ok_or(RuntimeError::InvalidProgram)?;
+        Ok(self.stack.split_off(start))
+    }
+
+    fn peek(&self) -> Result<&Value, RuntimeError> {
+        self.stack.last().ok_or(RuntimeError::InvalidProgram)
+    }
+}
+
+#[cfg(test)]
+mod vm_tests {
+    use super::Vm;
+    use crate::{
+        compiler::{Chunk, Compiler, OpCode},
+        interpreter::{Interpreter, RuntimeError, Value},
+        parser::Parser,
+        scanner::Scanner,
+    };
+
+    const PROGRAMS: [&str; 8] = [
+        "(5 - (3 - 1)) + -1",
+        "-1 - 2 * (4 - 2) + 10 / 4",
+        r#""con" + "cat""#,
+        "!nil == !false",
+        "1 < 2 != 3 >= 4",
+        r#"2 <= 2 == ("a" == "a")"#,
+        "!!123",
+        "nil",
+    ];
+
+    fn both_backends(source: &str) -> (Result<Value, RuntimeError>, Result<Value, RuntimeError>) {
+        let mut scanner = Scanner::new(source.to_string());
+        let tokens = scanner.scan_tokens().unwrap();
+        let mut parser = Parser::new(tokens.clone());
+        let expr = parser.expression().unwrap();
+        let chunk = Compiler::compile_expression(&expr).unwrap();
+        (Interpreter::new().eval(&expr), Vm::new().run(&chunk))
+    }
+
+    #[test]
+    fn matches_tree_walker() {
+        for source in PROGRAMS {
+            let (tree_walker, vm) = both_backends(source);
+            assert_eq!(tree_walker, vm, "{}", source);
+        }
+    }
+
+    #[test]
+    fn leaves_stack_empty() {
+        let mut scanner = Scanner::new(r#"print 1 + 2; 3 * 4;"#.to_string());
+        let tokens = scanner.scan_tokens().unwrap();
+        let mut parser = Parser::new(tokens.clone());
+        let chunk = Compiler::compile(&parser.parse().unwrap()).unwrap();
+        let mut vm = Vm::new();
+        assert_eq!(vm.run(&chunk), Ok(Value::Nil));
+        assert!(vm.stack.is_empty());
+    }
+
+    fn run(source: &str) -> (Vm, Interpreter, Result<Value, RuntimeError>) {
+        let mut scanner = Scanner::new(source.to_string());
+        let tokens = scanner.scan_tokens().unwrap();
+        let mut parser = Parser::new(tokens.clone());
+        let chunk = Compiler::compile(&parser.parse().unwrap()).unwrap();
+        let mut vm = Vm::new();
+        let mut interpreter = Interpreter::new();
+        let result = vm.run_with(&chunk, &mut interpreter);
+        (vm, interpreter, result)
+    }
+
+    #[test]
+    fn variables_and_scopes() {
+        let (vm, interpreter, result) = run(r#"
+            var a = 1;
+            var b;
+            {
+                var a = 10;
+                var c = a;
+                {
+                    var a = a + c;
+                    b = a;
+                }
+                c = c + 1;
+                a = c;
+            }
+            a = a + b;
+            "#);
+        assert_eq!(result, Ok(Value::Nil));
+        assert_eq!(interpreter.get_global("a"), Some(Value::Number(21.0)));
+        assert!(vm.stack.is_empty());
+    }
+
+    #[test]
+    fn undefined_variable() {
+        let (_, _, result) = run("{ var a = 1; } print a;");
+        assert_eq!(
+            result,
+            Err(RuntimeError::UndefinedVariable {
+                name: "a".to_string(),
+                suggestion: None,
+            })
+        );
+        let (_, _, result) = run("b = 1;");
+        assert_eq!(
+            result,
+            Err(RuntimeError::UndefinedVariable {
+                name: "b".to_string(),
+                suggestion: None,
+            })
+        );
+    }
+
+    #[test]
+    fn if_else() {
+        let (_, interpreter, result) = run(r#"
+            var a = "unset";
+            var b = "unset";
+            var c = "unset";
+            if (1 < 2) a = "then"; else a = "else";
+            if (nil) b = "then"; else b = "else";
+            if (false) c = "then";
+            "#);
+        assert_eq!(result, Ok(Value::Nil));
+        assert_eq!(interpreter.get_global("a"), Some(Value::from("then")));
+        assert_eq!(interpreter.get_global("b"), Some(Value::from("else")));
+        assert_eq!(interpreter.get_global("c"), Some(Value::from("unset")));
+    }
+
+    #[test]
+    fn for_in() {
+        let (vm, interpreter, result) = run(r#"
+            var sum = 0;
+            for (i in 1..=4) {
+                var twice = i * 2;
+                sum = sum + twice;
+            }
+            for (i in 3..3) sum = nil;
+            "#);
+        assert_eq!(result, Ok(Value::Nil));
+        assert_eq!(interpreter.get_global("sum"), Some(Value::Number(20.0)));
+        assert!(vm.stack.is_empty());
+        let (_, _, result) = run("for (i in nil) print i;");
+        assert!(matches!(result, Err(RuntimeError::TypeError(_))));
+    }
+
+    #[test]
+    fn try_catch_finally() {
+        let (vm, interpreter, result) = run(r#"
+            var log = "";
+            {
+                var local = "kept";
+                try {
+                    var doomed = 1;
+                    throw "up";
+                } catch (err) {
+                    log = log + err + " " + local;
+                } finally {
+                    log = log + " finally";
+                }
+            }
+            try { log = log + " " + len(1, 2); } catch (err) {}
+            "#);
+        assert_eq!(result, Ok(Value::Nil));
+        assert_eq!(
+            interpreter.get_global("log"),
+            Some(Value::from("up kept finally"))
+        );
+        assert!(vm.stack.is_empty());
+        assert!(vm.handlers.is_empty());
+    }
+
+    #[test]
+    fn finally_raises_again() {
+        let (vm, interpreter, result) = run(r#"
+            var ran = false;
+            try {
+                throw 42;
+            } finally {
+                ran = true;
+            }
+            "#);
+        assert_eq!(
+            result,
+            Err(RuntimeError::Thrown {
+                value: Value::Number(42.0),
+                line: 4,
+            })
+        );
+        assert_eq!(vm.line(), 4);
+        assert_eq!(interpreter.get_global("ran"), Some(Value::Boolean(true)));
+    }
+
+    #[test]
+    fn calls_natives() {
+        let (_, interpreter, result) = run(r#"var n = len("abc"[1] + "de");"#);
+        assert_eq!(result, Ok(Value::Nil));
+        assert_eq!(interpreter.get_global("n"), Some(Value::Number(3.0)));
+    }
+
+    #[test]
+    fn error_nodes_dont_compile() {
+        let mut scanner = Scanner::new("print 1; print (;".to_string());
+        let tokens = scanner.scan_tokens().unwrap();
+        let (statements, _) = Parser::new(tokens.clone()).parse_partial();
+        assert_eq!(
+            Compiler::compile(&statements),
+            Err(RuntimeError::InvalidProgram)
+        );
+    }
+
+    #[test]
+    fn stack_underflow() {
+        let chunk = Chunk {
+            code: vec![OpCode::Pop],
+            lines: vec![1],
+            ..Chunk::default()
+        };
+        assert_eq!(Vm::new().run(&chunk), Err(RuntimeError::InvalidProgram));
+    }
+
+    #[test]
+    fn runtime_error_line() {
+        let mut scanner = Scanner::new("print 1;\n\nprint -nil;".to_string());
+        let tokens = scanner.scan_tokens().unwrap();
+        let mut parser = Parser::new(tokens.clone());
+        let chunk = Compiler::compile(&parser.parse().unwrap()).unwrap();
+        let mut vm = Vm::new();
+        assert!(vm.run(&chunk).is_err());
+        assert_eq!(vm.line(), 3);
+    }
+
+    #[test]
+    fn incorrect_binary_expression() {
+        let (tree_walker, vm) = both_backends(r#"2 * (3 / -"muffin")"#);
+        assert_eq!(
+            vm,
+            Err(RuntimeError::TypeError(
+                "You can only negate a number".to_string()
+            ))
+        );
+        assert_eq!(tree_walker, vm);
+    }
+}
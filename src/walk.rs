@@ -0,0 +1,247 @@
+use crate::parser::{Expr, Stmt};
+
+// What a node is, without its children. One per variant of `Stmt` and
+// `Expr`, the matches below have no `_` arm so a new variant has to be
+// added here too.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum NodeKind {
+    ExpressionStmt,
+    Print,
+    Var,
+    Block,
+    If,
+    Try,
+    Throw,
+    ForIn,
+    ErrorStmt,
+    Binary,
+    Unary,
+    Literal,
+    Grouping,
+    Variable,
+    Assign,
+    Call,
+    Index,
+    SetIndex,
+    Range,
+    Dbg,
+    ErrorExpr,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Node<'a> {
+    Stmt(&'a Stmt),
+    Expr(&'a Expr),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct NodeRef<'a> {
+    pub node: Node<'a>,
+    pub kind: NodeKind,
+    // Top level statements are at depth 0.
+    pub depth: usize,
+    // Nodes only know their line through the statement they are in.
+    pub line: usize,
+}
+
+// Every statement and expression, parents before their children and
+// children in the order they are written. Pipes are the exception, they
+// are calls by now and `x |> f` comes out as `f` before `x`.
+pub fn walk(statements: &[Stmt]) -> impl Iterator<Item = NodeRef<'_>> {
+    let mut nodes = Vec::new();
+    for stmt in statements {
+        statement(stmt, 0, &mut nodes);
+    }
+    nodes.into_iter()
+}
+
+fn statement<'a>(stmt: &'a Stmt, depth: usize, nodes: &mut Vec<NodeRef<'a>>) {
+    let line = stmt.line();
+    let kind = match stmt {
+        Stmt::Expression { .. } => NodeKind::ExpressionStmt,
+        Stmt::Print { .. } => NodeKind::Print,
+        Stmt::Var { .. } => NodeKind::Var,
+        Stmt::Block { .. } => NodeKind::Block,
+        Stmt::If { .. } => NodeKind::If,
+        Stmt::Try { .. } => NodeKind::Try,
+        Stmt::Throw { .. } => NodeKind::Throw,
+        Stmt::ForIn { .. } => NodeKind::ForIn,
+        Stmt::Error { .. } => NodeKind::ErrorStmt,
+    };
+    nodes.push(NodeRef {
+        node: Node::Stmt(stmt),
+        kind,
+        depth,
+        line,
+    });
+    let depth = depth + 1;
+    match stmt {
+        Stmt::Expression { expr, .. } | Stmt::Throw { expr, .. } => {
+            expression(expr, depth, line, nodes)
+        }
+        Stmt::Print { exprs, .. } => {
+            for expr in exprs {
+                expression(expr, depth, line, nodes);
+            }
+        }
+        Stmt::Var { initializer, .. } => expression(initializer, depth, line, nodes),
+        Stmt::Block { statements, .. } => {
+            for stmt in statements {
+                statement(stmt, depth, nodes);
+            }
+        }
+        Stmt::If {
+            condition,
+            then_branch,
+            else_branch,
+            ..
+        } => {
+            expression(condition, depth, line, nodes);
+            statement(then_branch, depth, nodes);
+            if let Some(else_branch) = else_branch {
+                statement(else_branch, depth, nodes);
+            }
+        }
+        Stmt::Try {
+            body,
+            catch,
+            finally,
+            ..
+        } => {
+            statement(body, depth, nodes);
+            if let Some((_, handler)) = catch {
+                statement(handler, depth, nodes);
+            }
+            if let Some(finally) = finally {
+                statement(finally, depth, nodes);
+            }
+        }
+        Stmt::ForIn { iterable, body, .. } => {
+            expression(iterable, depth, line, nodes);
+            statement(body, depth, nodes);
+        }
+        Stmt::Error { .. } => {}
+    }
+}
+
+fn expression<'a>(expr: &'a Expr, depth: usize, line: usize, nodes: &mut Vec<NodeRef<'a>>) {
+    let kind = match expr {
+        Expr::Binary(_) => NodeKind::Binary,
+        Expr::Unary(_) => NodeKind::Unary,
+        Expr::Literal(_) => NodeKind::Literal,
+        Expr::Grouping(_) => NodeKind::Grouping,
+        Expr::Variable(_) => NodeKind::Variable,
+        Expr::Assign(_) => NodeKind::Assign,
+        Expr::Call(_) => NodeKind::Call,
+        Expr::Index(_) => NodeKind::Index,
+        Expr::SetIndex(_) => NodeKind::SetIndex,
+        Expr::Range(_) => NodeKind::Range,
+        Expr::Dbg(_) => NodeKind::Dbg,
+        Expr::Error(_) => NodeKind::ErrorExpr,
+    };
+    nodes.push(NodeRef {
+        node: Node::Expr(expr),
+        kind,
+        depth,
+        line,
+    });
+    let depth = depth + 1;
+    match expr {
+        Expr::Binary(b) => {
+            expression(&b.left, depth, line, nodes);
+            expression(&b.right, depth, line, nodes);
+        }
+        Expr::Unary(u) => expression(&u.right, depth, line, nodes),
+        Expr::Grouping(g) => expression(&g.expr, depth, line, nodes),
+        Expr::Assign(a) => expression(&a.value, depth, line, nodes),
+        Expr::Call(c) => {
+            expression(&c.callee, depth, line, nodes);
+            for argument in &c.arguments {
+                expression(argument, depth, line, nodes);
+            }
+        }
+        Expr::Index(i) => {
+            expression(&i.object, depth, line, nodes);
+            expression(&i.index, depth, line, nodes);
+        }
+        Expr::SetIndex(i) => {
+            expression(&i.object, depth, line, nodes);
+            expression(&i.index, depth, line, nodes);
+            expression(&i.value, depth, line, nodes);
+        }
+        Expr::Range(r) => {
+            expression(&r.start, depth, line, nodes);
+            expression(&r.end, depth, line, nodes);
+        }
+        Expr::Dbg(d) => expression(&d.expr, depth, line, nodes),
+        Expr::Literal(_) | Expr::Variable(_) | Expr::Error(_) => {}
+    }
+}
+
+#[cfg(test)]
+mod walk_tests {
+    use super::{walk, NodeKind, NodeKind::*};
+
+    fn kinds(source: &str) -> Vec<(NodeKind, usize)> {
+        walk(&crate::parse(source).unwrap())
+            .map(|node| (node.kind, node.depth))
+            .collect()
+    }
+
+    #[test]
+    fn pre_order() {
+        let source = "var a = -1;\nif (a < 2) {\n  print a[0];\n} else\n  a = f(a, 2..3);";
+        assert_eq!(
+            kinds(source),
+            [
+                (Var, 0),
+                (Unary, 1),
+                (Literal, 2),
+                (If, 0),
+                (Binary, 1),
+                (Variable, 2),
+                (Literal, 2),
+                (Block, 1),
+                (Print, 2),
+                (Index, 3),
+                (Variable, 4),
+                (Literal, 4),
+                (ExpressionStmt, 1),
+                (Assign, 2),
+                (Call, 3),
+                (Variable, 4),
+                (Variable, 4),
+                (Range, 4),
+                (Literal, 5),
+                (Literal, 5),
+            ]
+        );
+    }
+
+    #[test]
+    fn children_are_on_their_parents_lines() {
+        let source =
+            "try {\n  throw 1;\n} catch (e) {\n  print e;\n} finally {}\nfor (i in r) print i;";
+        let statements = crate::parse(source).unwrap();
+        let lines: Vec<(NodeKind, usize)> = walk(&statements)
+            .map(|node| (node.kind, node.line))
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                (Try, 1),
+                (Block, 1),
+                (Throw, 2),
+                (Literal, 2),
+                (Block, 3),
+                (Print, 4),
+                (Variable, 4),
+                (Block, 5),
+                (ForIn, 6),
+                (Variable, 6),
+                (Print, 6),
+                (Variable, 6),
+            ]
+        );
+    }
+}
@@ -0,0 +1,187 @@
+use std::sync::{Arc, Mutex};
+
+use roxy::{diagnostics::Code, Interpreter, InterpreterOptions, RoxyError, RuntimeError, Value};
+
+#[test]
+fn runs_a_program() {
+    assert_eq!(roxy::run("var a = 1; { var b = a + 1; a = b; }"), Ok(()));
+}
+
+#[test]
+fn keeps_globals_between_runs() {
+    let mut interpreter = Interpreter::new();
+    roxy::run_with("var a = 1;", &mut interpreter).unwrap();
+    roxy::run_with("a = a + 1;", &mut interpreter).unwrap();
+    assert_eq!(interpreter.get_global("a"), Some(Value::Number(2.0)));
+}
+
+#[test]
+fn scan_error() {
+    let err = roxy::run("var a = 1;\nprint @;\nprint \"open").unwrap_err();
+    assert!(matches!(&err, RoxyError::Scan(errors) if errors.len() == 2));
+    assert_eq!(
+        err.to_string(),
+        "[line 2] Error: Unexpected character.\n[line 3] Error: Unterminated string."
+    );
+}
+
+#[test]
+fn parse_error() {
+    let err = roxy::run("print 1 +;\nvar = 2;").unwrap_err();
+    assert!(matches!(&err, RoxyError::Parse(errors) if errors.len() == 2));
+    assert_eq!(
+        err.to_string(),
+        "[line 1] Error: Expect expression.\n[line 2] Error: Expect variable name."
+    );
+}
+
+#[test]
+fn runtime_error() {
+    let mut interpreter = Interpreter::new();
+    assert_eq!(
+        roxy::run_with("var a = 1;\nvar b = -\"a\";", &mut interpreter),
+        Err(RoxyError::Runtime(RuntimeError::TypeError(
+            "You can only negate a number".to_string()
+        )))
+    );
+    // Statements before the error already ran.
+    assert_eq!(interpreter.get_global("a"), Some(Value::Number(1.0)));
+    assert_eq!(interpreter.line(), 2);
+}
+
+#[test]
+fn check() {
+    // Never ends when run, and would fail if it got past the loop.
+    let source = "for (_i in 0..1000000000000) {}\nundefined();\n{\n  var unused = 1;\n}";
+    let warnings = roxy::check(source).unwrap();
+    assert_eq!(
+        warnings
+            .iter()
+            .map(|warning| warning.message.as_str())
+            .collect::<Vec<_>>(),
+        ["Local variable 'unused' is never used."]
+    );
+    assert!(matches!(
+        roxy::check("print 1 +;"),
+        Err(RoxyError::Parse(_))
+    ));
+}
+
+#[test]
+fn run_checked() {
+    let source = "var n = 9007199254740993;\n{\n  var unused = 1;\n}";
+    let warnings = roxy::run_checked(source, InterpreterOptions::default()).unwrap();
+    assert_eq!(
+        warnings
+            .iter()
+            .map(|warning| warning.code)
+            .collect::<Vec<_>>(),
+        [Code::InexactNumber, Code::UnusedVariable]
+    );
+    assert_eq!(warnings, roxy::check(source).unwrap());
+}
+
+#[test]
+fn unfinished_source() {
+    assert!(roxy::run("print (1").unwrap_err().at_end());
+    assert!(!roxy::run("print );").unwrap_err().at_end());
+}
+
+fn assert_send<T: Send>() {}
+
+#[test]
+fn types_are_send() {
+    assert_send::<Interpreter>();
+    assert_send::<Value>();
+    assert_send::<RoxyError>();
+    assert_send::<roxy::RunOutcome>();
+}
+
+#[test]
+fn interpreters_on_worker_threads() {
+    let workers: Vec<_> = (0..2)
+        .map(|worker| {
+            std::thread::spawn(move || {
+                let mut interpreter = Interpreter::new();
+                let source = format!(
+                    "var total = 0; for (i in 0..1000) total = total + {};",
+                    worker + 1
+                );
+                roxy::run_with(&source, &mut interpreter).unwrap();
+                // The interpreter and its values move back to the caller.
+                interpreter
+            })
+        })
+        .collect();
+    let totals: Vec<Option<Value>> = workers
+        .into_iter()
+        .map(|worker| worker.join().unwrap().get_global("total"))
+        .collect();
+    assert_eq!(
+        totals,
+        vec![Some(Value::Number(1000.0)), Some(Value::Number(2000.0))]
+    );
+}
+
+#[test]
+fn foreign_values() {
+    struct Db {
+        rows: usize,
+    }
+    let mut interpreter = Interpreter::new();
+    interpreter.define_global("db", Value::from_foreign(Db { rows: 3 }, "Db"));
+    interpreter.define_global("other", Value::from_foreign(Db { rows: 3 }, "Db"));
+    interpreter.define_native("rows", 1, |args| match args[0].downcast_foreign::<Db>() {
+        Some(db) => Ok(Value::Number(db.rows as f64)),
+        None => Err(format!("Expected a Db, got {}.", args[0].type_name())),
+    });
+    let printed = Arc::new(Mutex::new(Vec::new()));
+    let sink = Arc::clone(&printed);
+    interpreter.set_print_handler(move |value| sink.lock().unwrap().push(value.to_string()));
+    roxy::run_with(
+        "var copy = db; print db; print rows(copy); print copy == db; print db == other;",
+        &mut interpreter,
+    )
+    .unwrap();
+    assert_eq!(
+        *printed.lock().unwrap(),
+        ["<foreign Db>", "3", "true", "false"]
+    );
+    assert!(interpreter
+        .get_global("db")
+        .unwrap()
+        .downcast_foreign::<String>()
+        .is_none());
+
+    let err = roxy::run_with("print db + 1;", &mut interpreter).unwrap_err();
+    assert_eq!(err.to_string(), "Can't use a foreign Db with '+'.");
+    let err = roxy::run_with("print -db;", &mut interpreter).unwrap_err();
+    assert_eq!(err.to_string(), "Can't negate a foreign Db.");
+    let err = roxy::run_with("print rows(1);", &mut interpreter).unwrap_err();
+    assert!(err.to_string().contains("Expected a Db, got number."));
+}
+
+#[test]
+fn expression_with_spans() {
+    use roxy::diagnostics::{Code, Span};
+
+    let (expr, span) = roxy::parse_expression_with_spans("  a + f(1, 2) \n").unwrap();
+    assert_eq!(expr.to_string(), "a + f(1, 2)");
+    assert_eq!(span, Span::new(2, 13));
+
+    let errors = roxy::parse_expression_with_spans("1 + * 2").unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0].message, "Expect expression.");
+    assert_eq!(errors[0].span, Some(Span::new(4, 5)));
+
+    let errors = roxy::parse_expression_with_spans("").unwrap_err();
+    assert_eq!(errors[0].code, Code::ExpectExpression);
+    assert_eq!(errors[0].span, Some(Span::new(0, 0)));
+
+    let errors = roxy::parse_expression_with_spans("a + 1 b").unwrap_err();
+    assert_eq!(errors[0].code, Code::TrailingInput);
+    assert_eq!(errors[0].span, Some(Span::new(6, 7)));
+
+    let errors = roxy::parse_expression_with_spans("a @").unwrap_err();
+    assert_eq!(errors[0].span, Some(Span::new(2, 3)));
+}
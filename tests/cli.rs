@@ -0,0 +1,494 @@
+use std::{
+    env, fs,
+    io::Write,
+    path::PathBuf,
+    process::{Command, Output, Stdio},
+};
+
+fn roxy(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_roxy"))
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .unwrap()
+}
+
+fn roxy_stdin(args: &[&str], input: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_roxy"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn script(name: &str, source: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("roxy-{}-{}.lox", std::process::id(), name));
+    fs::write(&path, source).unwrap();
+    path
+}
+
+#[test]
+fn runs_script_file() {
+    let path = script(
+        "runs_script_file",
+        "var a = 1;\nprint a + 2;\nprint \"done\";\n",
+    );
+    let output = roxy(&[path.to_str().unwrap()]);
+    fs::remove_file(&path).unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "3\ndone\n");
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+}
+
+#[test]
+fn program_from_stdin() {
+    let output = roxy_stdin(&["-"], "var a = 1;\nprint a + 2;\n");
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "3\n");
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+
+    let output = roxy_stdin(&["-"], "print 1;\nprint 1 +;\n");
+    assert_eq!(output.status.code(), Some(65));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "[line 2] Error: Expect expression.\n\
+         \x20--> <stdin>:2:10\n\
+         2 | print 1 +;\n\
+         \x20 |          ^\n"
+    );
+
+    let output = roxy_stdin(&["-", "--diagnostics=json"], "print 1;\nprint -nil;\n");
+    assert_eq!(output.status.code(), Some(70));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "1\n");
+    assert!(
+        String::from_utf8_lossy(&output.stderr).starts_with(r#"{"file":"<stdin>","code":"E0202""#)
+    );
+}
+
+#[test]
+#[cfg(feature = "default-prelude")]
+fn default_prelude() {
+    let output = roxy_stdin(&["-"], "print PI, E;\n");
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "3.141592653589793 2.718281828459045\n"
+    );
+}
+
+#[test]
+fn missing_file() {
+    let output = roxy(&["does/not/exist.lox"]);
+    assert_eq!(output.status.code(), Some(66));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.starts_with("Could not read 'does/not/exist.lox': "));
+    assert_eq!(stderr.lines().count(), 1);
+}
+
+#[test]
+fn too_many_arguments() {
+    let output = roxy(&["a.lox", "b.lox"]);
+    assert_eq!(output.status.code(), Some(64));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "Usage: roxy [script | -]\n"
+    );
+}
+
+#[test]
+fn unknown_flag() {
+    let output = roxy(&["--vn", "tests/fixtures/runtime_error.lox"]);
+    assert_eq!(output.status.code(), Some(64));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "Unknown flag '--vn'.\nUsage: roxy [script | -]\n"
+    );
+}
+
+#[test]
+fn no_arguments_starts_repl() {
+    let output = roxy(&[]);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "> \n");
+}
+
+#[test]
+fn dump_tokens() {
+    let output = roxy(&["--dump-tokens", "tests/fixtures/dump.lox"]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "1:1 Var var\n\
+         1:5 Identifier a\n\
+         1:7 Equal =\n\
+         1:9 NumberLiteral 1\n\
+         1:11 Plus +\n\
+         1:13 NumberLiteral 2\n\
+         1:15 Star *\n\
+         1:17 NumberLiteral 3\n\
+         1:18 Semicolon ;\n\
+         2:1 If if\n\
+         2:4 LeftParen (\n\
+         2:5 Identifier a\n\
+         2:7 Greater >\n\
+         2:9 NumberLiteral 5\n\
+         2:10 RightParen )\n\
+         2:12 Print print\n\
+         2:18 StringLiteral \"big\"\n\
+         2:23 Semicolon ;\n\
+         3:1 EOF\n"
+    );
+}
+
+#[test]
+fn dump_ast() {
+    let output = roxy(&["--dump-ast", "tests/fixtures/dump.lox"]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "(var a (+ 1 (* 2 3)))\n(if (> a 5) (print \"big\"))\n"
+    );
+}
+
+#[test]
+fn dump_ast_and_run() {
+    let output = roxy(&["--dump-ast", "--run", "tests/fixtures/dump.lox"]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "(var a (+ 1 (* 2 3)))\n(if (> a 5) (print \"big\"))\nbig\n"
+    );
+}
+
+#[test]
+fn scan_error_exit_code() {
+    let output = roxy(&["tests/fixtures/scan_error.lox"]);
+    assert_eq!(output.status.code(), Some(65));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "[line 3] Error: Unterminated string.\n\
+         2 | print \"oops;\n\
+         \x20 |       ^^^^^^\n"
+    );
+}
+
+#[test]
+fn parse_error_exit_code() {
+    let output = roxy(&["tests/fixtures/parse_error.lox"]);
+    assert_eq!(output.status.code(), Some(65));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "[line 2] Error: Expect variable name.\n\
+         2 | var = 2;\n\
+         \x20 |     ^\n\
+         [line 3] Error: Expect ')' after expression.\n\
+         3 | print (3;\n\
+         \x20 |         ^\n"
+    );
+}
+
+#[test]
+fn runtime_error_exit_code() {
+    for args in [
+        &["tests/fixtures/runtime_error.lox"][..],
+        &["--vm", "tests/fixtures/runtime_error.lox"],
+    ] {
+        let output = roxy(args);
+        assert_eq!(output.status.code(), Some(70));
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "before\n");
+        assert_eq!(
+            String::from_utf8_lossy(&output.stderr),
+            "You can only negate a number\n[line 3]\n\
+             3 | print -\"after\";\n\
+             \x20 | ^^^^^^^^^^^^^^^\n"
+        );
+    }
+}
+
+#[test]
+fn uncaught_error_exit_code() {
+    let output = roxy(&["tests/fixtures/uncaught_error.lox"]);
+    assert_eq!(output.status.code(), Some(70));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "body\nfinally\n");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "You can only negate a number\n[line 3]\n\
+         3 |   print -\"oops\";\n\
+         \x20 |   ^^^^^^^^^^^^^^\n"
+    );
+}
+
+#[test]
+fn uncaught_throw_exit_code() {
+    let output = roxy(&["tests/fixtures/uncaught_throw.lox"]);
+    assert_eq!(output.status.code(), Some(70));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "before\n");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "bad input\n[line 2]\n\
+         2 | throw \"bad input\";\n\
+         \x20 | ^^^^^^^^^^^^^^^^^^\n"
+    );
+}
+
+#[test]
+fn unused_local_warning() {
+    let output = roxy(&["tests/fixtures/unused_local.lox"]);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "ran\n");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "[line 2] Warning: Local variable 'unused' is never used.\n\
+         2 |   var unused = 1;\n\
+         \x20 |   ^^^^^^^^^^^^^^^\n"
+    );
+}
+
+#[test]
+fn format() {
+    let output = roxy(&["--format", "tests/fixtures/dump.lox"]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "var a = 1 + 2 * 3;\nif (a > 5)\n    print \"big\";\n"
+    );
+}
+
+#[test]
+fn format_parse_error() {
+    let output = roxy(&["--format", "tests/fixtures/parse_error.lox"]);
+    assert_eq!(output.status.code(), Some(65));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "");
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .starts_with("[line 2] Error: Expect variable name."));
+}
+
+#[test]
+fn lint() {
+    let output = roxy(&["--lint", "tests/fixtures/lint.lox"]);
+    assert!(output.status.success());
+    // Nothing ran.
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "[line 3] Warning (unused-variable): Local variable 'a' is never used.\n\
+         3 |   var a = 2;\n\
+         \x20 |   ^^^^^^^^^^\n\
+         [line 3] Warning (shadowed-variable): Variable 'a' shadows a variable from an outer scope.\n\
+         3 |   var a = 2;\n\
+         \x20 |   ^^^^^^^^^^\n\
+         [line 5] Warning (self-comparison): 'a == a' compares a value to itself.\n\
+         5 | if (a == a) print \"same\";\n\
+         \x20 | ^^^^^^^^^^^^^^^^^^^^^^^^^\n"
+    );
+}
+
+#[test]
+fn json_diagnostics() {
+    let output = roxy(&["--diagnostics=json", "tests/fixtures/parse_error.lox"]);
+    assert_eq!(output.status.code(), Some(65));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "{\"code\":\"E0105\",\"severity\":\"error\",\"message\":\"Expect variable name.\",\"line\":2,\"column\":5,\"span\":{\"start\":13,\"end\":14}}\n\
+         {\"code\":\"E0104\",\"severity\":\"error\",\"message\":\"Expect ')' after expression.\",\"line\":3,\"column\":9,\"span\":{\"start\":26,\"end\":27}}\n"
+    );
+}
+
+// Warnings for what parsed come along with the parse errors, in the order
+// they are in the file.
+#[test]
+fn diagnostics_in_source_order() {
+    let path = script(
+        "diagnostics_in_source_order",
+        "{\n  var unused = 1;\n}\nvar = 2;\n{\n  var other = 1;\n}\nprint (3;\n",
+    );
+    let output = roxy(&[path.to_str().unwrap()]);
+    fs::remove_file(&path).unwrap();
+    assert_eq!(output.status.code(), Some(65));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let headings: Vec<&str> = stderr
+        .lines()
+        .filter(|line| line.starts_with('['))
+        .collect();
+    assert_eq!(
+        headings,
+        [
+            "[line 2] Warning: Local variable 'unused' is never used.",
+            "[line 4] Error: Expect variable name.",
+            "[line 6] Warning: Local variable 'other' is never used.",
+            "[line 8] Error: Expect ')' after expression.",
+        ]
+    );
+}
+
+#[test]
+fn json_runtime_diagnostics() {
+    let path = script(
+        "json_runtime_diagnostics",
+        "{\n  var unused = 1;\n}\nprint typo;\n",
+    );
+    let output = roxy(&["--diagnostics=json", path.to_str().unwrap()]);
+    fs::remove_file(&path).unwrap();
+    assert_eq!(output.status.code(), Some(70));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "{\"code\":\"W0301\",\"severity\":\"warning\",\"message\":\"Local variable 'unused' is never used.\",\"line\":2,\"column\":3,\"span\":{\"start\":4,\"end\":19}}\n\
+         {\"code\":\"E0201\",\"severity\":\"error\",\"message\":\"Undefined variable 'typo'. Did you mean 'type'?\",\"line\":4,\"column\":1,\"span\":{\"start\":22,\"end\":33}}\n"
+    );
+}
+
+#[test]
+fn profile() {
+    let path = script(
+        "profile",
+        "for (i in 1..=4) {\n  if (len(\"ab\") == i) print format(\"{}\", i);\n}\n",
+    );
+    let output = roxy(&["--profile", path.to_str().unwrap()]);
+    fs::remove_file(&path).unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "2\n");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let lines: Vec<&str> = stderr.lines().collect();
+    assert_eq!(lines.len(), 4, "{}", stderr);
+    assert!(lines[0].starts_with("function"));
+    assert!(lines[1].starts_with("len              4"), "{}", stderr);
+    assert!(lines[2].starts_with("format           1"), "{}", stderr);
+    assert_eq!(lines[3], "10 statements, 27 expressions, 4 loop iterations");
+}
+
+#[test]
+fn timings() {
+    let output = roxy_stdin(&["-", "--timings"], "var a = 1;\nprint (a + 2) * 3;\n");
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "9\n");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let lines: Vec<&str> = stderr.lines().collect();
+    assert_eq!(
+        lines[..4],
+        [
+            "tokens              14",
+            "statements           2",
+            "nodes                9",
+            "max depth            4",
+        ]
+    );
+    let stages: Vec<&str> = lines[4..]
+        .iter()
+        .map(|line| line.split_whitespace().next().unwrap())
+        .collect();
+    assert_eq!(stages, ["scan", "parse", "resolve", "run", "total"]);
+    assert!(lines.iter().skip(4).all(|line| line.ends_with(" ms")));
+}
+
+#[test]
+fn coverage() {
+    let path = script(
+        "coverage",
+        "var a = 1;\nif (a > 1)\n  print a;\nprint -a;\n",
+    );
+    let output = roxy(&["--coverage", path.to_str().unwrap()]);
+    fs::remove_file(&path).unwrap();
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "-1\n");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "    1 | var a = 1;\n\
+         \x20   1 | if (a > 1)\n\
+         ##### |   print a;\n\
+         \x20   1 | print -a;\n\
+         3 of 4 lines covered (75.0%)\n"
+    );
+}
+
+#[test]
+fn inexact_number_warning() {
+    let path = script("inexact", "print 9007199254740993;\n");
+    let output = roxy(&[path.to_str().unwrap()]);
+    fs::remove_file(&path).unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "9007199254740992\n"
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "[line 1] Warning: Number literal cannot be represented exactly; stored as 9007199254740992.\n\
+         1 | print 9007199254740993;\n\
+         \x20 |       ^^^^^^^^^^^^^^^^\n"
+    );
+}
+
+#[test]
+fn deny_warnings() {
+    let output = roxy(&["tests/fixtures/unused_local.lox"]);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "ran\n");
+    let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+    assert!(stderr.starts_with("[line 2] Warning: Local variable 'unused' is never used.\n"));
+
+    let output = roxy(&["--deny-warnings", "tests/fixtures/unused_local.lox"]);
+    assert_eq!(output.status.code(), Some(65));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        stderr + "Stopping because of 1 warning(s), --deny-warnings is on.\n"
+    );
+
+    let output = roxy(&["--deny-warnings", "tests/fixtures/dump.lox"]);
+    assert!(output.status.success());
+}
+
+#[test]
+fn check() {
+    let output = roxy(&["--check", "tests/fixtures/unused_local.lox"]);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "");
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .starts_with("[line 2] Warning: Local variable 'unused' is never used.\n"));
+
+    // Only fails once it runs.
+    let output = roxy(&["--check", "tests/fixtures/runtime_error.lox"]);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "");
+
+    let output = roxy(&["--check", "tests/fixtures/parse_error.lox"]);
+    assert_eq!(output.status.code(), Some(65));
+}
+
+#[test]
+fn clock() {
+    let output = roxy_stdin(&["-"], "print clock() > 0;\n");
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "true\n");
+}
+
+#[test]
+fn sleep() {
+    let output = roxy_stdin(&["-"], "sleep(0);\nprint 1;\n");
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "1\n");
+}
+
+#[test]
+fn deterministic() {
+    let source = "print clock(), clock();\nfor (i in 0..3) print i, random(), randomInt(1, 100);\n";
+    let first = roxy_stdin(&["-", "--deterministic"], source);
+    let second = roxy_stdin(&["-", "--deterministic"], source);
+    assert!(first.status.success());
+    assert_eq!(first.stdout, second.stdout);
+    assert!(String::from_utf8_lossy(&first.stdout).starts_with("0 0.001\n"));
+    assert_eq!(String::from_utf8_lossy(&first.stderr), "");
+}
@@ -0,0 +1,60 @@
+use std::{fs, path::Path};
+
+use roxy::ast_printer;
+
+fn lox_files(dir: &str) -> Vec<std::path::PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join(dir);
+    let mut paths: Vec<_> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "lox"))
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty());
+    paths
+}
+
+// Every tests/format/NAME.lox is formatted and compared to NAME.expected.
+#[test]
+fn golden() {
+    for path in lox_files("tests/format") {
+        let source = fs::read_to_string(&path).unwrap();
+        let expected = fs::read_to_string(path.with_extension("expected")).unwrap();
+        assert_eq!(
+            roxy::format_source(&source).unwrap(),
+            expected,
+            "{}",
+            path.display()
+        );
+    }
+}
+
+// Formatting is a no-op on formatted code and never changes what a program
+// means. The AST printer leaves out lines, which formatting does change.
+#[test]
+fn idempotent_and_same_program() {
+    let mut paths = lox_files("tests/programs");
+    paths.extend(lox_files("tests/format"));
+    let mut formatted_any = false;
+    for path in paths {
+        let source = fs::read_to_string(&path).unwrap();
+        // The programs checking error messages don't parse.
+        let Ok(formatted) = roxy::format_source(&source) else {
+            continue;
+        };
+        formatted_any = true;
+        assert_eq!(
+            roxy::format_source(&formatted).unwrap(),
+            formatted,
+            "{}",
+            path.display()
+        );
+        assert_eq!(
+            ast_printer::print(&roxy::parse(&formatted).unwrap()),
+            ast_printer::print(&roxy::parse(&source).unwrap()),
+            "{}",
+            path.display()
+        );
+    }
+    assert!(formatted_any);
+}
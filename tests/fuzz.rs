@@ -0,0 +1,80 @@
+// Inputs that once panicked, crashed or ran out of memory. Each one has to
+// come back from the fuzzing entry point.
+
+#[test]
+fn regressions() {
+    let inputs = [
+        // Doubling a string until memory runs out.
+        "var s = \"ab\"; for (i in 0..100) s = s + s;",
+        // Counting a huge range one value at a time.
+        "print len(-9223372036854775808..=9223372036854775807);",
+        "for (i in 1..=9223372036854775807) print i;",
+        "print \"abc\"[100000000000000000000];",
+        "print format(\"{99999999999999999999999}\", 1);",
+        "print \"unterminated",
+        "print (1 +",
+        "@ # é",
+        "",
+    ];
+    for input in inputs {
+        roxy::fuzz_pipeline(input.as_bytes());
+    }
+    roxy::fuzz_pipeline(&[0xff, 0xfe, b'(', 0x80]);
+}
+
+// Deep nesting used to overflow the stack in the parser and the passes after
+// it, now it is a parse error.
+#[test]
+fn deep_nesting() {
+    let inputs = [
+        "(".repeat(100_000),
+        "-".repeat(100_000) + "1;",
+        // The parser recovers at every brace, so many more take seconds.
+        // `deep_blocks` has them.
+        "{".repeat(1_000),
+        "a".to_string() + &" = a".repeat(100_000) + ";",
+        "f".to_string() + &"()".repeat(100_000) + ";",
+        "if (true) ".repeat(100_000) + "print 1;",
+    ];
+    for input in inputs {
+        roxy::fuzz_pipeline(input.as_bytes());
+        let err = roxy::parse(&input).unwrap_err();
+        assert!(
+            err.errors().any(|err| err.message == "Too much nesting."),
+            "{}",
+            err
+        );
+    }
+}
+
+#[test]
+#[ignore = "takes about half a minute"]
+fn deep_blocks() {
+    let input = "{".repeat(100_000);
+    roxy::fuzz_pipeline(input.as_bytes());
+    let err = roxy::parse(&input).unwrap_err();
+    assert!(err.errors().any(|err| err.message == "Too much nesting."));
+}
+
+// Operators in a row aren't nesting, but there can only be so many in one
+// statement.
+#[test]
+fn long_operator_chains() {
+    let source = "print 0".to_string() + &" + 1".repeat(200) + ";";
+    assert_eq!(roxy::run(&source), Ok(()));
+    let input = "1".to_string() + &" + 1".repeat(10_000) + ";";
+    roxy::fuzz_pipeline(input.as_bytes());
+    let err = roxy::parse(&input).unwrap_err();
+    assert!(
+        err.errors()
+            .any(|err| err.message == "Too many operators in one statement."),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn nesting_within_the_limit() {
+    let source = "print ".to_string() + &"(".repeat(100) + "1" + &")".repeat(100) + ";";
+    assert_eq!(roxy::run(&source), Ok(()));
+}
@@ -0,0 +1,97 @@
+// `run_checked` and everything else that takes a program from a user must
+// not panic, whatever the program is. Every file in tests/hostile/ goes
+// through all of it, read as bytes so files that aren't UTF-8 can be kept
+// there too. When something new is found to panic, add it as a file.
+
+use std::{
+    fs,
+    panic::{self, AssertUnwindSafe},
+    path::PathBuf,
+};
+
+use roxy::{
+    arena::Ast,
+    ast_printer,
+    compiler::Compiler,
+    lint::{self, LintOptions},
+    vm::Vm,
+    Interpreter, InterpreterOptions,
+};
+
+const FUEL: u64 = 10_000;
+
+fn options() -> InterpreterOptions {
+    InterpreterOptions {
+        fuel: Some(FUEL),
+        max_output_bytes: Some(4096),
+        ..InterpreterOptions::default()
+    }
+}
+
+fn everything(source: &str) {
+    let _ = roxy::run_checked(source, options());
+    let _ = roxy::check(source);
+    let _ = roxy::format_source(source);
+    let _ = roxy::parse_expression_with_spans(source);
+    if let Ok(statements) = roxy::parse(source) {
+        ast_printer::print(&statements);
+        lint::lint(source, &statements, &LintOptions::default());
+        let mut interpreter = Interpreter::with_options(options());
+        interpreter.set_print_handler(|_| {});
+        let _ = interpreter.interpret_ast(&Ast::from_stmts(&statements));
+        let mut interpreter = Interpreter::with_options(options());
+        interpreter.set_print_handler(|_| {});
+        if let Ok(chunk) = Compiler::compile(&statements) {
+            let _ = Vm::new().run_with(&chunk, &mut interpreter);
+        }
+    }
+}
+
+// The name of the input when it panicked.
+fn survives(name: &str, source: &str) -> Result<(), String> {
+    panic::catch_unwind(AssertUnwindSafe(|| everything(source))).map_err(|_| name.to_string())
+}
+
+#[test]
+fn hostile_corpus() {
+    let dir = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/hostile");
+    let mut paths: Vec<PathBuf> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty());
+    let panicked: Vec<String> = paths
+        .iter()
+        .filter_map(|path| {
+            let source = String::from_utf8_lossy(&fs::read(path).unwrap()).into_owned();
+            survives(&path.display().to_string(), &source).err()
+        })
+        .collect();
+    assert!(panicked.is_empty(), "Panicked on {:?}", panicked);
+}
+
+// Too big to keep as files.
+#[test]
+fn generated() {
+    let inputs = [
+        ("parens", "(".repeat(10_000)),
+        ("negations", "-".repeat(10_000) + "1;"),
+        ("blocks", "{".repeat(10_000) + &"}".repeat(10_000)),
+        ("dbg", "print ".to_string() + &"dbg(".repeat(10_000) + "1"),
+        ("bad characters", "@".repeat(100_000)),
+    ];
+    for (name, source) in inputs {
+        assert_eq!(survives(name, &source), Ok(()));
+    }
+}
+
+// Every way of cutting a program short.
+#[test]
+fn truncated() {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/hostile/runtime_errors.lox");
+    let source = fs::read_to_string(path).unwrap();
+    for end in (0..source.len()).filter(|&end| source.is_char_boundary(end)) {
+        assert_eq!(survives(&format!("prefix {}", end), &source[..end]), Ok(()));
+    }
+}
@@ -0,0 +1,160 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use roxy::{arena::Ast, compiler::Compiler, vm::Vm, Interpreter, RoxyError};
+
+// Runs every program in tests/programs and checks it against the comments in
+// it, following the convention of the Crafting Interpreters test suite:
+//
+//     print 1;     // expect: 1
+//     print -nil;  // expect runtime error: You can only negate a number
+//     print 1 +;   // expect error: Expect expression.
+//
+// Errors are expected on the line their comment is on. Adding a test is just
+// adding a file.
+
+#[derive(Debug, Default, PartialEq)]
+struct Outcome {
+    output: Vec<String>,
+    // Line and message of every scan or parse error.
+    errors: Vec<(usize, String)>,
+    runtime_error: Option<(usize, String)>,
+}
+
+fn expected(source: &str) -> Outcome {
+    let mut outcome = Outcome::default();
+    for (i, line) in source.lines().enumerate() {
+        let comment = match line.find("// expect") {
+            Some(start) => &line[start + "// ".len()..],
+            None => continue,
+        };
+        if let Some(output) = comment.strip_prefix("expect: ") {
+            outcome.output.push(output.to_string());
+        } else if let Some(message) = comment.strip_prefix("expect runtime error: ") {
+            outcome.runtime_error = Some((i + 1, message.to_string()));
+        } else if let Some(message) = comment.strip_prefix("expect error: ") {
+            outcome.errors.push((i + 1, message.to_string()));
+        } else {
+            panic!("unknown expectation on line {}: {}", i + 1, comment);
+        }
+    }
+    outcome
+}
+
+fn actual(source: &str) -> Outcome {
+    let printed = Arc::new(Mutex::new(Vec::new()));
+    let sink = Arc::clone(&printed);
+    let mut interpreter = Interpreter::new();
+    interpreter.set_print_handler(move |value| sink.lock().unwrap().push(value.to_string()));
+    let mut outcome = Outcome::default();
+    match roxy::run_with(source, &mut interpreter) {
+        Ok(()) => {}
+        Err(RoxyError::Runtime(err)) => {
+            let line = err.line().unwrap_or(interpreter.line());
+            outcome.runtime_error = Some((line, err.to_string()));
+        }
+        Err(err) => {
+            outcome.errors = err
+                .errors()
+                .map(|diagnostic| (diagnostic.line.unwrap_or(0), diagnostic.message))
+                .collect();
+        }
+    }
+    outcome.output = printed.lock().unwrap().clone();
+    outcome
+}
+
+// The same program lowered to the arena, with literals in its constant
+// table. None if it doesn't parse.
+fn actual_on_arena(source: &str) -> Option<Outcome> {
+    let statements = roxy::parse(source).ok()?;
+    let printed = Arc::new(Mutex::new(Vec::new()));
+    let sink = Arc::clone(&printed);
+    let mut interpreter = Interpreter::new();
+    interpreter.set_source(source);
+    interpreter.set_print_handler(move |value| sink.lock().unwrap().push(value.to_string()));
+    let mut outcome = Outcome::default();
+    if let Err(err) = interpreter.interpret_ast(&Ast::from_stmts(&statements)) {
+        let line = err.line().unwrap_or(interpreter.line());
+        outcome.runtime_error = Some((line, err.to_string()));
+    }
+    outcome.output = printed.lock().unwrap().clone();
+    Some(outcome)
+}
+
+// The same program compiled to bytecode and run on the VM. None if it
+// doesn't parse.
+fn actual_on_vm(source: &str) -> Option<Outcome> {
+    let statements = roxy::parse(source).ok()?;
+    let printed = Arc::new(Mutex::new(Vec::new()));
+    let sink = Arc::clone(&printed);
+    let mut interpreter = Interpreter::new();
+    interpreter.set_source(source);
+    interpreter.set_print_handler(move |value| sink.lock().unwrap().push(value.to_string()));
+    let mut outcome = Outcome::default();
+    let mut vm = Vm::new();
+    let result =
+        Compiler::compile(&statements).and_then(|chunk| vm.run_with(&chunk, &mut interpreter));
+    if let Err(err) = result {
+        let line = err.line().unwrap_or(vm.line());
+        outcome.runtime_error = Some((line, err.to_string()));
+    }
+    outcome.output = printed.lock().unwrap().clone();
+    Some(outcome)
+}
+
+fn paths() -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/programs");
+    let mut paths: Vec<_> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "lox"))
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty());
+    paths
+}
+
+#[test]
+fn programs() {
+    let paths = paths();
+    let failures: Vec<String> = paths
+        .iter()
+        .filter_map(|path| {
+            let source = fs::read_to_string(path).unwrap();
+            let (expected, actual) = (expected(&source), actual(&source));
+            (expected != actual).then(|| {
+                format!(
+                    "{}\n  expected: {:?}\n  actual:   {:?}",
+                    path.display(),
+                    expected,
+                    actual
+                )
+            })
+        })
+        .collect();
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
+
+#[test]
+fn programs_on_the_arena() {
+    for path in paths() {
+        let source = fs::read_to_string(&path).unwrap();
+        if let Some(outcome) = actual_on_arena(&source) {
+            assert_eq!(outcome, actual(&source), "{}", path.display());
+        }
+    }
+}
+
+#[test]
+fn programs_on_the_vm() {
+    for path in paths() {
+        let source = fs::read_to_string(&path).unwrap();
+        if let Some(outcome) = actual_on_vm(&source) {
+            assert_eq!(outcome, actual(&source), "{}", path.display());
+        }
+    }
+}
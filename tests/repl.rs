@@ -0,0 +1,111 @@
+use std::{
+    io::Write,
+    process::{Command, Output, Stdio},
+};
+
+fn repl(input: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_roxy"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn keeps_state_between_lines() {
+    let output = repl("var a = 1;\na = a + 1;\nprint a;\n");
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "> > 2\n> 2\n> \n");
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+}
+
+#[test]
+fn continues_after_errors() {
+    let output = repl("print b;\nprint 1 +;\nprint 3;\n");
+    // The last error was a syntax error.
+    assert_eq!(output.status.code(), Some(65));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "> > > 3\n> \n");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "Undefined variable 'b'.\n[line 1]\n\
+         1 | print b;\n\
+         \x20 | ^^^^^^^^\n\
+         [line 1] Error: Expect expression.\n\
+         1 | print 1 +;\n\
+         \x20 |          ^\n"
+    );
+}
+
+#[test]
+fn multi_line_input() {
+    let output = repl("{\n  var c = \"a\nb\";\n  print c;\n}\nprint (1\n+ 2);\n");
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "> ... ... ... ... a\nb\n> ... 3\n> \n"
+    );
+}
+
+#[test]
+fn echoes_strings_quoted() {
+    let output = repl("var s = \"a\nb\";\ns;\nprint s;\n");
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "> ... > \"a\\nb\"\n> a\nb\n> \n"
+    );
+}
+
+#[test]
+fn exit_command() {
+    let output = repl("print 1;\nexit\nprint 2;\n");
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "> 1\n> ");
+}
+
+#[test]
+fn unfinished_input_at_eof() {
+    let output = repl("print (1\n");
+    assert_eq!(output.status.code(), Some(65));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "> ... \n");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "[line 2] Error at end: Expect ')' after expression.\n2 |\n  | ^\n"
+    );
+}
+
+#[test]
+fn runtime_error_exit_code() {
+    let output = repl("1;\n-nil;\n");
+    assert_eq!(output.status.code(), Some(70));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "> 1\n> > \n");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "You can only negate a number\n[line 1]\n1 | -nil;\n  | ^^^^^\n"
+    );
+}
+
+#[test]
+fn warns_about_cross_type_equality() {
+    let output = repl("print 1 == \"1\";\nprint 1 == nil;\n");
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "> false\n> false\n> \n"
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "[line 1] Warning: '==' compares a number with a string, values of different types are \
+         never equal.\n\
+         1 | print 1 == \"1\";\n\
+         \x20 |       ^^^^^^^^\n"
+    );
+}
@@ -0,0 +1,73 @@
+use std::fs;
+
+use roxy::scanner::{Token, TokenKind};
+
+// Invariants every token has to keep, whatever the scanner does to find it.
+fn fixture() -> (String, Vec<Token>) {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/tokens.lox");
+    let source = fs::read_to_string(path).unwrap();
+    let tokens = roxy::scan(&source).unwrap();
+    (source, tokens)
+}
+
+#[test]
+fn spans_hold_their_lexemes() {
+    let (source, tokens) = fixture();
+    for token in &tokens {
+        let text = &source[token.span.start..token.span.end];
+        match &token.kind {
+            TokenKind::EOF => assert_eq!(text, "", "{:?}", token),
+            // Numbers can be written more than one way.
+            TokenKind::NumberLiteral(value) => {
+                assert_eq!(text.parse::<f64>().ok(), Some(*value), "{:?}", token)
+            }
+            TokenKind::CharLiteral(_) => {
+                assert!(
+                    text.starts_with('\'') && text.ends_with('\''),
+                    "{:?}",
+                    token
+                )
+            }
+            kind => assert_eq!(text, kind.to_string(), "{:?}", token),
+        }
+    }
+}
+
+#[test]
+fn tokens_dont_overlap() {
+    let (_, tokens) = fixture();
+    for pair in tokens.windows(2) {
+        let (before, after) = (&pair[0], &pair[1]);
+        assert!(before.span.start < before.span.end, "{:?}", before);
+        assert!(
+            before.span.end <= after.span.start,
+            "{:?} {:?}",
+            before,
+            after
+        );
+        assert!(before.pos < after.pos, "{:?} {:?}", before, after);
+    }
+    assert!(tokens.len() > 100);
+}
+
+#[test]
+fn positions_and_columns_match_spans() {
+    let (source, tokens) = fixture();
+    for token in &tokens {
+        assert_eq!(token.pos, token.span.start, "{:?}", token);
+        let line_start = source[..token.span.start].rfind('\n').map_or(0, |i| i + 1);
+        assert_eq!(
+            token.line,
+            source[..token.span.start].matches('\n').count() + 1,
+            "{:?}",
+            token
+        );
+        // In bytes, like `SourceMap::column`.
+        assert_eq!(
+            token.column,
+            token.span.start - line_start + 1,
+            "{:?}",
+            token
+        );
+    }
+}